@@ -1,12 +1,12 @@
 /// Benchmarks for the byte order reversal implementation.
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use reductionist::array::{build_array_mut_from_shape, get_shape, reverse_array_byte_order};
-use reductionist::models::{DType, RequestData, Slice};
+use reductionist::models::{DType, RequestData, Slice, Source};
 use url::Url;
 
 fn get_test_request_data() -> RequestData {
     RequestData {
-        source: Url::parse("http://example.com").unwrap(),
+        source: Source::Url(Url::parse("http://example.com").unwrap()),
         bucket: "bar".to_string(),
         object: "baz".to_string(),
         dtype: DType::Int32,
@@ -19,6 +19,19 @@ fn get_test_request_data() -> RequestData {
         compression: None,
         filters: None,
         missing: None,
+        shard: None,
+        cf_convention: false,
+        scale_factor: None,
+        add_offset: None,
+        group_by: None,
+        coarsen: None,
+        ensemble: None,
+        expression: None,
+        precision: None,
+        ddof: None,
+        nan_policy: None,
+        partial: false,
+        requester_pays: false,
     }
 }
 
@@ -30,7 +43,7 @@ fn criterion_benchmark(c: &mut Criterion) {
         request_data.dtype = DType::Uint32;
         let shape = get_shape(data.len(), &request_data);
         let mut array = build_array_mut_from_shape(shape, &mut data).unwrap();
-        for selection in [None, Some(vec![Slice::new(size / 4, size / 2, 2)])] {
+        for selection in [None, Some(vec![Slice::new(size / 4, size / 2, 2).into()])] {
             let name = format!("byte_order({}, {:?})", size, selection);
             c.bench_function(&name, |b| {
                 b.iter(|| {
@@ -1,4 +1,7 @@
-/// Benchmarks for the byte shuffle filter implementation.
+/// Benchmarks for the decompression implementations.
+///
+/// To compare decompression backends, run with and without the corresponding Cargo feature, e.g.
+/// `cargo bench --bench compression --features zlib-ng`.
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use reductionist::compression;
 use reductionist::models;
@@ -26,17 +29,61 @@ fn compress_zlib(data: &[u8]) -> Bytes {
     result.into()
 }
 
+fn compress_blosc(data: &[u8]) -> Bytes {
+    // A single unsplit block of LZ4 compressed data, without the byte shuffle.
+    let compressed = lz4_flex::block::compress(data);
+    let mut result = vec![2, 1, 0x10 | 1 << 5, 8];
+    // Uncompressed size, block size and compressed size.
+    result.extend((data.len() as u32).to_le_bytes());
+    result.extend((data.len() as u32).to_le_bytes());
+    result.extend(((24 + compressed.len()) as u32).to_le_bytes());
+    // Block start and stream size.
+    result.extend(20_u32.to_le_bytes());
+    result.extend((compressed.len() as u32).to_le_bytes());
+    result.extend(compressed);
+    result.into()
+}
+
+fn compress_lz4(data: &[u8]) -> Bytes {
+    // A numcodecs LZ4 block, prefixed with the uncompressed size.
+    let mut result = (data.len() as u32).to_le_bytes().to_vec();
+    result.extend(lz4_flex::block::compress(data));
+    result.into()
+}
+
+fn compress_zstd(data: &[u8]) -> Bytes {
+    // ruzstd has no encoder, so store the data in raw blocks of at most 128 KiB.
+    let mut result = vec![0x28, 0xb5, 0x2f, 0xfd];
+    // Single segment with an 8-byte content size.
+    result.push(0xe0);
+    result.extend((data.len() as u64).to_le_bytes());
+    let blocks: Vec<_> = data.chunks(128 * 1024).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        let last = (index == blocks.len() - 1) as u32;
+        let header = last | (block.len() as u32) << 3;
+        result.extend(&header.to_le_bytes()[..3]);
+        result.extend(*block);
+    }
+    result.into()
+}
+
 fn compress(compression: models::Compression, data: &[u8]) -> Bytes {
     match compression {
+        models::Compression::Blosc => compress_blosc(data),
         models::Compression::Gzip => compress_gzip(data),
+        models::Compression::Lz4 => compress_lz4(data),
         models::Compression::Zlib => compress_zlib(data),
+        models::Compression::Zstd => compress_zstd(data),
     }
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
     let compression_algs = [
+        (models::Compression::Blosc, "blosc"),
         (models::Compression::Gzip, "gzip"),
+        (models::Compression::Lz4, "lz4"),
         (models::Compression::Zlib, "zlib"),
+        (models::Compression::Zstd, "zstd"),
     ];
     for (compression, name) in compression_algs {
         for size_k in [64, 256, 1024] {
@@ -44,10 +91,11 @@ fn criterion_benchmark(c: &mut Criterion) {
             let data: Vec<i64> = (0_i64..size).map(|i| i % 256).collect::<Vec<i64>>();
             let bytes = Bytes::copy_from_slice(data.as_bytes());
             let compressed = compress(compression, &bytes);
-            let name = format!("decompress({}, {})", name, size);
+            let name = format!("decompress({}, {}, {})", name, compression::backend(), size);
             c.bench_function(&name, |b| {
                 b.iter(|| {
-                    compression::decompress(compression, black_box(&compressed)).unwrap();
+                    compression::decompress(compression, black_box(&compressed), None, None)
+                        .unwrap();
                 })
             });
         }
@@ -0,0 +1,159 @@
+/// Benchmarks for the reduction kernels of each operation.
+///
+/// Each operation is benchmarked for every combination of data type, missing data mode and array
+/// layout. Benchmark IDs have the form `<operation>/<dtype>/<missing>/<layout>`. Run using
+/// `make bench` to produce a JSON summary that may be compared between releases.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use num_traits::FromPrimitive;
+use reductionist::error::ActiveStorageError;
+use reductionist::models::{DType, Order, RequestData, Response, Slice, Source};
+use reductionist::operation::Operation;
+use reductionist::operations;
+use reductionist::types::Missing;
+use std::time::Duration;
+use url::Url;
+// Bring trait into scope to use as_bytes method.
+use zerocopy::AsBytes;
+
+/// Length of each dimension of the 2D test array.
+const DIM: usize = 512;
+
+fn get_test_request_data() -> RequestData {
+    RequestData {
+        source: Source::Url(Url::parse("http://example.com").unwrap()),
+        bucket: "bar".to_string(),
+        object: "baz".to_string(),
+        dtype: DType::Int32,
+        byte_order: None,
+        offset: None,
+        size: None,
+        shape: Some(vec![DIM, DIM]),
+        order: None,
+        selection: None,
+        compression: None,
+        filters: None,
+        missing: None,
+        shard: None,
+        cf_convention: false,
+        scale_factor: None,
+        add_offset: None,
+        group_by: None,
+        coarsen: None,
+        ensemble: None,
+        expression: None,
+        precision: None,
+        ddof: None,
+        nan_policy: None,
+        partial: false,
+        requester_pays: false,
+    }
+}
+
+fn make_data<T: AsBytes + FromPrimitive>() -> Vec<u8> {
+    let data: Vec<T> = (0..DIM * DIM)
+        .map(|i| T::from_usize(i % 128).unwrap())
+        .collect();
+    data.as_bytes().into()
+}
+
+fn make_data_for(dtype: DType) -> Vec<u8> {
+    match dtype {
+        DType::Int8 => make_data::<i8>(),
+        DType::Int16 => make_data::<i16>(),
+        DType::Int32 => make_data::<i32>(),
+        DType::Int64 => make_data::<i64>(),
+        DType::Uint8 => make_data::<u8>(),
+        DType::Uint16 => make_data::<u16>(),
+        DType::Uint32 => make_data::<u32>(),
+        DType::Uint64 => make_data::<u64>(),
+        DType::Float32 => make_data::<f32>(),
+        DType::Float64 => make_data::<f64>(),
+    }
+}
+
+/// Set the array layout of the request data.
+///
+/// Layouts are `c` (row-major), `f` (column-major) and `strided` (row-major with a stride of 2
+/// in each dimension).
+fn set_layout(request_data: &mut RequestData, layout: &str) {
+    match layout {
+        "c" => request_data.order = Some(Order::C),
+        "f" => request_data.order = Some(Order::F),
+        "strided" => {
+            let slice = Slice::new(0, DIM as isize, 2);
+            request_data.order = Some(Order::C);
+            request_data.selection = Some(vec![slice.into(), slice.into()]);
+        }
+        _ => panic!("unknown layout {}", layout),
+    }
+}
+
+type ExecuteFn = dyn Fn(&RequestData, Vec<u8>) -> Result<Response, ActiveStorageError>;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let dtypes = [
+        DType::Int8,
+        DType::Int16,
+        DType::Int32,
+        DType::Int64,
+        DType::Uint8,
+        DType::Uint16,
+        DType::Uint32,
+        DType::Uint64,
+        DType::Float32,
+        DType::Float64,
+    ];
+    let missings = [
+        ("none", None),
+        ("missing_value", Some(Missing::MissingValue(42.into()))),
+        (
+            "missing_values",
+            Some(Missing::MissingValues(vec![42.into(), 43.into()])),
+        ),
+        ("valid_max", Some(Missing::ValidMax(64.into()))),
+        ("valid_min", Some(Missing::ValidMin(64.into()))),
+        (
+            "valid_range",
+            Some(Missing::ValidRange(5.into(), 120.into())),
+        ),
+    ];
+    let layouts = ["c", "f", "strided"];
+    let operations: [(&str, Box<ExecuteFn>); 5] = [
+        ("count", Box::new(operations::Count::execute)),
+        ("max", Box::new(operations::Max::execute)),
+        ("min", Box::new(operations::Min::execute)),
+        ("select", Box::new(operations::Select::execute)),
+        ("sum", Box::new(operations::Sum::execute)),
+    ];
+    for (op_name, execute) in operations {
+        let mut group = c.benchmark_group(op_name);
+        // Keep the run time of the full matrix manageable.
+        group.sample_size(20);
+        group.warm_up_time(Duration::from_millis(500));
+        group.measurement_time(Duration::from_secs(2));
+        for dtype in dtypes {
+            let data = make_data_for(dtype);
+            for (missing_name, missing) in &missings {
+                for layout in layouts {
+                    let mut request_data = get_test_request_data();
+                    request_data.dtype = dtype;
+                    request_data.missing.clone_from(missing);
+                    set_layout(&mut request_data, layout);
+                    let id = format!(
+                        "{}/{}/{}",
+                        dtype.to_string().to_lowercase(),
+                        missing_name,
+                        layout
+                    );
+                    group.bench_function(&id, |b| {
+                        b.iter(|| execute(&request_data, black_box(data.clone())).unwrap())
+                    });
+                }
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
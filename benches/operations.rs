@@ -1,7 +1,7 @@
 /// Benchmarks for numerical operations.
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use reductionist::error::ActiveStorageError;
-use reductionist::models::{DType, RequestData, Response};
+use reductionist::models::{DType, RequestData, Response, Source};
 use reductionist::operation::Operation;
 use reductionist::operations;
 use reductionist::types::Missing;
@@ -11,7 +11,7 @@ use zerocopy::AsBytes;
 
 fn get_test_request_data() -> RequestData {
     RequestData {
-        source: Url::parse("http://example.com").unwrap(),
+        source: Source::Url(Url::parse("http://example.com").unwrap()),
         bucket: "bar".to_string(),
         object: "baz".to_string(),
         dtype: DType::Int32,
@@ -24,6 +24,19 @@ fn get_test_request_data() -> RequestData {
         compression: None,
         filters: None,
         missing: None,
+        shard: None,
+        cf_convention: false,
+        scale_factor: None,
+        add_offset: None,
+        group_by: None,
+        coarsen: None,
+        ensemble: None,
+        expression: None,
+        precision: None,
+        ddof: None,
+        nan_policy: None,
+        partial: false,
+        requester_pays: false,
     }
 }
 
@@ -42,12 +55,15 @@ fn criterion_benchmark(c: &mut Criterion) {
             Some(Missing::ValidMin(128.into())),
             Some(Missing::ValidRange(5.into(), 250.into())),
         ];
-        let operations: [(&str, Box<ExecuteFn>); 5] = [
+        let operations: [(&str, Box<ExecuteFn>); 8] = [
             ("count", Box::new(operations::Count::execute)),
             ("max", Box::new(operations::Max::execute)),
+            ("mean", Box::new(operations::Mean::execute)),
             ("min", Box::new(operations::Min::execute)),
             ("select", Box::new(operations::Select::execute)),
+            ("std", Box::new(operations::Std::execute)),
             ("sum", Box::new(operations::Sum::execute)),
+            ("var", Box::new(operations::Var::execute)),
         ];
         for (op_name, execute) in operations {
             for missing in missings.clone() {
@@ -43,8 +43,8 @@ fn criterion_benchmark(c: &mut Criterion) {
     let credentials = S3Credentials::access_key(username, password);
     let bucket = "s3-client-bench";
     let runtime = tokio::runtime::Runtime::new().unwrap();
-    let map = S3ClientMap::new();
-    let resource_manager = ResourceManager::new(None, None, None);
+    let map = S3ClientMap::new(None);
+    let resource_manager = ResourceManager::new(None, None, None, false);
     for size_k in [64, 256, 1024] {
         let size: isize = size_k * 1024;
         let data: Vec<u32> = (0_u32..(size as u32)).collect::<Vec<u32>>();
@@ -54,7 +54,7 @@ fn criterion_benchmark(c: &mut Criterion) {
         let name = format!("s3_client({})", size);
         c.bench_function(&name, |b| {
             b.to_async(&runtime).iter(|| async {
-                let client = S3Client::new(&url, credentials.clone()).await;
+                let client = S3Client::new(&url, credentials.clone(), None).await;
                 client
                     .download_object(black_box(bucket), &key, None, &resource_manager, &mut None)
                     .await
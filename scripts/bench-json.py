@@ -0,0 +1,38 @@
+"""
+Summarise Criterion benchmark results as JSON.
+
+Reads the estimates for each benchmark from a Criterion output directory and writes a JSON object
+mapping benchmark IDs to their mean, median and standard deviation in nanoseconds. The output is
+sorted by benchmark ID, allowing results from different releases to be compared using a diff.
+
+Usage: python3 scripts/bench-json.py [target/criterion] > bench.json
+"""
+
+import json
+import pathlib
+import sys
+
+
+def summarise(criterion_dir):
+    results = {}
+    for benchmark_file in criterion_dir.glob("**/new/benchmark.json"):
+        with open(benchmark_file) as f:
+            benchmark = json.load(f)
+        with open(benchmark_file.parent / "estimates.json") as f:
+            estimates = json.load(f)
+        results[benchmark["full_id"]] = {
+            "mean_ns": estimates["mean"]["point_estimate"],
+            "median_ns": estimates["median"]["point_estimate"],
+            "std_dev_ns": estimates["std_dev"]["point_estimate"],
+        }
+    return dict(sorted(results.items()))
+
+
+def main():
+    criterion_dir = pathlib.Path(sys.argv[1] if len(sys.argv) > 1 else "target/criterion")
+    json.dump(summarise(criterion_dir), sys.stdout, indent=2)
+    print()
+
+
+if __name__ == "__main__":
+    main()
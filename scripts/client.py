@@ -18,7 +18,7 @@ import numpy as np
 import sys
 
 
-DTYPES = ["int32", "int64", "uint32", "uint64", "float32", "float64"]
+DTYPES = ["int8", "int16", "int32", "int64", "uint8", "uint16", "uint32", "uint64", "float32", "float64"]
 
 
 def get_args() -> argparse.Namespace:
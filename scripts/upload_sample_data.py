@@ -17,10 +17,14 @@ class AllowedDatatypes(str, Enum):
     """ Data types supported by active storage proxy """
     int64 = 'int64'
     int32 = 'int32'
+    int16 = 'int16'
+    int8 = 'int8'
     float64 = 'float64'
     float32 = 'float32'
     uint64 = 'uint64'
     uint32 = 'uint32'
+    uint16 = 'uint16'
+    uint8 = 'uint8'
 
     def n_bytes(self):
         """ Returns the number of bytes in the data type """
@@ -1,36 +1,80 @@
 //! Active Storage server API
 
+use crate::array;
+use crate::auth::{self, Authenticator, Identity};
+use crate::autotune;
+use crate::cache_headers::{self, CachePolicies};
+use crate::cf;
+use crate::circuit_breaker::CircuitBreaker;
 use crate::cli::CommandLineArgs;
+use crate::coalesce::{ByteRange, Join, ReadCoalescer};
+use crate::compression;
+use crate::deadline;
+use crate::demo;
 use crate::error::ActiveStorageError;
+use crate::failover::{self, EndpointFailover};
 use crate::filter_pipeline;
-use crate::metrics::{metrics_handler, track_metrics};
+use crate::hdf5;
+use crate::http_client::{self, proxy::ProxyConfig, tls};
+use crate::maintenance::{Maintenance, MaintenanceStatus};
+use crate::metrics::{
+    self, metrics_handler, track_metrics, REQUEST_PANICS, S3_COALESCED_READS, S3_ENDPOINT_FAILOVERS,
+};
 use crate::models;
-use crate::operation;
+use crate::numa::NumaPools;
+use crate::object_limit::ObjectLimiter;
+use crate::operation::{self, OperationVisitor};
+use crate::operation_limits::{self, OperationLimits};
 use crate::operations;
-use crate::resource_manager::ResourceManager;
+use crate::precision;
+use crate::recorder::{self, Recorder};
+use crate::request_limit::{self, RequestLimiter};
+use crate::resource_manager::{self, ResourceManager, ResourceStatus};
+use crate::retry_budget::{self, RetryLimits};
 use crate::s3_client;
-use crate::types::{ByteOrder, NATIVE_BYTE_ORDER};
+use crate::shard;
+use crate::sources::{NamedSource, Sources};
+use crate::sparse;
+use crate::supervisor::{self, Supervisor, TaskStatus};
+use crate::types::{ByteOrder, NATIVE_BYTE_ORDER, NON_NATIVE_BYTE_ORDER};
+use crate::usage::CpuTimer;
 use crate::validated_json::ValidatedJson;
+use crate::zarr;
 
 use axum::middleware;
 use axum::{
-    body::Bytes,
-    extract::{Path, State},
-    headers::authorization::{Authorization, Basic},
-    http::header,
+    body::{Bytes, StreamBody},
+    extract::{DefaultBodyLimit, Extension, Path, State},
+    headers::IfNoneMatch,
+    http::{header, Request, StatusCode},
+    middleware::Next,
     response::{IntoResponse, Response},
-    routing::{get, post},
-    Router, TypedHeader,
+    routing::{get, post, MethodRouter},
+    Json, Router, TypedHeader,
 };
 
-use std::sync::Arc;
-use tokio::sync::SemaphorePermit;
+use serde::Deserialize;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, SemaphorePermit};
+use tokio::task::{JoinError, JoinSet};
+use tokio_rayon::AsyncThreadPool;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
 use tower::Layer;
 use tower::ServiceBuilder;
+use tower_http::catch_panic::CatchPanicLayer;
 use tower_http::normalize_path::NormalizePathLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
+use tower_http::validate_request::ValidateRequestHeaderLayer;
 use tracing::debug_span;
 use tracing::Instrument;
+use url::Url;
+use validator::ValidationError;
 
 /// `x-activestorage-dtype` header definition
 static HEADER_DTYPE: header::HeaderName = header::HeaderName::from_static("x-activestorage-dtype");
@@ -38,16 +82,50 @@ static HEADER_DTYPE: header::HeaderName = header::HeaderName::from_static("x-act
 static HEADER_SHAPE: header::HeaderName = header::HeaderName::from_static("x-activestorage-shape");
 /// `x-activestorage-count` header definition
 static HEADER_COUNT: header::HeaderName = header::HeaderName::from_static("x-activestorage-count");
+/// `x-activestorage-missing-count` header definition
+static HEADER_MISSING_COUNT: header::HeaderName =
+    header::HeaderName::from_static("x-activestorage-missing-count");
 /// `x-activestorage-byte-order` header definition
 static HEADER_BYTE_ORDER: header::HeaderName =
     header::HeaderName::from_static("x-activestorage-byte-order");
-const HEADER_BYTE_ORDER_VALUE: &str = match NATIVE_BYTE_ORDER {
-    ByteOrder::Big => "big",
-    ByteOrder::Little => "little",
-};
+const HEADER_BYTE_ORDER_VALUE: &str = byte_order_value(NATIVE_BYTE_ORDER);
+/// `x-activestorage-accept-byte-order` header definition
+static HEADER_ACCEPT_BYTE_ORDER: header::HeaderName =
+    header::HeaderName::from_static("x-activestorage-accept-byte-order");
+const HEADER_ACCEPT_BYTE_ORDER_ANY: &str = "any";
+/// `x-activestorage-encoding` header definition
+static HEADER_ENCODING: header::HeaderName =
+    header::HeaderName::from_static("x-activestorage-encoding");
+const HEADER_ENCODING_SPARSE: &str = "sparse";
+/// `x-activestorage-fill` header definition
+static HEADER_FILL: header::HeaderName = header::HeaderName::from_static("x-activestorage-fill");
+/// `x-activestorage-indices` header definition
+static HEADER_INDICES: header::HeaderName =
+    header::HeaderName::from_static("x-activestorage-indices");
+/// `x-activestorage-cpu-time` header definition
+static HEADER_CPU_TIME: header::HeaderName =
+    header::HeaderName::from_static("x-activestorage-cpu-time");
+/// `x-activestorage-crc32c` header definition
+static HEADER_CRC32C: header::HeaderName =
+    header::HeaderName::from_static("x-activestorage-crc32c");
+
+/// Returns the value of the `x-activestorage-byte-order` header for a byte order.
+const fn byte_order_value(byte_order: ByteOrder) -> &'static str {
+    match byte_order {
+        ByteOrder::Big => "big",
+        ByteOrder::Little => "little",
+    }
+}
+
+/// Content type of batch responses
+const BATCH_CONTENT_TYPE: &str = "application/x-reductionist-batch";
+
+/// Maximum size in bytes of the body of a request, for request data extractors and recorded
+/// requests
+pub const MAX_REQUEST_BODY_SIZE: usize = 2 << 20;
 
 /// Shared application state passed to each operation request handler.
-struct AppState {
+pub(crate) struct AppState {
     /// Command line arguments.
     args: CommandLineArgs,
 
@@ -56,18 +134,130 @@ struct AppState {
 
     /// Resource manager.
     resource_manager: ResourceManager,
+
+    /// Groups of equivalent S3 endpoints.
+    failover: EndpointFailover,
+
+    /// Optional circuit breakers for S3 endpoints.
+    circuit_breaker: Option<CircuitBreaker>,
+
+    /// Cache-Control policies for S3 sources.
+    cache_policies: CachePolicies,
+
+    /// Named S3 sources.
+    sources: Sources,
+
+    /// Maintenance mode state.
+    maintenance: Arc<Maintenance>,
+
+    /// Optional compute time budget for each operation.
+    compute_budget: Option<Duration>,
+
+    /// Limits configured for individual operations.
+    operation_limits: OperationLimits,
+
+    /// Limits on the retries of S3 requests for each request.
+    retry_limits: RetryLimits,
+
+    /// Optional Rayon thread pools for each NUMA node.
+    numa_pools: Option<NumaPools>,
+
+    /// Optional request recorder.
+    recorder: Option<Arc<Recorder>>,
+
+    /// Supervisor of background tasks.
+    supervisor: Supervisor,
+
+    /// Optional coalescer of concurrent downloads of the same object.
+    read_coalescer: Option<ReadCoalescer<CoalescedObject>>,
+
+    /// Optional limit on concurrent distinct objects per user.
+    object_limiter: Option<Arc<ObjectLimiter>>,
+
+    /// Limits on in-flight operation requests.
+    request_limiter: Arc<RequestLimiter>,
+
+    /// Authentication backend.
+    authenticator: Arc<dyn Authenticator>,
 }
 
 impl AppState {
     /// Create and return an [AppState].
-    fn new(args: &CommandLineArgs) -> Self {
-        let task_limit = args.thread_limit.or_else(|| Some(num_cpus::get() - 1));
-        let resource_manager =
-            ResourceManager::new(args.s3_connection_limit, args.memory_limit, task_limit);
+    pub(crate) fn new(args: &CommandLineArgs) -> Self {
+        let task_limit = args
+            .thread_limit
+            .or_else(|| Some(num_cpus::get().saturating_sub(1).max(1)));
+        let resource_manager = ResourceManager::new(
+            args.s3_connection_limit,
+            args.memory_limit,
+            task_limit,
+            args.adaptive_memory,
+        );
+        let proxy = args.s3_proxy.as_ref().map(|proxy| {
+            ProxyConfig::new(proxy, &args.s3_no_proxy).expect("invalid S3 proxy configuration")
+        });
+        let tls_config =
+            tls::client_config(args.s3_ca_bundle.as_deref(), args.s3_insecure_skip_verify)
+                .expect("invalid S3 TLS configuration");
+        let failover =
+            EndpointFailover::new(&args.s3_failover).expect("invalid S3 failover configuration");
+        let cache_policies =
+            CachePolicies::new(&args.cache_control).expect("invalid cache control configuration");
+        let mut sources = Sources::new(args.sources_file.as_deref(), args.auth_backend)
+            .expect("invalid sources configuration");
+        let compute_budget = args
+            .compute_timeout
+            .map(|timeout| Duration::try_from_secs_f64(timeout).expect("invalid compute timeout"));
+        let operation_limits = OperationLimits::new(&args.operation_limits, OPERATIONS)
+            .expect("invalid operation limits configuration");
+        let retry_limits = RetryLimits {
+            max_retries: args.s3_retry_limit,
+            timeout: args.s3_retry_timeout.map(|timeout| {
+                Duration::try_from_secs_f64(timeout).expect("invalid S3 retry timeout")
+            }),
+        };
+        let numa_pools = (args.use_rayon && args.numa_pinning)
+            .then(|| NumaPools::new().expect("failed to create NUMA thread pools"));
+        let supervisor = Supervisor::new();
+        if args.demo {
+            let url = demo::start(&supervisor).expect("failed to start demo object store");
+            sources.insert_anonymous(demo::SOURCE, url);
+        }
+        let circuit_breaker = args.s3_circuit_breaker_threshold.map(|threshold| {
+            let configured = sources.urls().chain(failover.configured()).cloned();
+            CircuitBreaker::new(
+                threshold,
+                Duration::from_secs(args.s3_circuit_breaker_cool_down),
+                configured,
+            )
+        });
+        let recorder = args.record_requests.as_deref().map(|path| {
+            Arc::new(
+                Recorder::new(path, &supervisor).expect("invalid request recording configuration"),
+            )
+        });
         Self {
             args: args.clone(),
-            s3_client_map: s3_client::S3ClientMap::new(),
+            s3_client_map: s3_client::S3ClientMap::new(http_client::build(proxy, tls_config)),
             resource_manager,
+            failover,
+            circuit_breaker,
+            cache_policies,
+            sources,
+            maintenance: Arc::new(Maintenance::new()),
+            compute_budget,
+            operation_limits,
+            retry_limits,
+            numa_pools,
+            recorder,
+            supervisor,
+            read_coalescer: args.coalesce_reads.then(ReadCoalescer::new),
+            object_limiter: args.user_object_limit.map(ObjectLimiter::new),
+            request_limiter: Arc::new(RequestLimiter::new(
+                args.max_concurrent_requests,
+                args.max_connection_requests,
+            )),
+            authenticator: auth::authenticator(args).expect("invalid authentication configuration"),
         }
     }
 }
@@ -78,7 +268,15 @@ type SharedAppState = Arc<AppState>;
 impl IntoResponse for models::Response {
     /// Convert a [crate::models::Response] into a [axum::response::Response].
     fn into_response(self) -> Response {
-        (
+        let fill = match &self.encoding {
+            models::Encoding::Dense => None,
+            models::Encoding::Sparse(fill) => Some(fill.to_string()),
+        };
+        let indices = self
+            .indices
+            .as_ref()
+            .map(|indices| serde_json::to_string(indices).unwrap());
+        let mut response = (
             [
                 (
                     &header::CONTENT_TYPE,
@@ -87,22 +285,81 @@ impl IntoResponse for models::Response {
                 (&HEADER_DTYPE, self.dtype.to_string().to_lowercase()),
                 (&HEADER_SHAPE, serde_json::to_string(&self.shape).unwrap()),
                 (&HEADER_COUNT, serde_json::to_string(&self.count).unwrap()),
+                (
+                    &HEADER_MISSING_COUNT,
+                    serde_json::to_string(&self.missing).unwrap(),
+                ),
                 (&HEADER_BYTE_ORDER, HEADER_BYTE_ORDER_VALUE.to_string()),
+                // Allow clients to verify the integrity of the response body.
+                (
+                    &HEADER_CRC32C,
+                    format!("{:08x}", crc32c::crc32c(&self.body)),
+                ),
             ],
             self.body,
         )
-            .into_response()
+            .into_response();
+        if let Some(indices) = indices {
+            response.headers_mut().insert(
+                &HEADER_INDICES,
+                indices
+                    .parse()
+                    .expect("indices should be a valid header value"),
+            );
+        }
+        if let Some(cpu_time) = self.cpu_time {
+            response.headers_mut().insert(
+                &HEADER_CPU_TIME,
+                format!("{:.6}", cpu_time.as_secs_f64())
+                    .parse()
+                    .expect("CPU time should be a valid header value"),
+            );
+        }
+        if let Some(fill) = fill {
+            let headers = response.headers_mut();
+            headers.insert(
+                &HEADER_ENCODING,
+                header::HeaderValue::from_static(HEADER_ENCODING_SPARSE),
+            );
+            headers.insert(
+                &HEADER_FILL,
+                fill.parse()
+                    .expect("fill value should be a valid header value"),
+            );
+        }
+        response
     }
 }
 
 /// Initialise the application
 pub fn init(args: &CommandLineArgs) {
-    if args.use_rayon {
+    // With NUMA pinning, per-node thread pools are used instead of the global pool.
+    if args.use_rayon && !args.numa_pinning {
         rayon::ThreadPoolBuilder::new()
-            .num_threads(num_cpus::get() - 1)
+            .num_threads(num_cpus::get().saturating_sub(1).max(1))
             .build_global()
             .expect("Failed to build Rayon thread pool");
     };
+    tracing::info!("Using {} decompression backend", compression::backend());
+    #[cfg(feature = "chaos")]
+    crate::chaos::get();
+}
+
+/// Names of the operations routed by the API, advertised by the schema endpoint
+const OPERATIONS: &[&str] = &[
+    "argmax", "argmin", "coarsen", "count", "ensemble", "expr", "extrema", "groupby", "max",
+    "mean", "min", "select", "std", "sum", "var",
+];
+
+/// Visitor returning the route of an operation
+struct OperationRoute;
+
+impl OperationVisitor for OperationRoute {
+    type Output = MethodRouter<SharedAppState>;
+
+    fn visit<T: operation::Operation + 'static>(self) -> Self::Output {
+        post(operation_handler::<T>)
+    }
 }
 
 /// Returns a [axum::Router] for the Active Storage server API
@@ -110,25 +367,195 @@ pub fn init(args: &CommandLineArgs) {
 /// The router is populated with all routes as well as the following middleware:
 ///
 /// * a [tower_http::trace::TraceLayer] for tracing requests and responses
-fn router(args: &CommandLineArgs) -> Router {
+/// * maintenance mode middleware for rejecting operation requests during maintenance
+/// * retry budget middleware for limiting the retries of S3 requests
+/// * request recording middleware, if enabled
+/// * authentication middleware for operation requests
+/// * middleware for limiting the operation requests in flight
+/// * a [tower_http::catch_panic::CatchPanicLayer] for converting panics into 500 responses
+/// * [tower_http::request_id] layers for identifying each request with an `x-request-id` header
+///
+/// Metrics are served by the administrative API if it is enabled.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+/// * `state`: Shared application state
+fn router(args: &CommandLineArgs, state: SharedAppState) -> Router {
     fn v1(state: SharedAppState) -> Router {
-        Router::new()
-            .route("/count", post(operation_handler::<operations::Count>))
-            .route("/max", post(operation_handler::<operations::Max>))
-            .route("/min", post(operation_handler::<operations::Min>))
-            .route("/select", post(operation_handler::<operations::Select>))
-            .route("/sum", post(operation_handler::<operations::Sum>))
+        let router = OPERATIONS.iter().fold(Router::new(), |router, &name| {
+            let route = operations::visit(name, OperationRoute).expect("operation should exist");
+            router.route(&format!("/{}", name), route)
+        });
+        let router = router
+            .route("/batch", post(batch_handler))
+            .route("/list", post(list_handler))
+            .route("/validate", post(validate_handler))
+            .route("/zarr/count", post(zarr_handler::<operations::Count>))
+            .route("/zarr/max", post(zarr_handler::<operations::Max>))
+            .route("/zarr/mean", post(zarr_handler::<operations::Mean>))
+            .route("/zarr/min", post(zarr_handler::<operations::Min>))
+            .route("/zarr/sum", post(zarr_handler::<operations::Sum>))
+            .route("/zarr/:operation", post(unknown_operation_handler))
+            .route("/hdf5/count", post(hdf5_handler::<operations::Count>))
+            .route("/hdf5/max", post(hdf5_handler::<operations::Max>))
+            .route("/hdf5/mean", post(hdf5_handler::<operations::Mean>))
+            .route("/hdf5/min", post(hdf5_handler::<operations::Min>))
+            .route("/hdf5/sum", post(hdf5_handler::<operations::Sum>))
+            .route("/hdf5/:operation", post(unknown_operation_handler))
             .route("/:operation", post(unknown_operation_handler))
+            .layer(DefaultBodyLimit::max(MAX_REQUEST_BODY_SIZE))
             .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()))
+            .route_layer(middleware::from_fn_with_state(
+                state.retry_limits,
+                retry_budget::track,
+            ))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                maintenance_middleware,
+            ));
+        let router = match &state.recorder {
+            Some(recorder) => router.route_layer(middleware::from_fn_with_state(
+                recorder.clone(),
+                recorder::record,
+            )),
+            None => router,
+        };
+        router
+            .route_layer(middleware::from_fn_with_state(
+                state.authenticator.clone(),
+                auth::authenticate,
+            ))
+            .route_layer(middleware::from_fn_with_state(
+                state.request_limiter.clone(),
+                request_limit::limit,
+            ))
             .with_state(state)
     }
 
-    let state = SharedAppState::new(AppState::new(args));
-    Router::new()
+    let router = Router::new()
         .route("/.well-known/reductionist-schema", get(schema))
+        .route("/status", get(status))
+        .with_state(state.clone())
+        .nest("/v1", v1(state));
+    let router = if args.admin_port.is_none() {
+        router.route("/metrics", get(metrics_handler))
+    } else {
+        router
+    };
+    with_request_id(catch_panics(router).route_layer(middleware::from_fn(track_metrics)))
+}
+
+/// Returns a router that converts panics while handling requests into 500 responses
+///
+/// # Arguments
+///
+/// * `router`: Router to wrap
+fn catch_panics(router: Router) -> Router {
+    router
+        .layer(CatchPanicLayer::custom(handle_panic))
+        .layer(middleware::from_fn(report_panic))
+}
+
+/// Returns a router that identifies each request with an `x-request-id` header
+///
+/// A request ID is generated unless the client provides one, and is returned in the response.
+///
+/// # Arguments
+///
+/// * `router`: Router to wrap
+fn with_request_id(router: Router) -> Router {
+    router
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+/// Message of a panic while handling a request, added to the extensions of the response
+#[derive(Clone)]
+struct PanicMessage(String);
+
+/// Returns a 500 Internal Server Error response for a panic while handling a request
+///
+/// The response is completed by [report_panic], which knows the ID of the request.
+///
+/// # Arguments
+///
+/// * `payload`: Panic payload
+fn handle_panic(payload: Box<dyn std::any::Any + Send>) -> Response {
+    REQUEST_PANICS.inc();
+    let mut response = StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    response
+        .extensions_mut()
+        .insert(PanicMessage(supervisor::panic_message(payload)));
+    response
+}
+
+/// Middleware that logs panics while handling requests, and reports them to the client
+///
+/// The response identifies the request, allowing the panic to be found in the server's logs.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request
+/// * `next`: Next middleware
+async fn report_panic<B>(request: Request<B>, next: Next<B>) -> Response {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let response = next.run(request).await;
+    match response.extensions().get::<PanicMessage>() {
+        Some(PanicMessage(message)) => {
+            tracing::error!("Request {} panicked: {}", request_id, message);
+            ActiveStorageError::Panic {
+                request_id,
+                message: message.clone(),
+            }
+            .into_response()
+        }
+        None => response,
+    }
+}
+
+/// Returns a [axum::Router] for the administrative API
+///
+/// The router is populated with the following routes:
+///
+/// * `GET /metrics`: Prometheus metrics
+/// * `GET /log-level`: the current log filter
+/// * `PUT /log-level`: change the log filter
+/// * `GET /resources`: available resources
+/// * `GET /maintenance`: maintenance mode status
+/// * `PUT /maintenance`: enable or disable maintenance mode
+///
+/// The router has the following middleware:
+///
+/// * a [tower_http::trace::TraceLayer] for tracing requests and responses
+/// * a [tower_http::validate_request::ValidateRequestHeaderLayer] for validating the bearer token,
+///   if one is configured
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+/// * `state`: Shared application state
+fn admin_router(args: &CommandLineArgs, state: SharedAppState) -> Router {
+    let router = Router::new()
         .route("/metrics", get(metrics_handler))
-        .nest("/v1", v1(state))
-        .route_layer(middleware::from_fn(track_metrics))
+        .route("/log-level", get(get_log_level).put(set_log_level))
+        .route("/resources", get(resources))
+        .route("/maintenance", get(status).put(set_maintenance))
+        .route("/tasks", get(tasks))
+        .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()))
+        .with_state(state);
+    match &args.admin_token {
+        Some(token) => router.layer(ValidateRequestHeaderLayer::bearer(token)),
+        None => {
+            tracing::warn!("administrative API does not require authentication");
+            router
+        }
+    }
 }
 
 /// Reductionist Server Service type alias
@@ -138,56 +565,431 @@ fn router(args: &CommandLineArgs) -> Router {
 // necessary trait bounds.
 pub type Service = tower_http::normalize_path::NormalizePath<Router>;
 
-/// Returns a [crate::app::Service] for the Active Storage server API
+/// Returns [crate::app::Service]s for the Active Storage server API and the administrative API
+///
+/// The administrative API service is only returned if an administrative port is configured.
 ///
-/// The service is populated with all routes as well as the following middleware:
+/// The services are populated with all routes as well as the following middleware:
 ///
 /// * a [tower_http::trace::TraceLayer] for tracing requests and responses
 /// * a [tower_http::validate_request::ValidateRequestHeaderLayer] for validating authorisation
 ///   headers
 /// * a [tower_http::normalize_path::NormalizePathLayer] for trimming trailing slashes from
 ///   requests
-pub fn service(args: &CommandLineArgs) -> Service {
+pub fn services(args: &CommandLineArgs) -> (Service, Option<Service>) {
+    let state = SharedAppState::new(AppState::new(args));
+    #[cfg(unix)]
+    {
+        let maintenance = state.maintenance.clone();
+        state.supervisor.spawn_restartable("maintenance", move || {
+            crate::maintenance::toggle_on_signal(maintenance.clone())
+        });
+    }
+    if args.autotune_thread_limit && !args.use_rayon {
+        let autotune_state = state.clone();
+        state.supervisor.spawn_restartable("autotune", move || {
+            let autotune_state = autotune_state.clone();
+            async move { autotune::run(&autotune_state.resource_manager).await }
+        });
+    }
+    if let Some(exporter) = metrics::exporter(args).expect("invalid metrics configuration") {
+        let interval = Duration::from_secs(args.metrics_interval);
+        state.supervisor.spawn_restartable("metrics", move || {
+            metrics::export(exporter.clone(), interval)
+        });
+    }
+    let warm_up_state = state.clone();
+    state.supervisor.spawn("warm-up", async move {
+        warm_up_state
+            .sources
+            .warm_up(&warm_up_state.s3_client_map)
+            .await
+    });
     // Note that any middleware that should affect routing must wrap the router.
     // See
     // https://docs.rs/axum/0.6.18/axum/middleware/index.html#rewriting-request-uri-in-middleware.
-    NormalizePathLayer::trim_trailing_slash().layer(router(args))
+    let service = NormalizePathLayer::trim_trailing_slash().layer(router(args, state.clone()));
+    let admin_service = args
+        .admin_port
+        .map(|_| NormalizePathLayer::trim_trailing_slash().layer(admin_router(args, state)));
+    (service, admin_service)
 }
 
-/// TODO: Return an OpenAPI schema
-async fn schema() -> &'static str {
-    "Hello, world!"
+/// Returns the current log filter
+async fn get_log_level() -> String {
+    crate::tracing::log_filter().unwrap_or_default()
 }
 
-/// Download an object from S3
+/// Changes the log filter
+///
+/// # Arguments
+///
+/// * `filter`: New log filter, using the same syntax as the `RUST_LOG` environment variable
+async fn set_log_level(filter: String) -> Result<(), ActiveStorageError> {
+    crate::tracing::set_log_filter(filter.trim())
+}
+
+/// Returns the maintenance mode status
+///
+/// Responds with 503 Service Unavailable if maintenance mode is enabled, allowing the status to be
+/// used as a load balancer health check.
+async fn status(State(state): State<SharedAppState>) -> (StatusCode, Json<MaintenanceStatus>) {
+    let status = state.maintenance.status();
+    let code = if status.enabled {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (code, Json(status))
+}
+
+/// Request body for changing maintenance mode
+#[derive(Deserialize)]
+struct SetMaintenance {
+    /// Whether maintenance mode should be enabled
+    enabled: bool,
+}
+
+/// Enables or disables maintenance mode
+///
+/// Returns the maintenance mode status.
+///
+/// # Arguments
+///
+/// * `body`: Whether maintenance mode should be enabled
+async fn set_maintenance(
+    State(state): State<SharedAppState>,
+    Json(body): Json<SetMaintenance>,
+) -> Json<MaintenanceStatus> {
+    state.maintenance.set(body.enabled);
+    Json(state.maintenance.status())
+}
+
+/// Maintenance mode middleware
+///
+/// Rejects requests with 503 Service Unavailable and a Retry-After header if maintenance mode is
+/// enabled. Otherwise, records the request as in flight until a response is returned.
+async fn maintenance_middleware<B>(
+    State(state): State<SharedAppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match state.maintenance.start_request() {
+        Ok(_guard) => next.run(request).await,
+        Err(err) => {
+            let mut response = err.into_response();
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                state.args.maintenance_retry_after.into(),
+            );
+            response
+        }
+    }
+}
+
+/// Returns the available quantity of each type of resource
+async fn resources(State(state): State<SharedAppState>) -> Json<ResourceStatus> {
+    Json(state.resource_manager.status())
+}
+
+/// Returns the status of each supervised background task
+async fn tasks(State(state): State<SharedAppState>) -> Json<Vec<TaskStatus>> {
+    Json(state.supervisor.status())
+}
+
+/// Returns the capabilities and limits of the server
 ///
-/// Requests a byte range if `offset` or `size` is specified in the request.
+/// Allows clients to split their requests to fit within the server's limits.
+async fn schema(State(state): State<SharedAppState>) -> Json<models::Capabilities> {
+    Json(models::Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        operations: OPERATIONS.to_vec(),
+        dtypes: models::DTYPES.to_vec(),
+        compression: models::COMPRESSIONS.to_vec(),
+        filters: models::FILTERS.to_vec(),
+        limits: models::Limits {
+            max_rank: models::MAX_RANK,
+            max_groups: operations::MAX_GROUPS,
+            max_response_size: state.args.max_response_size,
+            max_decompression_ratio: state.args.max_decompression_ratio,
+            memory_limit: state.args.memory_limit,
+            max_batch_size: state.args.max_batch_size,
+            operations: state
+                .operation_limits
+                .all()
+                .into_iter()
+                .map(|(operation, limits)| (operation.to_string(), limits.clone()))
+                .collect(),
+        },
+    })
+}
+
+/// Download an object from S3
 ///
 /// # Arguments
 ///
 /// * `client`: S3 client object
-/// * `request_data`: RequestData object for the request
-#[tracing::instrument(
-    level = "DEBUG",
-    skip(client, request_data, resource_manager, mem_permits)
-)]
-async fn download_object<'a>(
+/// * `state`: Shared application state
+/// * `bucket`: Name of the bucket
+/// * `key`: Name of the object in the bucket
+/// * `range`: Optional byte range to request
+/// * `mem_permits`: Optional SemaphorePermit for any memory resources reserved
+/// * `sink`: Function returning a sink for the data, given the length of the body
+///
+/// Downloads are retried up to the configured number of times if the response length does not
+/// match its Content-Length header or the response body fails, within the retry budget of the
+/// request.
+#[tracing::instrument(level = "DEBUG", skip(client, state, mem_permits, sink))]
+async fn download_object<'a, S: s3_client::BodySink>(
     client: &s3_client::S3Client,
-    request_data: &models::RequestData,
-    resource_manager: &'a ResourceManager,
+    state: &'a AppState,
+    bucket: &str,
+    key: &str,
+    range: Option<String>,
     mem_permits: &mut Option<SemaphorePermit<'a>>,
+    sink: &impl Fn(usize) -> S,
 ) -> Result<Bytes, ActiveStorageError> {
-    let range = s3_client::get_range(request_data.offset, request_data.size);
+    let resource_manager = &state.resource_manager;
+    let retries = state.args.s3_length_mismatch_retries;
     let _conn_permits = resource_manager.s3_connection().await?;
-    client
-        .download_object(
-            &request_data.bucket,
-            &request_data.object,
-            range,
-            resource_manager,
+    let mut attempt = 0;
+    loop {
+        #[cfg(feature = "chaos")]
+        crate::chaos::get().s3_get().await?;
+        let result = client
+            .download_object_to(
+                bucket,
+                key,
+                range.clone(),
+                resource_manager,
+                mem_permits,
+                sink,
+            )
+            .await;
+        #[cfg(feature = "chaos")]
+        let result = result.map(|data| crate::chaos::get().corrupt(data));
+        match result {
+            Err(
+                err @ (ActiveStorageError::S3ContentLengthMismatch { .. }
+                | ActiveStorageError::S3ByteStream(_)),
+            ) if attempt < retries && retry_budget::try_retry() => {
+                attempt += 1;
+                tracing::warn!("{}, retrying ({}/{})", err, attempt, retries);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Download an object from S3, failing over between equivalent endpoints
+///
+/// Each endpoint equivalent to the request's source is tried in turn until the download succeeds
+/// or fails for a reason other than the endpoint being unavailable. Endpoints whose circuit
+/// breaker is open are skipped, failing fast if there are no other endpoints to try. Failover
+/// counts as a retry against the retry budget of the request. S3 errors
+/// identify the endpoint, bucket and key of the failed download.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `bucket`: Name of the bucket
+/// * `key`: Name of the object in the bucket
+/// * `range`: Optional byte range to request
+/// * `source`: Resolved S3 source
+/// * `mem_permits`: Memory permits for the request
+/// * `sink`: Function returning a sink for the data, given the length of the body
+async fn download_with_failover<'a, S: s3_client::BodySink>(
+    state: &'a AppState,
+    bucket: &str,
+    key: &str,
+    range: Option<String>,
+    source: &ResolvedSource<'_>,
+    mem_permits: &mut Option<SemaphorePermit<'a>>,
+    sink: &impl Fn(usize) -> S,
+) -> Result<Bytes, ActiveStorageError> {
+    let endpoints = state.failover.endpoints(&source.url);
+    let mut endpoints = endpoints.iter().peekable();
+    while let Some(endpoint) = endpoints.next() {
+        let circuit_breaker = state.circuit_breaker.as_ref();
+        if circuit_breaker.is_some_and(|circuit_breaker| !circuit_breaker.allow(endpoint)) {
+            if endpoints.peek().is_some() {
+                continue;
+            }
+            return Err(ActiveStorageError::S3EndpointUnavailable {
+                endpoint: endpoint.to_string(),
+            });
+        }
+        let s3_client = state
+            .s3_client_map
+            .get_with_options(endpoint, &source.options, source.credentials.clone())
+            .instrument(tracing::Span::current())
+            .await;
+        let result = download_object(
+            &s3_client,
+            state,
+            bucket,
+            key,
+            range.clone(),
             mem_permits,
+            sink,
         )
-        .await
+        .instrument(tracing::Span::current())
+        .await;
+        if let Some(circuit_breaker) = circuit_breaker {
+            match &result {
+                Err(err) if failover::is_endpoint_failure(err) => circuit_breaker.failed(endpoint),
+                // Other errors show that the endpoint is responding.
+                _ => circuit_breaker.succeeded(endpoint),
+            }
+        }
+        match (result, endpoints.peek()) {
+            (Err(err), Some(next))
+                if failover::is_endpoint_failure(&err) && retry_budget::try_retry() =>
+            {
+                tracing::warn!(
+                    "S3 endpoint {} unavailable, failing over to {}: {:?}",
+                    endpoint,
+                    next,
+                    err
+                );
+                state.failover.failed(endpoint);
+                S3_ENDPOINT_FAILOVERS
+                    .with_label_values(&[endpoint.as_str()])
+                    .inc();
+            }
+            (result, _) => {
+                return result.map_err(|err| err.with_s3_object(endpoint, bucket, key));
+            }
+        }
+    }
+    unreachable!("at least one endpoint should be tried")
+}
+
+/// An object whose downloads may be coalesced, identified by its source URL, client options,
+/// credentials, bucket and key.
+///
+/// Downloads are only coalesced for requests using the same credentials, so that data is never
+/// shared with a request that could not have read it.
+type CoalescedObject = (
+    Url,
+    s3_client::S3ClientOptions,
+    s3_client::S3Credentials,
+    String,
+    String,
+);
+
+/// Download an object from S3 into an aligned buffer, coalescing concurrent downloads if enabled
+///
+/// If a download in flight for the same object contains the requested byte range, waits for it
+/// and returns a copy of the range. Otherwise, or if that download fails, downloads the range
+/// with failover between equivalent endpoints.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `bucket`: Name of the bucket
+/// * `key`: Name of the object in the bucket
+/// * `range`: Optional byte range to request
+/// * `source`: Resolved S3 source
+/// * `mem_permits`: Optional SemaphorePermit for any memory resources reserved
+async fn download_coalesced<'a>(
+    state: &'a AppState,
+    bucket: &str,
+    key: &str,
+    range: Option<String>,
+    source: &ResolvedSource<'_>,
+    mem_permits: &mut Option<SemaphorePermit<'a>>,
+) -> Result<Bytes, ActiveStorageError> {
+    let sink = &s3_client::AlignedBuffer::new;
+    let (Some(coalescer), Some(byte_range)) =
+        (&state.read_coalescer, ByteRange::parse(range.as_deref()))
+    else {
+        return download_with_failover(state, bucket, key, range, source, mem_permits, sink).await;
+    };
+    let object = (
+        source.url.clone(),
+        source.options.clone(),
+        source.credentials.clone(),
+        bucket.to_string(),
+        key.to_string(),
+    );
+    match coalescer.join(object, byte_range) {
+        Join::Lead(guard) => {
+            let data = download_with_failover(state, bucket, key, range, source, mem_permits, sink)
+                .await?;
+            guard.complete(&data)?;
+            Ok(data)
+        }
+        Join::Wait(receiver) => match receiver.await {
+            Ok(data) => {
+                S3_COALESCED_READS.inc();
+                if mem_permits.is_none() {
+                    *mem_permits = state.resource_manager.memory(data.len()).await?;
+                }
+                Ok(data)
+            }
+            Err(_) => {
+                download_with_failover(state, bucket, key, range, source, mem_permits, sink).await
+            }
+        },
+    }
+}
+
+/// S3 source of a request, resolved using the server's configuration
+///
+/// Contains the URL, client options and credentials for the source, and the named source if the
+/// request specified one.
+struct ResolvedSource<'a> {
+    /// URL of the S3 source.
+    url: Url,
+    /// S3 client options for the source.
+    options: s3_client::S3ClientOptions,
+    /// S3 credentials.
+    credentials: s3_client::S3Credentials,
+    /// Named source, if the request specified one.
+    named_source: Option<&'a NamedSource>,
+}
+
+/// Resolve the S3 source of a request
+///
+/// Named sources are resolved using the server's configuration. The requester pays if either the
+/// request or the named source requires it. Fails if the user may not use the credentials of a
+/// named source.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `source`: Source in the request
+/// * `identity`: Identity of the authenticated user
+/// * `requester_pays`: Whether the request specifies that the requester pays
+fn resolve_source<'a>(
+    state: &'a AppState,
+    source: &models::Source,
+    identity: &Identity,
+    requester_pays: bool,
+) -> Result<ResolvedSource<'a>, ActiveStorageError> {
+    let mut resolved = match source {
+        models::Source::Url(url) => ResolvedSource {
+            url: url.clone(),
+            options: s3_client::S3ClientOptions::default(),
+            credentials: identity.credentials.clone(),
+            named_source: None,
+        },
+        models::Source::Name(name) => {
+            let named_source = state.sources.get(name)?;
+            let credentials = named_source
+                .credentials(identity)
+                .ok_or_else(|| ActiveStorageError::SourceAccessDenied { name: name.clone() })?;
+            ResolvedSource {
+                url: named_source.url.clone(),
+                options: named_source.options.clone(),
+                credentials,
+                named_source: Some(named_source),
+            }
+        }
+    };
+    resolved.options.requester_pays |= requester_pays;
+    Ok(resolved)
 }
 
 /// Handler for Active Storage operations
@@ -197,85 +999,1976 @@ async fn download_object<'a>(
 /// This function is generic over any type implementing the [crate::operation::Operation] trait,
 /// allowing it to handle any operation conforming to that interface.
 ///
-/// Returns a `Result` with [crate::models::Response] on success and
-/// [crate::error::ActiveStorageError] on failure.
+/// Returns a `Result` with a [crate::models::Response] converted to an
+/// [axum::response::Response] on success and [crate::error::ActiveStorageError] on failure.
+/// See [execute].
 ///
 /// # Arguments
 ///
-/// * `auth`: Optional basic authentication header
+/// * `identity`: Identity of the authenticated user
+/// * `if_none_match`: Optional If-None-Match header
+/// * `headers`: Request headers
 /// * `request_data`: RequestData object for the request
 async fn operation_handler<T: operation::Operation>(
     State(state): State<SharedAppState>,
-    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Extension(identity): Extension<Identity>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+    headers: header::HeaderMap,
     ValidatedJson(request_data): ValidatedJson<models::RequestData>,
-) -> Result<models::Response, ActiveStorageError> {
-    let memory = request_data.size.unwrap_or(0);
-    let mut _mem_permits = state.resource_manager.memory(memory).await?;
-    let credentials = if let Some(TypedHeader(auth)) = auth {
-        s3_client::S3Credentials::access_key(auth.username(), auth.password())
-    } else {
-        s3_client::S3Credentials::None
-    };
-    let s3_client = state
-        .s3_client_map
-        .get(&request_data.source, credentials)
-        .instrument(tracing::Span::current())
-        .await;
-    let data = download_object(
-        &s3_client,
-        &request_data,
-        &state.resource_manager,
-        &mut _mem_permits,
+) -> Result<Response, ActiveStorageError> {
+    let if_none_match = if_none_match.map(|TypedHeader(if_none_match)| if_none_match);
+    execute::<T>(
+        state,
+        identity,
+        if_none_match,
+        Accepts::from_headers(&headers),
+        None,
+        request_data,
     )
-    .instrument(tracing::Span::current())
-    .await?;
-    // All remaining work is synchronous. If the use_rayon argument was specified, delegate to the
-    // Rayon thread pool. Otherwise, execute as normal using Tokio.
-    if state.args.use_rayon {
-        tokio_rayon::spawn(move || operation::<T>(request_data, data)).await
-    } else {
-        let _task_permit = state.resource_manager.task().await?;
-        operation::<T>(request_data, data)
+    .await
+}
+
+/// Forms of response accepted by the client
+#[derive(Clone, Copy, Debug, Default)]
+struct Accepts {
+    /// Whether array results may be encoded sparsely if this reduces their size
+    sparse: bool,
+    /// Whether response data may be in either byte order
+    any_byte_order: bool,
+    /// Whether the response body may be streamed as it is produced
+    stream: bool,
+}
+
+impl Accepts {
+    /// Returns the forms of response accepted by the client
+    ///
+    /// # Arguments
+    ///
+    /// * `headers`: Request headers
+    fn from_headers(headers: &header::HeaderMap) -> Self {
+        Self {
+            sparse: headers
+                .get(&HEADER_ENCODING)
+                .is_some_and(|encoding| encoding == HEADER_ENCODING_SPARSE),
+            any_byte_order: headers
+                .get(&HEADER_ACCEPT_BYTE_ORDER)
+                .is_some_and(|byte_order| byte_order == HEADER_ACCEPT_BYTE_ORDER_ANY),
+            stream: true,
+        }
     }
 }
 
-/// Perform a reduction operation
+/// Execute an Active Storage operation
 ///
-/// This function encapsulates the synchronous part of an operation.
+/// Downloads object data from S3 storage and executes the requested reduction operation,
+/// returning a response with caching headers added if a Cache-Control policy is configured for
+/// the source.
 ///
 /// # Arguments
 ///
-/// * `request_data`: RequestData object for the request.
-/// * `data`: Object data `Bytes`.
-fn operation<T: operation::Operation>(
-    request_data: models::RequestData,
-    data: Bytes,
-) -> Result<models::Response, ActiveStorageError> {
-    let ptr = data.as_ptr();
-    let data = filter_pipeline::filter_pipeline(&request_data, data)?;
-    if request_data.compression.is_some() || request_data.size.is_none() {
-        // Validate the raw uncompressed data size now that we know it.
+/// * `state`: Shared application state
+/// * `identity`: Identity of the authenticated user
+/// * `if_none_match`: Optional If-None-Match header
+/// * `accepts`: Forms of response accepted by the client
+/// * `decodes`: Optional decoded data shared with other operations
+/// * `request_data`: RequestData object for the request
+async fn execute<T: operation::Operation>(
+    state: SharedAppState,
+    identity: Identity,
+    if_none_match: Option<IfNoneMatch>,
+    accepts: Accepts,
+    decodes: Option<Arc<SharedDecodes>>,
+    mut request_data: models::RequestData,
+) -> Result<Response, ActiveStorageError> {
+    validate_request::<T>(&state, &request_data)?;
+    let _object_guard = match &state.object_limiter {
+        Some(object_limiter) => {
+            let object = (
+                request_data.source.clone(),
+                request_data.bucket.clone(),
+                request_data.object.clone(),
+            );
+            Some(object_limiter.acquire(&identity.user, object).await)
+        }
+        None => None,
+    };
+    let streaming = is_streamed(&state, &request_data);
+    let memory = request_memory(&request_data, streaming);
+    let limits = state.operation_limits.get(T::NAME);
+    let max_request_memory = limits.and_then(|limits| limits.max_request_memory);
+    let mut _mem_permits = state.resource_manager.memory(memory).await?;
+    let source = resolve_source(
+        &state,
+        &request_data.source,
+        &identity,
+        request_data.requester_pays,
+    )?;
+    let source_permit = match source.named_source {
+        Some(named_source) => named_source.connection().await?,
+        None => None,
+    };
+    if let Some(shard) = &request_data.shard {
+        // Read the shard index to locate the inner chunk.
+        let index = download_coalesced(
+            &state,
+            &request_data.bucket,
+            &request_data.object,
+            Some(shard::index_range(shard)),
+            &source,
+            &mut None,
+        )
+        .instrument(tracing::Span::current())
+        .await?;
+        let (offset, size) = shard::chunk_range(shard, &index)?;
+        if request_data.compression.is_none() {
+            models::validate_raw_size(size, request_data.dtype, &request_data.shape)?;
+        }
+        request_data.offset = Some(offset);
+        request_data.size = Some(size);
+    }
+    if let Some(group_by) = &mut request_data.group_by {
+        let range = s3_client::get_range(group_by.labels_offset, group_by.labels_size);
+        let labels = download_coalesced(
+            &state,
+            &request_data.bucket,
+            &group_by.labels,
+            range,
+            &source,
+            &mut None,
+        )
+        .instrument(tracing::Span::current())
+        .await?;
+        group_by.label_values = operations::GroupBy::labels(group_by, &labels)?;
+    }
+    // All synchronous work is subject to the compute time budget, including decoding data as it
+    // is downloaded.
+    let mut budget = limits
+        .and_then(operation_limits::Limits::compute_budget)
+        .or(state.compute_budget);
+    let range = s3_client::get_range(request_data.offset, request_data.size);
+    let data = if streaming {
+        let remaining_budget = Mutex::new(budget);
+        let data = download_with_failover(
+            &state,
+            &request_data.bucket,
+            &request_data.object,
+            range.clone(),
+            &source,
+            &mut _mem_permits,
+            &|content_length| {
+                let pipeline = filter_pipeline::StreamingPipeline::new(
+                    &request_data,
+                    content_length,
+                    state.args.max_decompression_ratio,
+                );
+                DecodeSink::new(&state, pipeline, &remaining_budget)
+            },
+        )
+        .instrument(tracing::Span::current())
+        .await?;
+        budget = remaining_budget.into_inner().unwrap();
         models::validate_raw_size(data.len(), request_data.dtype, &request_data.shape)?;
+        // The data has been decoded, and no longer has compression or filters. Memory was
+        // reserved for the decoded data if its size was known in advance.
+        if decoded_size(&request_data).is_some() {
+            request_data.size = Some(data.len());
+        }
+        request_data.compression = None;
+        request_data.filters = None;
+        data
+    } else {
+        download_coalesced(
+            &state,
+            &request_data.bucket,
+            &request_data.object,
+            range.clone(),
+            &source,
+            &mut _mem_permits,
+        )
+        .instrument(tracing::Span::current())
+        .await?
+    };
+    let mut members = vec![];
+    if let Some(ensemble) = &request_data.ensemble {
+        for member in &ensemble.members {
+            let data = download_coalesced(
+                &state,
+                &request_data.bucket,
+                member,
+                range.clone(),
+                &source,
+                &mut _mem_permits,
+            )
+            .instrument(tracing::Span::current())
+            .await?;
+            members.push(data);
+        }
     }
-    if request_data.compression.is_none() && request_data.filters.is_none() {
-        // Assert that we're using zero-copy.
-        assert_eq!(ptr, data.as_ptr());
+    drop(source_permit);
+    if request_data.size.is_none() {
+        // The memory of requests without a size is only known once the data has been downloaded.
+        let downloaded = members.iter().map(Bytes::len).sum::<usize>() + data.len();
+        check_request_memory(downloaded, max_request_memory)?;
     }
-    // Convert to a mutable vector to allow in-place byte order conversion.
-    let ptr = data.as_ptr();
-    let vec: Vec<u8> = data.into();
-    // Assert that we're using zero-copy.
-    assert_eq!(ptr, vec.as_ptr());
-    debug_span!("operation").in_scope(|| T::execute(&request_data, vec))
+    let policy = source
+        .named_source
+        .and_then(|named_source| named_source.cache_control.clone())
+        .or_else(|| state.cache_policies.get(&source.url).cloned());
+    let select = T::NAME == <operations::Select as operation::Operation>::NAME;
+    // Selections of non-native data are returned as stored to clients that accept either byte
+    // order, avoiding a conversion pass. Missing data and CF conventions need converted values.
+    let keep_byte_order = select
+        && accepts.any_byte_order
+        && request_data.byte_order == Some(NON_NATIVE_BYTE_ORDER)
+        && request_data.missing.is_none()
+        && !request_data.cf_convention
+        && !accepts.sparse
+        && request_data.precision.is_none();
+    if keep_byte_order {
+        request_data.byte_order = None;
+    }
+    let sparse = accepts.sparse;
+    let mut response = match state.args.stream_select_threshold {
+        // Streamed bodies cannot be tagged or encoded, since they are sent as they are produced.
+        Some(threshold)
+            if select
+                && accepts.stream
+                && !sparse
+                && request_data.precision.is_none()
+                && policy.is_none() =>
+        {
+            // The task permit is held until the response headers are known, and the memory
+            // permit until the body has been produced.
+            let resource_manager = &state.resource_manager;
+            let task_permit = resource_manager.task().await?;
+            let task_permit = resource_manager.own_task(task_permit);
+            let permits = resource_manager
+                .own_memory(_mem_permits.take())
+                .into_iter()
+                .collect();
+            stream_select(
+                state.clone(),
+                request_data,
+                data,
+                threshold,
+                budget,
+                task_permit,
+                permits,
+            )
+            .await?
+        }
+        _ => {
+            let shared_state = state.clone();
+            let response = run_compute(&state, budget, move || {
+                let decodes = decodes.as_deref();
+                operation::<T>(&shared_state, request_data, data, members, sparse, decodes)
+            })
+            .await??;
+            cache_headers::apply(policy.as_ref(), if_none_match, response)
+        }
+    };
+    if keep_byte_order {
+        response.headers_mut().insert(
+            &HEADER_BYTE_ORDER,
+            header::HeaderValue::from_static(byte_order_value(NON_NATIVE_BYTE_ORDER)),
+        );
+    }
+    Ok(response)
 }
 
-/// Handler for unknown operations
+/// Run synchronous work subject to a compute time budget
 ///
-/// Returns an [crate::error::ActiveStorageError].
+/// If the use_rayon argument was specified, the work is delegated to the Rayon thread pool. With
+/// NUMA pinning, the pool for the node of the current thread, which downloaded the data, is used.
+/// Otherwise, the work is executed as normal using Tokio, while holding a task permit.
 ///
 /// # Arguments
 ///
-/// * `operation`: the unknown operation from the URL path
-async fn unknown_operation_handler(Path(operation): Path<String>) -> ActiveStorageError {
-    ActiveStorageError::UnsupportedOperation { operation }
+/// * `state`: Shared application state
+/// * `budget`: Optional compute time budget
+/// * `f`: Function to run
+async fn run_compute<R: Send + 'static>(
+    state: &SharedAppState,
+    budget: Option<Duration>,
+    f: impl FnOnce() -> R + Send + 'static,
+) -> Result<R, ActiveStorageError> {
+    if let Some(numa_pools) = &state.numa_pools {
+        Ok(numa_pools
+            .local()
+            .spawn_async(move || deadline::run(budget, f))
+            .await)
+    } else if state.args.use_rayon {
+        Ok(tokio_rayon::spawn(move || deadline::run(budget, f)).await)
+    } else {
+        let _task_permit = state.resource_manager.task().await?;
+        Ok(deadline::run(budget, f))
+    }
+}
+
+/// Size in bytes of the batches of data decoded by a [DecodeSink]
+const DECODE_BATCH_SIZE: usize = 1 << 20;
+
+/// Body sink that decodes data as it is downloaded
+///
+/// The body is collected into batches, each of which is decoded by a
+/// [filter_pipeline::StreamingPipeline] using [run_compute], so that decoding uses the same
+/// threads and task permits as other synchronous work. The time spent decoding is deducted from
+/// the compute time budget of the request.
+struct DecodeSink<'a> {
+    /// Shared application state.
+    state: &'a SharedAppState,
+    /// Pipeline decoding the data, unless it is in use by a batch.
+    pipeline: Option<filter_pipeline::StreamingPipeline>,
+    /// Data waiting to be decoded.
+    batch: Vec<u8>,
+    /// Remaining compute time budget of the request.
+    budget: &'a Mutex<Option<Duration>>,
+}
+
+impl<'a> DecodeSink<'a> {
+    /// Returns a new DecodeSink object.
+    ///
+    /// # Arguments
+    ///
+    /// * `state`: Shared application state
+    /// * `pipeline`: Pipeline to decode the data
+    /// * `budget`: Remaining compute time budget of the request
+    fn new(
+        state: &'a SharedAppState,
+        pipeline: filter_pipeline::StreamingPipeline,
+        budget: &'a Mutex<Option<Duration>>,
+    ) -> Self {
+        Self {
+            state,
+            pipeline: Some(pipeline),
+            batch: Vec::new(),
+            budget,
+        }
+    }
+
+    /// Run a function on the pipeline, deducting the time taken from the budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `pipeline`: Pipeline to decode the data
+    /// * `f`: Function to run
+    async fn run<R: Send + 'static>(
+        &self,
+        pipeline: filter_pipeline::StreamingPipeline,
+        f: impl FnOnce(filter_pipeline::StreamingPipeline) -> R + Send + 'static,
+    ) -> Result<R, ActiveStorageError> {
+        let budget = *self.budget.lock().unwrap();
+        let (result, elapsed) = run_compute(self.state, budget, move || {
+            let start = Instant::now();
+            let result = f(pipeline);
+            (result, start.elapsed())
+        })
+        .await?;
+        let mut budget = self.budget.lock().unwrap();
+        *budget = budget.map(|budget| budget.saturating_sub(elapsed));
+        Ok(result)
+    }
+
+    /// Decode the data waiting in the batch.
+    async fn decode_batch(&mut self) -> Result<(), ActiveStorageError> {
+        let batch = std::mem::take(&mut self.batch);
+        let pipeline = self.pipeline.take().expect("pipeline should not be in use");
+        let (pipeline, result) = self
+            .run(pipeline, move |mut pipeline| {
+                let result = pipeline.write(&batch);
+                (pipeline, result)
+            })
+            .await?;
+        self.pipeline = Some(pipeline);
+        result
+    }
+}
+
+impl s3_client::BodySink for DecodeSink<'_> {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), ActiveStorageError> {
+        self.batch.extend_from_slice(chunk);
+        if self.batch.len() >= DECODE_BATCH_SIZE {
+            self.decode_batch().await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<Bytes, ActiveStorageError> {
+        if !self.batch.is_empty() {
+            self.decode_batch().await?;
+        }
+        let pipeline = self.pipeline.take().expect("pipeline should not be in use");
+        self.run(pipeline, filter_pipeline::StreamingPipeline::finish)
+            .await?
+    }
+}
+
+/// Maximum number of chunks of a streamed response body waiting to be sent
+const STREAM_QUEUE_SIZE: usize = 4;
+
+/// Execute a select operation, streaming the response body if it is larger than a threshold
+///
+/// The response is returned once its headers are known, and its body is then sent in chunks with
+/// chunked transfer encoding as the selected elements are copied from the decoded data. Streamed
+/// responses have no CRC32C checksum header. Since the thread sending the body waits for the
+/// client to receive it, the work is performed on Tokio's blocking thread pool rather than Rayon.
+/// The compute time budget and the task permit apply until the response headers are known, so
+/// that a slow client does not hold a task slot while its body is sent.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `request_data`: RequestData object for the request
+/// * `data`: Object data `Bytes`
+/// * `threshold`: Size in bytes above which the response body is streamed
+/// * `budget`: Optional compute time budget
+/// * `task_permit`: Task permit to hold until the response headers are known
+/// * `permits`: Resource permits to hold until the response body has been produced
+async fn stream_select(
+    state: SharedAppState,
+    request_data: models::RequestData,
+    data: Bytes,
+    threshold: usize,
+    budget: Option<Duration>,
+    task_permit: Option<resource_manager::OwnedPermit>,
+    permits: Vec<resource_manager::OwnedPermit>,
+) -> Result<Response, ActiveStorageError> {
+    let (head_sender, head_receiver) = oneshot::channel();
+    let (body_sender, body_receiver) = mpsc::channel(STREAM_QUEUE_SIZE);
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        let mut head_sender = Some(head_sender);
+        let mut task_permit = task_permit;
+        let result = deadline::run(budget, || {
+            let timer = CpuTimer::start();
+            let mut request_data = request_data;
+            let data = decode(&state, &mut request_data, data)?;
+            let max_response_size = state
+                .operation_limits
+                .get(<operations::Select as operation::Operation>::NAME)
+                .and_then(|limits| limits.max_response_size)
+                .or(state.args.max_response_size);
+            operations::Select::stream(
+                &request_data,
+                data,
+                threshold,
+                |mut response| {
+                    let size = response_size(&response);
+                    let response = match max_response_size {
+                        Some(limit) if size > limit => {
+                            Err(ActiveStorageError::ResponseTooLarge { size, limit })
+                        }
+                        _ => {
+                            if state.args.report_cpu_time {
+                                response.cpu_time = Some(timer.stop());
+                            }
+                            Ok(response)
+                        }
+                    };
+                    let streamed = response.is_ok();
+                    let sender = head_sender.take().expect("response should be sent once");
+                    task_permit.take();
+                    sender.send(response).is_ok() && streamed
+                },
+                |chunk| body_sender.blocking_send(Ok(chunk)).is_ok(),
+            )
+        });
+        if let Err(err) = result {
+            match head_sender.take() {
+                Some(sender) => {
+                    let _ = sender.send(Err(err));
+                }
+                // The response has been sent, so abort the body.
+                None => {
+                    let err = std::io::Error::other(err.to_string());
+                    let _ = body_sender.blocking_send(Err(err));
+                }
+            }
+        }
+        // Release the permits before the body sender, which ends the body.
+        drop(permits);
+    });
+    let response = head_receiver
+        .await
+        .expect("select task should send a response")?;
+    if response_size(&response) <= threshold {
+        return Ok(response.into_response());
+    }
+    let mut response = response.into_response();
+    let headers = response.headers_mut();
+    headers.remove(header::CONTENT_LENGTH);
+    headers.remove(&HEADER_CRC32C);
+    *response.body_mut() = axum::body::boxed(StreamBody::new(ReceiverStream::new(body_receiver)));
+    Ok(response)
+}
+
+/// Returns the size in bytes of the dense body of a response
+///
+/// # Arguments
+///
+/// * `response`: Response object
+fn response_size(response: &models::Response) -> usize {
+    response.shape.iter().product::<usize>() * response.dtype.size_of()
+}
+
+/// Check that a request is valid for an operation
+///
+/// These checks depend on the operation or the server's configuration, so are performed in
+/// addition to the validation of the request data when it is deserialised. No data is downloaded.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `request_data`: RequestData object for the request
+pub(crate) fn validate_request<T: operation::Operation>(
+    state: &AppState,
+    request_data: &models::RequestData,
+) -> Result<(), ActiveStorageError> {
+    match (T::GROUPED, &request_data.group_by) {
+        (true, None) => Err(ValidationError::new("group_by is required for groupby")),
+        (false, Some(_)) => Err(ValidationError::new(
+            "group_by is only supported for groupby",
+        )),
+        _ => Ok(()),
+    }?;
+    match (T::COARSENED, &request_data.coarsen) {
+        (true, None) => Err(ValidationError::new("coarsen is required for coarsen")),
+        (false, Some(_)) => Err(ValidationError::new(
+            "coarsen is only supported for coarsen",
+        )),
+        _ => Ok(()),
+    }?;
+    match (T::ENSEMBLE, &request_data.ensemble) {
+        (true, None) => Err(ValidationError::new("ensemble is required for ensemble")),
+        (false, Some(_)) => Err(ValidationError::new(
+            "ensemble is only supported for ensemble",
+        )),
+        _ => Ok(()),
+    }?;
+    match (T::EXPRESSION, &request_data.expression) {
+        (true, None) => Err(ValidationError::new("expression is required for expr")),
+        (false, Some(_)) => Err(ValidationError::new(
+            "expression is only supported for expr",
+        )),
+        _ => Ok(()),
+    }?;
+    match (T::DDOF, &request_data.ddof) {
+        (false, Some(_)) => Err(ValidationError::new(
+            "ddof is only supported for var and std",
+        )),
+        _ => Ok(()),
+    }?;
+    match (T::NAN_POLICY, &request_data.nan_policy) {
+        (false, Some(_)) => Err(ValidationError::new(
+            "nan_policy is only supported for count, max, mean, min and sum",
+        )),
+        _ => Ok(()),
+    }?;
+    match (T::PARTIAL, request_data.partial) {
+        (false, true) => Err(ValidationError::new("partial is only supported for sum")),
+        _ => Ok(()),
+    }?;
+    let limits = state.operation_limits.get(T::NAME);
+    let max_request_memory = limits.and_then(|limits| limits.max_request_memory);
+    let memory = request_memory(request_data, is_streamed(state, request_data));
+    check_request_memory(memory, max_request_memory)
+}
+
+/// Returns whether the data of a request is decoded as it is downloaded
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `request_data`: RequestData object for the request
+fn is_streamed(state: &AppState, request_data: &models::RequestData) -> bool {
+    // Ensemble members are decoded after download, in the same way as the request's object.
+    state.args.streaming_decode
+        && request_data.ensemble.is_none()
+        && request_data
+            .compression
+            .map_or(true, |compression| compression.is_streamable())
+        && (request_data.compression.is_some() || request_data.filters.is_some())
+}
+
+/// Returns the size in bytes of the decompressed data of a request, if it is compressed and the
+/// size is known in advance
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+fn decoded_size(request_data: &models::RequestData) -> Option<usize> {
+    match (request_data.compression, &request_data.shape) {
+        (Some(_), Some(shape)) => Some(
+            shape
+                .iter()
+                .product::<usize>()
+                .saturating_mul(request_data.dtype.size_of()),
+        ),
+        _ => None,
+    }
+}
+
+/// Returns the memory to reserve for a request, in bytes
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `streaming`: Whether the data is decoded as it is downloaded
+fn request_memory(request_data: &models::RequestData, streaming: bool) -> usize {
+    // Data decoded as it is downloaded is never held in its compressed form, so memory is
+    // reserved for the decoded data if its size is known.
+    if let (true, Some(size)) = (streaming, decoded_size(request_data)) {
+        return size;
+    }
+    // Memory is reserved for each member of an ensemble.
+    let num_members = request_data
+        .ensemble
+        .as_ref()
+        .map_or(1, |ensemble| ensemble.members.len() + 1);
+    request_data.size.unwrap_or(0).saturating_mul(num_members)
+}
+
+/// Check the memory of a request against the memory limit of its operation
+///
+/// Returns [crate::error::ActiveStorageError::InsufficientMemory] if the limit is exceeded.
+///
+/// # Arguments
+///
+/// * `memory`: Memory of the request in bytes
+/// * `limit`: Optional maximum memory of a request in bytes
+fn check_request_memory(memory: usize, limit: Option<usize>) -> Result<(), ActiveStorageError> {
+    match limit {
+        Some(limit) if memory > limit => Err(ActiveStorageError::InsufficientMemory {
+            requested: memory,
+            total: limit,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Perform a reduction operation
+///
+/// This function encapsulates the synchronous part of an operation.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state.
+/// * `request_data`: RequestData object for the request.
+/// * `data`: Object data `Bytes`.
+/// * `members`: Object data `Bytes` for the further members of an ensemble.
+/// * `sparse`: Whether to encode array results sparsely if this reduces their size.
+/// * `decodes`: Optional decoded data shared with other operations.
+fn operation<T: operation::Operation>(
+    state: &AppState,
+    mut request_data: models::RequestData,
+    data: Bytes,
+    members: Vec<Bytes>,
+    sparse: bool,
+    decodes: Option<&SharedDecodes>,
+) -> Result<models::Response, ActiveStorageError> {
+    let timer = CpuTimer::start();
+    // The shared decode is held until the operation completes, for use by other operations.
+    let mut _slot = None;
+    let data = if request_data.ensemble.is_some() {
+        // Each member of an ensemble is decoded using the request data as it was before decoding.
+        let original = request_data.clone();
+        let mut data = decode(state, &mut request_data, data)?;
+        // The decoded members are appended to the object's data, and must have the same size.
+        let size = data.len();
+        let names = original
+            .ensemble
+            .iter()
+            .flat_map(|ensemble| &ensemble.members);
+        for (name, member) in std::iter::zip(names, members) {
+            let member = decode(state, &mut original.clone(), member)?;
+            if member.len() != size {
+                return Err(ActiveStorageError::EnsembleMemberSize {
+                    member: name.clone(),
+                    size: member.len(),
+                    expected: size,
+                });
+            }
+            data.extend(member);
+        }
+        data.into()
+    } else if let Some(decodes) = decodes {
+        let (data, slot) = decodes.decode(state, &mut request_data, data)?;
+        _slot = Some(slot);
+        data
+    } else {
+        decode(state, &mut request_data, data)?.into()
+    };
+    let mut response = compute::<T>(&request_data, data, sparse)?;
+    let cpu_time = timer.stop();
+    if state.args.report_cpu_time {
+        response.cpu_time = Some(cpu_time);
+    }
+    let max_response_size = state
+        .operation_limits
+        .get(T::NAME)
+        .and_then(|limits| limits.max_response_size)
+        .or(state.args.max_response_size);
+    match max_response_size {
+        Some(limit) if response.body.len() > limit => Err(ActiveStorageError::ResponseTooLarge {
+            size: response.body.len(),
+            limit,
+        }),
+        _ => Ok(response),
+    }
+}
+
+/// Decode the data for an operation
+///
+/// Applies the filter pipeline, gathers any index lists in the selection and decodes CF
+/// conventions. The selection, shape and data type of the request are updated to describe the
+/// returned data, which may be passed to [compute] for any number of operations.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state.
+/// * `request_data`: RequestData object for the request.
+/// * `data`: Object data `Bytes`.
+pub(crate) fn decode(
+    state: &AppState,
+    request_data: &mut models::RequestData,
+    data: Bytes,
+) -> Result<Vec<u8>, ActiveStorageError> {
+    let ptr = data.as_ptr();
+    let downloaded = data.len();
+    let data =
+        filter_pipeline::filter_pipeline(request_data, data, state.args.max_decompression_ratio)?;
+    if request_data.compression.is_some() || request_data.size.is_none() {
+        // Validate the raw uncompressed data size now that we know it.
+        models::validate_raw_size(data.len(), request_data.dtype, &request_data.shape)?;
+    }
+    if request_data.compression.is_none() && request_data.filters.is_none() {
+        // Assert that we're using zero-copy.
+        assert_eq!(ptr, data.as_ptr());
+        state
+            .resource_manager
+            .observe_memory(request_data.size.unwrap_or(downloaded), downloaded);
+    } else {
+        // The downloaded and decoded data are both held while decoding.
+        state.resource_manager.observe_memory(
+            request_data.size.unwrap_or(downloaded),
+            downloaded + data.len(),
+        );
+    }
+    // Convert to a mutable vector to allow in-place byte order conversion.
+    let ptr = data.as_ptr();
+    let vec: Vec<u8> = data.into();
+    // Assert that we're using zero-copy.
+    assert_eq!(ptr, vec.as_ptr());
+    let vec = array::gather(request_data, vec)?;
+    if request_data.cf_convention {
+        cf::decode(request_data, vec)
+    } else {
+        Ok(vec)
+    }
+}
+
+/// Decoded data shared by the operations of a batch on the same data
+///
+/// Operations with identical request data, such as different reductions of the same selection of
+/// a chunk, decode the data once. Decoded data is kept only while an operation using it is in
+/// progress, and so is covered by the memory reserved by that operation.
+#[derive(Default)]
+struct SharedDecodes {
+    /// Request data before decoding, and the decoded data if it is in use
+    slots: Mutex<Vec<(models::RequestData, Weak<DecodeSlot>)>>,
+}
+
+/// Request data describing decoded data, and the decoded data, once decoded
+type DecodeSlot = Mutex<Option<(models::RequestData, Bytes)>>;
+
+impl SharedDecodes {
+    /// Decode the data for an operation, or reuse the data decoded for another operation with the
+    /// same request data
+    ///
+    /// Returns the decoded data and its slot, which should be held until the operation completes.
+    /// See [decode].
+    ///
+    /// # Arguments
+    ///
+    /// * `state`: Shared application state.
+    /// * `request_data`: RequestData object for the request.
+    /// * `data`: Object data `Bytes`.
+    fn decode(
+        &self,
+        state: &AppState,
+        request_data: &mut models::RequestData,
+        data: Bytes,
+    ) -> Result<(Bytes, Arc<DecodeSlot>), ActiveStorageError> {
+        let slot = {
+            let mut slots = self
+                .slots
+                .lock()
+                .expect("decode slots should not be poisoned");
+            slots.retain(|(_, slot)| slot.strong_count() > 0);
+            let existing = slots
+                .iter()
+                .find(|(key, _)| key == request_data)
+                .and_then(|(_, slot)| slot.upgrade());
+            existing.unwrap_or_else(|| {
+                let slot = Arc::new(DecodeSlot::default());
+                slots.push((request_data.clone(), Arc::downgrade(&slot)));
+                slot
+            })
+        };
+        // Other operations on the same data wait for it to be decoded.
+        let mut decoded = slot.lock().expect("decode slot should not be poisoned");
+        let data = match &*decoded {
+            Some((decoded_request, data)) => {
+                *request_data = decoded_request.clone();
+                data.clone()
+            }
+            None => {
+                let data = Bytes::from(decode(state, request_data, data)?);
+                *decoded = Some((request_data.clone(), data.clone()));
+                data
+            }
+        };
+        drop(decoded);
+        Ok((data, slot))
+    }
+}
+
+/// Perform an operation on decoded data
+///
+/// Decoded data that is shared with other operations is copied, since operations may modify it.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object describing the decoded data.
+/// * `data`: Decoded data returned by [decode].
+/// * `sparse`: Whether to encode array results sparsely if this reduces their size.
+pub(crate) fn compute<T: operation::Operation>(
+    request_data: &models::RequestData,
+    data: Bytes,
+    sparse: bool,
+) -> Result<models::Response, ActiveStorageError> {
+    let data = if data.is_unique() {
+        let ptr = data.as_ptr();
+        let vec: Vec<u8> = data.into();
+        // Assert that we're using zero-copy, preserving the alignment of the decoded data.
+        assert_eq!(ptr, vec.as_ptr());
+        vec
+    } else {
+        let mut vec = maligned::align_first::<u8, maligned::A8>(data.len());
+        vec.extend_from_slice(&data);
+        vec
+    };
+    let response = debug_span!("operation").in_scope(|| T::execute(request_data, data))?;
+    let response = match &request_data.precision {
+        Some(precision) => precision::apply(precision, response),
+        None => response,
+    };
+    Ok(if sparse {
+        sparse::encode(request_data, response)
+    } else {
+        response
+    })
+}
+
+/// Handler for batches of Active Storage operations
+///
+/// Executes each operation in the batch concurrently, subject to the same resource limits as
+/// individual requests. Each operation has its own retry budget, as if it were a separate request.
+///
+/// Returns a `Result` with a batch response on success and [crate::error::ActiveStorageError] if
+/// the batch is invalid. The response body is streamed, and contains a frame for each operation
+/// in the order in which the operations complete, consisting of a JSON [crate::models::BatchFrame]
+/// header and a newline, followed by the body the operation would have returned for an
+/// individual request. Failed operations have the status and JSON error body of an individual
+/// request, and do not affect other operations. Memory is reserved for each frame until it is
+/// sent.
+///
+/// # Arguments
+///
+/// * `identity`: Identity of the authenticated user
+/// * `headers`: Request headers
+/// * `request_data`: BatchRequestData object for the request
+async fn batch_handler(
+    State(state): State<SharedAppState>,
+    Extension(identity): Extension<Identity>,
+    headers: header::HeaderMap,
+    ValidatedJson(request_data): ValidatedJson<models::BatchRequestData>,
+) -> Result<Response, ActiveStorageError> {
+    if request_data.entries.len() > state.args.max_batch_size {
+        return Err(ValidationError::new("batch exceeds the maximum batch size").into());
+    }
+    // Frames must be complete, so operation bodies are not streamed.
+    let accepts = Accepts {
+        stream: false,
+        ..Accepts::from_headers(&headers)
+    };
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let decodes = Arc::new(SharedDecodes::default());
+    let mut entries = JoinSet::new();
+    for (index, entry) in request_data.entries.into_iter().enumerate() {
+        let limits = state.retry_limits;
+        let future = batch_entry(
+            state.clone(),
+            identity.clone(),
+            accepts,
+            decodes.clone(),
+            entry,
+        );
+        let state = state.clone();
+        let request_id = request_id.clone();
+        entries.spawn(
+            async move {
+                // The operation runs in its own task, so that a panic is reported in its frame.
+                // Dropping the JoinSet aborts the operation.
+                let mut operation = JoinSet::new();
+                operation.spawn(
+                    retry_budget::apply(limits, async move {
+                        future.await.unwrap_or_else(IntoResponse::into_response)
+                    })
+                    .instrument(tracing::Span::current()),
+                );
+                let response = match operation.join_next().await {
+                    Some(Ok(response)) => response,
+                    Some(Err(err)) => batch_panic(request_id, index, err),
+                    None => unreachable!("operation should have been spawned"),
+                };
+                let frame = batch_frame(index, response).await;
+                // Frames larger than the memory limit are sent without a reservation.
+                let permit = state.resource_manager.memory(frame.len()).await;
+                let permit = state.resource_manager.own_memory(permit.ok().flatten());
+                (frame, permit)
+            }
+            .instrument(tracing::Span::current()),
+        );
+    }
+    let (sender, receiver) = mpsc::channel(STREAM_QUEUE_SIZE);
+    // Dropping the operations when the client disconnects aborts them.
+    tokio::spawn(async move {
+        while let Some(result) = entries.join_next().await {
+            // A frame that cannot be sent fails the body, rather than ending it without the frame.
+            let frame = result.map_err(|err| {
+                REQUEST_PANICS.inc();
+                tracing::error!("Batch request {} failed: {}", request_id, err);
+                io::Error::other(err)
+            });
+            let failed = frame.is_err();
+            if sender.send(frame).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+    // The memory reserved for a frame is released once the frame is taken from the queue.
+    let body = ReceiverStream::new(receiver).map(|frame| frame.map(|(frame, _permit)| frame));
+    Ok((
+        [(&header::CONTENT_TYPE, BATCH_CONTENT_TYPE.to_string())],
+        StreamBody::new(body),
+    )
+        .into_response())
+}
+
+/// Returns an error response for an operation in a batch that panicked
+///
+/// # Arguments
+///
+/// * `request_id`: ID of the batch request
+/// * `index`: Index of the operation in the batch
+/// * `err`: Error joining the task of the operation
+fn batch_panic(request_id: String, index: usize, err: JoinError) -> Response {
+    REQUEST_PANICS.inc();
+    let message = match err.try_into_panic() {
+        Ok(payload) => supervisor::panic_message(payload),
+        Err(err) => err.to_string(),
+    };
+    tracing::error!(
+        "Request {} panicked in batch entry {}: {}",
+        request_id,
+        index,
+        message
+    );
+    ActiveStorageError::Panic {
+        request_id,
+        message,
+    }
+    .into_response()
+}
+
+/// Execute an operation in a batch
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `identity`: Identity of the authenticated user
+/// * `accepts`: Forms of response accepted by the client
+/// * `decodes`: Decoded data shared by the operations of the batch
+/// * `entry`: BatchEntry object for the operation
+async fn batch_entry(
+    state: SharedAppState,
+    identity: Identity,
+    accepts: Accepts,
+    decodes: Arc<SharedDecodes>,
+    entry: models::BatchEntry,
+) -> Result<Response, ActiveStorageError> {
+    let models::BatchEntry { operation, request } = entry;
+    let visitor = BatchOperation {
+        state,
+        identity,
+        accepts,
+        decodes,
+        request,
+    };
+    match operations::visit(&operation, visitor) {
+        Some(future) => future.await,
+        None => Err(ActiveStorageError::UnsupportedOperation { operation }),
+    }
+}
+
+/// Visitor executing an operation in a batch
+struct BatchOperation {
+    /// Shared application state
+    state: SharedAppState,
+    /// Identity of the authenticated user
+    identity: Identity,
+    /// Forms of response accepted by the client
+    accepts: Accepts,
+    /// Decoded data shared by the operations of the batch
+    decodes: Arc<SharedDecodes>,
+    /// RequestData object for the operation
+    request: models::RequestData,
+}
+
+impl OperationVisitor for BatchOperation {
+    type Output = Pin<Box<dyn Future<Output = Result<Response, ActiveStorageError>> + Send>>;
+
+    fn visit<T: operation::Operation + 'static>(self) -> Self::Output {
+        Box::pin(execute::<T>(
+            self.state,
+            self.identity,
+            None,
+            self.accepts,
+            Some(self.decodes),
+            self.request,
+        ))
+    }
+}
+
+/// Returns a frame of a batch response for the response of an operation
+///
+/// # Arguments
+///
+/// * `index`: Index of the operation in the batch
+/// * `response`: Response of the operation
+async fn batch_frame(index: usize, response: Response) -> Bytes {
+    let (parts, data) = response.into_parts();
+    // Operations in a batch do not stream their response bodies.
+    let data = hyper::body::to_bytes(data)
+        .await
+        .expect("operation response body should be in memory");
+    let headers = parts
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let frame = models::BatchFrame {
+        index,
+        status: parts.status.as_u16(),
+        headers,
+        size: data.len(),
+    };
+    let mut body = serde_json::to_vec(&frame).expect("batch frame should be serialisable");
+    body.push(b'\n');
+    body.extend_from_slice(&data);
+    body.into()
+}
+
+/// Handler for listing objects
+///
+/// Lists objects in an S3 bucket, allowing clients to discover the objects in a dataset.
+///
+/// Returns a `Result` with a page of object keys as JSON on success and
+/// [crate::error::ActiveStorageError] on failure.
+///
+/// # Arguments
+///
+/// * `identity`: Identity of the authenticated user
+/// * `request_data`: ListRequestData object for the request
+async fn list_handler(
+    State(state): State<SharedAppState>,
+    Extension(identity): Extension<Identity>,
+    ValidatedJson(request_data): ValidatedJson<models::ListRequestData>,
+) -> Result<Json<models::ListResponse>, ActiveStorageError> {
+    let source = resolve_source(
+        &state,
+        &request_data.source,
+        &identity,
+        request_data.requester_pays,
+    )?;
+    let _source_permit = match source.named_source {
+        Some(named_source) => named_source.connection().await?,
+        None => None,
+    };
+    let _conn_permits = state.resource_manager.s3_connection().await?;
+    // Listing is not retried on other endpoints, but uses the preferred endpoint.
+    let endpoint = &state.failover.endpoints(&source.url)[0];
+    let s3_client = state
+        .s3_client_map
+        .get_with_options(endpoint, &source.options, source.credentials)
+        .instrument(tracing::Span::current())
+        .await;
+    let response = s3_client
+        .list_objects(
+            &request_data.bucket,
+            request_data.prefix,
+            request_data.max_keys,
+            request_data.continuation_token,
+        )
+        .await?;
+    Ok(Json(response))
+}
+
+/// Handler for validating requests
+///
+/// Performs the same validation as a request for the operation, including resolution of its
+/// source, without downloading any data or computing a result. This allows clients to check the
+/// construction of their requests against a server.
+///
+/// Returns a `Result` with an empty `204 No Content` response if the request is valid and
+/// [crate::error::ActiveStorageError] otherwise.
+///
+/// # Arguments
+///
+/// * `identity`: Identity of the authenticated user
+/// * `entry`: BatchEntry object with the operation and its request data
+async fn validate_handler(
+    State(state): State<SharedAppState>,
+    Extension(identity): Extension<Identity>,
+    ValidatedJson(entry): ValidatedJson<models::BatchEntry>,
+) -> Result<StatusCode, ActiveStorageError> {
+    let models::BatchEntry { operation, request } = entry;
+    let visitor = ValidateOperation {
+        state: &state,
+        request_data: &request,
+    };
+    operations::visit(&operation, visitor)
+        .unwrap_or(Err(ActiveStorageError::UnsupportedOperation { operation }))?;
+    resolve_source(&state, &request.source, &identity, request.requester_pays)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Visitor validating a request for an operation
+struct ValidateOperation<'a> {
+    /// Shared application state
+    state: &'a AppState,
+    /// RequestData object for the request
+    request_data: &'a models::RequestData,
+}
+
+impl OperationVisitor for ValidateOperation<'_> {
+    type Output = Result<(), ActiveStorageError>;
+
+    fn visit<T: operation::Operation + 'static>(self) -> Self::Output {
+        validate_request::<T>(self.state, self.request_data)
+    }
+}
+
+/// Handler for operations on Zarr arrays
+///
+/// Reads the metadata of a Zarr array, then executes the operation on each chunk intersecting the
+/// selection concurrently, subject to the same resource limits as individual requests. Chunks
+/// that have not been written are read as the array's fill value. The responses for each chunk
+/// are merged into a single response.
+///
+/// Returns a `Result` with a [crate::models::Response] converted to an
+/// [axum::response::Response] on success and [crate::error::ActiveStorageError] on failure.
+///
+/// # Arguments
+///
+/// * `identity`: Identity of the authenticated user
+/// * `request`: ZarrRequestData object for the request
+async fn zarr_handler<T: operation::Operation + 'static>(
+    State(state): State<SharedAppState>,
+    Extension(identity): Extension<Identity>,
+    ValidatedJson(request): ValidatedJson<models::ZarrRequestData>,
+) -> Result<models::Response, ActiveStorageError> {
+    let metadata = Arc::new(zarr_metadata(&state, &identity, &request).await?);
+    let chunks =
+        metadata.chunk_selections(request.selection.as_deref(), state.args.max_batch_size)?;
+    let mut futures = vec![];
+    for chunk in &chunks {
+        let request_data = metadata.chunk_request(&request, chunk)?;
+        futures.push(zarr_chunk::<T>(
+            state.clone(),
+            identity.clone(),
+            metadata.clone(),
+            request_data,
+        ));
+    }
+    merge_chunks::<T, _>(&state, metadata.dtype, &chunks, futures).await
+}
+
+/// Execute the operation on each chunk of an array concurrently, and merge the responses
+///
+/// The chunks share the retry budget of the request. The selected elements of chunks without a
+/// response are counted as missing.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `dtype`: Data type of the array
+/// * `chunks`: Selection within each chunk
+/// * `futures`: Future executing the operation on each chunk
+async fn merge_chunks<T: operation::Operation, F>(
+    state: &AppState,
+    dtype: models::DType,
+    chunks: &[zarr::ChunkSelection],
+    futures: Vec<F>,
+) -> Result<models::Response, ActiveStorageError>
+where
+    F: std::future::Future<Output = Result<Option<models::Response>, ActiveStorageError>>
+        + Send
+        + 'static,
+{
+    let budget = retry_budget::current()
+        .unwrap_or_else(|| Arc::new(retry_budget::RetryBudget::new(state.retry_limits)));
+    let handles: Vec<_> = futures
+        .into_iter()
+        .map(|future| {
+            tokio::spawn(
+                retry_budget::scope(budget.clone(), future).instrument(tracing::Span::current()),
+            )
+        })
+        .collect();
+    let mut responses = vec![];
+    let mut missing = 0;
+    for (chunk, handle) in std::iter::zip(chunks, handles) {
+        let response = handle
+            .await
+            .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))?;
+        match response {
+            Some(response) => responses.push(response),
+            // All of the selected elements in the chunk are missing.
+            None => missing += i64::try_from(chunk.num_elements())?,
+        }
+    }
+    zarr::merge(T::NAME, dtype, missing, responses)
+}
+
+/// Read the metadata of a Zarr array
+///
+/// The `zarr.json` object of a Zarr v3 array is read if it exists, and otherwise the `.zarray`
+/// object of a Zarr v2 array.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `identity`: Identity of the authenticated user
+/// * `request`: ZarrRequestData object for the request
+async fn zarr_metadata(
+    state: &AppState,
+    identity: &Identity,
+    request: &models::ZarrRequestData,
+) -> Result<zarr::ArrayMetadata, ActiveStorageError> {
+    let source = resolve_source(state, &request.source, identity, request.requester_pays)?;
+    let _source_permit = match source.named_source {
+        Some(named_source) => named_source.connection().await?,
+        None => None,
+    };
+    let array = request.array.trim_end_matches('/');
+    let key = format!("{}/{}", array, zarr::METADATA_V3);
+    match download_coalesced(state, &request.bucket, &key, None, &source, &mut None)
+        .instrument(tracing::Span::current())
+        .await
+    {
+        Ok(data) => return zarr::ArrayMetadata::from_v3(&data),
+        Err(err) if err.is_no_such_key() => (),
+        Err(err) => return Err(err),
+    }
+    let key = format!("{}/{}", array, zarr::METADATA_V2);
+    let data = download_coalesced(state, &request.bucket, &key, None, &source, &mut None)
+        .instrument(tracing::Span::current())
+        .await?;
+    zarr::ArrayMetadata::from_v2(&data)
+}
+
+/// Execute an operation on a chunk of a Zarr array
+///
+/// Returns `None` if all of the selected elements in the chunk are missing, such that the
+/// operation has no result for the chunk.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `identity`: Identity of the authenticated user
+/// * `metadata`: Metadata of the Zarr array
+/// * `request_data`: RequestData object for the operation on the chunk
+async fn zarr_chunk<T: operation::Operation>(
+    state: SharedAppState,
+    identity: Identity,
+    metadata: Arc<zarr::ArrayMetadata>,
+    mut request_data: models::RequestData,
+) -> Result<Option<models::Response>, ActiveStorageError> {
+    validate_request::<T>(&state, &request_data)?;
+    let source = resolve_source(
+        &state,
+        &request_data.source,
+        &identity,
+        request_data.requester_pays,
+    )?;
+    let source_permit = match source.named_source {
+        Some(named_source) => named_source.connection().await?,
+        None => None,
+    };
+    let mut _mem_permits = None;
+    let data = match download_coalesced(
+        &state,
+        &request_data.bucket,
+        &request_data.object,
+        None,
+        &source,
+        &mut _mem_permits,
+    )
+    .instrument(tracing::Span::current())
+    .await
+    {
+        Ok(data) => data,
+        Err(err) if err.is_no_such_key() => {
+            _mem_permits = state
+                .resource_manager
+                .memory(metadata.chunk_size()?)
+                .await?;
+            metadata.fill_chunk(&mut request_data)?
+        }
+        Err(err) => return Err(err),
+    };
+    drop(source_permit);
+    chunk_operation::<T>(&state, request_data, data).await
+}
+
+/// Handler for operations on datasets in HDF5 files
+///
+/// Reads the chunk index of a dataset in an HDF5 file, then executes the operation on each chunk
+/// intersecting the selection concurrently, subject to the same resource limits as individual
+/// requests. Chunks that have not been written are read as the dataset's fill value. The
+/// responses for each chunk are merged into a single response.
+///
+/// Returns a `Result` with a [crate::models::Response] converted to an
+/// [axum::response::Response] on success and [crate::error::ActiveStorageError] on failure.
+///
+/// # Arguments
+///
+/// * `identity`: Identity of the authenticated user
+/// * `request`: Hdf5RequestData object for the request
+async fn hdf5_handler<T: operation::Operation + 'static>(
+    State(state): State<SharedAppState>,
+    Extension(identity): Extension<Identity>,
+    ValidatedJson(request): ValidatedJson<models::Hdf5RequestData>,
+) -> Result<models::Response, ActiveStorageError> {
+    let dataset = Arc::new(hdf5_dataset(&state, &identity, &request).await?);
+    let chunks = dataset
+        .metadata
+        .chunk_selections(request.selection.as_deref(), state.args.max_batch_size)?;
+    let mut futures = vec![];
+    for chunk in &chunks {
+        let request_data = dataset.chunk_request(&request, chunk)?;
+        futures.push(hdf5_chunk::<T>(
+            state.clone(),
+            identity.clone(),
+            dataset.clone(),
+            dataset.is_allocated(&chunk.coords),
+            request_data,
+        ));
+    }
+    merge_chunks::<T, _>(&state, dataset.metadata.dtype, &chunks, futures).await
+}
+
+/// Reads byte ranges of an object in S3
+struct S3Reader<'a> {
+    state: &'a AppState,
+    bucket: &'a str,
+    key: &'a str,
+    source: &'a ResolvedSource<'a>,
+}
+
+impl hdf5::ReadAt for S3Reader<'_> {
+    async fn read_at(&self, offset: u64, len: u64) -> Result<Bytes, ActiveStorageError> {
+        let range = format!("bytes={}-{}", offset, offset + len - 1);
+        download_coalesced(
+            self.state,
+            self.bucket,
+            self.key,
+            Some(range),
+            self.source,
+            &mut None,
+        )
+        .await
+    }
+}
+
+/// Open a dataset in an HDF5 file
+///
+/// The superblock, the object headers on the path to the dataset and the dataset's chunk index
+/// are read from the object.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `identity`: Identity of the authenticated user
+/// * `request`: Hdf5RequestData object for the request
+async fn hdf5_dataset(
+    state: &AppState,
+    identity: &Identity,
+    request: &models::Hdf5RequestData,
+) -> Result<hdf5::Dataset, ActiveStorageError> {
+    let source = resolve_source(state, &request.source, identity, request.requester_pays)?;
+    let _source_permit = match source.named_source {
+        Some(named_source) => named_source.connection().await?,
+        None => None,
+    };
+    let reader = S3Reader {
+        state,
+        bucket: &request.bucket,
+        key: &request.object,
+        source: &source,
+    };
+    hdf5::open(reader, request.superblock_offset, &request.dataset)
+        .instrument(tracing::Span::current())
+        .await
+}
+
+/// Execute an operation on a chunk of a dataset in an HDF5 file
+///
+/// Returns `None` if all of the selected elements in the chunk are missing, such that the
+/// operation has no result for the chunk.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `identity`: Identity of the authenticated user
+/// * `dataset`: The dataset
+/// * `allocated`: Whether the chunk has been written
+/// * `request_data`: RequestData object for the operation on the chunk
+async fn hdf5_chunk<T: operation::Operation>(
+    state: SharedAppState,
+    identity: Identity,
+    dataset: Arc<hdf5::Dataset>,
+    allocated: bool,
+    mut request_data: models::RequestData,
+) -> Result<Option<models::Response>, ActiveStorageError> {
+    validate_request::<T>(&state, &request_data)?;
+    let mut _mem_permits = None;
+    let data = if allocated {
+        let source = resolve_source(
+            &state,
+            &request_data.source,
+            &identity,
+            request_data.requester_pays,
+        )?;
+        let _source_permit = match source.named_source {
+            Some(named_source) => named_source.connection().await?,
+            None => None,
+        };
+        let range = s3_client::get_range(request_data.offset, request_data.size);
+        download_coalesced(
+            &state,
+            &request_data.bucket,
+            &request_data.object,
+            range,
+            &source,
+            &mut _mem_permits,
+        )
+        .instrument(tracing::Span::current())
+        .await?
+    } else {
+        _mem_permits = state
+            .resource_manager
+            .memory(dataset.metadata.chunk_size()?)
+            .await?;
+        dataset.metadata.fill_chunk(&mut request_data)?
+    };
+    chunk_operation::<T>(&state, request_data, data).await
+}
+
+/// Execute an operation on the data of a chunk of an array
+///
+/// Returns `None` if all of the selected elements in the chunk are missing, such that the
+/// operation has no result for the chunk. The operation is executed using [run_compute], as for
+/// other operations.
+///
+/// # Arguments
+///
+/// * `state`: Shared application state
+/// * `request_data`: RequestData object for the operation on the chunk
+/// * `data`: Data of the chunk
+async fn chunk_operation<T: operation::Operation>(
+    state: &SharedAppState,
+    request_data: models::RequestData,
+    data: Bytes,
+) -> Result<Option<models::Response>, ActiveStorageError> {
+    let budget = state
+        .operation_limits
+        .get(T::NAME)
+        .and_then(operation_limits::Limits::compute_budget)
+        .or(state.compute_budget);
+    let shared_state = state.clone();
+    let result = run_compute(state, budget, move || {
+        operation::<T>(&shared_state, request_data, data, vec![], false, None)
+    })
+    .await?;
+    match result {
+        Ok(response) => Ok(Some(response)),
+        Err(ActiveStorageError::EmptyArray { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Handler for unknown operations
+///
+/// Returns an [crate::error::ActiveStorageError].
+///
+/// # Arguments
+///
+/// * `operation`: the unknown operation from the URL path
+async fn unknown_operation_handler(Path(operation): Path<String>) -> ActiveStorageError {
+    ActiveStorageError::UnsupportedOperation { operation }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::DType;
+
+    use clap::Parser;
+
+    #[test]
+    fn response_headers() {
+        let body = Bytes::from_static(b"123456789");
+        let response = models::Response::new(body, DType::Int32, vec![], 2)
+            .with_missing(1)
+            .into_response();
+        let headers = response.headers();
+        assert_eq!("int32", headers[&HEADER_DTYPE]);
+        assert_eq!("[]", headers[&HEADER_SHAPE]);
+        assert_eq!("2", headers[&HEADER_COUNT]);
+        assert_eq!("1", headers[&HEADER_MISSING_COUNT]);
+        assert_eq!(HEADER_BYTE_ORDER_VALUE, headers[&HEADER_BYTE_ORDER]);
+        // Standard CRC-32C check value.
+        assert_eq!("e3069283", headers[&HEADER_CRC32C]);
+        assert!(!headers.contains_key(&HEADER_ENCODING));
+        assert!(!headers.contains_key(&HEADER_INDICES));
+        assert!(!headers.contains_key(&HEADER_CPU_TIME));
+    }
+
+    #[test]
+    fn response_headers_cpu_time() {
+        let mut response = models::Response::new(Bytes::new(), DType::Int32, vec![], 0);
+        response.cpu_time = Some(Duration::from_micros(1500));
+        let response = response.into_response();
+        assert_eq!("0.001500", response.headers()[&HEADER_CPU_TIME]);
+    }
+
+    #[test]
+    fn response_headers_indices() {
+        let mut response = models::Response::new(Bytes::new(), DType::Int32, vec![2], 4);
+        response.indices = Some(vec![vec![0, 1], vec![1, 0]]);
+        let response = response.into_response();
+        assert_eq!("[[0,1],[1,0]]", response.headers()[&HEADER_INDICES]);
+    }
+
+    #[test]
+    fn request_memory() {
+        check_request_memory(2, None).unwrap();
+        check_request_memory(2, Some(2)).unwrap();
+        assert!(matches!(
+            check_request_memory(3, Some(2)),
+            Err(ActiveStorageError::InsufficientMemory {
+                requested: 3,
+                total: 2
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn validate_request_checks() {
+        let args = CommandLineArgs::parse_from([
+            "reductionist",
+            "--operation-limits",
+            "sum:max_request_memory=4",
+        ]);
+        let state = AppState::new(&args);
+        let mut request_data = crate::test_utils::get_test_request_data();
+        validate_request::<operations::Sum>(&state, &request_data).unwrap();
+        assert!(matches!(
+            validate_request::<operations::GroupBy>(&state, &request_data),
+            Err(ActiveStorageError::RequestDataValidationSingle(_))
+        ));
+        request_data.size = Some(8);
+        validate_request::<operations::Max>(&state, &request_data).unwrap();
+        assert!(matches!(
+            validate_request::<operations::Sum>(&state, &request_data),
+            Err(ActiveStorageError::InsufficientMemory {
+                requested: 8,
+                total: 4
+            })
+        ));
+        request_data.nan_policy = Some(models::NanPolicy::Ignore);
+        validate_request::<operations::Max>(&state, &request_data).unwrap();
+        assert!(matches!(
+            validate_request::<operations::Select>(&state, &request_data),
+            Err(ActiveStorageError::RequestDataValidationSingle(_))
+        ));
+        request_data.nan_policy = None;
+        request_data.size = None;
+        request_data.partial = true;
+        validate_request::<operations::Sum>(&state, &request_data).unwrap();
+        assert!(matches!(
+            validate_request::<operations::Mean>(&state, &request_data),
+            Err(ActiveStorageError::RequestDataValidationSingle(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn streamed_request_memory() {
+        let args = CommandLineArgs::parse_from(["reductionist", "--streaming-decode"]);
+        let state = AppState::new(&args);
+        let mut request_data = crate::test_utils::get_test_request_data();
+        request_data.size = Some(8);
+        request_data.shape = Some(vec![4, 2]);
+        // Uncompressed data without filters is not decoded.
+        assert!(!is_streamed(&state, &request_data));
+        assert_eq!(8, super::request_memory(&request_data, false));
+        request_data.compression = Some(models::Compression::Gzip);
+        assert!(is_streamed(&state, &request_data));
+        assert_eq!(32, super::request_memory(&request_data, true));
+        assert_eq!(8, super::request_memory(&request_data, false));
+        request_data.shape = None;
+        assert_eq!(8, super::request_memory(&request_data, true));
+        request_data.compression = Some(models::Compression::Zstd);
+        assert!(!is_streamed(&state, &request_data));
+    }
+
+    #[tokio::test]
+    async fn decode_sink() {
+        use flate2::{write::GzEncoder, Compression};
+        use io::Write;
+        use s3_client::BodySink;
+
+        // Enough data for several batches.
+        let data: Vec<u8> = (0..3 * DECODE_BATCH_SIZE)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut request_data = crate::test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Gzip);
+        request_data.shape = Some(vec![data.len() / 4]);
+        for args in [vec!["reductionist"], vec!["reductionist", "--use-rayon"]] {
+            let state = Arc::new(AppState::new(&CommandLineArgs::parse_from(args)));
+            let budget = Mutex::new(Some(Duration::from_secs(60)));
+            let pipeline =
+                filter_pipeline::StreamingPipeline::new(&request_data, compressed.len(), None);
+            let mut sink = DecodeSink::new(&state, pipeline, &budget);
+            for chunk in compressed.chunks(1 << 16) {
+                sink.write(chunk).await.unwrap();
+            }
+            let result = sink.finish().await.unwrap();
+            assert_eq!(data, result);
+            assert!(budget.into_inner().unwrap().unwrap() < Duration::from_secs(60));
+        }
+    }
+
+    #[tokio::test]
+    async fn chunk_operation() {
+        let data = || Bytes::from([7_i32; 4].map(i32::to_ne_bytes).concat());
+        let mut request_data = crate::test_utils::get_test_request_data();
+        for args in [vec!["reductionist"], vec!["reductionist", "--use-rayon"]] {
+            let state = Arc::new(AppState::new(&CommandLineArgs::parse_from(args)));
+            request_data.missing = None;
+            let response =
+                super::chunk_operation::<operations::Max>(&state, request_data.clone(), data())
+                    .await
+                    .unwrap();
+            assert_eq!(&7_i32.to_ne_bytes(), &response.unwrap().body[..]);
+            // A chunk whose elements are all missing has no result.
+            request_data.missing = Some(crate::types::Missing::MissingValue(7.into()));
+            let response =
+                super::chunk_operation::<operations::Max>(&state, request_data.clone(), data())
+                    .await
+                    .unwrap();
+            assert!(response.is_none());
+        }
+    }
+
+    #[test]
+    fn shared_decodes() {
+        let args = CommandLineArgs::parse_from(["reductionist"]);
+        let state = AppState::new(&args);
+        let decodes = SharedDecodes::default();
+        let request_data = crate::test_utils::get_test_request_data();
+        let data = || Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        let (first, slot) = decodes
+            .decode(&state, &mut request_data.clone(), data())
+            .unwrap();
+        // Operations on the same data share the decoded data while it is in use.
+        let (second, _) = decodes
+            .decode(&state, &mut request_data.clone(), data())
+            .unwrap();
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        let mut other = request_data.clone();
+        other.offset = Some(1);
+        let (third, _) = decodes.decode(&state, &mut other, data()).unwrap();
+        assert_ne!(first.as_ptr(), third.as_ptr());
+        // Shared data is copied for computation.
+        let response = compute::<operations::Max>(&request_data, first.clone(), false).unwrap();
+        assert_eq!(2, response.count);
+        assert_eq!(&first[4..], &response.body[..]);
+        drop((first, second, slot));
+        assert_eq!(0, decodes.slots.lock().unwrap()[0].1.strong_count());
+    }
+
+    #[tokio::test]
+    async fn stream_select_body() {
+        let args = CommandLineArgs::parse_from(["reductionist"]);
+        let state = SharedAppState::new(AppState::new(&args));
+        let mut request_data = crate::test_utils::get_test_request_data();
+        request_data.dtype = DType::Uint32;
+        // Data is only decoded without copying if it is not shared.
+        let data = || Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // Small responses are not streamed.
+        let response = stream_select(
+            state.clone(),
+            request_data.clone(),
+            data(),
+            8,
+            None,
+            None,
+            vec![],
+        )
+        .await
+        .unwrap();
+        assert_eq!("[2]", response.headers()[&HEADER_SHAPE]);
+        assert!(response.headers().contains_key(&HEADER_CRC32C));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(data(), body);
+
+        let response = stream_select(state, request_data, data(), 4, None, None, vec![])
+            .await
+            .unwrap();
+        assert_eq!("[2]", response.headers()[&HEADER_SHAPE]);
+        assert_eq!("2", response.headers()[&HEADER_COUNT]);
+        assert!(!response.headers().contains_key(&HEADER_CRC32C));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(data(), body);
+    }
+
+    #[tokio::test]
+    async fn stream_select_permits() {
+        let args = CommandLineArgs::parse_from([
+            "reductionist",
+            "--memory-limit",
+            "100",
+            "--thread-limit",
+            "1",
+        ]);
+        let state = SharedAppState::new(AppState::new(&args));
+        let mut request_data = crate::test_utils::get_test_request_data();
+        request_data.dtype = DType::Uint8;
+        let task_permit = state.resource_manager.task().await.unwrap();
+        let task_permit = state.resource_manager.own_task(task_permit);
+        // The body has more chunks than are queued, so it is not produced until it is read.
+        let len = (STREAM_QUEUE_SIZE + 2) * operations::STREAM_CHUNK_SIZE;
+        let data = Bytes::from(vec![1; len]);
+        let permit = state.resource_manager.memory(10).await.unwrap();
+        let permits = state
+            .resource_manager
+            .own_memory(permit)
+            .into_iter()
+            .collect();
+        let response = stream_select(
+            state.clone(),
+            request_data,
+            data,
+            0,
+            None,
+            task_permit,
+            permits,
+        )
+        .await
+        .unwrap();
+        // The task permit is released once the headers are known.
+        assert_eq!(Some(1), state.resource_manager.status().tasks);
+        assert_eq!(Some(90), state.resource_manager.status().memory);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(len, body.len());
+        assert_eq!(Some(100), state.resource_manager.status().memory);
+    }
+
+    #[tokio::test]
+    async fn stream_select_too_large() {
+        let args = CommandLineArgs::parse_from(["reductionist", "--max-response-size", "4"]);
+        let state = SharedAppState::new(AppState::new(&args));
+        let mut request_data = crate::test_utils::get_test_request_data();
+        request_data.dtype = DType::Uint32;
+        let data = Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(matches!(
+            stream_select(state, request_data, data, 0, None, None, vec![]).await,
+            Err(ActiveStorageError::ResponseTooLarge { size: 8, limit: 4 })
+        ));
+    }
+
+    #[test]
+    fn accepts_from_headers() {
+        let mut headers = header::HeaderMap::new();
+        let accepts = Accepts::from_headers(&headers);
+        assert!(!accepts.sparse);
+        assert!(!accepts.any_byte_order);
+        headers.insert(&HEADER_ENCODING, HEADER_ENCODING_SPARSE.parse().unwrap());
+        headers.insert(&HEADER_ACCEPT_BYTE_ORDER, "any".parse().unwrap());
+        let accepts = Accepts::from_headers(&headers);
+        assert!(accepts.sparse);
+        assert!(accepts.any_byte_order);
+        headers.insert(&HEADER_ACCEPT_BYTE_ORDER, "little".parse().unwrap());
+        assert!(!Accepts::from_headers(&headers).any_byte_order);
+    }
+
+    #[tokio::test]
+    async fn panic_response() {
+        let router = Router::new().route(
+            "/panic",
+            get(|| async {
+                panic!("oops");
+                #[allow(unreachable_code)]
+                StatusCode::OK
+            }),
+        );
+        let router = with_request_id(catch_panics(router));
+        let panics = REQUEST_PANICS.get();
+        let request = Request::get("/panic")
+            .header("x-request-id", "1234")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = tower::ServiceExt::oneshot(router, request).await.unwrap();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!("1234", response.headers()["x-request-id"]);
+        assert!(REQUEST_PANICS.get() > panics);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "internal error while handling request 1234: oops",
+            error["error"]["message"]
+        );
+    }
+
+    #[tokio::test]
+    async fn request_id_generated() {
+        let router = Router::new().route("/", get(|| async {}));
+        let request = Request::get("/").body(axum::body::Body::empty()).unwrap();
+        let response = tower::ServiceExt::oneshot(with_request_id(router), request)
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(!response.headers()["x-request-id"].is_empty());
+    }
+
+    #[tokio::test]
+    async fn batch_frames() {
+        let response = models::Response::new(Bytes::from_static(b"1234"), DType::Int32, vec![], 1)
+            .into_response();
+        let mut body = batch_frame(0, response).await.to_vec();
+        let error = ActiveStorageError::UnsupportedOperation {
+            operation: "foo".to_string(),
+        };
+        body.extend_from_slice(&batch_frame(1, error.into_response()).await);
+
+        let mut lines = body.splitn(2, |&byte| byte == b'\n');
+        let frame: serde_json::Value = serde_json::from_slice(lines.next().unwrap()).unwrap();
+        assert_eq!(0, frame["index"]);
+        assert_eq!(200, frame["status"]);
+        assert_eq!("int32", frame["headers"]["x-activestorage-dtype"]);
+        assert_eq!(4, frame["size"]);
+        let rest = lines.next().unwrap();
+        assert_eq!(b"1234", &rest[..4]);
+
+        let mut lines = rest[4..].splitn(2, |&byte| byte == b'\n');
+        let frame: serde_json::Value = serde_json::from_slice(lines.next().unwrap()).unwrap();
+        assert_eq!(1, frame["index"]);
+        assert_eq!(404, frame["status"]);
+        assert_eq!("application/json", frame["headers"]["content-type"]);
+        let error = lines.next().unwrap();
+        assert_eq!(frame["size"], error.len());
+        let error: serde_json::Value = serde_json::from_slice(error).unwrap();
+        assert_eq!("unsupported operation foo", error["error"]["message"]);
+    }
+
+    #[tokio::test]
+    async fn batch_response() {
+        let args = CommandLineArgs::parse_from(["reductionist", "--memory-limit", "1000"]);
+        let state = SharedAppState::new(AppState::new(&args));
+        let entry = |operation: &str| models::BatchEntry {
+            operation: operation.to_string(),
+            request: crate::test_utils::get_test_request_data(),
+        };
+        let request_data = models::BatchRequestData {
+            entries: vec![entry("foo"), entry("bar")],
+        };
+        let response = batch_handler(
+            State(state.clone()),
+            Extension(Identity::anonymous()),
+            header::HeaderMap::new(),
+            ValidatedJson(request_data),
+        )
+        .await
+        .unwrap();
+        assert_eq!(BATCH_CONTENT_TYPE, response.headers()[header::CONTENT_TYPE]);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        // Frames are in the order in which the operations complete.
+        let mut indices = vec![];
+        let mut rest = &body[..];
+        while !rest.is_empty() {
+            let (line, data) = rest.split_at(rest.iter().position(|&b| b == b'\n').unwrap());
+            let frame: serde_json::Value = serde_json::from_slice(line).unwrap();
+            assert_eq!(404, frame["status"]);
+            indices.push(frame["index"].as_u64().unwrap());
+            rest = &data[1 + frame["size"].as_u64().unwrap() as usize..];
+        }
+        indices.sort();
+        assert_eq!(vec![0, 1], indices);
+        // The memory reserved for the frames has been released.
+        assert_eq!(Some(1000), state.resource_manager.status().memory);
+    }
+
+    #[tokio::test]
+    async fn batch_panic_frame() {
+        let err = tokio::spawn(async { panic!("oops") }).await.unwrap_err();
+        let panics = REQUEST_PANICS.get();
+        let response = batch_panic("1234".to_string(), 1, err);
+        assert!(REQUEST_PANICS.get() > panics);
+        let frame = batch_frame(1, response).await;
+        let (line, error) = frame.split_at(frame.iter().position(|&b| b == b'\n').unwrap());
+        let frame: serde_json::Value = serde_json::from_slice(line).unwrap();
+        assert_eq!(1, frame["index"]);
+        assert_eq!(500, frame["status"]);
+        let error: serde_json::Value = serde_json::from_slice(&error[1..]).unwrap();
+        assert_eq!(
+            "internal error while handling request 1234: oops",
+            error["error"]["message"]
+        );
+    }
+
+    #[test]
+    fn response_headers_sparse() {
+        let mut response = models::Response::new(Bytes::new(), DType::Float32, vec![4], 0);
+        response.encoding = models::Encoding::Sparse(models::Fill::NaN);
+        let response = response.into_response();
+        let headers = response.headers();
+        assert_eq!("sparse", headers[&HEADER_ENCODING]);
+        assert_eq!("nan", headers[&HEADER_FILL]);
+        assert_eq!("00000000", headers[&HEADER_CRC32C]);
+    }
 }
@@ -1,5 +1,6 @@
 //! Functions and utilities for working with [ndarray] objects.
 
+use crate::deadline;
 use crate::error::ActiveStorageError;
 use crate::models;
 use crate::types::NON_NATIVE_BYTE_ORDER;
@@ -105,7 +106,7 @@ fn to_ndarray_index(index: isize, length: usize, reverse: bool) -> isize {
 ///
 /// See [ndarray docs](https://docs.rs/ndarray/0.15.6/ndarray/macro.s.html#negative-step) for
 /// information about ndarray's handling of negative strides.
-fn to_ndarray_slice(slice: &models::Slice, length: usize) -> ndarray::SliceInfoElem {
+pub(crate) fn to_ndarray_slice(slice: &models::Slice, length: usize) -> ndarray::SliceInfoElem {
     let reverse = slice.stride < 0;
     let start = to_ndarray_index(slice.start, length, reverse);
     let end = to_ndarray_index(slice.end, length, reverse);
@@ -118,14 +119,21 @@ fn to_ndarray_slice(slice: &models::Slice, length: usize) -> ndarray::SliceInfoE
 }
 
 /// Returns an [ndarray] SliceInfo object corresponding to the selection.
+///
+/// Index lists cannot be represented as slices, and must be gathered using [gather] first.
 pub fn build_slice_info<T>(
-    selection: &Option<Vec<models::Slice>>,
+    selection: &Option<Vec<models::Selector>>,
     shape: &[usize],
 ) -> ndarray::SliceInfo<Vec<ndarray::SliceInfoElem>, ndarray::IxDyn, ndarray::IxDyn> {
     match selection {
         Some(selection) => {
             let si: Vec<ndarray::SliceInfoElem> = std::iter::zip(selection, shape)
-                .map(|(slice, length)| to_ndarray_slice(slice, *length))
+                .map(|(selector, length)| match selector {
+                    models::Selector::Slice(slice) => to_ndarray_slice(slice, *length),
+                    models::Selector::Indices { .. } => {
+                        unreachable!("index lists should be gathered before slicing")
+                    }
+                })
                 .collect();
             ndarray::SliceInfo::try_from(si).expect("SliceInfo should not fail for IxDyn")
         }
@@ -161,7 +169,7 @@ where
 /// * `selection`: Optional selection. If provided only data in this selection will be converted.
 pub fn reverse_array_byte_order<T>(
     array: &mut ArrayViewMutD<T>,
-    selection: &Option<Vec<models::Slice>>,
+    selection: &Option<Vec<models::Selector>>,
 ) where
     T: Copy
         + num_traits::FromBytes<Bytes = <T as num_traits::ToBytes>::Bytes>
@@ -207,11 +215,134 @@ where
     build_array_from_shape(shape, data)
 }
 
+/// Returns the indices of the elements of a dimension selected by a selector.
+///
+/// # Arguments
+///
+/// * `selector`: Selector for the dimension
+/// * `length`: Length of the dimension
+fn selected_indices(selector: &models::Selector, length: usize) -> Vec<usize> {
+    match selector {
+        models::Selector::Slice(slice) => {
+            let indices = Array::from_iter(0..length).into_dyn();
+            let slice_info =
+                build_slice_info::<usize>(&Some(vec![models::Selector::Slice(*slice)]), &[length]);
+            indices.slice(slice_info).iter().copied().collect()
+        }
+        // Indices are validated to be within the bounds of the dimension.
+        models::Selector::Indices { indices } => indices
+            .iter()
+            .map(|&index| {
+                if index < 0 {
+                    (index + length as isize) as usize
+                } else {
+                    index as usize
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Gather the elements of an array selected by index lists.
+///
+/// Index lists cannot be represented as views of the data, so if the selection contains any index
+/// lists, the selected elements are copied into a new array with the same order and byte order as
+/// the data. The request data is updated to describe the gathered data, which has no selection,
+/// and any group labels are gathered along the group axis. Otherwise, the data is returned
+/// unchanged.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `data`: Data bytes
+pub fn gather(
+    request_data: &mut models::RequestData,
+    data: Vec<u8>,
+) -> Result<Vec<u8>, ActiveStorageError> {
+    let has_indices = request_data.selection.as_ref().is_some_and(|selection| {
+        selection
+            .iter()
+            .any(|selector| matches!(selector, models::Selector::Indices { .. }))
+    });
+    if !has_indices {
+        return Ok(data);
+    }
+    // Elements are copied without interpretation, so only their size matters.
+    let (gathered, indices) = match request_data.dtype {
+        models::DType::Int8 | models::DType::Uint8 => gather_t::<u8>(request_data, data),
+        models::DType::Int16 | models::DType::Uint16 => gather_t::<u16>(request_data, data),
+        models::DType::Int32 | models::DType::Uint32 | models::DType::Float32 => {
+            gather_t::<u32>(request_data, data)
+        }
+        models::DType::Int64 | models::DType::Uint64 | models::DType::Float64 => {
+            gather_t::<u64>(request_data, data)
+        }
+    }?;
+    if let Some(group_by) = &mut request_data.group_by {
+        let labels = &group_by.label_values;
+        // Leave mismatched labels for the groupby operation to reject.
+        if indices[group_by.axis]
+            .iter()
+            .all(|&index| index < labels.len())
+        {
+            group_by.label_values = indices[group_by.axis]
+                .iter()
+                .map(|&index| labels[index])
+                .collect();
+        }
+    }
+    request_data.shape = Some(indices.iter().map(Vec::len).collect());
+    request_data.selection = None;
+    Ok(gathered)
+}
+
+/// Gather the elements of an array of a specific element size selected by index lists.
+///
+/// Returns the gathered data and the selected indices of each dimension.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `data`: Data bytes
+fn gather_t<T: Copy + zerocopy::AsBytes + zerocopy::FromBytes>(
+    request_data: &models::RequestData,
+    mut data: Vec<u8>,
+) -> Result<(Vec<u8>, Vec<Vec<usize>>), ActiveStorageError> {
+    let data = from_bytes::<T>(&mut data)?;
+    let shape = get_shape(data.len(), request_data);
+    let array = build_array_from_shape(shape, data)?;
+    let selection = request_data
+        .selection
+        .as_ref()
+        .expect("selection should contain index lists");
+    let indices: Vec<Vec<usize>> = std::iter::zip(selection, array.shape())
+        .map(|(selector, length)| selected_indices(selector, *length))
+        .collect();
+    let mut gathered = array.select(Axis(0), &indices[0]);
+    for (axis, indices) in indices.iter().enumerate().skip(1) {
+        gathered = gathered.select(Axis(axis), indices);
+    }
+    // Create an aligned Vec<u8> for the gathered data.
+    let mut bytes =
+        maligned::align_first::<u8, maligned::A8>(gathered.len() * std::mem::size_of::<T>());
+    // Preserve the order of the data by transposing Fortran ordered arrays before iterating.
+    let gathered = match request_data.order {
+        Some(models::Order::F) => gathered.t(),
+        _ => gathered.view(),
+    };
+    for value in deadline::checkpoints(gathered.iter()) {
+        bytes.extend_from_slice(value.as_bytes());
+    }
+    deadline::check()?;
+    Ok((bytes, indices))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_utils;
     use num_traits::Float;
+    use zerocopy::AsBytes;
 
     #[test]
     fn from_bytes_u32() {
@@ -378,7 +509,7 @@ mod tests {
 
     #[test]
     fn build_slice_info_1d_selection() {
-        let selection = Some(vec![models::Slice::new(0, 1, 1)]);
+        let selection = Some(vec![models::Slice::new(0, 1, 1).into()]);
         let shape = [1];
         let slice_info = build_slice_info::<u32>(&selection, &shape);
         assert_eq!(
@@ -393,7 +524,7 @@ mod tests {
 
     #[test]
     fn build_slice_info_1d_selection_negative_stride() {
-        let selection = Some(vec![models::Slice::new(1, 0, -1)]);
+        let selection = Some(vec![models::Slice::new(1, 0, -1).into()]);
         let shape = [1];
         let slice_info = build_slice_info::<u32>(&selection, &shape);
         assert_eq!(
@@ -408,7 +539,7 @@ mod tests {
 
     #[test]
     fn build_slice_info_1d_selection_negative_start() {
-        let selection = Some(vec![models::Slice::new(-1, 1, 1)]);
+        let selection = Some(vec![models::Slice::new(-1, 1, 1).into()]);
         let shape = [1];
         let slice_info = build_slice_info::<u32>(&selection, &shape);
         assert_eq!(
@@ -423,7 +554,7 @@ mod tests {
 
     #[test]
     fn build_slice_info_1d_selection_negative_end() {
-        let selection = Some(vec![models::Slice::new(0, -1, 1)]);
+        let selection = Some(vec![models::Slice::new(0, -1, 1).into()]);
         let shape = [1];
         let slice_info = build_slice_info::<u32>(&selection, &shape);
         assert_eq!(
@@ -461,8 +592,8 @@ mod tests {
     #[test]
     fn build_slice_info_2d_selection() {
         let selection = Some(vec![
-            models::Slice::new(0, 1, 1),
-            models::Slice::new(0, 1, 1),
+            models::Slice::new(0, 1, 1).into(),
+            models::Slice::new(0, 1, 1).into(),
         ]);
         let shape = [1, 1];
         let slice_info = build_slice_info::<u32>(&selection, &shape);
@@ -521,7 +652,7 @@ mod tests {
         request_data.dtype = models::DType::Uint32;
         let array = build_array::<u32>(&request_data, &mut data).unwrap();
         let shape = vec![2];
-        let slice_info = build_slice_info::<u32>(&Some(vec![slice]), &shape);
+        let slice_info = build_slice_info::<u32>(&Some(vec![slice.into()]), &shape);
         let sliced = array.slice(slice_info);
         assert_eq!(sliced, expected.into_dyn().view());
     }
@@ -635,4 +766,92 @@ mod tests {
         // translates to [1, 2]
         test_selection(models::Slice::new(3, 0, -1), array![0x08070605_u32])
     }
+
+    #[test]
+    fn gather_no_indices() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![2]);
+        request_data.selection = Some(vec![models::Slice::new(0, 1, 1).into()]);
+        let data = [1_u32, 2].as_bytes().to_vec();
+        let gathered = gather(&mut request_data, data.clone()).unwrap();
+        assert_eq!(data, gathered);
+        assert!(request_data.selection.is_some());
+    }
+
+    #[test]
+    fn gather_indices_1d() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![5]);
+        request_data.selection = Some(vec![models::Selector::Indices {
+            indices: vec![4, 0, -2, 0],
+        }]);
+        let data = [10_i32, 11, 12, 13, 14].as_bytes().to_vec();
+        let gathered = gather(&mut request_data, data).unwrap();
+        assert_eq!([14_i32, 10, 13, 10].as_bytes(), gathered);
+        assert_eq!(Some(vec![4]), request_data.shape);
+        assert_eq!(None, request_data.selection);
+    }
+
+    #[test]
+    fn gather_indices_1d_u8() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint8;
+        request_data.shape = Some(vec![5]);
+        request_data.selection = Some(vec![models::Selector::Indices {
+            indices: vec![4, 0, -2],
+        }]);
+        let gathered = gather(&mut request_data, vec![10, 11, 12, 13, 14]).unwrap();
+        assert_eq!(vec![14, 10, 13], gathered);
+        assert_eq!(Some(vec![3]), request_data.shape);
+    }
+
+    #[test]
+    fn gather_mixed_2d() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        request_data.shape = Some(vec![3, 4]);
+        request_data.selection = Some(vec![
+            models::Selector::Indices {
+                indices: vec![0, 2],
+            },
+            models::Slice::new(1, 4, 2).into(),
+        ]);
+        let data: Vec<f64> = (0..12).map(f64::from).collect();
+        let gathered = gather(&mut request_data, data.as_bytes().to_vec()).unwrap();
+        assert_eq!([1.0_f64, 3.0, 9.0, 11.0].as_bytes(), gathered);
+        assert_eq!(Some(vec![2, 2]), request_data.shape);
+    }
+
+    #[test]
+    fn gather_fortran_order() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![2, 3]);
+        request_data.order = Some(models::Order::F);
+        request_data.selection = Some(vec![
+            models::Slice::new(0, 2, 1).into(),
+            models::Selector::Indices {
+                indices: vec![2, 0],
+            },
+        ]);
+        // [[0, 2, 4], [1, 3, 5]] in Fortran order.
+        let data = [0_i32, 1, 2, 3, 4, 5].as_bytes().to_vec();
+        let gathered = gather(&mut request_data, data).unwrap();
+        // [[4, 0], [5, 1]] in Fortran order.
+        assert_eq!([4_i32, 5, 0, 1].as_bytes(), gathered);
+        assert_eq!(Some(models::Order::F), request_data.order);
+    }
+
+    #[test]
+    fn gather_non_native_byte_order() {
+        // Elements are copied without byte order conversion.
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Int64;
+        request_data.byte_order = Some(NON_NATIVE_BYTE_ORDER);
+        request_data.shape = Some(vec![3]);
+        request_data.selection = Some(vec![models::Selector::Indices { indices: vec![1] }]);
+        let data = [1_i64.swap_bytes(), 2_i64.swap_bytes(), 3_i64.swap_bytes()];
+        let gathered = gather(&mut request_data, data.as_bytes().to_vec()).unwrap();
+        assert_eq!(2_i64.swap_bytes().as_bytes(), gathered);
+        assert_eq!(Some(NON_NATIVE_BYTE_ORDER), request_data.byte_order);
+    }
 }
@@ -0,0 +1,381 @@
+//! Authentication backends
+//!
+//! Operation requests are authenticated by middleware before they reach the handlers, using the
+//! backend chosen at startup. A backend resolves the request's headers to an [Identity]: the name
+//! of the user, used to apply per-user limits and for auditing, and the credentials used to
+//! access S3 sources with passthrough credentials.
+//!
+//! The following backends are available:
+//!
+//! * `passthrough`: The credentials in the request's basic authentication header are passed
+//!   through to S3, and the access key identifies the user. Requests without the header are
+//!   anonymous. This is the default.
+//! * `static`: Users are configured on the server in a JSON file, and requests must authenticate
+//!   as one of them using basic authentication. Each user may have S3 credentials, so that
+//!   clients never hold the credentials for the object store. For example:
+//!
+//! ```json
+//! {
+//!     "users": {
+//!         "alice": {
+//!             "password_hash": "$argon2id$v=19$m=4096,t=3,p=1$cmVkdWN0aW9uaXN0LXNhbHQ$0a1eysdv2W5UmADqFw9pA1LFmL7Y0oLfEaVaixc0ci4",
+//!             "credentials": {"access_key": "AKIA...", "secret_key": "..."}
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Passwords are stored as salted Argon2 hashes in PHC string format, such as those produced by
+//! `echo -n "$PASSWORD" | argon2 "$SALT" -id -e`. Users without credentials access S3 sources
+//! anonymously. Other backends, such as OpenID Connect or Keystone tokens, may be added by
+//! implementing the [Authenticator] trait.
+
+use crate::cli::{AuthBackend, CommandLineArgs};
+use crate::error::ActiveStorageError;
+use crate::metrics::{AUTHENTICATION_FAILURES, USER_REQUESTS};
+use crate::s3_client::S3Credentials;
+
+use axum::{
+    extract::State,
+    headers::authorization::{Authorization, Basic},
+    headers::HeaderMapExt,
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use expanduser::expanduser;
+use hashbrown::HashMap;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Identity of the user making a request.
+#[derive(Clone, PartialEq)]
+pub struct Identity {
+    /// Name of the user. Anonymous requests have an empty name.
+    pub user: String,
+    /// Whether the name of the user was verified by the authentication backend.
+    pub verified: bool,
+    /// Credentials for S3 sources with passthrough credentials.
+    pub credentials: S3Credentials,
+}
+
+impl Identity {
+    /// Returns the identity of an anonymous user.
+    pub fn anonymous() -> Self {
+        Self {
+            user: String::new(),
+            verified: false,
+            credentials: S3Credentials::None,
+        }
+    }
+}
+
+impl std::fmt::Debug for Identity {
+    // Credentials are omitted so that they are never logged.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Identity")
+            .field("user", &self.user)
+            .field("verified", &self.verified)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An authentication backend.
+pub trait Authenticator: Send + Sync {
+    /// Returns the name of the backend.
+    fn name(&self) -> &'static str;
+
+    /// Returns the identity of the user making a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers`: Request headers
+    fn authenticate(&self, headers: &HeaderMap) -> Result<Identity, ActiveStorageError>;
+}
+
+/// Returns the authentication backend configured by the command line arguments.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+pub fn authenticator(args: &CommandLineArgs) -> Result<Arc<dyn Authenticator>, String> {
+    match args.auth_backend {
+        AuthBackend::Passthrough => Ok(Arc::new(BasicPassthrough)),
+        AuthBackend::Static => {
+            let path = args
+                .auth_users_file
+                .as_deref()
+                .ok_or("the static authentication backend requires a users file")?;
+            Ok(Arc::new(StaticUsers::new(path)?))
+        }
+    }
+}
+
+/// Backend passing the credentials in the basic authentication header through to S3.
+pub struct BasicPassthrough;
+
+impl Authenticator for BasicPassthrough {
+    fn name(&self) -> &'static str {
+        "passthrough"
+    }
+
+    fn authenticate(&self, headers: &HeaderMap) -> Result<Identity, ActiveStorageError> {
+        Ok(match headers.typed_get::<Authorization<Basic>>() {
+            Some(auth) => Identity {
+                user: auth.username().to_string(),
+                verified: false,
+                credentials: S3Credentials::access_key(auth.username(), auth.password()),
+            },
+            None => Identity::anonymous(),
+        })
+    }
+}
+
+/// S3 credentials of a user in the users file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserCredentials {
+    /// S3 access key
+    access_key: String,
+    /// S3 secret key
+    secret_key: String,
+}
+
+/// A user in the users file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct User {
+    /// Argon2 hash of the user's password, in PHC string format
+    password_hash: String,
+    /// Optional S3 credentials
+    credentials: Option<UserCredentials>,
+}
+
+/// Users file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UsersConfig {
+    /// Map of user names to users
+    users: std::collections::HashMap<String, User>,
+}
+
+/// Backend authenticating users configured on the server.
+pub struct StaticUsers {
+    /// Map of user names to users.
+    users: HashMap<String, User>,
+}
+
+impl StaticUsers {
+    /// Returns a new StaticUsers object, read from a users file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: Path to a JSON users file
+    pub fn new(path: &str) -> Result<Self, String> {
+        let path = expanduser(path).map_err(|err| err.to_string())?;
+        let json = std::fs::read_to_string(&path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        Self::from_json(&json)
+    }
+
+    /// Returns a new StaticUsers object, parsed from JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `json`: JSON users file
+    fn from_json(json: &str) -> Result<Self, String> {
+        let config: UsersConfig = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let users = config
+            .users
+            .into_iter()
+            .map(|(name, user)| {
+                // Verification fails only if the hash is invalid.
+                if argon2::verify_encoded(&user.password_hash, b"").is_err() {
+                    return Err(format!("user {} has an invalid password_hash", name));
+                }
+                Ok((name, user))
+            })
+            .collect::<Result<HashMap<String, User>, String>>()?;
+        Ok(Self { users })
+    }
+}
+
+impl Authenticator for StaticUsers {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    fn authenticate(&self, headers: &HeaderMap) -> Result<Identity, ActiveStorageError> {
+        let auth = headers
+            .typed_get::<Authorization<Basic>>()
+            .ok_or(ActiveStorageError::AuthenticationFailed)?;
+        let user = self
+            .users
+            .get(auth.username())
+            .ok_or(ActiveStorageError::AuthenticationFailed)?;
+        // The hash is compared in constant time.
+        let valid = argon2::verify_encoded(&user.password_hash, auth.password().as_bytes())
+            .unwrap_or(false);
+        if !valid {
+            return Err(ActiveStorageError::AuthenticationFailed);
+        }
+        Ok(Identity {
+            user: auth.username().to_string(),
+            verified: true,
+            credentials: user
+                .credentials
+                .as_ref()
+                .map_or(S3Credentials::None, |credentials| {
+                    S3Credentials::access_key(&credentials.access_key, &credentials.secret_key)
+                }),
+        })
+    }
+}
+
+/// Middleware that authenticates requests.
+///
+/// The identity of the user is added to the request's extensions, and requests are counted by
+/// user in the `user_requests` metric. Only users verified by the backend are counted by name, so
+/// that clients cannot add arbitrary labels to the metric; other requests have an empty label. Requests that fail authentication are rejected, and
+/// counted in the `authentication_failures` metric.
+///
+/// # Arguments
+///
+/// * `authenticator`: Authentication backend
+/// * `request`: HTTP request
+/// * `next`: Next middleware
+pub async fn authenticate<B>(
+    State(authenticator): State<Arc<dyn Authenticator>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match authenticator.authenticate(request.headers()) {
+        Ok(identity) => {
+            let user = if identity.verified {
+                &identity.user
+            } else {
+                ""
+            };
+            USER_REQUESTS.with_label_values(&[user]).inc();
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Err(err) => {
+            AUTHENTICATION_FAILURES
+                .with_label_values(&[authenticator.name()])
+                .inc();
+            err.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Argon2 hash of "secret".
+    const SECRET_HASH: &str =
+        "$argon2id$v=19$m=4096,t=3,p=1$cmVkdWN0aW9uaXN0LXNhbHQ$0a1eysdv2W5UmADqFw9pA1LFmL7Y0oLfEaVaixc0ci4";
+
+    fn basic(username: &str, password: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(Authorization::basic(username, password));
+        headers
+    }
+
+    fn static_users() -> StaticUsers {
+        StaticUsers::from_json(&format!(
+            r#"{{"users": {{
+                "alice": {{
+                    "password_hash": "{}",
+                    "credentials": {{"access_key": "ak", "secret_key": "sk"}}
+                }},
+                "bob": {{"password_hash": "{}"}}
+            }}}}"#,
+            SECRET_HASH, SECRET_HASH
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn passthrough_basic() {
+        let identity = BasicPassthrough
+            .authenticate(&basic("user", "pass"))
+            .unwrap();
+        assert_eq!(
+            Identity {
+                user: "user".to_string(),
+                verified: false,
+                credentials: S3Credentials::access_key("user", "pass"),
+            },
+            identity
+        );
+    }
+
+    #[test]
+    fn passthrough_anonymous() {
+        let identity = BasicPassthrough.authenticate(&HeaderMap::new()).unwrap();
+        assert_eq!(Identity::anonymous(), identity);
+    }
+
+    #[test]
+    fn static_users_valid() {
+        let users = static_users();
+        let identity = users.authenticate(&basic("alice", "secret")).unwrap();
+        assert_eq!(
+            Identity {
+                user: "alice".to_string(),
+                verified: true,
+                credentials: S3Credentials::access_key("ak", "sk"),
+            },
+            identity
+        );
+        // Users without credentials are anonymous to S3.
+        let identity = users.authenticate(&basic("bob", "secret")).unwrap();
+        assert_eq!("bob", identity.user);
+        assert!(identity.credentials == S3Credentials::None);
+    }
+
+    #[tokio::test]
+    async fn authenticate_user_requests() {
+        use axum::{middleware, routing::get, Router};
+        let authenticator: Arc<dyn Authenticator> = Arc::new(BasicPassthrough);
+        let router = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(authenticator, authenticate));
+        let mut request = Request::get("/").body(axum::body::Body::empty()).unwrap();
+        *request.headers_mut() = basic("unverified-user", "pass");
+        let anonymous = USER_REQUESTS.with_label_values(&[""]).get();
+        tower::ServiceExt::oneshot(router, request).await.unwrap();
+        // Users of the passthrough backend are not verified, so are not counted by name.
+        assert_eq!(
+            0,
+            USER_REQUESTS.with_label_values(&["unverified-user"]).get()
+        );
+        assert!(USER_REQUESTS.with_label_values(&[""]).get() > anonymous);
+    }
+
+    #[test]
+    fn static_users_rejected() {
+        let users = static_users();
+        for headers in [
+            HeaderMap::new(),
+            basic("alice", "wrong"),
+            basic("carol", "secret"),
+        ] {
+            assert!(matches!(
+                users.authenticate(&headers),
+                Err(ActiveStorageError::AuthenticationFailed)
+            ));
+        }
+    }
+
+    #[test]
+    fn static_users_invalid_hash() {
+        let result = StaticUsers::from_json(r#"{"users": {"alice": {"password_hash": "abc"}}}"#);
+        assert_eq!(
+            Some("user alice has an invalid password_hash".to_string()),
+            result.err()
+        );
+    }
+}
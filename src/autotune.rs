@@ -0,0 +1,161 @@
+//! Thread limit autotuning
+//!
+//! The best limit on concurrent CPU-bound tasks depends on the workload: large compressed chunks
+//! need many threads to keep up with downloads, while small chunks are cheap enough to process with
+//! fewer. When autotuning is enabled, the task limit is adjusted periodically using an additive
+//! increase, multiplicative decrease (AIMD) controller:
+//!
+//! * If the CPU utilisation of the process exceeds a target, the limit is reduced by a factor,
+//!   leaving CPU time for handling asynchronous tasks.
+//! * Otherwise, if tasks waited too long on average for a permit, the limit is increased by one.
+//!
+//! The limit is kept between one and the number of CPUs.
+
+use crate::metrics::TASK_LIMIT;
+use crate::resource_manager::ResourceManager;
+
+use std::time::{Duration, Instant};
+
+/// Interval between adjustments of the task limit.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// CPU utilisation of the process, as a fraction of all CPUs, above which the limit is reduced.
+const CPU_TARGET: f64 = 0.9;
+
+/// Mean time waiting for a task permit above which the limit is increased.
+const LATENCY_TARGET: Duration = Duration::from_millis(10);
+
+/// Factor by which the limit is reduced.
+const DECREASE_FACTOR: f64 = 0.75;
+
+/// AIMD controller for the task limit.
+#[derive(Debug)]
+pub struct Controller {
+    /// Current task limit.
+    limit: usize,
+    /// Maximum task limit.
+    max: usize,
+}
+
+impl Controller {
+    /// Returns a new Controller object.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit`: Initial task limit
+    /// * `max`: Maximum task limit
+    pub fn new(limit: usize, max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            limit: limit.clamp(1, max),
+            max,
+        }
+    }
+
+    /// Returns the new task limit, given the conditions over the last interval.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu_utilisation`: CPU utilisation of the process, as a fraction of all CPUs
+    /// * `queue_latency`: Mean time waiting for a task permit, if any were acquired
+    pub fn update(&mut self, cpu_utilisation: f64, queue_latency: Option<Duration>) -> usize {
+        if cpu_utilisation > CPU_TARGET {
+            self.limit = ((self.limit as f64 * DECREASE_FACTOR) as usize).max(1);
+        } else if queue_latency.is_some_and(|latency| latency > LATENCY_TARGET) {
+            self.limit = (self.limit + 1).min(self.max);
+        }
+        self.limit
+    }
+}
+
+/// Returns the CPU time used by the process.
+fn cpu_time() -> Duration {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage initialises the structure on success.
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return Duration::ZERO;
+        }
+        usage.assume_init()
+    };
+    let duration =
+        |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    duration(usage.ru_utime) + duration(usage.ru_stime)
+}
+
+/// Periodically adjust the task limit of a resource manager.
+///
+/// Runs until the process exits. Has no effect if tasks are not limited.
+///
+/// # Arguments
+///
+/// * `resource_manager`: Resource manager
+pub async fn run(resource_manager: &ResourceManager) {
+    let Some(limit) = resource_manager.task_limit() else {
+        return;
+    };
+    let cpus = num_cpus::get();
+    let mut controller = Controller::new(limit, cpus);
+    TASK_LIMIT.set(limit as i64);
+    tracing::info!("Autotuning the thread limit, starting at {}", limit);
+    let mut interval = tokio::time::interval(INTERVAL);
+    let (mut last_time, mut last_cpu_time) = (Instant::now(), cpu_time());
+    loop {
+        interval.tick().await;
+        let (now, now_cpu) = (Instant::now(), cpu_time());
+        let elapsed = (now - last_time).as_secs_f64() * cpus as f64;
+        let cpu_utilisation = if elapsed > 0.0 {
+            now_cpu.saturating_sub(last_cpu_time).as_secs_f64() / elapsed
+        } else {
+            0.0
+        };
+        (last_time, last_cpu_time) = (now, now_cpu);
+        let limit = controller.update(cpu_utilisation, resource_manager.take_task_wait());
+        if Some(limit) != resource_manager.task_limit() {
+            tracing::debug!("Changing thread limit to {}", limit);
+            TASK_LIMIT.set(limit as i64);
+        }
+        resource_manager.set_task_limit(limit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDLE: Option<Duration> = Some(Duration::ZERO);
+    const QUEUED: Option<Duration> = Some(Duration::from_millis(100));
+
+    #[test]
+    fn controller_increase() {
+        let mut controller = Controller::new(2, 3);
+        assert_eq!(3, controller.update(0.5, QUEUED));
+        // Limited by the maximum.
+        assert_eq!(3, controller.update(0.5, QUEUED));
+    }
+
+    #[test]
+    fn controller_steady() {
+        let mut controller = Controller::new(2, 4);
+        assert_eq!(2, controller.update(0.5, IDLE));
+        assert_eq!(2, controller.update(0.5, None));
+    }
+
+    #[test]
+    fn controller_decrease() {
+        let mut controller = Controller::new(8, 8);
+        // CPU saturation takes precedence over queueing.
+        assert_eq!(6, controller.update(0.95, QUEUED));
+        assert_eq!(4, controller.update(0.95, None));
+        assert_eq!(3, controller.update(0.95, None));
+        assert_eq!(2, controller.update(0.95, None));
+        assert_eq!(1, controller.update(0.95, None));
+        assert_eq!(1, controller.update(0.95, None));
+    }
+
+    #[test]
+    fn controller_clamp() {
+        let mut controller = Controller::new(0, 0);
+        assert_eq!(1, controller.update(0.5, QUEUED));
+    }
+}
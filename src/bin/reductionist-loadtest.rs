@@ -0,0 +1,559 @@
+//! Load test harness for a Reductionist server.
+//!
+//! Sends a mix of operation requests to a server with a configurable number of concurrent
+//! clients, and reports latency percentiles and error rates. The request mix is either replayed
+//! from a JSON lines file or synthesised for a single object.
+//!
+//! Each line of a request file is a JSON object with the following fields:
+//!
+//! * `operation`: Name of the operation, e.g. `sum`
+//! * `request`: JSON request data for the operation
+//!
+//! Other fields are ignored. Requests are sent in the order they appear in the file, repeating
+//! the file as necessary. Files written by the server's request recorder (`--record-requests`)
+//! may be replayed directly.
+//!
+//! Example usage against the minio sample data:
+//!
+//! ```sh
+//! reductionist-loadtest \
+//!   --server http://localhost:8080 \
+//!   --source http://localhost:9000 --bucket sample-data --object data-uint32.dat \
+//!   --dtype uint32 --shape '[10]' \
+//!   --username minioadmin --password minioadmin \
+//!   --concurrency 16 --duration 60
+//! ```
+
+use axum::headers::authorization::{Authorization, Basic};
+use axum::headers::HeaderMapExt;
+use clap::Parser;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use hyper_rustls::HttpsConnector;
+use reductionist::http_client::tls;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Operations used in synthesised request mixes.
+const OPERATIONS: [&str; 5] = ["count", "max", "min", "select", "sum"];
+
+/// Reductionist load test harness
+#[derive(Clone, Debug, Parser)]
+struct Args {
+    /// URL of the Reductionist server
+    #[arg(long, default_value = "http://localhost:8080")]
+    server: Url,
+    /// Path to a JSON lines file containing requests to replay
+    #[arg(long, required_unless_present = "source", conflicts_with = "source")]
+    requests: Option<String>,
+    /// URL of the S3 object store, for a synthesised request mix
+    #[arg(long, requires_all = ["bucket", "object", "dtype"])]
+    source: Option<String>,
+    /// Bucket of the object, for a synthesised request mix
+    #[arg(long)]
+    bucket: Option<String>,
+    /// Name of the object, for a synthesised request mix
+    #[arg(long)]
+    object: Option<String>,
+    /// Data type of the object, for a synthesised request mix
+    #[arg(long)]
+    dtype: Option<String>,
+    /// Shape of the object as a JSON list, for a synthesised request mix. If specified, requests
+    /// include random selections.
+    #[arg(long)]
+    shape: Option<String>,
+    /// Seed for synthesising requests
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Username for basic authentication with the object store
+    #[arg(long, requires = "password")]
+    username: Option<String>,
+    /// Password for basic authentication with the object store
+    #[arg(long, requires = "username")]
+    password: Option<String>,
+    /// Number of concurrent clients
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+    /// Total number of requests to send. Ignored if a duration is specified.
+    #[arg(long, default_value_t = 100)]
+    num_requests: usize,
+    /// Duration of the test in seconds
+    #[arg(long)]
+    duration: Option<f64>,
+    /// Path to a PEM file containing CA certificates to trust for HTTPS connections to the server
+    #[arg(long)]
+    cacert: Option<String>,
+    /// Whether to skip verification of the server's certificate
+    #[arg(long, default_value_t = false)]
+    insecure: bool,
+    /// Whether to output the report as JSON
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+/// A request to replay
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+struct ReplayRequest {
+    /// Name of the operation
+    operation: String,
+    /// JSON request data
+    request: serde_json::Value,
+}
+
+/// Outcome of a single request
+#[derive(Clone, Copy, Debug)]
+struct Outcome {
+    /// Time taken to receive the complete response
+    latency: Duration,
+    /// HTTP status code, or `None` if the request failed without a response
+    status: Option<u16>,
+}
+
+impl Outcome {
+    /// Returns whether the request failed.
+    fn is_error(&self) -> bool {
+        !matches!(self.status, Some(status) if (200..400).contains(&status))
+    }
+}
+
+/// Latency percentiles in milliseconds
+#[derive(Debug, PartialEq, Serialize)]
+struct Latencies {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+/// Load test report
+#[derive(Debug, PartialEq, Serialize)]
+struct Report {
+    /// Number of requests sent
+    requests: usize,
+    /// Number of failed requests
+    errors: usize,
+    /// Fraction of requests that failed
+    error_rate: f64,
+    /// Requests per second
+    throughput: f64,
+    /// Latency percentiles in milliseconds
+    latency_ms: Latencies,
+    /// Number of responses for each status code, or `error` for requests without a response
+    statuses: BTreeMap<String, usize>,
+}
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in the range `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Read requests to replay from a JSON lines file.
+///
+/// # Arguments
+///
+/// * `reader`: Reader for the file
+fn read_requests(reader: impl BufRead) -> Result<Vec<ReplayRequest>, String> {
+    reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(i, line)| {
+            let line = line.map_err(|err| err.to_string())?;
+            serde_json::from_str(&line).map_err(|err| format!("line {}: {}", i + 1, err))
+        })
+        .collect()
+}
+
+/// Synthesise a mix of requests for a single object.
+///
+/// Operations are chosen at random. If a shape is specified, each request has a random
+/// selection.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+/// * `count`: Number of requests to synthesise
+fn synthesise_requests(args: &Args, count: usize) -> Result<Vec<ReplayRequest>, String> {
+    let shape = args
+        .shape
+        .as_deref()
+        .map(serde_json::from_str::<Vec<usize>>)
+        .transpose()
+        .map_err(|err| format!("invalid shape: {}", err))?;
+    if shape.as_ref().is_some_and(|shape| shape.contains(&0)) {
+        return Err("invalid shape: dimensions must be greater than 0".to_string());
+    }
+    let mut rng = Rng(args.seed);
+    let requests = (0..count)
+        .map(|_| {
+            let mut request = serde_json::json!({
+                "source": args.source,
+                "bucket": args.bucket,
+                "object": args.object,
+                "dtype": args.dtype,
+            });
+            if let Some(shape) = &shape {
+                let selection = shape
+                    .iter()
+                    .map(|&length| {
+                        let start = rng.below(length);
+                        let end = start + 1 + rng.below(length - start);
+                        let stride = 1 + rng.below(2);
+                        [start, end, stride]
+                    })
+                    .collect::<Vec<_>>();
+                request["shape"] = serde_json::json!(shape);
+                request["selection"] = serde_json::json!(selection);
+            }
+            ReplayRequest {
+                operation: OPERATIONS[rng.below(OPERATIONS.len())].to_string(),
+                request,
+            }
+        })
+        .collect();
+    Ok(requests)
+}
+
+/// Returns a percentile of a sorted list of latencies, in milliseconds.
+///
+/// # Arguments
+///
+/// * `sorted`: Latencies sorted in ascending order
+/// * `percentile`: Percentile in the range 0 to 100
+fn percentile(sorted: &[Duration], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1].as_secs_f64() * 1000.0
+}
+
+/// Returns a report summarising the outcomes of a load test.
+///
+/// # Arguments
+///
+/// * `outcomes`: Outcome of each request
+/// * `elapsed`: Duration of the test
+fn report(outcomes: &[Outcome], elapsed: Duration) -> Report {
+    let mut latencies = outcomes
+        .iter()
+        .map(|outcome| outcome.latency)
+        .collect::<Vec<_>>();
+    latencies.sort();
+    let mut statuses = BTreeMap::new();
+    for outcome in outcomes {
+        let status = outcome
+            .status
+            .map_or("error".to_string(), |status| status.to_string());
+        *statuses.entry(status).or_insert(0) += 1;
+    }
+    let requests = outcomes.len();
+    let errors = outcomes.iter().filter(|outcome| outcome.is_error()).count();
+    Report {
+        requests,
+        errors,
+        error_rate: if requests > 0 {
+            errors as f64 / requests as f64
+        } else {
+            0.0
+        },
+        throughput: requests as f64 / elapsed.as_secs_f64(),
+        latency_ms: Latencies {
+            p50: percentile(&latencies, 50.0),
+            p90: percentile(&latencies, 90.0),
+            p99: percentile(&latencies, 99.0),
+            max: percentile(&latencies, 100.0),
+        },
+        statuses,
+    }
+}
+
+/// Returns an HTTP client for connections to the server.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+fn build_client(args: &Args) -> Result<Client<HttpsConnector<HttpConnector>>, String> {
+    let builder = hyper_rustls::HttpsConnectorBuilder::new();
+    let builder = match tls::client_config(args.cacert.as_deref(), args.insecure)? {
+        Some(tls_config) => builder.with_tls_config(tls_config),
+        None => builder.with_native_roots(),
+    };
+    let connector = builder.https_or_http().enable_http1().build();
+    Ok(Client::builder().build(connector))
+}
+
+/// Send a single request to the server.
+///
+/// # Arguments
+///
+/// * `client`: HTTP client
+/// * `args`: Command line arguments
+/// * `request`: Request to send
+async fn send(
+    client: &Client<HttpsConnector<HttpConnector>>,
+    args: &Args,
+    request: &ReplayRequest,
+) -> Outcome {
+    let start = Instant::now();
+    let status = async {
+        let url = args
+            .server
+            .join(&format!("v1/{}/", request.operation))
+            .map_err(|err| err.to_string())?;
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(url.as_str())
+            .header(hyper::header::CONTENT_TYPE, "application/json");
+        if let (Some(username), Some(password)) = (&args.username, &args.password) {
+            let headers = builder
+                .headers_mut()
+                .expect("request builder should be valid");
+            headers.typed_insert(Authorization::<Basic>::basic(username, password));
+        }
+        let body = serde_json::to_vec(&request.request).map_err(|err| err.to_string())?;
+        let http_request = builder
+            .body(Body::from(body))
+            .map_err(|err| err.to_string())?;
+        let response = client
+            .request(http_request)
+            .await
+            .map_err(|err| err.to_string())?;
+        let status = response.status().as_u16();
+        hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|err| err.to_string())?;
+        Ok::<u16, String>(status)
+    }
+    .await;
+    Outcome {
+        latency: start.elapsed(),
+        status: status.ok(),
+    }
+}
+
+/// Run the load test.
+///
+/// Returns the outcome of each request and the duration of the test.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+/// * `requests`: Requests to send, repeated as necessary
+async fn run(args: Arc<Args>, requests: Arc<Vec<ReplayRequest>>) -> (Vec<Outcome>, Duration) {
+    let client = build_client(&args).expect("invalid TLS configuration");
+    let next = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let deadline = args
+        .duration
+        .map(|duration| start + Duration::from_secs_f64(duration));
+    let workers = (0..args.concurrency.max(1))
+        .map(|_| {
+            let (client, args, requests, next) =
+                (client.clone(), args.clone(), requests.clone(), next.clone());
+            tokio::spawn(async move {
+                let mut outcomes = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let done = match deadline {
+                        Some(deadline) => Instant::now() >= deadline,
+                        None => index >= args.num_requests,
+                    };
+                    if done {
+                        break;
+                    }
+                    let request = &requests[index % requests.len()];
+                    outcomes.push(send(&client, &args, request).await);
+                }
+                outcomes
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut outcomes = Vec::new();
+    for worker in workers {
+        outcomes.extend(worker.await.expect("load test worker panicked"));
+    }
+    (outcomes, start.elapsed())
+}
+
+/// Load test entry point
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let requests = match &args.requests {
+        Some(path) => {
+            let file = std::fs::File::open(path).expect("failed to open requests file");
+            read_requests(std::io::BufReader::new(file)).expect("invalid requests file")
+        }
+        None => synthesise_requests(&args, args.num_requests.clamp(1, 10000))
+            .expect("invalid request parameters"),
+    };
+    if requests.is_empty() {
+        panic!("no requests to send");
+    }
+    let (outcomes, elapsed) = run(Arc::new(args.clone()), Arc::new(requests)).await;
+    let report = report(&outcomes, elapsed);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        println!("Requests:   {}", report.requests);
+        println!(
+            "Errors:     {} ({:.2}%)",
+            report.errors,
+            report.error_rate * 100.0
+        );
+        println!("Throughput: {:.1} requests/s", report.throughput);
+        println!(
+            "Latency:    p50 {:.1} ms, p90 {:.1} ms, p99 {:.1} ms, max {:.1} ms",
+            report.latency_ms.p50,
+            report.latency_ms.p90,
+            report.latency_ms.p99,
+            report.latency_ms.max
+        );
+        for (status, count) in &report.statuses {
+            println!("Status {}: {}", status, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_args(shape: Option<&str>) -> Args {
+        let mut args = vec![
+            "reductionist-loadtest",
+            "--source",
+            "http://localhost:9000",
+            "--bucket",
+            "sample-data",
+            "--object",
+            "data-uint32.dat",
+            "--dtype",
+            "uint32",
+        ];
+        if let Some(shape) = shape {
+            args.extend(["--shape", shape]);
+        }
+        Args::parse_from(args)
+    }
+
+    #[test]
+    fn read_requests_valid() {
+        let input =
+            "{\"operation\": \"sum\", \"request\": {\"dtype\": \"int32\"}, \"extra\": 1}\n\n";
+        let requests = read_requests(input.as_bytes()).unwrap();
+        let expected = ReplayRequest {
+            operation: "sum".to_string(),
+            request: serde_json::json!({"dtype": "int32"}),
+        };
+        assert_eq!(vec![expected], requests);
+    }
+
+    #[test]
+    fn read_requests_invalid() {
+        let input = "{\"operation\": \"sum\", \"request\": {}}\n{\"operation\": 1}\n";
+        let err = read_requests(input.as_bytes()).unwrap_err();
+        assert!(err.starts_with("line 2: "), "{}", err);
+    }
+
+    #[test]
+    fn synthesise_requests_no_shape() {
+        let requests = synthesise_requests(&make_args(None), 10).unwrap();
+        assert_eq!(10, requests.len());
+        for request in requests {
+            assert!(OPERATIONS.contains(&request.operation.as_str()));
+            assert_eq!("data-uint32.dat", request.request["object"]);
+            assert!(request.request.get("selection").is_none());
+        }
+    }
+
+    #[test]
+    fn synthesise_requests_shape() {
+        let requests = synthesise_requests(&make_args(Some("[10, 20]")), 100).unwrap();
+        for request in requests {
+            let selection: Vec<[usize; 3]> =
+                serde_json::from_value(request.request["selection"].clone()).unwrap();
+            for ([start, end, stride], length) in selection.into_iter().zip([10, 20]) {
+                assert!(start < end && end <= length, "{} {} {}", start, end, length);
+                assert!(stride == 1 || stride == 2);
+            }
+        }
+    }
+
+    #[test]
+    fn synthesise_requests_reproducible() {
+        let args = make_args(Some("[10]"));
+        let requests1 = synthesise_requests(&args, 10).unwrap();
+        let requests2 = synthesise_requests(&args, 10).unwrap();
+        assert_eq!(requests1, requests2);
+    }
+
+    #[test]
+    fn synthesise_requests_invalid_shape() {
+        let err = synthesise_requests(&make_args(Some("[0]")), 1).unwrap_err();
+        assert_eq!("invalid shape: dimensions must be greater than 0", err);
+    }
+
+    #[test]
+    fn percentiles() {
+        let latencies = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(50.0, percentile(&latencies, 50.0));
+        assert_eq!(99.0, percentile(&latencies, 99.0));
+        assert_eq!(100.0, percentile(&latencies, 100.0));
+        assert_eq!(0.0, percentile(&[], 50.0));
+    }
+
+    #[test]
+    fn report_errors() {
+        let outcomes = [
+            Outcome {
+                latency: Duration::from_millis(10),
+                status: Some(200),
+            },
+            Outcome {
+                latency: Duration::from_millis(20),
+                status: Some(503),
+            },
+            Outcome {
+                latency: Duration::from_millis(30),
+                status: None,
+            },
+            Outcome {
+                latency: Duration::from_millis(40),
+                status: Some(200),
+            },
+        ];
+        let report = report(&outcomes, Duration::from_secs(2));
+        assert_eq!(4, report.requests);
+        assert_eq!(2, report.errors);
+        assert_eq!(0.5, report.error_rate);
+        assert_eq!(2.0, report.throughput);
+        assert_eq!(20.0, report.latency_ms.p50);
+        assert_eq!(40.0, report.latency_ms.max);
+        let expected = BTreeMap::from([
+            ("200".to_string(), 2),
+            ("503".to_string(), 1),
+            ("error".to_string(), 1),
+        ]);
+        assert_eq!(expected, report.statuses);
+    }
+}
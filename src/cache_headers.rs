@@ -0,0 +1,212 @@
+//! HTTP caching headers for operation responses
+//!
+//! Caching headers allow a CDN or caching proxy in front of Reductionist to absorb repeated
+//! identical requests, for example reductions of public datasets. They are only added to responses
+//! for sources with a configured Cache-Control policy. Since operations use the POST method, the
+//! cache must be configured to include the request body in its cache key.
+
+use crate::models;
+
+use axum::headers::{ETag, HeaderMapExt, IfNoneMatch};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use hashbrown::HashMap;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// Cache-Control policies for S3 sources.
+#[derive(Debug)]
+pub struct CachePolicies {
+    /// Map from a source URL to the value of its Cache-Control header.
+    policies: HashMap<Url, HeaderValue>,
+}
+
+impl CachePolicies {
+    /// Returns a new CachePolicies object.
+    ///
+    /// # Arguments
+    ///
+    /// * `policies`: List of policies, each of the form `<url>=<cache-control>`
+    pub fn new(policies: &[String]) -> Result<Self, String> {
+        let policies = policies
+            .iter()
+            .map(|policy| {
+                let (url, value) = policy
+                    .split_once('=')
+                    .ok_or(format!("invalid cache policy {}", policy))?;
+                let url = Url::parse(url.trim()).map_err(|err| format!("{}: {}", url, err))?;
+                let value = HeaderValue::from_str(value.trim())
+                    .map_err(|err| format!("{}: {}", value, err))?;
+                Ok((url, value))
+            })
+            .collect::<Result<HashMap<Url, HeaderValue>, String>>()?;
+        Ok(Self { policies })
+    }
+
+    /// Returns the Cache-Control header value for a source, if one is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: URL of the S3 source in the request
+    pub fn get(&self, source: &Url) -> Option<&HeaderValue> {
+        self.policies.get(source)
+    }
+}
+
+/// Returns a strong entity tag for a response.
+///
+/// The tag is derived from the response data and all response headers that describe it.
+///
+/// # Arguments
+///
+/// * `response`: Response object
+pub fn etag(response: &models::Response) -> ETag {
+    let mut hasher = Sha256::new();
+    hasher.update(&response.body);
+    hasher.update(response.dtype.to_string());
+    hasher.update(format!("{:?}", response.shape));
+    hasher.update(response.count.to_le_bytes());
+    hasher.update(response.missing.to_le_bytes());
+    hasher.update(format!("{:?}", response.encoding));
+    hasher.update(format!("{:?}", response.indices));
+    format!("\"{:x}\"", hasher.finalize())
+        .parse()
+        .expect("hex digest should be a valid ETag")
+}
+
+/// Convert a response into an [axum::response::Response] with caching headers.
+///
+/// If no policy is provided, the response is returned without caching headers. If the request's
+/// If-None-Match header matches the response, a 304 Not Modified response is returned.
+///
+/// # Arguments
+///
+/// * `policy`: Optional Cache-Control header value for the request's source
+/// * `if_none_match`: Optional If-None-Match header from the request
+/// * `response`: Response object
+pub fn apply(
+    policy: Option<&HeaderValue>,
+    if_none_match: Option<IfNoneMatch>,
+    response: models::Response,
+) -> Response {
+    let Some(policy) = policy else {
+        return response.into_response();
+    };
+    let etag = etag(&response);
+    let not_modified =
+        if_none_match.is_some_and(|if_none_match| !if_none_match.precondition_passes(&etag));
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        response.into_response()
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, policy.clone());
+    headers.typed_insert(etag);
+    // Responses depend on the credentials used to access the source, and the accepted encoding
+    // and byte order.
+    headers.insert(
+        header::VARY,
+        HeaderValue::from_static(
+            "authorization, x-activestorage-encoding, x-activestorage-accept-byte-order",
+        ),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::DType;
+
+    use axum::body::Bytes;
+
+    fn make_response(body: &'static [u8]) -> models::Response {
+        models::Response::new(Bytes::from_static(body), DType::Int32, vec![1], 1)
+    }
+
+    fn make_policies() -> CachePolicies {
+        CachePolicies::new(&["http://example.com=public, max-age=3600".to_string()]).unwrap()
+    }
+
+    #[test]
+    fn get() {
+        let policies = make_policies();
+        let url = Url::parse("http://example.com").unwrap();
+        assert_eq!("public, max-age=3600", policies.get(&url).unwrap());
+        let url = Url::parse("http://example.org").unwrap();
+        assert_eq!(None, policies.get(&url));
+    }
+
+    #[test]
+    fn new_invalid_policy() {
+        let err = CachePolicies::new(&["http://example.com".to_string()]).unwrap_err();
+        assert_eq!("invalid cache policy http://example.com", err);
+    }
+
+    #[test]
+    fn etag_differs() {
+        let etag1 = etag(&make_response(&[1, 0, 0, 0]));
+        let etag2 = etag(&make_response(&[2, 0, 0, 0]));
+        assert_eq!(etag1, etag(&make_response(&[1, 0, 0, 0])));
+        assert_ne!(etag1, etag2);
+    }
+
+    #[test]
+    fn etag_differs_by_encoding() {
+        let mut response = make_response(&[1, 0, 0, 0]);
+        let etag1 = etag(&response);
+        response.encoding = models::Encoding::Sparse(models::Fill::NaN);
+        assert_ne!(etag1, etag(&response));
+    }
+
+    #[test]
+    fn apply_no_policy() {
+        let response = apply(None, None, make_response(&[1, 0, 0, 0]));
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(response.headers().get(header::CACHE_CONTROL).is_none());
+        assert!(response.headers().get(header::ETAG).is_none());
+    }
+
+    #[test]
+    fn apply_policy() {
+        let policy = HeaderValue::from_static("public");
+        let response = apply(Some(&policy), None, make_response(&[1, 0, 0, 0]));
+        assert_eq!(StatusCode::OK, response.status());
+        let headers = response.headers();
+        assert_eq!("public", headers.get(header::CACHE_CONTROL).unwrap());
+        assert_eq!(
+            "authorization, x-activestorage-encoding, x-activestorage-accept-byte-order",
+            headers.get(header::VARY).unwrap()
+        );
+        assert_eq!(
+            etag(&make_response(&[1, 0, 0, 0])),
+            headers.typed_get::<ETag>().unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_not_modified() {
+        let policy = HeaderValue::from_static("public");
+        let etag = etag(&make_response(&[1, 0, 0, 0]));
+        let mut headers = axum::http::HeaderMap::new();
+        headers.typed_insert(etag.clone());
+        let value = headers.remove(header::ETAG).unwrap();
+        headers.insert(header::IF_NONE_MATCH, value);
+        let if_none_match = headers.typed_get::<IfNoneMatch>();
+        let response = apply(Some(&policy), if_none_match, make_response(&[1, 0, 0, 0]));
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+        assert_eq!(etag, response.headers().typed_get::<ETag>().unwrap());
+    }
+
+    #[test]
+    fn apply_modified() {
+        let policy = HeaderValue::from_static("public");
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"foo\""));
+        let if_none_match = headers.typed_get::<IfNoneMatch>();
+        let response = apply(Some(&policy), if_none_match, make_response(&[1, 0, 0, 0]));
+        assert_eq!(StatusCode::OK, response.status());
+    }
+}
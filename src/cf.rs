@@ -0,0 +1,196 @@
+//! CF conventions mask-and-scale decoding
+//!
+//! Variables in netCDF files following the [CF conventions](https://cfconventions.org/) are often
+//! packed, with missing data indicated by `_FillValue`, `missing_value` or `valid_range`
+//! attributes, and with `scale_factor` and `add_offset` attributes for unpacking. When
+//! `cf_convention` is set in a request, the data is decoded in the same order as xarray's
+//! defaults before the operation is performed:
+//!
+//! 1. Packed values matching the `missing` descriptor are masked.
+//! 2. Unmasked values are converted to float64 and unpacked as
+//!    `value * scale_factor + add_offset`.
+//!
+//! The operation is then performed in float64, ignoring masked values.
+
+use crate::array;
+use crate::deadline;
+use crate::error::ActiveStorageError;
+use crate::models::{DType, RequestData};
+use crate::operation::Element;
+use crate::operations::missing_filter;
+use crate::types::{DValue, Missing};
+
+/// Returns a missing data descriptor that excludes NaN, which is used for masked values in
+/// decoded data.
+fn decoded_missing() -> Missing<DValue> {
+    let min = DValue::from_f64(f64::MIN).expect("f64::MIN should be finite");
+    let max = DValue::from_f64(f64::MAX).expect("f64::MAX should be finite");
+    Missing::ValidRange(min, max)
+}
+
+/// Decode packed data following CF conventions.
+///
+/// Returns float64 data in native byte order, with masked values set to NaN. The request data is
+/// updated to describe the decoded data.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `data`: Packed data
+pub fn decode(
+    request_data: &mut RequestData,
+    data: Vec<u8>,
+) -> Result<Vec<u8>, ActiveStorageError> {
+    let decoded = match request_data.dtype {
+        DType::Int8 => decode_t::<i8>(request_data, data),
+        DType::Int16 => decode_t::<i16>(request_data, data),
+        DType::Int32 => decode_t::<i32>(request_data, data),
+        DType::Int64 => decode_t::<i64>(request_data, data),
+        DType::Uint8 => decode_t::<u8>(request_data, data),
+        DType::Uint16 => decode_t::<u16>(request_data, data),
+        DType::Uint32 => decode_t::<u32>(request_data, data),
+        DType::Uint64 => decode_t::<u64>(request_data, data),
+        DType::Float32 => decode_t::<f32>(request_data, data),
+        DType::Float64 => decode_t::<f64>(request_data, data),
+    }?;
+    request_data.dtype = DType::Float64;
+    request_data.byte_order = None;
+    request_data.missing = Some(decoded_missing());
+    Ok(decoded)
+}
+
+/// Decode packed data of a specific type following CF conventions.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `data`: Packed data
+fn decode_t<T: Element>(
+    request_data: &RequestData,
+    mut data: Vec<u8>,
+) -> Result<Vec<u8>, ActiveStorageError> {
+    let scale_factor = request_data.scale_factor.unwrap_or(1.0);
+    let add_offset = request_data.add_offset.unwrap_or(0.0);
+    let missing = request_data
+        .missing
+        .as_ref()
+        .map(Missing::<T>::try_from)
+        .transpose()?;
+    let valid = missing.as_ref().map(missing_filter);
+    let array = array::build_array::<T>(request_data, &mut data)?;
+    // Preserve the memory layout so that the shape and order still apply.
+    let packed = array
+        .as_slice_memory_order()
+        .expect("array should be contiguous");
+    // Create an 8-byte aligned Vec<u8> for the float64 data.
+    let mut decoded =
+        maligned::align_first::<u8, maligned::A8>(packed.len() * std::mem::size_of::<f64>());
+    for value in deadline::checkpoints(packed.iter()) {
+        let unpacked = match &valid {
+            Some(valid) if !valid(value) => f64::NAN,
+            // Use the same operations as xarray to produce identical results.
+            _ => value.to_f64().unwrap_or(f64::NAN) * scale_factor + add_offset,
+        };
+        decoded.extend_from_slice(&unpacked.to_ne_bytes());
+    }
+    deadline::check()?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Order, Slice};
+    use crate::operation::Operation;
+    use crate::operations;
+    use crate::test_utils;
+    use crate::types::NON_NATIVE_BYTE_ORDER;
+    use zerocopy::AsBytes;
+
+    fn to_f64(data: &[u8]) -> Vec<f64> {
+        data.chunks_exact(8)
+            .map(|chunk| f64::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn decode_scale_offset() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = DType::Int32;
+        request_data.scale_factor = Some(0.5);
+        request_data.add_offset = Some(10.0);
+        request_data.missing = Some(Missing::MissingValue((-1).into()));
+        let data = [1_i32, -1, 4].as_bytes().to_vec();
+        let decoded = decode(&mut request_data, data).unwrap();
+        let decoded = to_f64(&decoded);
+        assert_eq!(10.5, decoded[0]);
+        assert!(decoded[1].is_nan());
+        assert_eq!(12.0, decoded[2]);
+        assert_eq!(DType::Float64, request_data.dtype);
+        assert_eq!(Some(decoded_missing()), request_data.missing);
+    }
+
+    #[test]
+    fn decode_defaults() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = DType::Uint32;
+        let data = [1_u32, 2].as_bytes().to_vec();
+        let decoded = decode(&mut request_data, data).unwrap();
+        assert_eq!(vec![1.0, 2.0], to_f64(&decoded));
+    }
+
+    #[test]
+    fn decode_non_native_byte_order() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = DType::Int64;
+        request_data.byte_order = Some(NON_NATIVE_BYTE_ORDER);
+        request_data.scale_factor = Some(2.0);
+        let data = [1_i64.swap_bytes(), 2_i64.swap_bytes()].as_bytes().to_vec();
+        let decoded = decode(&mut request_data, data).unwrap();
+        assert_eq!(vec![2.0, 4.0], to_f64(&decoded));
+        assert_eq!(None, request_data.byte_order);
+    }
+
+    #[test]
+    fn decode_fortran_order() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = DType::Float32;
+        request_data.shape = Some(vec![2, 2]);
+        request_data.order = Some(Order::F);
+        request_data.add_offset = Some(1.0);
+        let data = [1_f32, 2.0, 3.0, 4.0].as_bytes().to_vec();
+        let decoded = decode(&mut request_data, data).unwrap();
+        // Memory order is preserved.
+        assert_eq!(vec![2.0, 3.0, 4.0, 5.0], to_f64(&decoded));
+    }
+
+    #[test]
+    fn decode_then_sum() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = DType::Int32;
+        request_data.shape = Some(vec![4]);
+        request_data.selection = Some(vec![Slice::new(1, 4, 1).into()]);
+        request_data.scale_factor = Some(0.1);
+        request_data.missing = Some(Missing::ValidMin(0.into()));
+        let data = [100_i32, 10, -5, 20].as_bytes().to_vec();
+        let decoded = decode(&mut request_data, data).unwrap();
+        let response = operations::Sum::execute(&request_data, decoded).unwrap();
+        assert_eq!(DType::Float64, response.dtype);
+        assert_eq!(2, response.count);
+        let sum = f64::from_ne_bytes(response.body[..].try_into().unwrap());
+        assert_eq!(10.0 * 0.1 + 20.0 * 0.1, sum);
+    }
+
+    #[test]
+    fn decode_then_max_excludes_nan() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = DType::Float64;
+        request_data.scale_factor = Some(-1.0);
+        let data = [1.0_f64, f64::NAN, 3.0].as_bytes().to_vec();
+        let decoded = decode(&mut request_data, data).unwrap();
+        let response = operations::Max::execute(&request_data, decoded).unwrap();
+        assert_eq!(2, response.count);
+        let max = f64::from_ne_bytes(response.body[..].try_into().unwrap());
+        assert_eq!(-1.0, max);
+    }
+}
@@ -0,0 +1,205 @@
+//! Fault injection for resiliency testing
+//!
+//! This module is only compiled when the `chaos` Cargo feature is enabled, and must not be used in
+//! production. Faults are configured using the `REDUCTIONIST_CHAOS` environment variable, which
+//! contains a comma-separated list of `<key>=<value>` settings:
+//!
+//! * `s3_delay_probability`: Probability of delaying each S3 GET request
+//! * `s3_delay_ms`: Delay in milliseconds
+//! * `s3_fail_probability`: Probability of failing each S3 GET request with a connection error
+//! * `corrupt_probability`: Probability of corrupting each downloaded object
+//! * `corrupt_bytes`: Number of bytes to corrupt
+//!
+//! For example: `s3_fail_probability=0.1,corrupt_probability=0.05,corrupt_bytes=4`.
+
+use crate::error::ActiveStorageError;
+
+use aws_sdk_s3::error::SdkError;
+use aws_smithy_runtime_api::client::result::ConnectorError;
+use axum::body::Bytes;
+use rand::Rng;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Name of the environment variable containing the fault configuration.
+const CHAOS_ENV: &str = "REDUCTIONIST_CHAOS";
+
+/// Fault injection configuration
+#[derive(Debug, Default, PartialEq)]
+pub struct Chaos {
+    /// Probability of delaying each S3 GET request.
+    s3_delay_probability: f64,
+    /// Delay for S3 GET requests.
+    s3_delay: Duration,
+    /// Probability of failing each S3 GET request.
+    s3_fail_probability: f64,
+    /// Probability of corrupting each downloaded object.
+    corrupt_probability: f64,
+    /// Number of bytes to corrupt.
+    corrupt_bytes: usize,
+}
+
+impl Chaos {
+    /// Returns a new Chaos object, parsed from a configuration string.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`: Comma-separated list of `<key>=<value>` settings
+    pub fn parse(config: &str) -> Result<Self, String> {
+        let mut chaos = Self::default();
+        for setting in config
+            .split(',')
+            .filter(|setting| !setting.trim().is_empty())
+        {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or(format!("invalid chaos setting {}", setting))?;
+            let value = value.trim();
+            let invalid = |err: &dyn std::fmt::Display| format!("{}: {}", setting, err);
+            let probability = || {
+                value
+                    .parse::<f64>()
+                    .map_err(|err| invalid(&err))
+                    .and_then(|p| {
+                        (0.0..=1.0)
+                            .contains(&p)
+                            .then_some(p)
+                            .ok_or(invalid(&"probability must be between 0 and 1"))
+                    })
+            };
+            match key.trim() {
+                "s3_delay_probability" => chaos.s3_delay_probability = probability()?,
+                "s3_delay_ms" => {
+                    chaos.s3_delay =
+                        Duration::from_millis(value.parse().map_err(|err| invalid(&err))?)
+                }
+                "s3_fail_probability" => chaos.s3_fail_probability = probability()?,
+                "corrupt_probability" => chaos.corrupt_probability = probability()?,
+                "corrupt_bytes" => {
+                    chaos.corrupt_bytes = value.parse().map_err(|err| invalid(&err))?
+                }
+                key => return Err(format!("unknown chaos setting {}", key)),
+            }
+        }
+        Ok(chaos)
+    }
+
+    /// Inject faults before an S3 GET request.
+    ///
+    /// The request may be delayed, and may fail with a connection error.
+    pub async fn s3_get(&self) -> Result<(), ActiveStorageError> {
+        if rand::thread_rng().gen_bool(self.s3_delay_probability) {
+            tracing::warn!("Injecting S3 GET delay of {:?}", self.s3_delay);
+            tokio::time::sleep(self.s3_delay).await;
+        }
+        if rand::thread_rng().gen_bool(self.s3_fail_probability) {
+            tracing::warn!("Injecting S3 GET failure");
+            return Err(ActiveStorageError::S3GetObject(SdkError::dispatch_failure(
+                ConnectorError::io("injected S3 GET failure".into()),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Inject faults into downloaded data.
+    ///
+    /// The data may have some of its bytes corrupted.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: Downloaded data
+    pub fn corrupt(&self, data: Bytes) -> Bytes {
+        let mut rng = rand::thread_rng();
+        if data.is_empty() || !rng.gen_bool(self.corrupt_probability) {
+            return data;
+        }
+        tracing::warn!("Injecting corruption of {} bytes", self.corrupt_bytes);
+        // Preserve the alignment of the data.
+        let mut buf = maligned::align_first::<u8, maligned::A8>(data.len());
+        buf.extend_from_slice(&data);
+        for _ in 0..self.corrupt_bytes {
+            let index = rng.gen_range(0..buf.len());
+            buf[index] ^= rng.gen_range(1..=u8::MAX);
+        }
+        buf.into()
+    }
+}
+
+/// Returns the fault injection configuration from the environment.
+///
+/// Panics if the configuration is invalid.
+pub fn get() -> &'static Chaos {
+    static CHAOS: OnceLock<Chaos> = OnceLock::new();
+    CHAOS.get_or_init(|| {
+        let config = std::env::var(CHAOS_ENV).unwrap_or_default();
+        let chaos = Chaos::parse(&config).expect("invalid chaos configuration");
+        if chaos != Chaos::default() {
+            tracing::warn!("Fault injection is enabled: {:?}", chaos);
+        }
+        chaos
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(Chaos::default(), Chaos::parse("").unwrap());
+    }
+
+    #[test]
+    fn parse_all() {
+        let chaos = Chaos::parse(
+            "s3_delay_probability=0.5, s3_delay_ms=100,s3_fail_probability=0.25,corrupt_probability=1,corrupt_bytes=4",
+        )
+        .unwrap();
+        let expected = Chaos {
+            s3_delay_probability: 0.5,
+            s3_delay: Duration::from_millis(100),
+            s3_fail_probability: 0.25,
+            corrupt_probability: 1.0,
+            corrupt_bytes: 4,
+        };
+        assert_eq!(expected, chaos);
+    }
+
+    #[test]
+    fn parse_invalid_probability() {
+        let err = Chaos::parse("s3_fail_probability=2").unwrap_err();
+        assert_eq!(
+            "s3_fail_probability=2: probability must be between 0 and 1",
+            err
+        );
+    }
+
+    #[test]
+    fn parse_unknown() {
+        let err = Chaos::parse("foo=1").unwrap_err();
+        assert_eq!("unknown chaos setting foo", err);
+    }
+
+    #[tokio::test]
+    async fn s3_get_no_faults() {
+        Chaos::default().s3_get().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn s3_get_fail() {
+        let chaos = Chaos::parse("s3_fail_probability=1").unwrap();
+        let err = chaos.s3_get().await.unwrap_err();
+        assert!(crate::failover::is_endpoint_failure(&err));
+    }
+
+    #[test]
+    fn corrupt() {
+        let data = Bytes::from_static(&[0; 16]);
+        assert_eq!(data, Chaos::default().corrupt(data.clone()));
+        let chaos = Chaos::parse("corrupt_probability=1,corrupt_bytes=1").unwrap();
+        let corrupted = chaos.corrupt(data.clone());
+        assert_eq!(data.len(), corrupted.len());
+        assert_eq!(1, corrupted.iter().filter(|&&b| b != 0).count());
+        assert_eq!(0, corrupted.as_ptr().align_offset(8));
+    }
+}
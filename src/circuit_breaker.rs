@@ -0,0 +1,227 @@
+//! Circuit breakers for S3 endpoints
+//!
+//! When an S3 endpoint is down, every request for it holds memory and connection permits until
+//! the client times out. A circuit breaker tracks consecutive failures of each endpoint. After a
+//! threshold of consecutive failures the circuit opens, and requests for the endpoint fail fast
+//! for a cool-down period. After the cool-down, requests are allowed through again, and the
+//! circuit closes on the first success. A further failure reopens the circuit immediately.
+//!
+//! Only failures that indicate that an endpoint is unavailable are counted, as for failover
+//! between equivalent endpoints. Requests for an open endpoint that belongs to a failover group
+//! are sent to the other endpoints of the group.
+//!
+//! Since requests may name arbitrary endpoints, the number of endpoints tracked is limited, and
+//! endpoints whose circuits are not open are evicted when the limit is reached. Only endpoints
+//! configured on the server are used as metric labels.
+
+use crate::metrics::S3_CIRCUIT_BREAKER_REJECTIONS;
+
+use hashbrown::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Maximum number of endpoints whose failures are tracked.
+const MAX_ENDPOINTS: usize = 1024;
+
+/// Metric label for endpoints that are not configured on the server.
+const OTHER_ENDPOINT: &str = "other";
+
+/// Failure state of an endpoint.
+#[derive(Debug, Default)]
+struct EndpointState {
+    /// Number of consecutive failures.
+    failures: usize,
+    /// Time until which the circuit is open, if it has been opened.
+    open_until: Option<Instant>,
+}
+
+/// Circuit breakers for S3 endpoints.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Number of consecutive failures after which the circuit opens.
+    threshold: usize,
+    /// Time for which the circuit stays open.
+    cool_down: Duration,
+    /// Failure state of each endpoint that has failed since its last success.
+    endpoints: Mutex<HashMap<Url, EndpointState>>,
+    /// Endpoints configured on the server, which are used as metric labels.
+    configured: HashSet<Url>,
+}
+
+impl CircuitBreaker {
+    /// Returns a new CircuitBreaker object.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold`: Number of consecutive failures after which the circuit opens
+    /// * `cool_down`: Time for which the circuit stays open
+    /// * `configured`: Endpoints configured on the server
+    pub fn new(
+        threshold: usize,
+        cool_down: Duration,
+        configured: impl IntoIterator<Item = Url>,
+    ) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cool_down,
+            endpoints: Mutex::new(HashMap::new()),
+            configured: configured.into_iter().collect(),
+        }
+    }
+
+    /// Returns whether a request may be sent to an endpoint.
+    ///
+    /// Requests that are rejected are counted in the `s3_circuit_breaker_rejections` metric.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint`: URL of the endpoint
+    pub fn allow(&self, endpoint: &Url) -> bool {
+        let endpoints = self.endpoints.lock().unwrap();
+        let open = endpoints
+            .get(endpoint)
+            .and_then(|state| state.open_until)
+            .is_some_and(|open_until| Instant::now() < open_until);
+        if open {
+            let label = if self.configured.contains(endpoint) {
+                endpoint.as_str()
+            } else {
+                OTHER_ENDPOINT
+            };
+            S3_CIRCUIT_BREAKER_REJECTIONS
+                .with_label_values(&[label])
+                .inc();
+        }
+        !open
+    }
+
+    /// Record a successful request to an endpoint, closing its circuit.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint`: URL of the endpoint
+    pub fn succeeded(&self, endpoint: &Url) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if endpoints
+            .remove(endpoint)
+            .is_some_and(|state| state.open_until.is_some())
+        {
+            tracing::info!("Closing circuit for S3 endpoint {}", endpoint);
+        }
+    }
+
+    /// Record a failed request to an endpoint, opening its circuit if the threshold is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint`: URL of the endpoint
+    pub fn failed(&self, endpoint: &Url) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if endpoints.len() >= MAX_ENDPOINTS && !endpoints.contains_key(endpoint) {
+            let now = Instant::now();
+            endpoints
+                .retain(|_, state| state.open_until.is_some_and(|open_until| now < open_until));
+            // If every tracked circuit is open, the endpoint is not tracked.
+            if endpoints.len() >= MAX_ENDPOINTS {
+                return;
+            }
+        }
+        let state = endpoints.entry(endpoint.clone()).or_default();
+        state.failures += 1;
+        if state.failures >= self.threshold {
+            tracing::warn!(
+                "Opening circuit for S3 endpoint {} for {:?} after {} consecutive failures",
+                endpoint,
+                self.cool_down,
+                state.failures
+            );
+            state.open_until = Some(Instant::now() + self.cool_down);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn opens_after_threshold() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60), []);
+        let endpoint = url("http://a");
+        breaker.failed(&endpoint);
+        assert!(breaker.allow(&endpoint));
+        breaker.failed(&endpoint);
+        assert!(!breaker.allow(&endpoint));
+        // Other endpoints are unaffected.
+        assert!(breaker.allow(&url("http://b")));
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60), []);
+        let endpoint = url("http://a");
+        breaker.failed(&endpoint);
+        breaker.succeeded(&endpoint);
+        breaker.failed(&endpoint);
+        assert!(breaker.allow(&endpoint));
+    }
+
+    #[test]
+    fn cool_down() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO, []);
+        let endpoint = url("http://a");
+        breaker.failed(&endpoint);
+        // The cool-down has passed, so a trial request is allowed.
+        assert!(breaker.allow(&endpoint));
+        // A failed trial reopens the circuit immediately.
+        breaker.failed(&endpoint);
+        assert_eq!(2, breaker.endpoints.lock().unwrap()[&endpoint].failures);
+        breaker.succeeded(&endpoint);
+        assert!(breaker.endpoints.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn evicts_closed_circuits() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60), []);
+        let open = url("http://open");
+        breaker.failed(&open);
+        breaker.failed(&open);
+        for i in 1..MAX_ENDPOINTS {
+            breaker.failed(&url(&format!("http://{}", i)));
+        }
+        assert_eq!(MAX_ENDPOINTS, breaker.endpoints.lock().unwrap().len());
+        // Endpoints whose circuits are not open are evicted to make room.
+        let endpoint = url("http://new");
+        breaker.failed(&endpoint);
+        let endpoints = breaker.endpoints.lock().unwrap();
+        assert_eq!(2, endpoints.len());
+        assert_eq!(1, endpoints[&endpoint].failures);
+        drop(endpoints);
+        assert!(!breaker.allow(&open));
+    }
+
+    #[test]
+    fn metric_labels() {
+        let configured = url("http://configured");
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60), [configured.clone()]);
+        let other = url("http://other.example.com");
+        breaker.failed(&configured);
+        breaker.failed(&other);
+        let rejections = |label| {
+            S3_CIRCUIT_BREAKER_REJECTIONS
+                .with_label_values(&[label])
+                .get()
+        };
+        let (configured_before, other_before) =
+            (rejections(configured.as_str()), rejections(OTHER_ENDPOINT));
+        assert!(!breaker.allow(&configured));
+        assert!(!breaker.allow(&other));
+        assert_eq!(configured_before + 1, rejections(configured.as_str()));
+        assert!(rejections(OTHER_ENDPOINT) > other_before);
+    }
+}
@@ -0,0 +1,314 @@
+//! Coalescing of concurrent reads of the same object
+//!
+//! PyActiveStorage may split the selection of one chunk across several threads, resulting in
+//! concurrent requests that download the same byte range of an object. When enabled, a download
+//! whose byte range is contained in that of a download already in flight for the same object
+//! waits for that download rather than sending its own GET. Operations modify data in place, so
+//! each waiting request receives its own copy of its range.
+//!
+//! If the download in flight fails or is cancelled, the waiting requests send their own GETs, so
+//! that errors and retries are handled independently for each request.
+
+use crate::error::ActiveStorageError;
+use crate::s3_client::AlignedBuffer;
+
+use axum::body::Bytes;
+use hashbrown::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+/// Byte range of an object
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ByteRange {
+    /// Offset of the first byte
+    start: usize,
+    /// Offset after the last byte, or `None` for the end of the object
+    end: Option<usize>,
+}
+
+impl ByteRange {
+    /// Returns the byte range of an HTTP Range header value, as returned by
+    /// [get_range](crate::s3_client::get_range), or `None` if it is not a single byte range.
+    ///
+    /// # Arguments
+    ///
+    /// * `range`: Optional Range header value, or `None` for the whole object
+    pub fn parse(range: Option<&str>) -> Option<Self> {
+        let Some(range) = range else {
+            return Some(Self {
+                start: 0,
+                end: None,
+            });
+        };
+        let (start, last) = range.strip_prefix("bytes=")?.split_once('-')?;
+        let start = start.parse().ok()?;
+        let end = match last {
+            "" => None,
+            last => Some(last.parse::<usize>().ok()?.checked_add(1)?),
+        };
+        (end.map_or(true, |end| start < end)).then_some(Self { start, end })
+    }
+
+    /// Returns whether another range is contained in this one.
+    fn contains(&self, other: &ByteRange) -> bool {
+        other.start >= self.start
+            && match (self.end, other.end) {
+                (None, _) => true,
+                (Some(end), Some(other_end)) => other_end <= end,
+                (Some(_), None) => false,
+            }
+    }
+}
+
+/// Request waiting for a read in flight
+struct Waiter {
+    /// Byte range requested.
+    range: ByteRange,
+    /// Sender for a copy of the data in the range.
+    sender: oneshot::Sender<Bytes>,
+}
+
+/// Read in flight
+struct Read {
+    /// Unique identifier of the read.
+    id: u64,
+    /// Byte range being read.
+    range: ByteRange,
+    /// Requests waiting for the read.
+    waiters: Vec<Waiter>,
+}
+
+/// Coalesces concurrent reads of contained byte ranges of the same object.
+///
+/// Objects are identified by a key, which must distinguish any differences in how they are read,
+/// such as the source and credentials.
+pub struct ReadCoalescer<K> {
+    /// Reads in flight for each object.
+    reads: Mutex<HashMap<K, Vec<Read>>>,
+    /// Identifier of the next read.
+    next_id: AtomicU64,
+}
+
+/// Result of joining a [ReadCoalescer]
+pub enum Join<'a, K: Eq + Hash> {
+    /// No read in flight contains the range, and the caller should read it, then complete the
+    /// guard.
+    Lead(ReadGuard<'a, K>),
+    /// A read in flight contains the range. The receiver returns a copy of the data in the range,
+    /// or an error if the read failed, in which case the caller should read it itself.
+    Wait(oneshot::Receiver<Bytes>),
+}
+
+impl<K: Eq + Hash + Clone> ReadCoalescer<K> {
+    /// Returns a new ReadCoalescer object.
+    pub fn new() -> Self {
+        Self {
+            reads: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Join a read of a byte range of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `object`: Key identifying the object
+    /// * `range`: Byte range to read
+    pub fn join(&self, object: K, range: ByteRange) -> Join<'_, K> {
+        let mut reads = self.reads.lock().unwrap();
+        let object_reads = reads.entry(object.clone()).or_default();
+        if let Some(read) = object_reads
+            .iter_mut()
+            .find(|read| read.range.contains(&range))
+        {
+            let (sender, receiver) = oneshot::channel();
+            read.waiters.push(Waiter { range, sender });
+            return Join::Wait(receiver);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        object_reads.push(Read {
+            id,
+            range,
+            waiters: vec![],
+        });
+        Join::Lead(ReadGuard {
+            coalescer: self,
+            object,
+            id,
+        })
+    }
+
+    /// Remove a read from the reads in flight, returning it if present.
+    fn remove(&self, object: &K, id: u64) -> Option<Read> {
+        let mut reads = self.reads.lock().unwrap();
+        let object_reads = reads.get_mut(object)?;
+        let index = object_reads.iter().position(|read| read.id == id)?;
+        let read = object_reads.swap_remove(index);
+        if object_reads.is_empty() {
+            reads.remove(object);
+        }
+        Some(read)
+    }
+}
+
+impl<K: Eq + Hash + Clone> Default for ReadCoalescer<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Guard for a read in flight, which is removed when the guard is completed or dropped
+///
+/// Requests waiting for a read whose guard is dropped without completing it receive an error.
+pub struct ReadGuard<'a, K: Eq + Hash> {
+    /// Coalescer of the read.
+    coalescer: &'a ReadCoalescer<K>,
+    /// Key identifying the object.
+    object: K,
+    /// Identifier of the read.
+    id: u64,
+}
+
+impl<K: Eq + Hash + Clone> ReadGuard<'_, K> {
+    /// Complete the read, sending a copy of each waiting request's range of the data.
+    ///
+    /// Requests whose range extends beyond the data, such as past the end of the object, receive
+    /// an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: Data read
+    pub fn complete(self, data: &Bytes) -> Result<(), ActiveStorageError> {
+        let Some(read) = self.coalescer.remove(&self.object, self.id) else {
+            return Ok(());
+        };
+        for waiter in read.waiters {
+            let start = waiter.range.start - read.range.start;
+            let end = waiter
+                .range
+                .end
+                .map_or(data.len(), |end| end - read.range.start);
+            if start > end || end > data.len() {
+                continue;
+            }
+            // Copy into an aligned buffer, as for a download.
+            let buffer = AlignedBuffer::copy(&data[start..end]);
+            // The waiting request may have been cancelled.
+            let _ = waiter.sender.send(buffer);
+        }
+        Ok(())
+    }
+}
+
+impl<K: Eq + Hash> Drop for ReadGuard<'_, K> {
+    fn drop(&mut self) {
+        let mut reads = self.coalescer.reads.lock().unwrap();
+        if let Some(object_reads) = reads.get_mut(&self.object) {
+            object_reads.retain(|read| read.id != self.id);
+            if object_reads.is_empty() {
+                reads.remove(&self.object);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: usize, end: Option<usize>) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    fn lead<'a>(
+        coalescer: &'a ReadCoalescer<&'static str>,
+        r: ByteRange,
+    ) -> ReadGuard<'a, &'static str> {
+        match coalescer.join("foo", r) {
+            Join::Lead(guard) => guard,
+            Join::Wait(_) => panic!("expected to lead the read"),
+        }
+    }
+
+    fn wait(coalescer: &ReadCoalescer<&'static str>, r: ByteRange) -> oneshot::Receiver<Bytes> {
+        match coalescer.join("foo", r) {
+            Join::Lead(_) => panic!("expected to wait for the read"),
+            Join::Wait(receiver) => receiver,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Some(range(0, None)), ByteRange::parse(None));
+        assert_eq!(Some(range(2, Some(6))), ByteRange::parse(Some("bytes=2-5")));
+        assert_eq!(Some(range(2, None)), ByteRange::parse(Some("bytes=2-")));
+        assert_eq!(None, ByteRange::parse(Some("bytes=-5")));
+        assert_eq!(None, ByteRange::parse(Some("bytes=5-2")));
+        assert_eq!(None, ByteRange::parse(Some("bytes=0-1,4-5")));
+        assert_eq!(None, ByteRange::parse(Some("items=0-1")));
+    }
+
+    #[test]
+    fn contains() {
+        assert!(range(0, None).contains(&range(4, None)));
+        assert!(range(0, None).contains(&range(4, Some(8))));
+        assert!(range(2, Some(8)).contains(&range(2, Some(8))));
+        assert!(range(2, Some(8)).contains(&range(4, Some(6))));
+        assert!(!range(2, Some(8)).contains(&range(0, Some(6))));
+        assert!(!range(2, Some(8)).contains(&range(4, Some(10))));
+        assert!(!range(2, Some(8)).contains(&range(4, None)));
+    }
+
+    #[test]
+    fn coalesce_contained_ranges() {
+        let coalescer = ReadCoalescer::new();
+        let guard = lead(&coalescer, range(2, Some(10)));
+        let mut same = wait(&coalescer, range(2, Some(10)));
+        let mut inner = wait(&coalescer, range(4, Some(6)));
+        // Overlapping but not contained ranges are read separately.
+        let _other = lead(&coalescer, range(8, Some(12)));
+        let data = Bytes::from_static(&[2, 3, 4, 5, 6, 7, 8, 9]);
+        guard.complete(&data).unwrap();
+        assert_eq!(data, same.try_recv().unwrap());
+        let inner = inner.try_recv().unwrap();
+        assert_eq!(&[4, 5][..], inner);
+        // Each waiter receives its own aligned copy.
+        assert_ne!(data.as_ptr(), inner.as_ptr());
+        assert_eq!(0, inner.as_ptr() as usize % 8);
+        // Completed reads are no longer joined.
+        let _guard = lead(&coalescer, range(2, Some(10)));
+    }
+
+    #[test]
+    fn coalesce_range_beyond_data() {
+        let coalescer = ReadCoalescer::new();
+        let guard = lead(&coalescer, range(0, None));
+        let mut beyond = wait(&coalescer, range(2, Some(6)));
+        let mut open = wait(&coalescer, range(2, None));
+        guard.complete(&Bytes::from_static(&[0, 1, 2, 3])).unwrap();
+        assert!(beyond.try_recv().is_err());
+        assert_eq!(&[2, 3][..], open.try_recv().unwrap());
+    }
+
+    #[test]
+    fn coalesce_failed_read() {
+        let coalescer = ReadCoalescer::new();
+        let guard = lead(&coalescer, range(0, None));
+        let mut waiter = wait(&coalescer, range(0, None));
+        drop(guard);
+        assert!(waiter.try_recv().is_err());
+        assert!(coalescer.reads.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn coalesce_distinct_objects() {
+        let coalescer = ReadCoalescer::new();
+        let _foo = lead(&coalescer, range(0, None));
+        assert!(matches!(
+            coalescer.join("bar", range(0, None)),
+            Join::Lead(_)
+        ));
+    }
+}
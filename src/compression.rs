@@ -1,31 +1,436 @@
 //! (De)compression support.
+//!
+//! Gzip and zlib decompression may use one of several backends. By default, pure Rust
+//! implementations are used: [flate2] with the miniz_oxide backend for gzip, and [zune_inflate]
+//! for zlib. Hardware-accelerated backends may be enabled using Cargo features:
+//!
+//! * `zlib-ng`: [zlib-ng](https://github.com/zlib-ng/zlib-ng) via [flate2]
+//! * `isal`: [Intel ISA-L](https://github.com/intel/isa-l) igzip
+//!
+//! The backend is selected at runtime based on the enabled features and the capabilities of the
+//! CPU, preferring ISA-L, then zlib-ng, then the pure Rust implementations.
+//!
+//! Zstandard decompression uses [ruzstd], and Blosc and LZ4 decompression are implemented in the
+//! [blosc] and [lz4] modules using pure Rust codecs.
+
+pub mod blosc;
+pub mod lz4;
 
 use crate::error::ActiveStorageError;
 use crate::models;
 
 use axum::body::Bytes;
-use flate2::read::GzDecoder;
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+use std::cell::RefCell;
 use std::io::Read;
+use std::sync::OnceLock;
+use strum_macros::Display;
+use zune_inflate::errors::DecodeErrorStatus;
 use zune_inflate::{DeflateDecoder, DeflateOptions};
 
+/// Gzip and zlib decompression backend.
+#[derive(Clone, Copy, Debug, Display, PartialEq)]
+pub enum Backend {
+    /// Intel ISA-L igzip
+    #[strum(serialize = "isa-l")]
+    Isal,
+    /// zlib-ng via flate2
+    #[strum(serialize = "zlib-ng")]
+    ZlibNg,
+    /// flate2 with miniz_oxide for gzip, and zune-inflate for zlib
+    #[strum(serialize = "rust")]
+    Rust,
+}
+
+/// Returns whether the CPU supports the instructions used by the ISA-L backend.
+///
+/// ISA-L includes generic implementations, but these are no faster than the other backends.
+#[cfg(feature = "isal")]
+fn isal_supported() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        std::arch::is_x86_feature_detected!("avx2")
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        std::arch::is_aarch64_feature_detected!("neon")
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        false
+    }
+}
+
+/// Returns the decompression backend to use.
+///
+/// The backend is selected on first use, and the same backend is used thereafter.
+pub fn backend() -> Backend {
+    static BACKEND: OnceLock<Backend> = OnceLock::new();
+    *BACKEND.get_or_init(|| {
+        #[cfg(feature = "isal")]
+        if isal_supported() {
+            return Backend::Isal;
+        }
+        if cfg!(feature = "zlib-ng") {
+            Backend::ZlibNg
+        } else {
+            Backend::Rust
+        }
+    })
+}
+
 /// Decompresses some Bytes and returns the uncompressed data.
 ///
 /// # Arguments
 ///
 /// * `compression`: Compression algorithm
 /// * `data`: Compressed data [Bytes]
+/// * `max_size`: Optional maximum size in bytes of the uncompressed data
+/// * `expected_size`: Optional size in bytes of the uncompressed data, if known from the request.
+///   Formats that store the uncompressed size in a header are rejected before any memory is
+///   allocated if the sizes differ.
 pub fn decompress(
     compression: models::Compression,
     data: &Bytes,
+    max_size: Option<usize>,
+    expected_size: Option<usize>,
 ) -> Result<Bytes, ActiveStorageError> {
-    match compression {
-        models::Compression::Gzip => decompress_flate2_gzip(data),
-        models::Compression::Zlib => decompress_zune_zlib(data),
+    match (compression, backend()) {
+        (models::Compression::Blosc, _) => blosc::decompress(data, max_size, expected_size),
+        (models::Compression::Lz4, _) => lz4::decompress(data, max_size, expected_size),
+        (models::Compression::Zstd, _) => decompress_zstd(data, max_size),
+        #[cfg(feature = "isal")]
+        (models::Compression::Gzip, Backend::Isal) => read_aligned(
+            isal::read::GzipDecoder::new(&data[..]),
+            data.len(),
+            max_size,
+        ),
+        #[cfg(feature = "isal")]
+        (models::Compression::Zlib, Backend::Isal) => read_aligned(
+            isal::read::ZlibDecoder::new(&data[..]),
+            data.len(),
+            max_size,
+        ),
+        (models::Compression::Gzip, _) => decompress_flate2_gzip(data, max_size),
+        #[cfg(feature = "zlib-ng")]
+        (models::Compression::Zlib, Backend::ZlibNg) => decompress_flate2_zlib(data, max_size),
+        (models::Compression::Zlib, _) => decompress_zune_zlib(data, max_size),
+    }
+}
+
+/// Check that the size of some uncompressed data does not exceed a maximum.
+///
+/// # Arguments
+///
+/// * `size`: Size in bytes of the uncompressed data
+/// * `max_size`: Optional maximum size in bytes of the uncompressed data
+fn check_size(size: usize, max_size: Option<usize>) -> Result<(), ActiveStorageError> {
+    match max_size {
+        Some(limit) if size > limit => Err(ActiveStorageError::DecompressionLimit { limit }),
+        _ => Ok(()),
+    }
+}
+
+/// Gzip header flag indicating a header CRC.
+const GZIP_FHCRC: u8 = 0x02;
+/// Gzip header flag indicating an extra field.
+const GZIP_FEXTRA: u8 = 0x04;
+/// Gzip header flag indicating a file name.
+const GZIP_FNAME: u8 = 0x08;
+/// Gzip header flag indicating a comment.
+const GZIP_FCOMMENT: u8 = 0x10;
+
+thread_local! {
+    /// Deflate decompressor for reuse by decompression on the current thread.
+    ///
+    /// A decompressor has tens of kilobytes of internal state, so reusing them avoids repeated
+    /// large allocations under high request rates.
+    static DECOMPRESS: RefCell<Option<Decompress>> = const { RefCell::new(None) };
+}
+
+/// Run a function with a deflate decompressor from the current thread's pool.
+///
+/// # Arguments
+///
+/// * `zlib_header`: Whether the stream has a zlib header
+/// * `f`: Function to run
+fn with_decompress<R>(zlib_header: bool, f: impl FnOnce(&mut Decompress) -> R) -> R {
+    let mut decompress = take_decompress(zlib_header);
+    let result = f(&mut decompress);
+    DECOMPRESS.with(|cell| cell.replace(Some(decompress)));
+    result
+}
+
+/// Returns a deflate decompressor from the current thread's pool, or a new one if the pool is
+/// empty.
+///
+/// # Arguments
+///
+/// * `zlib_header`: Whether the stream has a zlib header
+fn take_decompress(zlib_header: bool) -> Decompress {
+    match DECOMPRESS.with(RefCell::take) {
+        Some(mut decompress) => {
+            decompress.reset(zlib_header);
+            decompress
+        }
+        None => Decompress::new(zlib_header),
+    }
+}
+
+/// Returns the length of a gzip member header, as defined in RFC 1952.
+///
+/// # Arguments
+///
+/// * `data`: Gzip data
+///
+/// Returns an error of kind [UnexpectedEof](std::io::ErrorKind::UnexpectedEof) if the data ends
+/// before the end of the header.
+fn gzip_header_len(data: &[u8]) -> std::io::Result<usize> {
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid gzip header");
+    let truncated =
+        || std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated gzip header");
+    // Magic bytes, compression method (deflate) and no reserved flags.
+    if std::iter::zip(data, [0x1f, 0x8b, 8]).any(|(a, b)| *a != b)
+        || data.get(3).is_some_and(|flags| flags & 0xe0 != 0)
+    {
+        return Err(invalid());
+    }
+    if data.len() < 10 {
+        return Err(truncated());
+    }
+    let flags = data[3];
+    let mut len = 10;
+    if flags & GZIP_FEXTRA != 0 {
+        let extra_len = data.get(len..len + 2).ok_or_else(truncated)?;
+        len += 2 + u16::from_le_bytes([extra_len[0], extra_len[1]]) as usize;
+    }
+    for flag in [GZIP_FNAME, GZIP_FCOMMENT] {
+        if flags & flag != 0 {
+            // Zero-terminated string.
+            let string_len = data
+                .get(len..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or_else(truncated)?;
+            len += string_len + 1;
+        }
+    }
+    if flags & GZIP_FHCRC != 0 {
+        len += 2;
+    }
+    if len > data.len() {
+        return Err(truncated());
+    }
+    Ok(len)
+}
+
+/// Returns whether a gzip trailer matches the CRC32 and length of the uncompressed data.
+///
+/// # Arguments
+///
+/// * `trailer`: Gzip trailer
+/// * `crc`: CRC32 of the uncompressed data
+fn gzip_trailer_matches(trailer: &[u8], crc: &Crc) -> bool {
+    trailer[0..4] == crc.sum().to_le_bytes() && trailer[4..8] == crc.amount().to_le_bytes()
+}
+
+/// Returns an error for a gzip stream with a trailer that does not match its data.
+fn gzip_checksum_error() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "corrupt gzip stream does not have a matching checksum",
+    )
+}
+
+/// Stage of a streaming decompression.
+enum InflaterStage {
+    /// Reading the gzip header, which has been partially received.
+    Header(Vec<u8>),
+    /// Decompressing the deflate stream.
+    Body,
+    /// Reading the gzip trailer, which has been partially received.
+    Trailer(Vec<u8>),
+    /// The stream is complete.
+    Done,
+}
+
+/// Streaming gzip and zlib decompressor.
+///
+/// Decompresses data in chunks as it arrives. [flate2] is used regardless of the selected
+/// [backend], since the other backends require all of the compressed data at once.
+pub struct Inflater {
+    /// Deflate decompressor, taken from the current thread's pool.
+    decompress: Option<Decompress>,
+    /// CRC32 of the decompressed data, for gzip streams.
+    crc: Option<Crc>,
+    /// Stage of the decompression.
+    stage: InflaterStage,
+    /// Optional maximum size in bytes of the decompressed data.
+    max_size: Option<usize>,
+}
+
+impl Inflater {
+    /// Returns a new Inflater object.
+    ///
+    /// # Arguments
+    ///
+    /// * `compression`: Compression algorithm
+    /// * `max_size`: Optional maximum size in bytes of the decompressed data
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compression algorithm does not support streaming decompression.
+    pub fn new(compression: models::Compression, max_size: Option<usize>) -> Self {
+        let (zlib_header, crc, stage) = match compression {
+            models::Compression::Gzip => {
+                (false, Some(Crc::new()), InflaterStage::Header(Vec::new()))
+            }
+            models::Compression::Zlib => (true, None, InflaterStage::Body),
+            models::Compression::Blosc | models::Compression::Lz4 | models::Compression::Zstd => {
+                panic!("{:?} does not support streaming decompression", compression)
+            }
+        };
+        Self {
+            decompress: Some(take_decompress(zlib_header)),
+            crc,
+            stage,
+            max_size,
+        }
+    }
+
+    /// Decompress a chunk of compressed data, appending the decompressed data to a buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `input`: Chunk of compressed data
+    /// * `out`: Buffer for decompressed data
+    pub fn write(&mut self, mut input: &[u8], out: &mut Vec<u8>) -> Result<(), ActiveStorageError> {
+        let decompress = self
+            .decompress
+            .as_mut()
+            .expect("decompressor should be present until dropped");
+        loop {
+            match &mut self.stage {
+                InflaterStage::Header(header) => {
+                    header.extend_from_slice(input);
+                    match gzip_header_len(header) {
+                        Ok(len) => {
+                            let rest = header.split_off(len);
+                            self.stage = InflaterStage::Body;
+                            return self.write(&rest, out);
+                        }
+                        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                            return Ok(())
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                InflaterStage::Body => {
+                    // Continue while there is input, or the output buffer was filled and there
+                    // may be more output pending.
+                    if input.is_empty() && out.len() < out.capacity() {
+                        return Ok(());
+                    }
+                    let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+                    let status = if input.is_empty() {
+                        // The output buffer is full, but there may be output pending. Check for
+                        // it without growing the buffer, which may change its alignment.
+                        let mut pending = [0; 1024];
+                        let status = decompress
+                            .decompress(input, &mut pending, FlushDecompress::None)
+                            .map_err(std::io::Error::from)?;
+                        out.extend_from_slice(
+                            &pending[..(decompress.total_out() - total_out) as usize],
+                        );
+                        status
+                    } else {
+                        if out.len() == out.capacity() {
+                            // FIXME: As in inflate, this may change the alignment of the buffer.
+                            out.reserve(std::cmp::max(out.capacity(), 1024));
+                        }
+                        decompress
+                            .decompress_vec(input, out, FlushDecompress::None)
+                            .map_err(std::io::Error::from)?
+                    };
+                    let consumed = (decompress.total_in() - total_in) as usize;
+                    let written = (decompress.total_out() - total_out) as usize;
+                    check_size(decompress.total_out() as usize, self.max_size)?;
+                    if let Some(crc) = self.crc.as_mut() {
+                        crc.update(&out[out.len() - written..]);
+                    }
+                    input = &input[consumed..];
+                    match status {
+                        Status::StreamEnd if self.crc.is_some() => {
+                            self.stage = InflaterStage::Trailer(Vec::with_capacity(8));
+                        }
+                        // Any data following a zlib stream is ignored.
+                        Status::StreamEnd => self.stage = InflaterStage::Done,
+                        Status::Ok | Status::BufError => {
+                            if consumed == 0 && written == 0 {
+                                // More input is required.
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+                InflaterStage::Trailer(trailer) => {
+                    // Any data following the trailer is ignored.
+                    let len = std::cmp::min(8 - trailer.len(), input.len());
+                    trailer.extend_from_slice(&input[..len]);
+                    return Ok(());
+                }
+                InflaterStage::Done => return Ok(()),
+            }
+        }
+    }
+
+    /// Check that the compressed stream is complete and valid.
+    pub fn finish(&self) -> Result<(), ActiveStorageError> {
+        let unexpected_eof =
+            |message| std::io::Error::new(std::io::ErrorKind::UnexpectedEof, message);
+        match &self.stage {
+            InflaterStage::Header(_) => Err(unexpected_eof("truncated gzip header").into()),
+            InflaterStage::Body => Err(unexpected_eof("unexpected end of compressed data").into()),
+            InflaterStage::Trailer(trailer) if trailer.len() < 8 => {
+                Err(unexpected_eof("missing gzip trailer").into())
+            }
+            InflaterStage::Trailer(trailer) => {
+                let crc = self.crc.as_ref().expect("gzip stream should have a CRC");
+                if gzip_trailer_matches(trailer, crc) {
+                    Ok(())
+                } else {
+                    Err(gzip_checksum_error().into())
+                }
+            }
+            InflaterStage::Done => Ok(()),
+        }
+    }
+}
+
+impl Drop for Inflater {
+    fn drop(&mut self) {
+        // Return the decompressor to the pool of the current thread.
+        if let Some(decompress) = self.decompress.take() {
+            DECOMPRESS.with(|cell| cell.replace(Some(decompress)));
+        }
     }
 }
 
-fn decompress_flate2_gzip(data: &Bytes) -> Result<Bytes, ActiveStorageError> {
-    let mut decoder = GzDecoder::<&[u8]>::new(data);
+/// Decompresses a deflate stream into an aligned buffer.
+///
+/// Returns the decompressed data and the number of bytes of input consumed.
+///
+/// # Arguments
+///
+/// * `decompress`: Deflate decompressor
+/// * `input`: Compressed data
+/// * `crc`: Optional CRC32 to update with the decompressed data
+/// * `max_size`: Optional maximum size in bytes of the decompressed data
+fn inflate(
+    decompress: &mut Decompress,
+    input: &[u8],
+    mut crc: Option<&mut Crc>,
+    max_size: Option<usize>,
+) -> Result<(Vec<u8>, usize), ActiveStorageError> {
     // The data returned by the S3 client does not have any alignment guarantees. In order to
     // reinterpret the data as an array of numbers with a higher alignment than 1, we need to
     // return the data in Bytes object in which the underlying data has a higher alignment.
@@ -34,17 +439,126 @@ fn decompress_flate2_gzip(data: &Bytes) -> Result<Bytes, ActiveStorageError> {
     // Create an 8-byte aligned Vec<u8>.
     // FIXME: The compressed length will not be enough to store the uncompressed data, and may
     // result in a change in the underlying buffer to one that is not correctly aligned.
-    let mut buf = maligned::align_first::<u8, maligned::A8>(data.len());
-    decoder.read_to_end(&mut buf)?;
+    let mut buf = maligned::align_first::<u8, maligned::A8>(input.len());
+    loop {
+        if buf.len() == buf.capacity() {
+            buf.reserve(std::cmp::max(buf.capacity(), 1024));
+        }
+        let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+        let remaining = &input[total_in as usize..];
+        let status = decompress
+            .decompress_vec(remaining, &mut buf, FlushDecompress::None)
+            .map_err(std::io::Error::from)?;
+        check_size(buf.len(), max_size)?;
+        if let Some(crc) = crc.as_mut() {
+            // Update the CRC while the new data is still in the CPU cache.
+            let written = (decompress.total_out() - total_out) as usize;
+            crc.update(&buf[buf.len() - written..]);
+        }
+        match status {
+            Status::StreamEnd => break,
+            Status::Ok | Status::BufError => {
+                // No progress with space in the output buffer means the input is truncated.
+                if decompress.total_in() == total_in
+                    && decompress.total_out() == total_out
+                    && buf.len() < buf.capacity()
+                {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "unexpected end of compressed data",
+                    )
+                    .into());
+                }
+            }
+        }
+    }
+    Ok((buf, decompress.total_in() as usize))
+}
+
+fn decompress_flate2_gzip(
+    data: &Bytes,
+    max_size: Option<usize>,
+) -> Result<Bytes, ActiveStorageError> {
+    let header_len = gzip_header_len(data)?;
+    let mut crc = Crc::new();
+    let (mut buf, consumed) = with_decompress(false, |decompress| {
+        inflate(decompress, &data[header_len..], Some(&mut crc), max_size)
+    })?;
+    // The trailer contains the CRC32 and length modulo 2^32 of the uncompressed data.
+    let trailer_start = header_len + consumed;
+    let trailer = data
+        .get(trailer_start..trailer_start + 8)
+        .ok_or(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "missing gzip trailer",
+        ))?;
+    if !gzip_trailer_matches(trailer, &crc) {
+        return Err(gzip_checksum_error().into());
+    }
+    // Release any unnecessary capacity.
+    buf.shrink_to(0);
+    Ok(buf.into())
+}
+
+#[cfg(feature = "zlib-ng")]
+fn decompress_flate2_zlib(
+    data: &Bytes,
+    max_size: Option<usize>,
+) -> Result<Bytes, ActiveStorageError> {
+    // The zlib trailer checksum is verified by the decompressor.
+    let (mut buf, _) =
+        with_decompress(true, |decompress| inflate(decompress, data, None, max_size))?;
+    // Release any unnecessary capacity.
+    buf.shrink_to(0);
+    Ok(buf.into())
+}
+
+/// Reads all data from a decoder into an aligned buffer.
+///
+/// # Arguments
+///
+/// * `decoder`: Decoder to read from
+/// * `size_hint`: Initial capacity of the buffer
+/// * `max_size`: Optional maximum size in bytes of the data
+fn read_aligned(
+    decoder: impl std::io::Read,
+    size_hint: usize,
+    max_size: Option<usize>,
+) -> Result<Bytes, ActiveStorageError> {
+    // See inflate for the rationale for alignment.
+    let mut buf = maligned::align_first::<u8, maligned::A8>(size_hint);
+    // Read at most one byte more than the maximum, to detect when it is exceeded.
+    let limit = max_size.map_or(u64::MAX, |max_size| max_size as u64 + 1);
+    decoder.take(limit).read_to_end(&mut buf)?;
+    check_size(buf.len(), max_size)?;
     // Release any unnecessary capacity.
     buf.shrink_to(0);
     Ok(buf.into())
 }
 
-fn decompress_zune_zlib(data: &Bytes) -> Result<Bytes, ActiveStorageError> {
-    let options = DeflateOptions::default().set_size_hint(data.len());
+fn decompress_zstd(data: &Bytes, max_size: Option<usize>) -> Result<Bytes, ActiveStorageError> {
+    let decoder = ruzstd::StreamingDecoder::new(&data[..])
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    read_aligned(decoder, data.len(), max_size)
+}
+
+fn decompress_zune_zlib(
+    data: &Bytes,
+    max_size: Option<usize>,
+) -> Result<Bytes, ActiveStorageError> {
+    // The default limit is 1 GiB.
+    let options = DeflateOptions::default()
+        .set_size_hint(data.len())
+        .set_limit(max_size.unwrap_or(usize::MAX));
     let mut decoder = DeflateDecoder::new_with_options(data, options);
-    let data = decoder.decode_zlib()?;
+    let data = decoder.decode_zlib().map_err(|err| match err.error {
+        DecodeErrorStatus::OutputLimitExceeded(limit, _) => {
+            ActiveStorageError::DecompressionLimit { limit }
+        }
+        _ => err.into(),
+    })?;
+    // The decoder checks the limit as its output buffer grows, so may exceed it slightly.
+    check_size(data.len(), max_size)?;
     Ok(data.into())
 }
 
@@ -52,8 +566,8 @@ fn decompress_zune_zlib(data: &Bytes) -> Result<Bytes, ActiveStorageError> {
 mod tests {
     use super::*;
     use flate2::read::{GzEncoder, ZlibEncoder};
-    use flate2::Compression;
-    use zune_inflate::errors::DecodeErrorStatus;
+    use flate2::{Compression, GzBuilder};
+    use std::io::Read;
 
     fn compress_gzip() -> Vec<u8> {
         // Adapated from flate2 documentation.
@@ -76,7 +590,7 @@ mod tests {
     #[test]
     fn test_decompress_gzip() {
         let compressed = compress_gzip();
-        let result = decompress(models::Compression::Gzip, &compressed.into()).unwrap();
+        let result = decompress(models::Compression::Gzip, &compressed.into(), None, None).unwrap();
         assert_eq!(result, b"hello world".as_ref());
         assert_eq!(result.as_ptr().align_offset(8), 0);
     }
@@ -84,15 +598,86 @@ mod tests {
     #[test]
     fn test_decompress_zlib() {
         let compressed = compress_zlib();
-        let result = decompress(models::Compression::Zlib, &compressed.into()).unwrap();
+        let result = decompress(models::Compression::Zlib, &compressed.into(), None, None).unwrap();
         assert_eq!(result, b"hello world".as_ref());
         assert_eq!(result.as_ptr().align_offset(8), 0);
     }
 
+    #[test]
+    fn test_decompress_blosc() {
+        let data = b"hello world";
+        let compressed = blosc::test_utils::compress(data, 1, 16, false, 1, |split| {
+            lz4_flex::block::compress(split)
+        });
+        let result =
+            decompress(models::Compression::Blosc, &compressed.into(), None, None).unwrap();
+        assert_eq!(result, data.as_ref());
+        assert_eq!(result.as_ptr().align_offset(8), 0);
+    }
+
+    #[test]
+    fn test_decompress_lz4() {
+        let data = b"hello world";
+        let mut compressed = (data.len() as u32).to_le_bytes().to_vec();
+        compressed.extend(lz4_flex::block::compress(data));
+        let result = decompress(models::Compression::Lz4, &compressed.into(), None, None).unwrap();
+        assert_eq!(result, data.as_ref());
+        assert_eq!(result.as_ptr().align_offset(8), 0);
+    }
+
+    #[test]
+    fn test_decompress_zstd() {
+        let compressed = blosc::test_utils::zstd_raw_frame(b"hello world");
+        let result = decompress(models::Compression::Zstd, &compressed.into(), None, None).unwrap();
+        assert_eq!(result, b"hello world".as_ref());
+        assert_eq!(result.as_ptr().align_offset(8), 0);
+    }
+
+    #[test]
+    fn test_decompress_zstd_limit() {
+        let compressed: Bytes = blosc::test_utils::zstd_raw_frame(b"hello world").into();
+        let err = decompress(models::Compression::Zstd, &compressed, Some(10), None).unwrap_err();
+        assert!(matches!(
+            err,
+            ActiveStorageError::DecompressionLimit { limit: 10 }
+        ));
+        decompress(models::Compression::Zstd, &compressed, Some(11), None).unwrap();
+    }
+
+    #[test]
+    fn test_decompress_zstd_invalid() {
+        let invalid = Bytes::from_static(b"invalid format");
+        let err = decompress(models::Compression::Zstd, &invalid, None, None).unwrap_err();
+        assert!(matches!(err, ActiveStorageError::DecompressionFlate2(_)));
+    }
+
+    #[test]
+    fn test_backend() {
+        let expected = if cfg!(feature = "zlib-ng") {
+            Backend::ZlibNg
+        } else {
+            Backend::Rust
+        };
+        #[cfg(feature = "isal")]
+        let expected = if isal_supported() {
+            Backend::Isal
+        } else {
+            expected
+        };
+        assert_eq!(expected, backend());
+    }
+
+    #[cfg(not(any(feature = "isal", feature = "zlib-ng")))]
     #[test]
     fn test_decompress_invalid_gzip() {
         let invalid = b"invalid format";
-        let err = decompress(models::Compression::Gzip, &invalid.as_ref().into()).unwrap_err();
+        let err = decompress(
+            models::Compression::Gzip,
+            &invalid.as_ref().into(),
+            None,
+            None,
+        )
+        .unwrap_err();
         match err {
             ActiveStorageError::DecompressionFlate2(io_err) => {
                 assert_eq!(io_err.kind(), std::io::ErrorKind::InvalidInput);
@@ -102,10 +687,17 @@ mod tests {
         }
     }
 
+    #[cfg(not(any(feature = "isal", feature = "zlib-ng")))]
     #[test]
     fn test_decompress_invalid_zlib() {
         let invalid = b"invalid format";
-        let err = decompress(models::Compression::Zlib, &invalid.as_ref().into()).unwrap_err();
+        let err = decompress(
+            models::Compression::Zlib,
+            &invalid.as_ref().into(),
+            None,
+            None,
+        )
+        .unwrap_err();
         match err {
             ActiveStorageError::DecompressionZune(zune_err) => match zune_err.error {
                 DecodeErrorStatus::GenericStr(message) => {
@@ -116,4 +708,245 @@ mod tests {
             err => panic!("unexpected error {}", err),
         }
     }
+
+    #[test]
+    fn test_decompress_gzip_header_fields() {
+        let mut result = Vec::<u8>::new();
+        let input = b"hello world";
+        GzBuilder::new()
+            .filename("foo")
+            .comment("bar")
+            .extra(vec![1, 2, 3])
+            .read(&input[..], Compression::fast())
+            .read_to_end(&mut result)
+            .unwrap();
+        let result = decompress(models::Compression::Gzip, &result.into(), None, None).unwrap();
+        assert_eq!(result, b"hello world".as_ref());
+    }
+
+    fn inflate_chunks(compression: models::Compression, data: &[u8], chunk_size: usize) -> Vec<u8> {
+        let mut inflater = Inflater::new(compression, None);
+        let mut out = Vec::new();
+        for chunk in data.chunks(chunk_size) {
+            inflater.write(chunk, &mut out).unwrap();
+        }
+        inflater.finish().unwrap();
+        out
+    }
+
+    #[test]
+    fn test_inflater() {
+        let input = (0..1 << 16).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let mut gzip = Vec::new();
+        GzBuilder::new()
+            .filename("foo")
+            .extra(vec![1, 2, 3])
+            .read(&input[..], Compression::fast())
+            .read_to_end(&mut gzip)
+            .unwrap();
+        let mut zlib = Vec::new();
+        ZlibEncoder::new(&input[..], Compression::fast())
+            .read_to_end(&mut zlib)
+            .unwrap();
+        for chunk_size in [1, 7, 4096, gzip.len()] {
+            assert_eq!(
+                input,
+                inflate_chunks(models::Compression::Gzip, &gzip, chunk_size)
+            );
+            assert_eq!(
+                input,
+                inflate_chunks(models::Compression::Zlib, &zlib, chunk_size)
+            );
+        }
+    }
+
+    #[test]
+    fn test_inflater_exact_capacity() {
+        let input = b"hello world";
+        let compressed = compress_gzip();
+        let mut inflater = Inflater::new(models::Compression::Gzip, None);
+        let mut out = maligned::align_first::<u8, maligned::A8>(input.len());
+        let ptr = out.as_ptr();
+        inflater.write(&compressed, &mut out).unwrap();
+        inflater.finish().unwrap();
+        assert_eq!(input, &out[..]);
+        // The buffer was not reallocated.
+        assert_eq!(ptr, out.as_ptr());
+    }
+
+    #[test]
+    fn test_inflater_truncated() {
+        let compressed = compress_gzip();
+        for len in [5, compressed.len() - 10, compressed.len() - 4] {
+            let mut inflater = Inflater::new(models::Compression::Gzip, None);
+            inflater.write(&compressed[..len], &mut Vec::new()).unwrap();
+            inflater.finish().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_inflater_bad_checksum() {
+        let mut compressed = compress_gzip();
+        let len = compressed.len();
+        compressed[len - 8] ^= 1;
+        let mut inflater = Inflater::new(models::Compression::Gzip, None);
+        inflater.write(&compressed, &mut Vec::new()).unwrap();
+        match inflater.finish().unwrap_err() {
+            ActiveStorageError::DecompressionFlate2(io_err) => assert_eq!(
+                "corrupt gzip stream does not have a matching checksum",
+                io_err.to_string()
+            ),
+            err => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_inflater_invalid_header() {
+        let mut inflater = Inflater::new(models::Compression::Gzip, None);
+        match inflater.write(b"invalid", &mut Vec::new()).unwrap_err() {
+            ActiveStorageError::DecompressionFlate2(io_err) => {
+                assert_eq!("invalid gzip header", io_err.to_string())
+            }
+            err => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_decompress_gzip_large() {
+        let input = (0..1 << 20).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let mut compressed = Vec::<u8>::new();
+        GzEncoder::new(&input[..], Compression::fast())
+            .read_to_end(&mut compressed)
+            .unwrap();
+        let result = decompress(models::Compression::Gzip, &compressed.into(), None, None).unwrap();
+        assert_eq!(result, input);
+    }
+
+    #[test]
+    fn test_decompress_gzip_reuse() {
+        // Decompressors are reused, including after an error.
+        let compressed: Bytes = compress_gzip().into();
+        let truncated = compressed.slice(..compressed.len() - 12);
+        decompress(models::Compression::Gzip, &truncated, None, None).unwrap_err();
+        for _ in 0..2 {
+            let result = decompress(models::Compression::Gzip, &compressed, None, None).unwrap();
+            assert_eq!(result, b"hello world".as_ref());
+        }
+    }
+
+    #[test]
+    fn test_decompress_gzip_truncated() {
+        let compressed = compress_gzip();
+        for len in [10, compressed.len() - 4] {
+            let truncated = compressed[..len].to_vec();
+            let err =
+                decompress(models::Compression::Gzip, &truncated.into(), None, None).unwrap_err();
+            assert_eq!("failed to decompress data", err.to_string());
+        }
+    }
+
+    #[test]
+    fn test_decompress_gzip_bad_checksum() {
+        let mut compressed = compress_gzip();
+        let len = compressed.len();
+        compressed[len - 8] ^= 0xff;
+        let err =
+            decompress(models::Compression::Gzip, &compressed.into(), None, None).unwrap_err();
+        match err {
+            ActiveStorageError::DecompressionFlate2(io_err) => assert_eq!(
+                io_err.to_string(),
+                "corrupt gzip stream does not have a matching checksum"
+            ),
+            err => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_gzip_header_len() {
+        let compressed = compress_gzip();
+        assert_eq!(10, gzip_header_len(&compressed).unwrap());
+        // Truncated file name.
+        let header = [0x1f, 0x8b, 8, GZIP_FNAME, 0, 0, 0, 0, 0, 0, b'f'];
+        gzip_header_len(&header).unwrap_err();
+        // Reserved flag.
+        let header = [0x1f, 0x8b, 8, 0x20, 0, 0, 0, 0, 0, 0];
+        gzip_header_len(&header).unwrap_err();
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let input = vec![0_u8; 1 << 16];
+        let mut gzip = Vec::new();
+        GzEncoder::new(&input[..], Compression::fast())
+            .read_to_end(&mut gzip)
+            .unwrap();
+        let mut zlib = Vec::new();
+        ZlibEncoder::new(&input[..], Compression::fast())
+            .read_to_end(&mut zlib)
+            .unwrap();
+        for (compression, data) in [
+            (models::Compression::Gzip, gzip),
+            (models::Compression::Zlib, zlib),
+        ] {
+            let data = Bytes::from(data);
+            let result = decompress(compression, &data, Some(input.len()), None).unwrap();
+            assert_eq!(input, result);
+            let err = decompress(compression, &data, Some(1000), None).unwrap_err();
+            assert!(matches!(
+                err,
+                ActiveStorageError::DecompressionLimit { limit: 1000 }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_inflater_limit() {
+        let input = vec![0_u8; 1 << 16];
+        let mut compressed = Vec::new();
+        GzEncoder::new(&input[..], Compression::fast())
+            .read_to_end(&mut compressed)
+            .unwrap();
+        let mut inflater = Inflater::new(models::Compression::Gzip, Some(1000));
+        let mut out = Vec::new();
+        let err = compressed
+            .chunks(64)
+            .try_for_each(|chunk| inflater.write(chunk, &mut out))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ActiveStorageError::DecompressionLimit { limit: 1000 }
+        ));
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        let invalid = b"invalid format";
+        for compression in [models::Compression::Gzip, models::Compression::Zlib] {
+            let err = decompress(compression, &invalid.as_ref().into(), None, None).unwrap_err();
+            assert_eq!("failed to decompress data", err.to_string());
+        }
+    }
+
+    /// Returns zlib-compressed zeros, without holding the uncompressed data in memory.
+    fn compress_zlib_zeros(size: usize) -> Bytes {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::fast());
+        let zeros = vec![0_u8; 1 << 20];
+        for _ in 0..size >> 20 {
+            std::io::Write::write_all(&mut encoder, &zeros).unwrap();
+        }
+        encoder.finish().unwrap().into()
+    }
+
+    // Run with `cargo test --release -- --ignored`.
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    #[ignore = "decompresses more than 4 GiB of data"]
+    fn test_decompress_large() {
+        // Data beyond u32::MAX bytes is decompressed without truncation.
+        let size = (4 << 30) + (1 << 20);
+        let compressed = compress_zlib_zeros(size);
+        let data = decompress(models::Compression::Zlib, &compressed, None, None).unwrap();
+        assert_eq!(size, data.len());
+        assert!(data.iter().all(|byte| *byte == 0));
+    }
 }
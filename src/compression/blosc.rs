@@ -0,0 +1,633 @@
+//! Blosc decompression
+//!
+//! [Blosc](https://www.blosc.org/) is a meta-compressor, which splits data into blocks, applies a
+//! shuffle to each block and compresses it using one of several codecs. It is commonly used to
+//! compress Zarr chunks. This module decodes data in the Blosc 1 format, as written by c-blosc
+//! 1.x and numcodecs, with the byte shuffle, the bit shuffle and the BloscLZ, LZ4, LZ4HC, zlib
+//! and Zstandard codecs. The Snappy codec is not supported.
+
+use super::check_size;
+use crate::error::ActiveStorageError;
+use crate::filters::bitshuffle;
+
+use axum::body::Bytes;
+use std::io::Read;
+use thiserror::Error;
+
+/// Size of the Blosc header in bytes.
+const HEADER_SIZE: usize = 16;
+/// Header flag indicating that each block is byte shuffled.
+const FLAG_SHUFFLE: u8 = 0x01;
+/// Header flag indicating that the data is stored uncompressed.
+const FLAG_MEMCPYED: u8 = 0x02;
+/// Header flag indicating that each block is bit shuffled.
+const FLAG_BITSHUFFLE: u8 = 0x04;
+/// Header flag indicating that blocks are not split into streams for each byte of an element.
+const FLAG_DONT_SPLIT: u8 = 0x10;
+/// Maximum element size for which blocks are split.
+const MAX_SPLITS: usize = 16;
+/// Minimum number of elements in a block for which blocks are split.
+const MIN_BUFFERSIZE: usize = 128;
+/// Maximum distance of a short BloscLZ match.
+const BLOSCLZ_MAX_DISTANCE: usize = 8191;
+
+/// Error decoding Blosc data.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct BloscError(String);
+
+impl From<&str> for BloscError {
+    fn from(reason: &str) -> Self {
+        Self(reason.to_string())
+    }
+}
+
+/// Codec used to compress the blocks of Blosc data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Codec {
+    BloscLz,
+    Lz4,
+    Zlib,
+    Zstd,
+}
+
+impl Codec {
+    /// Returns the codec for the compressor format stored in the header flags.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags`: Header flags
+    fn from_flags(flags: u8) -> Result<Self, BloscError> {
+        match flags >> 5 {
+            0 => Ok(Self::BloscLz),
+            // LZ4HC produces LZ4 streams.
+            1 => Ok(Self::Lz4),
+            2 => Err("blosc snappy codec is not supported".into()),
+            3 => Ok(Self::Zlib),
+            4 => Ok(Self::Zstd),
+            format => Err(BloscError(format!("unknown blosc codec {}", format))),
+        }
+    }
+
+    /// Decompresses a stream into a buffer, which the decompressed data must fill exactly.
+    ///
+    /// # Arguments
+    ///
+    /// * `input`: Compressed stream
+    /// * `out`: Buffer for decompressed data
+    fn decompress(self, input: &[u8], out: &mut [u8]) -> Result<(), BloscError> {
+        let size = match self {
+            Self::BloscLz => blosclz_decompress(input, out),
+            Self::Lz4 => lz4_flex::block::decompress_into(input, out).ok(),
+            Self::Zlib => read_exact(flate2::read::ZlibDecoder::new(input), out),
+            Self::Zstd => ruzstd::StreamingDecoder::new(input)
+                .ok()
+                .and_then(|decoder| read_exact(decoder, out)),
+        };
+        match size {
+            Some(size) if size == out.len() => Ok(()),
+            _ => Err(BloscError(format!("corrupt blosc {:?} stream", self))),
+        }
+    }
+}
+
+/// Fills a buffer from a reader, returning the size of the buffer, or None if there is too little
+/// data or too much.
+///
+/// # Arguments
+///
+/// * `reader`: Reader to read from
+/// * `out`: Buffer for the data
+fn read_exact(mut reader: impl Read, out: &mut [u8]) -> Option<usize> {
+    reader.read_exact(out).ok()?;
+    let mut extra = [0; 1];
+    (reader.read(&mut extra).ok()? == 0).then_some(out.len())
+}
+
+/// Returns a little-endian 32-bit integer at an offset of some data.
+///
+/// # Arguments
+///
+/// * `data`: Data to read from
+/// * `offset`: Offset of the integer in bytes
+fn read_u32(data: &[u8], offset: usize) -> Result<usize, BloscError> {
+    let bytes = data.get(offset..offset + 4).ok_or("truncated blosc data")?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+/// Decompresses Blosc data and returns the uncompressed data in an 8-byte aligned buffer.
+///
+/// # Arguments
+///
+/// * `data`: Compressed data
+/// * `max_size`: Optional maximum size in bytes of the uncompressed data
+/// * `expected_size`: Optional size in bytes of the uncompressed data, if known
+pub fn decompress(
+    data: &[u8],
+    max_size: Option<usize>,
+    expected_size: Option<usize>,
+) -> Result<Bytes, ActiveStorageError> {
+    if data.len() < HEADER_SIZE {
+        return Err(BloscError::from("truncated blosc header").into());
+    }
+    let version = data[0];
+    if !(1..=2).contains(&version) {
+        return Err(BloscError(format!("unsupported blosc format version {}", version)).into());
+    }
+    let flags = data[2];
+    let typesize = data[3] as usize;
+    let nbytes = read_u32(data, 4)?;
+    let blocksize = read_u32(data, 8)?;
+    let cbytes = read_u32(data, 12)?;
+    if cbytes < HEADER_SIZE {
+        return Err(BloscError::from("invalid blosc header").into());
+    }
+    // The header is checked before the uncompressed size is allocated.
+    if let Some(expected_size) = expected_size.filter(|size| *size != nbytes) {
+        return Err(BloscError(format!(
+            "blosc data has {} uncompressed bytes, expected {}",
+            nbytes, expected_size
+        ))
+        .into());
+    }
+    check_size(nbytes, max_size)?;
+    let data = data
+        .get(..cbytes)
+        .ok_or(BloscError::from("truncated blosc data"))?;
+
+    let mut out = maligned::align_first::<u8, maligned::A8>(nbytes);
+    out.resize(nbytes, 0);
+    if flags & FLAG_MEMCPYED != 0 {
+        let src = data
+            .get(HEADER_SIZE..HEADER_SIZE + nbytes)
+            .ok_or(BloscError::from("truncated blosc data"))?;
+        out.copy_from_slice(src);
+        return Ok(out.into());
+    }
+    if typesize == 0 || (nbytes > 0 && blocksize == 0) {
+        return Err(BloscError::from("invalid blosc header").into());
+    }
+    let codec = Codec::from_flags(flags)?;
+    let shuffle = flags & FLAG_SHUFFLE != 0 && typesize > 1;
+    // The byte shuffle takes precedence if both shuffles are flagged.
+    let bitshuffle = !shuffle && flags & FLAG_BITSHUFFLE != 0 && blocksize >= typesize;
+    let split = flags & FLAG_DONT_SPLIT == 0
+        && typesize <= MAX_SPLITS
+        && blocksize / typesize >= MIN_BUFFERSIZE;
+    // Shuffled blocks are decompressed into a temporary buffer, then unshuffled into place.
+    let mut tmp = if shuffle || bitshuffle {
+        vec![0; blocksize]
+    } else {
+        vec![]
+    };
+    for (index, dest) in out.chunks_mut(blocksize.max(1)).enumerate() {
+        let start = read_u32(data, HEADER_SIZE + index * 4)?;
+        // Blocks other than the last, leftover, block are split into a stream for each byte of
+        // an element.
+        let nsplits = if split && dest.len() == blocksize {
+            typesize
+        } else {
+            1
+        };
+        let block = if shuffle || bitshuffle {
+            &mut tmp[..dest.len()]
+        } else {
+            &mut *dest
+        };
+        decompress_block(data, start, block, nsplits, codec)?;
+        if shuffle {
+            unshuffle(&tmp[..dest.len()], dest, typesize);
+        } else if bitshuffle {
+            unbitshuffle(&tmp[..dest.len()], dest, typesize);
+        }
+    }
+    Ok(out.into())
+}
+
+/// Decompresses a block of Blosc data.
+///
+/// # Arguments
+///
+/// * `data`: Compressed data, including the header
+/// * `start`: Offset of the block in the data
+/// * `block`: Buffer for the decompressed block
+/// * `nsplits`: Number of streams the block is split into
+/// * `codec`: Codec used to compress each stream
+fn decompress_block(
+    data: &[u8],
+    mut start: usize,
+    block: &mut [u8],
+    nsplits: usize,
+    codec: Codec,
+) -> Result<(), BloscError> {
+    let split_size = block.len() / nsplits;
+    for dest in block.chunks_mut(split_size.max(1)) {
+        let size = read_u32(data, start)?;
+        start += 4;
+        let src = data
+            .get(start..start + size)
+            .ok_or("truncated blosc data")?;
+        // Streams that do not compress are stored as is.
+        if size == dest.len() {
+            dest.copy_from_slice(src);
+        } else {
+            codec.decompress(src, dest)?;
+        }
+        start += size;
+    }
+    Ok(())
+}
+
+/// Reverses the byte shuffle of a block.
+///
+/// Any trailing bytes that do not form a whole element are not shuffled.
+///
+/// # Arguments
+///
+/// * `src`: Shuffled block
+/// * `dest`: Buffer for the unshuffled block
+/// * `typesize`: Size of each element in bytes
+fn unshuffle(src: &[u8], dest: &mut [u8], typesize: usize) {
+    let num_elements = src.len() / typesize;
+    let shuffled = num_elements * typesize;
+    for (index, element) in dest[..shuffled].chunks_exact_mut(typesize).enumerate() {
+        for (byte, dest) in element.iter_mut().enumerate() {
+            *dest = src[byte * num_elements + index];
+        }
+    }
+    dest[shuffled..].copy_from_slice(&src[shuffled..]);
+}
+
+/// Reverses the bit shuffle of a block.
+///
+/// As in c-blosc 1.x, the block is only shuffled if it contains a multiple of 8 elements, and any
+/// trailing bytes that do not form a whole element are not shuffled.
+///
+/// # Arguments
+///
+/// * `src`: Shuffled block
+/// * `dest`: Buffer for the unshuffled block
+/// * `typesize`: Size of each element in bytes
+fn unbitshuffle(src: &[u8], dest: &mut [u8], typesize: usize) {
+    let num_elements = src.len() / typesize;
+    let shuffled = if num_elements % 8 == 0 {
+        num_elements * typesize
+    } else {
+        0
+    };
+    bitshuffle::untranspose(&src[..shuffled], &mut dest[..shuffled], typesize);
+    dest[shuffled..].copy_from_slice(&src[shuffled..]);
+}
+
+/// Decompresses a BloscLZ stream, returning the size of the decompressed data, or None if the
+/// stream is corrupt or does not fit in the buffer.
+///
+/// BloscLZ is derived from FastLZ. A stream is a sequence of literal runs and back references,
+/// each introduced by a control byte.
+///
+/// # Arguments
+///
+/// * `input`: Compressed stream
+/// * `out`: Buffer for decompressed data
+fn blosclz_decompress(input: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut ip = 0;
+    let mut op = 0;
+    // Returns the next byte of the input.
+    let next = |ip: &mut usize| {
+        let byte = input.get(*ip).copied();
+        *ip += 1;
+        byte.map(usize::from)
+    };
+    let mut ctrl = next(&mut ip)? & 31;
+    loop {
+        if ctrl >= 32 {
+            // Back reference.
+            let mut len = (ctrl >> 5) - 1;
+            let mut offset = (ctrl & 31) << 8;
+            if len == 6 {
+                loop {
+                    let code = next(&mut ip)?;
+                    len += code;
+                    if code != 255 {
+                        break;
+                    }
+                }
+            }
+            let code = next(&mut ip)?;
+            len += 3;
+            let mut distance = offset + code + 1;
+            if code == 255 && offset == 31 << 8 {
+                // Long distance match.
+                offset = next(&mut ip)? << 8;
+                offset += next(&mut ip)?;
+                distance = offset + BLOSCLZ_MAX_DISTANCE + 1;
+            }
+            if op + len > out.len() || distance > op {
+                return None;
+            }
+            // The source and destination may overlap, so copy byte by byte.
+            for i in op..op + len {
+                out[i] = out[i - distance];
+            }
+            op += len;
+        } else {
+            // Literal run.
+            let len = ctrl + 1;
+            let src = input.get(ip..ip + len)?;
+            out.get_mut(op..op + len)?.copy_from_slice(src);
+            ip += len;
+            op += len;
+        }
+        match next(&mut ip) {
+            Some(byte) => ctrl = byte,
+            None => return Some(op),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::*;
+
+    /// Returns a Zstandard frame storing some data in a raw block.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: Data to store, at most 255 bytes
+    pub fn zstd_raw_frame(data: &[u8]) -> Vec<u8> {
+        assert!(data.len() < 256);
+        let mut frame = vec![0x28, 0xb5, 0x2f, 0xfd];
+        // Single segment with a 1-byte content size.
+        frame.extend([0x20, data.len() as u8]);
+        // Last raw block.
+        let block_header = 1 | (data.len() as u32) << 3;
+        frame.extend(&block_header.to_le_bytes()[..3]);
+        frame.extend(data);
+        frame
+    }
+
+    /// Returns Blosc data compressed with the same choice of splits as c-blosc.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: Data to compress
+    /// * `typesize`: Size of each element in bytes
+    /// * `blocksize`: Size of each block in bytes
+    /// * `shuffle`: Whether to byte shuffle each block
+    /// * `codec`: Compressor format in the header flags
+    /// * `compress`: Function compressing a stream
+    pub fn compress(
+        data: &[u8],
+        typesize: usize,
+        blocksize: usize,
+        shuffle: bool,
+        codec: u8,
+        compress: impl Fn(&[u8]) -> Vec<u8>,
+    ) -> Vec<u8> {
+        let flags = (codec << 5) | if shuffle { FLAG_SHUFFLE } else { 0 };
+        let mut result = vec![2, 1, flags, typesize as u8];
+        result.extend((data.len() as u32).to_le_bytes());
+        result.extend((blocksize as u32).to_le_bytes());
+        result.extend([0; 4]);
+        let nblocks = data.len().div_ceil(blocksize);
+        result.extend(vec![0; nblocks * 4]);
+        for (index, block) in data.chunks(blocksize).enumerate() {
+            let start = result.len() as u32;
+            result[HEADER_SIZE + index * 4..][..4].copy_from_slice(&start.to_le_bytes());
+            let block = if shuffle {
+                shuffle_block(block, typesize)
+            } else {
+                block.to_vec()
+            };
+            let nsplits = if block.len() == blocksize
+                && typesize <= MAX_SPLITS
+                && blocksize / typesize >= MIN_BUFFERSIZE
+            {
+                typesize
+            } else {
+                1
+            };
+            for split in block.chunks(block.len() / nsplits) {
+                let compressed = compress(split);
+                result.extend((compressed.len() as u32).to_le_bytes());
+                result.extend(compressed);
+            }
+        }
+        let cbytes = result.len() as u32;
+        result[12..16].copy_from_slice(&cbytes.to_le_bytes());
+        result
+    }
+
+    /// Byte shuffles a block, leaving any trailing bytes in place.
+    fn shuffle_block(block: &[u8], typesize: usize) -> Vec<u8> {
+        let num_elements = block.len() / typesize;
+        let mut result = block.to_vec();
+        for index in 0..num_elements {
+            for byte in 0..typesize {
+                result[byte * num_elements + index] = block[index * typesize + byte];
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_utils::*;
+    use super::*;
+
+    use flate2::read::ZlibEncoder;
+    use std::error::Error;
+
+    /// Returns some test data with repeated values.
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len as u32)
+            .flat_map(|i| (i / 8).to_le_bytes())
+            .take(len)
+            .collect()
+    }
+
+    fn lz4(data: &[u8]) -> Vec<u8> {
+        lz4_flex::block::compress(data)
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut result = vec![];
+        ZlibEncoder::new(data, flate2::Compression::fast())
+            .read_to_end(&mut result)
+            .unwrap();
+        result
+    }
+
+    #[test]
+    fn test_decompress_lz4() {
+        let data = test_data(4096);
+        for shuffle in [false, true] {
+            // Split blocks, and a leftover block.
+            let compressed = compress(&data, 4, 1000, shuffle, 1, lz4);
+            let result = decompress(&compressed, None, None).unwrap();
+            assert_eq!(data, result);
+            assert_eq!(0, result.as_ptr().align_offset(8));
+        }
+    }
+
+    #[test]
+    fn test_decompress_zlib() {
+        let data = test_data(1024);
+        let compressed = compress(&data, 8, 512, true, 3, zlib);
+        assert_eq!(data, decompress(&compressed, None, None).unwrap());
+    }
+
+    #[test]
+    fn test_decompress_zstd() {
+        let data = test_data(30);
+        let compressed = compress(&data, 2, 16, true, 4, zstd_raw_frame);
+        assert_eq!(data, decompress(&compressed, None, None).unwrap());
+    }
+
+    #[test]
+    fn test_decompress_blosclz() {
+        // A literal run of "abc", then a back reference of length 6 and distance 3.
+        let stream = [0x02, b'a', b'b', b'c', 0x80, 0x02];
+        let compressed = compress(b"abcabcabc", 1, 16, false, 0, |_| stream.to_vec());
+        assert_eq!(
+            b"abcabcabc".as_ref(),
+            decompress(&compressed, None, None).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decompress_incompressible() {
+        // Streams that do not compress are stored as is.
+        let data = test_data(100);
+        let compressed = compress(&data, 4, 64, true, 1, |split| split.to_vec());
+        assert_eq!(data, decompress(&compressed, None, None).unwrap());
+    }
+
+    #[test]
+    fn test_decompress_memcpyed() {
+        let mut compressed = vec![2, 1, FLAG_MEMCPYED, 1, 3, 0, 0, 0, 3, 0, 0, 0, 19, 0, 0, 0];
+        compressed.extend(b"abc");
+        assert_eq!(
+            b"abc".as_ref(),
+            decompress(&compressed, None, None).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let data = test_data(1024);
+        let compressed = compress(&data, 4, 256, true, 1, lz4);
+        let err = decompress(&compressed, Some(1023), None).unwrap_err();
+        assert!(matches!(
+            err,
+            ActiveStorageError::DecompressionLimit { limit: 1023 }
+        ));
+        decompress(&compressed, Some(1024), None).unwrap();
+    }
+
+    #[test]
+    fn test_decompress_expected_size() {
+        let data = test_data(1024);
+        let compressed = compress(&data, 4, 256, true, 1, lz4);
+        assert_eq!(data, decompress(&compressed, None, Some(1024)).unwrap());
+        // A header claiming 4 GiB is rejected before the memory is allocated.
+        let mut compressed = vec![
+            2,
+            1,
+            FLAG_MEMCPYED,
+            1,
+            255,
+            255,
+            255,
+            255,
+            0,
+            0,
+            1,
+            0,
+            16,
+            0,
+            0,
+            0,
+        ];
+        let err = decompress(&compressed, None, Some(1024)).unwrap_err();
+        assert_eq!(
+            "blosc data has 4294967295 uncompressed bytes, expected 1024",
+            err.source().unwrap().to_string()
+        );
+        // The compressed size includes the header.
+        compressed[12] = 15;
+        let err = decompress(&compressed, None, None).unwrap_err();
+        assert_eq!("invalid blosc header", err.source().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_decompress_truncated() {
+        let data = test_data(1024);
+        let compressed = compress(&data, 4, 256, true, 1, lz4);
+        let err = decompress(&compressed[..compressed.len() - 1], None, None).unwrap_err();
+        assert_eq!("truncated blosc data", err.source().unwrap().to_string());
+        let err = decompress(&compressed[..10], None, None).unwrap_err();
+        assert_eq!("truncated blosc header", err.source().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_decompress_corrupt() {
+        let data = test_data(1024);
+        let compressed = compress(&data, 4, 1024, false, 1, |split| {
+            let mut compressed = lz4(split);
+            compressed.truncate(compressed.len() - 2);
+            compressed
+        });
+        let err = decompress(&compressed, None, None).unwrap_err();
+        assert_eq!(
+            "corrupt blosc Lz4 stream",
+            err.source().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_decompress_bitshuffle() {
+        let data = test_data(1024);
+        // A block of 256 elements, split into a stream for each byte of an element.
+        let shuffled = bitshuffle::test_utils::transpose(&data, 4);
+        let mut compressed = compress(&shuffled, 4, 1024, false, 1, lz4);
+        compressed[2] |= FLAG_BITSHUFFLE;
+        assert_eq!(data, decompress(&compressed, None, None).unwrap());
+        // A block of 9 elements is not shuffled.
+        let data = test_data(36);
+        let mut compressed = compress(&data, 4, 36, false, 1, lz4);
+        compressed[2] |= FLAG_BITSHUFFLE;
+        assert_eq!(data, decompress(&compressed, None, None).unwrap());
+    }
+
+    #[test]
+    fn test_decompress_unsupported() {
+        let data = test_data(64);
+        let compressed = compress(&data, 4, 64, false, 2, |split| split.to_vec());
+        let err = decompress(&compressed, None, None).unwrap_err();
+        assert_eq!(
+            "blosc snappy codec is not supported",
+            err.source().unwrap().to_string()
+        );
+        let mut compressed = compress(&data, 4, 64, false, 1, lz4);
+        compressed[0] = 3;
+        decompress(&compressed, None, None).unwrap_err();
+    }
+
+    #[test]
+    fn test_blosclz_long_run() {
+        // A literal "a", then a back reference of length 300 and distance 1.
+        let stream = [0x00, b'a', 0xe0, 255, 36, 0x00];
+        let mut out = [0; 301];
+        assert_eq!(Some(301), blosclz_decompress(&stream, &mut out));
+        assert!(out.iter().all(|byte| *byte == b'a'));
+        // The back reference exceeds the buffer.
+        assert_eq!(None, blosclz_decompress(&stream, &mut [0; 300]));
+        // The back reference precedes the start of the data.
+        assert_eq!(
+            None,
+            blosclz_decompress(&[0x00, b'a', 0x20, 0x01], &mut [0; 8])
+        );
+    }
+}
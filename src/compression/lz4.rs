@@ -0,0 +1,235 @@
+//! LZ4 decompression
+//!
+//! [LZ4](https://lz4.org/) data is accepted in two formats, distinguished by the magic number at
+//! the start of the LZ4 frame format:
+//!
+//! * The LZ4 frame format, as written by the `lz4` command line tool and the `lz4.frame` Python
+//!   module.
+//! * A raw LZ4 block prefixed with the size of the uncompressed data as a little-endian 32-bit
+//!   integer, as written by the numcodecs `LZ4` codec used by Zarr.
+//!
+//! LZ4HC produces data in the same formats, so is decoded in the same way. Decompression uses the
+//! pure Rust [lz4_flex] library.
+
+use super::check_size;
+use crate::error::ActiveStorageError;
+
+use axum::body::Bytes;
+use std::io::Read;
+use thiserror::Error;
+
+/// Magic number at the start of an LZ4 frame.
+const FRAME_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+/// Size of the uncompressed size prefix of a numcodecs LZ4 block in bytes.
+const PREFIX_SIZE: usize = 4;
+
+/// Error decoding LZ4 data.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct Lz4Error(String);
+
+impl From<&str> for Lz4Error {
+    fn from(reason: &str) -> Self {
+        Self(reason.to_string())
+    }
+}
+
+/// Decompresses LZ4 data and returns the uncompressed data in an 8-byte aligned buffer.
+///
+/// # Arguments
+///
+/// * `data`: Compressed data
+/// * `max_size`: Optional maximum size in bytes of the uncompressed data
+/// * `expected_size`: Optional size in bytes of the uncompressed data, if known
+pub fn decompress(
+    data: &[u8],
+    max_size: Option<usize>,
+    expected_size: Option<usize>,
+) -> Result<Bytes, ActiveStorageError> {
+    if data.starts_with(&FRAME_MAGIC) {
+        decompress_frame(data, max_size)
+    } else {
+        decompress_block(data, max_size, expected_size)
+    }
+}
+
+/// Decompresses data in the LZ4 frame format.
+///
+/// # Arguments
+///
+/// * `data`: Compressed data
+/// * `max_size`: Optional maximum size in bytes of the uncompressed data
+fn decompress_frame(data: &[u8], max_size: Option<usize>) -> Result<Bytes, ActiveStorageError> {
+    // See inflate for the rationale for alignment.
+    let mut out = maligned::align_first::<u8, maligned::A8>(data.len());
+    // Read at most one byte more than the maximum, to detect when it is exceeded.
+    let limit = max_size.map_or(u64::MAX, |max_size| max_size as u64 + 1);
+    lz4_flex::frame::FrameDecoder::new(data)
+        .take(limit)
+        .read_to_end(&mut out)
+        .map_err(|err| Lz4Error(format!("corrupt lz4 frame: {}", err)))?;
+    check_size(out.len(), max_size)?;
+    // Release any unnecessary capacity.
+    out.shrink_to(0);
+    Ok(out.into())
+}
+
+/// Decompresses a raw LZ4 block prefixed with the size of the uncompressed data.
+///
+/// The size prefix is checked against the expected and maximum sizes before any memory is
+/// allocated for the uncompressed data.
+///
+/// # Arguments
+///
+/// * `data`: Compressed data, including the size prefix
+/// * `max_size`: Optional maximum size in bytes of the uncompressed data
+/// * `expected_size`: Optional size in bytes of the uncompressed data, if known
+fn decompress_block(
+    data: &[u8],
+    max_size: Option<usize>,
+    expected_size: Option<usize>,
+) -> Result<Bytes, ActiveStorageError> {
+    let prefix = data
+        .get(..PREFIX_SIZE)
+        .ok_or(Lz4Error::from("truncated lz4 size prefix"))?;
+    let size = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+    if let Some(expected_size) = expected_size.filter(|expected_size| *expected_size != size) {
+        return Err(Lz4Error(format!(
+            "lz4 size prefix of {} bytes does not match the expected {} bytes",
+            size, expected_size
+        ))
+        .into());
+    }
+    check_size(size, max_size)?;
+    let mut out = maligned::align_first::<u8, maligned::A8>(size);
+    out.resize(size, 0);
+    let len = lz4_flex::block::decompress_into(&data[PREFIX_SIZE..], &mut out)
+        .map_err(|err| Lz4Error(format!("corrupt lz4 block: {}", err)))?;
+    if len != size {
+        return Err(Lz4Error(format!(
+            "lz4 block decompressed to {} bytes, expected {} from its size prefix",
+            len, size
+        ))
+        .into());
+    }
+    Ok(out.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error;
+    use std::io::Write;
+
+    /// Returns some test data with repeated values.
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len as u32)
+            .flat_map(|i| (i / 8).to_le_bytes())
+            .take(len)
+            .collect()
+    }
+
+    fn block(data: &[u8]) -> Vec<u8> {
+        let mut result = (data.len() as u32).to_le_bytes().to_vec();
+        result.extend(lz4_flex::block::compress(data));
+        result
+    }
+
+    fn frame(data: &[u8]) -> Vec<u8> {
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(vec![]);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress_block() {
+        let data = test_data(1000);
+        let result = decompress(&block(&data), None, None).unwrap();
+        assert_eq!(data, result);
+        assert_eq!(result.as_ptr().align_offset(8), 0);
+    }
+
+    #[test]
+    fn test_decompress_frame() {
+        let data = test_data(1000);
+        let result = decompress(&frame(&data), None, None).unwrap();
+        assert_eq!(data, result);
+        assert_eq!(result.as_ptr().align_offset(8), 0);
+    }
+
+    #[test]
+    fn test_decompress_empty() {
+        assert!(decompress(&block(&[]), None, None).unwrap().is_empty());
+        assert!(decompress(&frame(&[]), None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let data = test_data(1000);
+        for compressed in [block(&data), frame(&data)] {
+            let err = decompress(&compressed, Some(999), None).unwrap_err();
+            assert!(matches!(
+                err,
+                ActiveStorageError::DecompressionLimit { limit: 999 }
+            ));
+            decompress(&compressed, Some(1000), None).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_decompress_truncated() {
+        let err = decompress(&[1, 0], None, None).unwrap_err();
+        assert_eq!(
+            "truncated lz4 size prefix",
+            err.source().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_decompress_size_mismatch() {
+        let data = test_data(1000);
+        let mut compressed = block(&data);
+        compressed[..PREFIX_SIZE].copy_from_slice(&1001_u32.to_le_bytes());
+        let err = decompress(&compressed, None, None).unwrap_err();
+        assert_eq!(
+            "lz4 block decompressed to 1000 bytes, expected 1001 from its size prefix",
+            err.source().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_decompress_expected_size() {
+        let data = test_data(1000);
+        let mut compressed = block(&data);
+        assert_eq!(data, decompress(&compressed, None, Some(1000)).unwrap());
+        // A prefix claiming 4 GiB is rejected before the memory is allocated.
+        compressed[..PREFIX_SIZE].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = decompress(&compressed, None, Some(1000)).unwrap_err();
+        assert_eq!(
+            "lz4 size prefix of 4294967295 bytes does not match the expected 1000 bytes",
+            err.source().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_decompress_corrupt() {
+        let data = test_data(1000);
+        let mut compressed = block(&data);
+        compressed.truncate(compressed.len() / 2);
+        let err = decompress(&compressed, None, None).unwrap_err();
+        assert!(err
+            .source()
+            .unwrap()
+            .to_string()
+            .starts_with("corrupt lz4 block"));
+        let mut compressed = frame(&data);
+        compressed.truncate(compressed.len() / 2);
+        let err = decompress(&compressed, None, None).unwrap_err();
+        assert!(err
+            .source()
+            .unwrap()
+            .to_string()
+            .starts_with("corrupt lz4 frame"));
+    }
+}
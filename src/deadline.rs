@@ -0,0 +1,119 @@
+//! Compute time budget for operations
+//!
+//! The synchronous part of an operation may run on a Rayon or Tokio worker thread, where it cannot
+//! be cancelled from outside. Instead, a deadline is set for the current thread, and long-running
+//! loops periodically check it using [checkpoints], returning
+//! [ActiveStorageError::ComputeTimeout] once the deadline has passed.
+
+use crate::error::ActiveStorageError;
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Number of iterations between deadline checks in [checkpoints].
+const CHECKPOINT_INTERVAL: usize = 1 << 16;
+
+thread_local! {
+    /// Deadline for the operation running on the current thread.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Restores the previous deadline when dropped.
+struct DeadlineGuard {
+    previous: Option<Instant>,
+}
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        DEADLINE.with(|deadline| deadline.set(self.previous));
+    }
+}
+
+/// Run a function with a compute time budget on the current thread.
+///
+/// # Arguments
+///
+/// * `budget`: Optional compute time budget. If `None`, there is no deadline.
+/// * `f`: Function to run
+pub fn run<R>(budget: Option<Duration>, f: impl FnOnce() -> R) -> R {
+    let deadline = budget.map(|budget| Instant::now() + budget);
+    let _guard = DeadlineGuard {
+        previous: DEADLINE.with(|cell| cell.replace(deadline)),
+    };
+    f()
+}
+
+/// Returns whether the deadline for the current thread has passed.
+fn expired() -> bool {
+    DEADLINE.with(|deadline| {
+        deadline
+            .get()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    })
+}
+
+/// Check the deadline for the current thread.
+///
+/// Returns [ActiveStorageError::ComputeTimeout] if the deadline has passed.
+pub fn check() -> Result<(), ActiveStorageError> {
+    if expired() {
+        Err(ActiveStorageError::ComputeTimeout)
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns an iterator that checks the deadline for the current thread periodically, and stops
+/// early if it has passed.
+///
+/// Callers must call [check] after consuming the iterator to determine whether it stopped early.
+///
+/// # Arguments
+///
+/// * `iter`: Iterator to wrap
+pub fn checkpoints<I: Iterator>(iter: I) -> impl Iterator<Item = I::Item> {
+    iter.enumerate()
+        .take_while(|(i, _)| i % CHECKPOINT_INTERVAL != 0 || !expired())
+        .map(|(_, item)| item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_deadline() {
+        run(None, || {
+            assert_eq!(10, checkpoints(0..10).count());
+            check().unwrap();
+        })
+    }
+
+    #[test]
+    fn within_deadline() {
+        run(Some(Duration::from_secs(60)), || {
+            assert_eq!(10, checkpoints(0..10).count());
+            check().unwrap();
+        })
+    }
+
+    #[test]
+    fn expired_deadline() {
+        run(Some(Duration::ZERO), || {
+            assert_eq!(0, checkpoints(0..10).count());
+            assert_eq!(
+                "operation exceeded its compute time limit",
+                check().unwrap_err().to_string()
+            );
+        })
+    }
+
+    #[test]
+    fn deadline_restored() {
+        run(Some(Duration::ZERO), || {
+            run(None, || check().unwrap());
+            check().unwrap_err();
+        });
+        check().unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+//! Demonstration object store
+//!
+//! In demo mode, the server starts an in-memory S3-compatible object store on a local port,
+//! preloaded with small sample datasets, and configures it as a named source called `demo`. This
+//! allows new users and the CI jobs of client libraries to exercise the full API without an
+//! object store of their own.
+//!
+//! The object store supports only the requests made by Reductionist: ranged GET requests for
+//! objects, listing objects, and HEAD requests for buckets. Requests are not authenticated.
+//!
+//! Each sample dataset contains 1000 elements with shape `[10, 10, 10]`, and element `i` has the
+//! value `i`. The datasets are:
+//!
+//! * `int32.dat`, `int64.dat`, `uint32.dat`, `uint64.dat`, `float32.dat`, `float64.dat`:
+//!   uncompressed data of each data type
+//! * `float64-gzip.dat`, `float64-zlib.dat`: float64 data compressed using gzip or zlib
+//! * `float32-shuffle-zlib.dat`: float32 data with the byte shuffle filter, compressed using zlib
+//! * `int32-missing.dat`: int32 data in which every tenth element is the missing value `-999`
+
+use crate::supervisor::Supervisor;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use flate2::read::{GzEncoder, ZlibEncoder};
+use hashbrown::HashMap;
+use serde::Deserialize;
+use std::fmt::Write;
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use url::Url;
+use zerocopy::AsBytes;
+
+/// Name of the source for the object store.
+pub const SOURCE: &str = "demo";
+
+/// Name of the bucket containing the sample datasets.
+pub const BUCKET: &str = "sample-data";
+
+/// Number of elements in each sample dataset.
+const SIZE: usize = 1000;
+
+/// Objects in the sample data bucket.
+type Objects = Arc<HashMap<String, Bytes>>;
+
+/// Returns the data compressed using a flate2 encoder.
+///
+/// # Arguments
+///
+/// * `encoder`: Encoder reading the data to compress
+fn compress(mut encoder: impl Read) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    encoder
+        .read_to_end(&mut compressed)
+        .expect("compressing in memory should not fail");
+    compressed
+}
+
+/// Returns the data with the byte shuffle filter applied.
+///
+/// # Arguments
+///
+/// * `data`: Data to shuffle
+/// * `element_size`: Size of each element in bytes
+fn shuffle(data: &[u8], element_size: usize) -> Vec<u8> {
+    let num_elements = data.len() / element_size;
+    let mut shuffled = vec![0; data.len()];
+    for (i, element) in data.chunks_exact(element_size).enumerate() {
+        for (j, byte) in element.iter().enumerate() {
+            shuffled[j * num_elements + i] = *byte;
+        }
+    }
+    shuffled
+}
+
+/// Returns the sample datasets, keyed by object name.
+pub fn objects() -> HashMap<String, Bytes> {
+    let int32: Vec<i32> = (0..SIZE as i32).collect();
+    let float32: Vec<f32> = (0..SIZE).map(|i| i as f32).collect();
+    let float64: Vec<f64> = (0..SIZE).map(|i| i as f64).collect();
+    let missing: Vec<i32> = (0..SIZE as i32)
+        .map(|i| if i % 10 == 0 { -999 } else { i })
+        .collect();
+    let level = flate2::Compression::default();
+    [
+        ("int32.dat", int32.as_bytes().to_vec()),
+        (
+            "int64.dat",
+            (0..SIZE as i64).collect::<Vec<_>>().as_bytes().to_vec(),
+        ),
+        (
+            "uint32.dat",
+            (0..SIZE as u32).collect::<Vec<_>>().as_bytes().to_vec(),
+        ),
+        (
+            "uint64.dat",
+            (0..SIZE as u64).collect::<Vec<_>>().as_bytes().to_vec(),
+        ),
+        ("float32.dat", float32.as_bytes().to_vec()),
+        ("float64.dat", float64.as_bytes().to_vec()),
+        (
+            "float64-gzip.dat",
+            compress(GzEncoder::new(float64.as_bytes(), level)),
+        ),
+        (
+            "float64-zlib.dat",
+            compress(ZlibEncoder::new(float64.as_bytes(), level)),
+        ),
+        (
+            "float32-shuffle-zlib.dat",
+            compress(ZlibEncoder::new(&shuffle(float32.as_bytes(), 4)[..], level)),
+        ),
+        ("int32-missing.dat", missing.as_bytes().to_vec()),
+    ]
+    .into_iter()
+    .map(|(key, data)| (key.to_string(), Bytes::from(data)))
+    .collect()
+}
+
+/// Start the object store on a local port, returning its URL.
+///
+/// # Arguments
+///
+/// * `supervisor`: Supervisor of the task serving the object store
+pub fn start(supervisor: &Supervisor) -> std::io::Result<Url> {
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))?;
+    let url = Url::parse(&format!("http://{}", listener.local_addr()?))
+        .expect("local address should be a valid URL");
+    let server = axum::Server::from_tcp(listener)
+        .map_err(std::io::Error::other)?
+        .serve(router(Arc::new(objects())).into_make_service());
+    supervisor.spawn("demo", async move {
+        if let Err(err) = server.await {
+            tracing::error!("Demo object store failed: {}", err);
+        }
+    });
+    tracing::info!("Serving sample data from {} as source {}", url, SOURCE);
+    Ok(url)
+}
+
+/// Returns a [axum::Router] for the object store.
+///
+/// # Arguments
+///
+/// * `objects`: Objects in the sample data bucket
+fn router(objects: Objects) -> Router {
+    Router::new()
+        .route("/:bucket", get(list_objects))
+        .route("/:bucket/", get(list_objects))
+        .route("/:bucket/*key", get(get_object))
+        .with_state(objects)
+}
+
+/// Returns an S3 error response.
+///
+/// # Arguments
+///
+/// * `status`: HTTP status code
+/// * `code`: S3 error code
+fn error(status: StatusCode, code: &str) -> Response {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>{}</Code><Message>{}</Message></Error>"#,
+        code, code
+    );
+    (status, [(header::CONTENT_TYPE, "application/xml")], body).into_response()
+}
+
+/// Query parameters of a request to list objects
+#[derive(Deserialize)]
+struct ListQuery {
+    /// Prefix of the keys to list
+    prefix: Option<String>,
+}
+
+/// Lists the objects in a bucket, or checks that it exists for a HEAD request.
+///
+/// All objects are returned in a single page.
+async fn list_objects(
+    State(objects): State<Objects>,
+    Path(bucket): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    if bucket != BUCKET {
+        return error(StatusCode::NOT_FOUND, "NoSuchBucket");
+    }
+    let prefix = query.prefix.unwrap_or_default();
+    let mut keys: Vec<(&String, usize)> = objects
+        .iter()
+        .filter(|(key, _)| key.starts_with(&prefix))
+        .map(|(key, data)| (key, data.len()))
+        .collect();
+    keys.sort_unstable();
+    let mut contents = String::new();
+    for (key, size) in &keys {
+        write!(
+            contents,
+            "<Contents><Key>{}</Key><Size>{}</Size></Contents>",
+            key, size
+        )
+        .expect("writing to a string should not fail");
+    }
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>{}</Name><KeyCount>{}</KeyCount><MaxKeys>1000</MaxKeys><IsTruncated>false</IsTruncated>{}</ListBucketResult>"#,
+        bucket,
+        keys.len(),
+        contents
+    );
+    ([(header::CONTENT_TYPE, "application/xml")], body).into_response()
+}
+
+/// Returns the byte range of an object requested by a Range header, as a start and inclusive end.
+///
+/// Returns `None` if the range is not satisfiable.
+///
+/// # Arguments
+///
+/// * `range`: Value of the Range header
+/// * `len`: Length of the object
+fn parse_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start, end) {
+        // A suffix range contains the last bytes of the object.
+        ("", suffix) => (len.checked_sub(suffix.parse().ok()?)?, len.checked_sub(1)?),
+        (start, "") => (start.parse().ok()?, len.checked_sub(1)?),
+        (start, end) => (start.parse().ok()?, end.parse::<usize>().ok()?.min(len - 1)),
+    };
+    (start <= end && end < len).then_some((start, end))
+}
+
+/// Returns an object, or a byte range of an object.
+async fn get_object(
+    State(objects): State<Objects>,
+    Path((bucket, key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    if bucket != BUCKET {
+        return error(StatusCode::NOT_FOUND, "NoSuchBucket");
+    }
+    let Some(data) = objects.get(&key) else {
+        return error(StatusCode::NOT_FOUND, "NoSuchKey");
+    };
+    let Some(range) = headers.get(header::RANGE) else {
+        return data.clone().into_response();
+    };
+    match range
+        .to_str()
+        .ok()
+        .and_then(|range| parse_range(range, data.len()))
+    {
+        Some((start, end)) => (
+            StatusCode::PARTIAL_CONTENT,
+            [(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, data.len()),
+            )],
+            data.slice(start..=end),
+        )
+            .into_response(),
+        None => error(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::resource_manager::ResourceManager;
+    use crate::s3_client::{S3ClientMap, S3Credentials};
+
+    #[test]
+    fn ranges() {
+        assert_eq!(Some((0, 9)), parse_range("bytes=0-9", 10));
+        assert_eq!(Some((2, 4)), parse_range("bytes=2-4", 10));
+        assert_eq!(Some((5, 9)), parse_range("bytes=5-", 10));
+        assert_eq!(Some((7, 9)), parse_range("bytes=-3", 10));
+        // The end is clamped to the length of the object.
+        assert_eq!(Some((8, 9)), parse_range("bytes=8-20", 10));
+        assert_eq!(None, parse_range("bytes=10-", 10));
+        assert_eq!(None, parse_range("bytes=4-2", 10));
+        assert_eq!(None, parse_range("bytes=-11", 10));
+        assert_eq!(None, parse_range("items=0-1", 10));
+    }
+
+    #[test]
+    fn shuffle_bytes() {
+        assert_eq!(vec![1, 3, 5, 2, 4, 6], shuffle(&[1, 2, 3, 4, 5, 6], 2));
+    }
+
+    #[test]
+    fn sample_objects() {
+        let objects = objects();
+        assert_eq!(10, objects.len());
+        assert_eq!(4000, objects["int32.dat"].len());
+        assert_eq!(8000, objects["float64.dat"].len());
+        assert!(objects["float64-zlib.dat"].len() < 8000);
+    }
+
+    #[tokio::test]
+    async fn s3_requests() {
+        let supervisor = Supervisor::new();
+        let url = start(&supervisor).unwrap();
+        let client = S3ClientMap::new(None).get(&url, S3Credentials::None).await;
+        client.head_bucket(BUCKET).await.unwrap();
+        let list = client
+            .list_objects(BUCKET, Some("float64-".to_string()), None, None)
+            .await
+            .unwrap();
+        assert_eq!(vec!["float64-gzip.dat", "float64-zlib.dat"], list.keys);
+        let resource_manager = ResourceManager::new(None, None, None, false);
+        let data = client
+            .download_object(
+                BUCKET,
+                "int32.dat",
+                Some("bytes=4-11".to_string()),
+                &resource_manager,
+                &mut None,
+            )
+            .await
+            .unwrap();
+        assert_eq!([1_i32, 2].as_bytes(), data);
+        let result = client
+            .download_object(BUCKET, "missing.dat", None, &resource_manager, &mut None)
+            .await;
+        assert!(result.is_err());
+        supervisor.abort_all();
+    }
+}
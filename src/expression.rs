@@ -0,0 +1,485 @@
+//! Expressions for the experimental expr operation.
+//!
+//! An expression applies element-wise arithmetic to the selected elements, bound to the variable
+//! `x`, and reduces the results to a single value, for example `sum((x - 273.15) * 2)`. The
+//! language is deliberately small: the only values are float64 numbers, there are no loops or
+//! user-defined functions, and the length and nesting depth of expressions are limited. This
+//! allows expressions from clients to be parsed and evaluated safely on the server.
+//!
+//! The grammar is as follows, where `reduction` is one of `max`, `mean`, `min` or `sum` and
+//! `function` is one of `abs`, `exp`, `log`, `log10` or `sqrt`:
+//!
+//! ```text
+//! expression := reduction "(" sum ")"
+//! sum        := product (("+" | "-") product)*
+//! product    := unary (("*" | "/") unary)*
+//! unary      := "-" unary | primary
+//! primary    := number | "x" | function "(" sum ")" | "(" sum ")"
+//! ```
+
+/// Maximum length of an expression in bytes.
+pub const MAX_LENGTH: usize = 1024;
+
+/// Maximum nesting depth of an expression.
+pub const MAX_DEPTH: usize = 32;
+
+/// Error parsing an expression
+#[derive(Debug, PartialEq)]
+pub struct ExpressionError {
+    /// Reason the expression is invalid
+    pub reason: &'static str,
+    /// Byte offset in the expression at which the error was found
+    pub position: usize,
+}
+
+impl std::fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.reason, self.position)
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+/// Reduction applied to the results of an expression
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reduction {
+    /// Maximum of the results
+    Max,
+    /// Mean of the results
+    Mean,
+    /// Minimum of the results
+    Min,
+    /// Sum of the results
+    Sum,
+}
+
+/// Element-wise function
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    /// Absolute value
+    Abs,
+    /// Exponential
+    Exp,
+    /// Natural logarithm
+    Log,
+    /// Base 10 logarithm
+    Log10,
+    /// Square root
+    Sqrt,
+}
+
+/// Binary arithmetic operator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// Element-wise part of an expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    /// A numeric constant
+    Constant(f64),
+    /// The selected element, `x`
+    Variable,
+    /// Negation of a node
+    Negate(Box<Node>),
+    /// A binary operator applied to two nodes
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    /// A function applied to a node
+    Function(Function, Box<Node>),
+}
+
+impl Node {
+    /// Evaluate the node for an element.
+    ///
+    /// # Arguments
+    ///
+    /// * `x`: Value of the element
+    pub fn evaluate(&self, x: f64) -> f64 {
+        match self {
+            Node::Constant(value) => *value,
+            Node::Variable => x,
+            Node::Negate(node) => -node.evaluate(x),
+            Node::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(x), rhs.evaluate(x));
+                match op {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Subtract => lhs - rhs,
+                    BinaryOp::Multiply => lhs * rhs,
+                    BinaryOp::Divide => lhs / rhs,
+                }
+            }
+            Node::Function(function, node) => {
+                let value = node.evaluate(x);
+                match function {
+                    Function::Abs => value.abs(),
+                    Function::Exp => value.exp(),
+                    Function::Log => value.ln(),
+                    Function::Log10 => value.log10(),
+                    Function::Sqrt => value.sqrt(),
+                }
+            }
+        }
+    }
+}
+
+/// A parsed expression: an element-wise node followed by a reduction
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    /// Reduction applied to the results
+    pub reduction: Reduction,
+    /// Element-wise part of the expression
+    pub node: Node,
+}
+
+/// Token of an expression
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Symbol(char),
+    End,
+}
+
+/// Split an expression into tokens.
+///
+/// # Arguments
+///
+/// * `source`: Expression to tokenise
+fn tokenise(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(position, c)) = chars.peek() {
+        if c.is_ascii_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = position;
+            let mut exponent = false;
+            while let Some(&(i, c)) = chars.peek() {
+                let sign = (c == '+' || c == '-')
+                    && exponent
+                    && matches!(source[..i].chars().last(), Some('e' | 'E'));
+                if c.is_ascii_digit() || c == '.' || sign {
+                    end = i + 1;
+                } else if (c == 'e' || c == 'E') && !exponent {
+                    exponent = true;
+                    end = i + 1;
+                } else {
+                    break;
+                }
+                chars.next();
+            }
+            let number = source[position..end].parse().map_err(|_| ExpressionError {
+                reason: "invalid number",
+                position,
+            })?;
+            tokens.push((position, Token::Number(number)));
+        } else if c.is_ascii_alphabetic() {
+            let mut end = position;
+            while let Some(&(i, c)) = chars.peek() {
+                if !c.is_ascii_alphanumeric() {
+                    break;
+                }
+                end = i + 1;
+                chars.next();
+            }
+            tokens.push((
+                position,
+                Token::Identifier(source[position..end].to_string()),
+            ));
+        } else if "+-*/()".contains(c) {
+            tokens.push((position, Token::Symbol(c)));
+            chars.next();
+        } else {
+            return Err(ExpressionError {
+                reason: "unexpected character",
+                position,
+            });
+        }
+    }
+    tokens.push((source.len(), Token::End));
+    Ok(tokens)
+}
+
+/// Recursive descent parser for expressions
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    depth: usize,
+}
+
+impl Parser {
+    /// Returns the next token and its position without consuming it.
+    fn peek(&self) -> (usize, &Token) {
+        let (position, token) = &self.tokens[self.next];
+        (*position, token)
+    }
+
+    /// Consumes and returns the next token and its position.
+    fn advance(&mut self) -> (usize, Token) {
+        let token = self.tokens[self.next].clone();
+        if self.next < self.tokens.len() - 1 {
+            self.next += 1;
+        }
+        token
+    }
+
+    /// Consumes the next token, which must be the symbol `c`.
+    fn expect(&mut self, c: char, reason: &'static str) -> Result<(), ExpressionError> {
+        match self.advance() {
+            (_, Token::Symbol(symbol)) if symbol == c => Ok(()),
+            (position, _) => Err(ExpressionError { reason, position }),
+        }
+    }
+
+    /// Parses a complete expression.
+    fn expression(&mut self) -> Result<Expression, ExpressionError> {
+        let reduction = match self.advance() {
+            (_, Token::Identifier(name)) if name == "max" => Reduction::Max,
+            (_, Token::Identifier(name)) if name == "mean" => Reduction::Mean,
+            (_, Token::Identifier(name)) if name == "min" => Reduction::Min,
+            (_, Token::Identifier(name)) if name == "sum" => Reduction::Sum,
+            (position, _) => {
+                return Err(ExpressionError {
+                    reason: "expected reduction max, mean, min or sum",
+                    position,
+                })
+            }
+        };
+        self.expect('(', "expected '('")?;
+        let node = self.sum()?;
+        self.expect(')', "expected ')'")?;
+        match self.peek() {
+            (_, Token::End) => Ok(Expression { reduction, node }),
+            (position, _) => Err(ExpressionError {
+                reason: "unexpected input after reduction",
+                position,
+            }),
+        }
+    }
+
+    /// Parses a sum or difference of products.
+    fn sum(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.product()?;
+        loop {
+            let op = match self.peek() {
+                (_, Token::Symbol('+')) => BinaryOp::Add,
+                (_, Token::Symbol('-')) => BinaryOp::Subtract,
+                _ => return Ok(node),
+            };
+            self.advance();
+            node = Node::Binary(op, Box::new(node), Box::new(self.product()?));
+        }
+    }
+
+    /// Parses a product or quotient of unary terms.
+    fn product(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                (_, Token::Symbol('*')) => BinaryOp::Multiply,
+                (_, Token::Symbol('/')) => BinaryOp::Divide,
+                _ => return Ok(node),
+            };
+            self.advance();
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    /// Parses an optionally negated primary term, limiting the nesting depth.
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        let (position, _) = self.peek();
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(ExpressionError {
+                reason: "expression is nested too deeply",
+                position,
+            });
+        }
+        let node = if let (_, Token::Symbol('-')) = self.peek() {
+            self.advance();
+            Node::Negate(Box::new(self.unary()?))
+        } else {
+            self.primary()?
+        };
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    /// Parses a number, variable, function call or parenthesised sum.
+    fn primary(&mut self) -> Result<Node, ExpressionError> {
+        match self.advance() {
+            (_, Token::Number(value)) => Ok(Node::Constant(value)),
+            (_, Token::Symbol('(')) => {
+                let node = self.sum()?;
+                self.expect(')', "expected ')'")?;
+                Ok(node)
+            }
+            (position, Token::Identifier(name)) => {
+                let function = match name.as_str() {
+                    "x" => return Ok(Node::Variable),
+                    "abs" => Function::Abs,
+                    "exp" => Function::Exp,
+                    "log" => Function::Log,
+                    "log10" => Function::Log10,
+                    "sqrt" => Function::Sqrt,
+                    "max" | "mean" | "min" | "sum" => {
+                        return Err(ExpressionError {
+                            reason: "reductions may only be applied to the whole expression",
+                            position,
+                        })
+                    }
+                    _ => {
+                        return Err(ExpressionError {
+                            reason: "unknown variable or function",
+                            position,
+                        })
+                    }
+                };
+                self.expect('(', "expected '('")?;
+                let node = self.sum()?;
+                self.expect(')', "expected ')'")?;
+                Ok(Node::Function(function, Box::new(node)))
+            }
+            (position, _) => Err(ExpressionError {
+                reason: "expected a number, variable, function or '('",
+                position,
+            }),
+        }
+    }
+}
+
+/// Parse an expression.
+///
+/// # Arguments
+///
+/// * `source`: Expression to parse
+pub fn parse(source: &str) -> Result<Expression, ExpressionError> {
+    if source.len() > MAX_LENGTH {
+        return Err(ExpressionError {
+            reason: "expression is too long",
+            position: MAX_LENGTH,
+        });
+    }
+    let mut parser = Parser {
+        tokens: tokenise(source)?,
+        next: 0,
+        depth: 0,
+    };
+    parser.expression()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(reason: &'static str, position: usize) -> ExpressionError {
+        ExpressionError { reason, position }
+    }
+
+    #[test]
+    fn parse_sum() {
+        let expression = parse("sum((x - 273.15) * 2)").unwrap();
+        assert_eq!(Reduction::Sum, expression.reduction);
+        assert_eq!(
+            Node::Binary(
+                BinaryOp::Multiply,
+                Box::new(Node::Binary(
+                    BinaryOp::Subtract,
+                    Box::new(Node::Variable),
+                    Box::new(Node::Constant(273.15))
+                )),
+                Box::new(Node::Constant(2.0))
+            ),
+            expression.node
+        );
+    }
+
+    #[test]
+    fn evaluate_precedence() {
+        let expression = parse("max(1 + 2 * x - x / 4)").unwrap();
+        assert_eq!(Reduction::Max, expression.reduction);
+        assert_eq!(15.0, expression.node.evaluate(8.0));
+    }
+
+    #[test]
+    fn evaluate_functions() {
+        let expression = parse("mean(sqrt(abs(-x)) + log10(100) - log(exp(1)))").unwrap();
+        assert_eq!(Reduction::Mean, expression.reduction);
+        assert_eq!(4.0, expression.node.evaluate(9.0));
+    }
+
+    #[test]
+    fn evaluate_exponent_and_negation() {
+        let expression = parse("min(--x * 1.5e-1 + 2E+1)").unwrap();
+        assert_eq!(Reduction::Min, expression.reduction);
+        assert_eq!(21.5, expression.node.evaluate(10.0));
+    }
+
+    #[test]
+    fn parse_no_reduction() {
+        assert_eq!(
+            error("expected reduction max, mean, min or sum", 0),
+            parse("x + 1").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_nested_reduction() {
+        assert_eq!(
+            error("reductions may only be applied to the whole expression", 4),
+            parse("sum(sum(x))").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_unknown_identifier() {
+        assert_eq!(
+            error("unknown variable or function", 8),
+            parse("sum(x * weights)").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_unexpected_character() {
+        assert_eq!(
+            error("unexpected character", 6),
+            parse("sum(x ^ 2)").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_unbalanced() {
+        assert_eq!(error("expected ')'", 11), parse("sum((x + 1)").unwrap_err());
+        assert_eq!(
+            error("unexpected input after reduction", 6),
+            parse("sum(x))").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_invalid_number() {
+        assert_eq!(error("invalid number", 4), parse("sum(1.2.3)").unwrap_err());
+    }
+
+    #[test]
+    fn parse_too_deep() {
+        let source = format!("sum({}x{})", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert_eq!(
+            error("expression is nested too deeply", 4 + MAX_DEPTH),
+            parse(&source).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_too_long() {
+        let source = format!("sum(x{})", " + 1".repeat(MAX_LENGTH));
+        assert_eq!(
+            error("expression is too long", MAX_LENGTH),
+            parse(&source).unwrap_err()
+        );
+    }
+}
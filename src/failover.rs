@@ -0,0 +1,241 @@
+//! Failover between equivalent S3 endpoints
+//!
+//! An object store may be reachable via multiple equivalent endpoints, for example several RGW
+//! gateways in front of the same Ceph cluster. Requests for any endpoint in a group of equivalent
+//! endpoints may be served by any other endpoint in the group, allowing requests to succeed when
+//! one of the endpoints is unavailable.
+
+use crate::error::ActiveStorageError;
+
+use aws_sdk_s3::error::SdkError;
+use hashbrown::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::Url;
+
+/// A group of equivalent endpoints.
+#[derive(Debug)]
+struct EndpointGroup {
+    /// Equivalent endpoint URLs.
+    endpoints: Vec<Url>,
+    /// Index of the endpoint to try first.
+    preferred: AtomicUsize,
+}
+
+/// Groups of equivalent S3 endpoints.
+#[derive(Debug)]
+pub struct EndpointFailover {
+    /// Endpoint groups.
+    groups: Vec<EndpointGroup>,
+    /// Map from an endpoint URL to the index of its group.
+    index: HashMap<Url, usize>,
+}
+
+impl EndpointFailover {
+    /// Returns a new EndpointFailover object.
+    ///
+    /// # Arguments
+    ///
+    /// * `groups`: List of endpoint groups, each of the form `<url>=<url>[,<url>...]`. The first
+    ///   URL is the primary endpoint, followed by its alternatives.
+    pub fn new(groups: &[String]) -> Result<Self, String> {
+        let mut failover = Self {
+            groups: Vec::new(),
+            index: HashMap::new(),
+        };
+        for group in groups {
+            let (primary, alternatives) = group
+                .split_once('=')
+                .ok_or(format!("invalid endpoint group {}", group))?;
+            let endpoints = std::iter::once(primary)
+                .chain(alternatives.split(','))
+                .map(|url| Url::parse(url.trim()).map_err(|err| format!("{}: {}", url, err)))
+                .collect::<Result<Vec<Url>, String>>()?;
+            for endpoint in &endpoints {
+                if failover
+                    .index
+                    .insert(endpoint.clone(), failover.groups.len())
+                    .is_some()
+                {
+                    return Err(format!("endpoint {} is in multiple groups", endpoint));
+                }
+            }
+            failover.groups.push(EndpointGroup {
+                endpoints,
+                preferred: AtomicUsize::new(0),
+            });
+        }
+        Ok(failover)
+    }
+
+    /// Returns the endpoints to try for a source URL, in order.
+    ///
+    /// The list starts with the currently preferred endpoint of the source's group. If the source
+    /// is not in any group, the list contains only the source.
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: URL of the S3 source in the request
+    pub fn endpoints(&self, source: &Url) -> Vec<Url> {
+        match self.index.get(source) {
+            Some(index) => {
+                let group = &self.groups[*index];
+                let preferred = group.preferred.load(Ordering::Relaxed);
+                let mut endpoints = group.endpoints.clone();
+                endpoints.rotate_left(preferred);
+                endpoints
+            }
+            None => vec![source.clone()],
+        }
+    }
+
+    /// Returns the endpoints in all groups.
+    pub fn configured(&self) -> impl Iterator<Item = &Url> {
+        self.index.keys()
+    }
+
+    /// Record that an endpoint has failed.
+    ///
+    /// If the endpoint is the preferred endpoint of its group, the next endpoint in the group
+    /// becomes the preferred endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint`: URL of the failed endpoint
+    pub fn failed(&self, endpoint: &Url) {
+        if let Some(index) = self.index.get(endpoint) {
+            let group = &self.groups[*index];
+            if let Some(position) = group.endpoints.iter().position(|e| e == endpoint) {
+                let next = (position + 1) % group.endpoints.len();
+                // Ignore failure, which means another request already moved on.
+                let _ = group.preferred.compare_exchange(
+                    position,
+                    next,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+            }
+        }
+    }
+}
+
+/// Returns whether an error indicates that an endpoint is unavailable, such that the request
+/// should be retried using an equivalent endpoint.
+///
+/// # Arguments
+///
+/// * `error`: Error returned by the download
+pub fn is_endpoint_failure(error: &ActiveStorageError) -> bool {
+    match error {
+        ActiveStorageError::S3GetObject(sdk_error) => match sdk_error {
+            SdkError::DispatchFailure(_)
+            | SdkError::ResponseError(_)
+            | SdkError::TimeoutError(_) => true,
+            SdkError::ServiceError(service_error) => service_error.raw().status().is_server_error(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use aws_sdk_s3::operation::get_object::GetObjectError;
+    use aws_sdk_s3::types::error::NoSuchKey;
+    use aws_smithy_runtime_api::http::Response as SmithyResponse;
+    use aws_smithy_runtime_api::http::StatusCode as SmithyStatusCode;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn make_failover() -> EndpointFailover {
+        EndpointFailover::new(&["http://a=http://b,http://c".to_string()]).unwrap()
+    }
+
+    #[test]
+    fn endpoints_not_in_group() {
+        let failover = make_failover();
+        assert_eq!(vec![url("http://d")], failover.endpoints(&url("http://d")));
+    }
+
+    #[test]
+    fn endpoints_in_group() {
+        let failover = make_failover();
+        let expected = vec![url("http://a"), url("http://b"), url("http://c")];
+        assert_eq!(expected, failover.endpoints(&url("http://a")));
+        // Alternatives are equivalent to the primary.
+        assert_eq!(expected, failover.endpoints(&url("http://c")));
+    }
+
+    #[test]
+    fn failed() {
+        let failover = make_failover();
+        failover.failed(&url("http://a"));
+        let expected = vec![url("http://b"), url("http://c"), url("http://a")];
+        assert_eq!(expected, failover.endpoints(&url("http://a")));
+        // Failure of a non-preferred endpoint has no effect.
+        failover.failed(&url("http://a"));
+        assert_eq!(expected, failover.endpoints(&url("http://a")));
+        failover.failed(&url("http://b"));
+        failover.failed(&url("http://c"));
+        let expected = vec![url("http://a"), url("http://b"), url("http://c")];
+        assert_eq!(expected, failover.endpoints(&url("http://a")));
+    }
+
+    #[test]
+    fn new_invalid_group() {
+        let err = EndpointFailover::new(&["http://a".to_string()]).unwrap_err();
+        assert_eq!("invalid endpoint group http://a", err);
+    }
+
+    #[test]
+    fn new_invalid_url() {
+        let err = EndpointFailover::new(&["http://a=foo".to_string()]).unwrap_err();
+        assert_eq!("foo: relative URL without a base", err);
+    }
+
+    #[test]
+    fn new_duplicate_endpoint() {
+        let groups = [
+            "http://a=http://b".to_string(),
+            "http://c=http://a".to_string(),
+        ];
+        let err = EndpointFailover::new(&groups).unwrap_err();
+        assert_eq!("endpoint http://a/ is in multiple groups", err);
+    }
+
+    fn get_smithy_response(status: u16) -> SmithyResponse {
+        let status: SmithyStatusCode = status.try_into().unwrap();
+        SmithyResponse::new(status, "body".into())
+    }
+
+    #[test]
+    fn is_endpoint_failure_service_error() {
+        let get_object_error = GetObjectError::NoSuchKey(NoSuchKey::builder().build());
+        let error = ActiveStorageError::S3GetObject(SdkError::service_error(
+            get_object_error,
+            get_smithy_response(404),
+        ));
+        assert!(!is_endpoint_failure(&error));
+        let get_object_error = GetObjectError::NoSuchKey(NoSuchKey::builder().build());
+        let error = ActiveStorageError::S3GetObject(SdkError::service_error(
+            get_object_error,
+            get_smithy_response(503),
+        ));
+        assert!(is_endpoint_failure(&error));
+    }
+
+    #[test]
+    fn is_endpoint_failure_timeout() {
+        let error = ActiveStorageError::S3GetObject(SdkError::timeout_error("timeout"));
+        assert!(is_endpoint_failure(&error));
+    }
+
+    #[test]
+    fn is_endpoint_failure_other() {
+        let error = ActiveStorageError::S3ContentLengthMissing;
+        assert!(!is_endpoint_failure(&error));
+    }
+}
@@ -1,8 +1,12 @@
 //! Compression and filter pipeline.
+//!
+//! The pipeline may be applied to the data once it has been downloaded using
+//! [filter_pipeline], or as it is downloaded using a [StreamingPipeline].
 
-use crate::compression;
+use crate::compression::{self, Inflater};
+use crate::deadline;
 use crate::error::ActiveStorageError;
-use crate::filters;
+use crate::filters::{self, shuffle::Deshuffler};
 use crate::models;
 
 use axum::body::Bytes;
@@ -15,24 +19,196 @@ use axum::body::Bytes;
 ///
 /// * `request_data`: RequestData object for the request
 /// * `data`: Data [Bytes](axum::body::Bytes) to apply the pipeline to.
+/// * `max_ratio`: Optional maximum ratio of decompressed to compressed data size
 #[tracing::instrument(skip(request_data, data))]
 pub fn filter_pipeline(
     request_data: &models::RequestData,
     mut data: Bytes,
+    max_ratio: Option<usize>,
 ) -> Result<Bytes, ActiveStorageError> {
     // First decompress.
     if let Some(compression) = request_data.compression {
-        data = compression::decompress(compression, &data)?
+        let max_size = max_decompressed_size(data.len(), max_ratio);
+        data = compression::decompress(compression, &data, max_size, decoded_size(request_data))?;
+        deadline::check()?;
     };
     // Then decode the filters in reverse order.
     if let Some(filters) = &request_data.filters {
         for filter in filters.iter().rev() {
-            data = filters::decode(filter, &data)?
+            data = filters::decode(filter, &data)?;
+            deadline::check()?;
         }
     };
     Ok(data)
 }
 
+/// Returns the size in bytes of the decoded data, if known from the shape of the array.
+///
+/// Filters do not change the size of the data, so this is also the size of the decompressed data.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+fn decoded_size(request_data: &models::RequestData) -> Option<usize> {
+    let shape = request_data.shape.as_ref()?;
+    Some(shape.iter().product::<usize>() * request_data.dtype.size_of())
+}
+
+/// Returns the maximum size in bytes of decompressed data, if limited.
+///
+/// # Arguments
+///
+/// * `compressed_size`: Size in bytes of the compressed data
+/// * `max_ratio`: Optional maximum ratio of decompressed to compressed data size
+fn max_decompressed_size(compressed_size: usize, max_ratio: Option<usize>) -> Option<usize> {
+    max_ratio.map(|ratio| ratio.saturating_mul(compressed_size))
+}
+
+/// Filter pipeline that decodes data as it is downloaded.
+///
+/// Decompression and the first filter to be decoded are applied to each chunk of the data as it
+/// arrives, reducing the time to a result and the peak memory usage for large compressed chunks.
+/// The first filter is streamed only if the size of its input is known in advance, either because
+/// the data is not compressed, or from the shape of the array. Any remaining filters are decoded
+/// once all of the data has been received.
+pub struct StreamingPipeline {
+    /// Decompressor, if the data is compressed.
+    inflater: Option<Inflater>,
+    /// Streaming decoder for the first filter.
+    deshuffler: Option<Deshuffler>,
+    /// Decoded data that has not been passed to a streaming filter.
+    buf: Vec<u8>,
+    /// Filters to decode once all of the data has been received, in order of decoding.
+    remaining: Vec<models::Filter>,
+    /// Expected size of the decoded data before filters, if known in advance.
+    expected_size: Option<usize>,
+    /// Size of the decoded data before filters.
+    decoded_size: usize,
+    /// Data type of the data.
+    dtype: models::DType,
+    /// Shape of the data.
+    shape: Option<Vec<usize>>,
+}
+
+impl StreamingPipeline {
+    /// Returns a new StreamingPipeline object.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_data`: RequestData object for the request
+    /// * `content_length`: Length of the downloaded data
+    /// * `max_ratio`: Optional maximum ratio of decompressed to compressed data size
+    pub fn new(
+        request_data: &models::RequestData,
+        content_length: usize,
+        max_ratio: Option<usize>,
+    ) -> Self {
+        let expected_size = match (request_data.compression, &request_data.shape) {
+            (None, _) => Some(content_length),
+            (Some(_), _) => decoded_size(request_data),
+        };
+        let mut remaining: Vec<models::Filter> = request_data
+            .filters
+            .iter()
+            .flatten()
+            .rev()
+            .copied()
+            .collect();
+        let deshuffler = match (remaining.first(), expected_size) {
+            (Some(models::Filter::Shuffle { element_size }), Some(size))
+                if size % element_size == 0 =>
+            {
+                Some(Deshuffler::new(*element_size, size))
+            }
+            _ => None,
+        };
+        if deshuffler.is_some() {
+            remaining.remove(0);
+        }
+        // Without a streaming filter, the buffer holds the decoded data, and should be aligned.
+        // Otherwise, it holds each chunk of decompressed data, if the data is compressed.
+        let capacity = match (&deshuffler, request_data.compression, expected_size) {
+            (None, _, Some(size)) => size,
+            (Some(_), None, _) => 0,
+            _ => content_length,
+        };
+        Self {
+            inflater: request_data.compression.map(|compression| {
+                Inflater::new(
+                    compression,
+                    max_decompressed_size(content_length, max_ratio),
+                )
+            }),
+            deshuffler,
+            buf: maligned::align_first::<u8, maligned::A8>(capacity),
+            remaining,
+            expected_size,
+            decoded_size: 0,
+            dtype: request_data.dtype,
+            shape: request_data.shape.clone(),
+        }
+    }
+}
+
+impl StreamingPipeline {
+    /// Decode a chunk of the data.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk`: Chunk of the data
+    pub fn write(&mut self, chunk: &[u8]) -> Result<(), ActiveStorageError> {
+        let start = self.buf.len();
+        let decoded = match &mut self.inflater {
+            Some(inflater) => {
+                inflater.write(chunk, &mut self.buf)?;
+                &self.buf[start..]
+            }
+            None => chunk,
+        };
+        self.decoded_size += decoded.len();
+        match &mut self.deshuffler {
+            Some(deshuffler) => {
+                // If the data is too large it is discarded, and the size of the decoded data is
+                // checked when finished.
+                deshuffler.write(decoded);
+                self.buf.clear();
+            }
+            None if self.inflater.is_none() => self.buf.extend_from_slice(chunk),
+            None => (),
+        }
+        deadline::check()
+    }
+
+    /// Returns the decoded data once all of the data has been written.
+    pub fn finish(self) -> Result<Bytes, ActiveStorageError> {
+        if let Some(inflater) = &self.inflater {
+            inflater.finish()?;
+        }
+        if self
+            .expected_size
+            .is_some_and(|size| size != self.decoded_size)
+        {
+            models::validate_raw_size(self.decoded_size, self.dtype, &self.shape)?;
+        }
+        let mut data = match self.deshuffler {
+            Some(deshuffler) => deshuffler
+                .finish()
+                .expect("deshuffler should receive all of the data"),
+            // The buffer may have been reallocated without alignment if it outgrew its capacity.
+            None if self.buf.as_ptr().align_offset(8) != 0 => {
+                let mut buf = maligned::align_first::<u8, maligned::A8>(self.buf.len());
+                buf.extend_from_slice(&self.buf);
+                buf.into()
+            }
+            None => self.buf.into(),
+        };
+        for filter in &self.remaining {
+            data = filters::decode(filter, &data)?;
+        }
+        Ok(data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -62,7 +238,7 @@ mod tests {
         let data = [1, 2, 3, 4];
         let bytes = Bytes::copy_from_slice(&data);
         let request_data = test_utils::get_test_request_data();
-        let result = filter_pipeline(&request_data, bytes).unwrap();
+        let result = filter_pipeline(&request_data, bytes, None).unwrap();
         assert_eq!(data.as_ref(), result);
     }
 
@@ -72,7 +248,7 @@ mod tests {
         let bytes = compress_gzip(data.as_ref());
         let mut request_data = test_utils::get_test_request_data();
         request_data.compression = Some(models::Compression::Gzip);
-        let result = filter_pipeline(&request_data, bytes).unwrap();
+        let result = filter_pipeline(&request_data, bytes, None).unwrap();
         assert_eq!(data.as_ref(), result);
     }
 
@@ -83,7 +259,7 @@ mod tests {
         let shuffled = filters::shuffle::test_utils::shuffle(&bytes, 4);
         let mut request_data = test_utils::get_test_request_data();
         request_data.filters = Some(vec![models::Filter::Shuffle { element_size: 4 }]);
-        let result = filter_pipeline(&request_data, shuffled).unwrap();
+        let result = filter_pipeline(&request_data, shuffled, None).unwrap();
         assert_eq!(data.as_ref(), result);
     }
 
@@ -96,7 +272,7 @@ mod tests {
         let mut request_data = test_utils::get_test_request_data();
         request_data.compression = Some(models::Compression::Zlib);
         request_data.filters = Some(vec![models::Filter::Shuffle { element_size: 4 }]);
-        let result = filter_pipeline(&request_data, bytes).unwrap();
+        let result = filter_pipeline(&request_data, bytes, None).unwrap();
         assert_eq!(data.as_ref(), result.as_ref());
     }
 
@@ -115,7 +291,171 @@ mod tests {
             models::Filter::Shuffle { element_size: 4 },
             models::Filter::Shuffle { element_size: 2 },
         ]);
-        let result = filter_pipeline(&request_data, bytes).unwrap();
+        let result = filter_pipeline(&request_data, bytes, None).unwrap();
         assert_eq!(data.as_ref(), result.as_ref());
     }
+
+    #[test]
+    fn test_filter_pipeline_max_ratio() {
+        let data = vec![0_u8; 1 << 16];
+        let bytes = compress_zlib(&data);
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Zlib);
+        let result = filter_pipeline(&request_data, bytes.clone(), Some(data.len())).unwrap();
+        assert_eq!(data, result);
+        let err = filter_pipeline(&request_data, bytes.clone(), Some(10)).unwrap_err();
+        assert!(matches!(
+            err,
+            ActiveStorageError::DecompressionLimit { limit } if limit == 10 * bytes.len()
+        ));
+    }
+
+    #[test]
+    fn test_streaming_pipeline_max_ratio() {
+        let data = vec![0_u8; 1 << 16];
+        let bytes = compress_gzip(&data);
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Gzip);
+        let mut pipeline = StreamingPipeline::new(&request_data, bytes.len(), Some(10));
+        let err = bytes
+            .chunks(64)
+            .try_for_each(|chunk| pipeline.write(chunk))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ActiveStorageError::DecompressionLimit { limit } if limit == 10 * bytes.len()
+        ));
+    }
+
+    fn stream(
+        request_data: &models::RequestData,
+        data: &[u8],
+        chunk_size: usize,
+    ) -> Result<Bytes, ActiveStorageError> {
+        let mut pipeline = StreamingPipeline::new(request_data, data.len(), None);
+        for chunk in data.chunks(chunk_size) {
+            pipeline.write(chunk)?;
+        }
+        pipeline.finish()
+    }
+
+    #[test]
+    fn test_streaming_pipeline_shuffle() {
+        let data: Vec<u8> = (0..32).collect();
+        let shuffled = filters::shuffle::test_utils::shuffle(&data.clone().into(), 4);
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.filters = Some(vec![models::Filter::Shuffle { element_size: 4 }]);
+        for chunk_size in [1, 3, 32] {
+            let result = stream(&request_data, &shuffled, chunk_size).unwrap();
+            assert_eq!(data, result);
+            assert_eq!(0, result.as_ptr().align_offset(8));
+        }
+    }
+
+    #[test]
+    fn test_streaming_pipeline_gzip() {
+        let data: Vec<u8> = (0..32).collect();
+        let bytes = compress_gzip(&data);
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Gzip);
+        for shape in [None, Some(vec![8])] {
+            request_data.shape = shape;
+            for chunk_size in [1, 5, bytes.len()] {
+                let result = stream(&request_data, &bytes, chunk_size).unwrap();
+                assert_eq!(data, result);
+                assert_eq!(0, result.as_ptr().align_offset(8));
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_pipeline_gzip_unknown_shape_large() {
+        // Without a shape, the output is larger than the initial capacity of the buffer.
+        let data: Vec<u8> = (0..1 << 16).map(|i| (i % 251) as u8).collect();
+        let bytes = compress_gzip(&data);
+        assert!(bytes.len() < data.len());
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Gzip);
+        request_data.shape = None;
+        for chunk_size in [7, 1024, bytes.len()] {
+            let result = stream(&request_data, &bytes, chunk_size).unwrap();
+            assert_eq!(data, result);
+            assert_eq!(0, result.as_ptr().align_offset(8));
+        }
+    }
+
+    #[test]
+    fn test_streaming_pipeline_shuffle_x2_zlib() {
+        let data: Vec<u8> = (0..32).collect();
+        let shuffled = filters::shuffle::test_utils::shuffle(&data.clone().into(), 4);
+        let reshuffled = filters::shuffle::test_utils::shuffle(&shuffled, 2);
+        let bytes = compress_zlib(&reshuffled);
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Zlib);
+        request_data.filters = Some(vec![
+            models::Filter::Shuffle { element_size: 4 },
+            models::Filter::Shuffle { element_size: 2 },
+        ]);
+        // Without a shape, shuffle decoding is not streamed.
+        for shape in [None, Some(vec![8])] {
+            request_data.shape = shape;
+            for chunk_size in [1, 7, bytes.len()] {
+                let result = stream(&request_data, &bytes, chunk_size).unwrap();
+                assert_eq!(data, result);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_pipeline_shape_mismatch() {
+        let data: Vec<u8> = (0..32).collect();
+        let shuffled = filters::shuffle::test_utils::shuffle(&data.into(), 4);
+        let bytes = compress_zlib(&shuffled);
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Zlib);
+        request_data.filters = Some(vec![models::Filter::Shuffle { element_size: 4 }]);
+        for shape in [vec![4], vec![16]] {
+            request_data.shape = Some(shape);
+            let err = stream(&request_data, &bytes, 5).unwrap_err();
+            assert!(matches!(
+                err,
+                ActiveStorageError::RequestDataValidationSingle(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_streaming_pipeline_truncated() {
+        let data: Vec<u8> = (0..32).collect();
+        let bytes = compress_gzip(&data);
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Gzip);
+        stream(&request_data, &bytes[..bytes.len() - 8], 5).unwrap_err();
+    }
+
+    /// Returns zlib-compressed zeros, without holding the uncompressed data in memory.
+    fn compress_zlib_zeros(size: usize) -> Bytes {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::fast());
+        let zeros = vec![0_u8; 1 << 20];
+        for _ in 0..size >> 20 {
+            std::io::Write::write_all(&mut encoder, &zeros).unwrap();
+        }
+        encoder.finish().unwrap().into()
+    }
+
+    // Run with `cargo test --release -- --ignored`.
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    #[ignore = "decompresses more than 4 GiB of data"]
+    fn test_streaming_pipeline_large() {
+        // Data beyond u32::MAX bytes is decompressed without truncation.
+        let size = (4 << 30) + (1 << 20);
+        let compressed = compress_zlib_zeros(size);
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.compression = Some(models::Compression::Zlib);
+        request_data.shape = Some(vec![size / request_data.dtype.size_of()]);
+        let data = stream(&request_data, &compressed, 1 << 16).unwrap();
+        assert_eq!(size, data.len());
+        assert!(data.iter().all(|byte| *byte == 0));
+    }
 }
@@ -1,5 +1,6 @@
 //! Filter implementations.
 
+pub mod bitshuffle;
 pub mod shuffle;
 
 use crate::error::ActiveStorageError;
@@ -15,6 +16,9 @@ use axum::body::Bytes;
 /// * `data`: Filtered data [Bytes]
 pub fn decode(filter: &models::Filter, data: &Bytes) -> Result<Bytes, ActiveStorageError> {
     match filter {
+        models::Filter::Bitshuffle { element_size } => {
+            bitshuffle::unbitshuffle(data, *element_size)
+        }
         models::Filter::Shuffle { element_size } => Ok(shuffle::deshuffle(data, *element_size)),
     }
 }
@@ -24,6 +28,15 @@ mod tests {
     use super::*;
     use crate::filters;
 
+    #[test]
+    fn test_decode_bitshuffle() {
+        let data: Vec<u8> = (0..64).collect();
+        let shuffled = filters::bitshuffle::test_utils::bitshuffle(&data, 4);
+        let filter = models::Filter::Bitshuffle { element_size: 4 };
+        let result = decode(&filter, &shuffled).unwrap();
+        assert_eq!(data, result);
+    }
+
     #[test]
     fn test_decode_shuffle() {
         let data = [1, 2, 3, 4, 5, 6, 7, 8];
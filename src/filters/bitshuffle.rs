@@ -0,0 +1,215 @@
+//! Bit shuffle filter
+
+use crate::error::ActiveStorageError;
+
+use axum::body::Bytes;
+
+/// Target size in bytes of each block of the blocked bit shuffle.
+const TARGET_BLOCK_SIZE: usize = 8192;
+/// The number of elements in each full block is a multiple of this.
+const BLOCKED_MULT: usize = 8;
+/// Minimum number of elements in each full block.
+const MIN_BLOCK_SIZE: usize = 128;
+
+/// Returns the number of elements in each block of the blocked bit shuffle, as chosen by the
+/// bitshuffle library when the block size is not specified.
+///
+/// # Arguments
+///
+/// * `element_size`: Size of each element in bytes.
+pub fn default_block_size(element_size: usize) -> usize {
+    let block_size = TARGET_BLOCK_SIZE / element_size / BLOCKED_MULT * BLOCKED_MULT;
+    block_size.max(MIN_BLOCK_SIZE)
+}
+
+/// Decode the bit shuffle filter.
+///
+/// The bit shuffle filter extends the byte shuffle to individual bits: within each block of
+/// elements, it writes the 0th bit of each element first, followed by the 1st bit of each element,
+/// and so on. This function inverts the filter as written by the
+/// [bitshuffle](https://github.com/kiyo-masui/bitshuffle) library and its HDF5 filter (ID 32008)
+/// without compression, using the default block size.
+///
+/// The data is split into blocks of [default_block_size] elements. A final partial block is
+/// shuffled in the largest multiple of 8 elements, and any remaining elements are not shuffled.
+///
+/// # Arguments
+///
+/// * `data`: `Bytes` to unshuffle.
+/// * `element_size`: Size of each element in bytes.
+pub fn unbitshuffle(data: &Bytes, element_size: usize) -> Result<Bytes, ActiveStorageError> {
+    if element_size == 0 || data.len() % element_size != 0 {
+        return Err(ActiveStorageError::FilterDataSize {
+            filter: "bitshuffle",
+            size: data.len(),
+            element_size,
+        });
+    }
+    let mut result = maligned::align_first::<u8, maligned::A8>(data.len());
+    result.resize(data.len(), 0);
+    let block_len = default_block_size(element_size) * element_size;
+    let mut src = &data[..];
+    let mut dest = &mut result[..];
+    while !src.is_empty() {
+        let num_elements = std::cmp::min(src.len(), block_len) / element_size;
+        let len = (num_elements - num_elements % BLOCKED_MULT) * element_size;
+        if len == 0 {
+            // Leftover elements are not shuffled.
+            dest.copy_from_slice(src);
+            break;
+        }
+        untranspose(&src[..len], &mut dest[..len], element_size);
+        src = &src[len..];
+        dest = &mut dest[len..];
+    }
+    Ok(result.into())
+}
+
+/// Reverses the bit transpose of a single block of elements.
+///
+/// The block contains a row of bits for each bit of each byte of an element, ordered by byte then
+/// by bit from least significant. Each row contains the corresponding bit of every element, packed
+/// into bytes from the least significant bit.
+///
+/// # Arguments
+///
+/// * `src`: Bit shuffled block, containing a multiple of 8 elements
+/// * `dest`: Buffer for the unshuffled block
+/// * `element_size`: Size of each element in bytes
+pub(crate) fn untranspose(src: &[u8], dest: &mut [u8], element_size: usize) {
+    let num_elements = src.len() / element_size;
+    debug_assert_eq!(num_elements % BLOCKED_MULT, 0);
+    let row_len = num_elements / 8;
+    for byte in 0..element_size {
+        let rows = &src[byte * 8 * row_len..][..8 * row_len];
+        for group in 0..row_len {
+            // Gather the same byte of each row, containing one bit of 8 consecutive elements.
+            let mut x = 0_u64;
+            for bit in 0..8 {
+                x |= (rows[bit * row_len + group] as u64) << (8 * bit);
+            }
+            for (index, value) in transpose_8x8(x).to_le_bytes().into_iter().enumerate() {
+                dest[(group * 8 + index) * element_size + byte] = value;
+            }
+        }
+    }
+}
+
+/// Transposes an 8x8 matrix of bits, with each byte a row and the least significant bit of each
+/// byte in the first column.
+///
+/// # Arguments
+///
+/// * `x`: Matrix to transpose
+fn transpose_8x8(mut x: u64) -> u64 {
+    let t = (x ^ (x >> 7)) & 0x00aa_00aa_00aa_00aa;
+    x = x ^ t ^ (t << 7);
+    let t = (x ^ (x >> 14)) & 0x0000_cccc_0000_cccc;
+    x = x ^ t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x0000_0000_f0f0_f0f0;
+    x ^ t ^ (t << 28)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn test_default_block_size() {
+        assert_eq!(8192, default_block_size(1));
+        assert_eq!(2048, default_block_size(4));
+        assert_eq!(1024, default_block_size(8));
+        assert_eq!(544, default_block_size(15));
+        assert_eq!(128, default_block_size(128));
+    }
+
+    #[test]
+    fn test_transpose_8x8() {
+        let x = 0x0123_4567_89ab_cdef;
+        assert_eq!(x, transpose_8x8(transpose_8x8(x)));
+        assert_eq!(0x0101_0101_0101_0101, transpose_8x8(0xff));
+        assert_eq!(0x8040_2010_0804_0201, transpose_8x8(0x8040_2010_0804_0201));
+    }
+
+    #[test]
+    fn test_unbitshuffle_1() {
+        // Each row of bits contains only the lowest bit of the first element.
+        let shuffled = Bytes::copy_from_slice(&[1, 1, 1, 1, 1, 1, 1, 1]);
+        let result = unbitshuffle(&shuffled, 1).unwrap();
+        assert_eq!([0xff, 0, 0, 0, 0, 0, 0, 0].as_ref(), result);
+        assert_eq!(0, result.as_ptr().align_offset(8));
+    }
+
+    #[test]
+    fn test_unbitshuffle_round_trip() {
+        for element_size in [1, 2, 4, 8, 3] {
+            // Multiple blocks, a partial block and leftover elements.
+            for num_elements in [0, 5, 8, 24, 8192 + 45] {
+                let data: Vec<u8> = (0..num_elements * element_size)
+                    .map(|i| (i * 7 + i / 3) as u8)
+                    .collect();
+                let shuffled = bitshuffle(&data, element_size);
+                assert_eq!(data.len(), shuffled.len());
+                let result = unbitshuffle(&shuffled, element_size).unwrap();
+                assert_eq!(data, result);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unbitshuffle_size_mismatch() {
+        let data = Bytes::copy_from_slice(&[0; 6]);
+        assert!(matches!(
+            unbitshuffle(&data, 4),
+            Err(ActiveStorageError::FilterDataSize {
+                filter: "bitshuffle",
+                size: 6,
+                element_size: 4
+            })
+        ));
+        unbitshuffle(&data, 0).unwrap_err();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::*;
+
+    /// Bit shuffles a block of elements, one bit at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `block`: Block containing a multiple of 8 elements
+    /// * `element_size`: Size of each element in bytes
+    pub(crate) fn transpose(block: &[u8], element_size: usize) -> Vec<u8> {
+        let num_elements = block.len() / element_size;
+        let mut result = vec![0; block.len()];
+        for element in 0..num_elements {
+            for bit in 0..element_size * 8 {
+                let value = (block[element * element_size + bit / 8] >> (bit % 8)) & 1;
+                let position = bit * num_elements + element;
+                result[position / 8] |= value << (position % 8);
+            }
+        }
+        result
+    }
+
+    // Bit shuffle isn't required for the server, but is useful for testing.
+    pub(crate) fn bitshuffle(data: &[u8], element_size: usize) -> Bytes {
+        let block_len = default_block_size(element_size) * element_size;
+        let mut result = Vec::with_capacity(data.len());
+        let mut data = data;
+        while !data.is_empty() {
+            let num_elements = std::cmp::min(data.len(), block_len) / element_size;
+            let len = (num_elements - num_elements % BLOCKED_MULT) * element_size;
+            if len == 0 {
+                result.extend_from_slice(data);
+                break;
+            }
+            result.extend(transpose(&data[..len], element_size));
+            data = &data[len..];
+        }
+        result.into()
+    }
+}
@@ -84,10 +84,104 @@ pub fn deshuffle(data: &Bytes, element_size: usize) -> Bytes {
     result.into()
 }
 
+/// Streaming decoder for the byte shuffle filter.
+///
+/// Each chunk of shuffled data is scattered into place as it arrives, which requires the size of
+/// the data to be known in advance. The result is returned in an 8-byte aligned buffer.
+pub struct Deshuffler {
+    /// Size of each element in bytes.
+    element_size: usize,
+    /// Number of elements.
+    num_elements: usize,
+    /// Number of bytes of shuffled data received.
+    position: usize,
+    /// Deshuffled data.
+    result: Vec<u8>,
+}
+
+impl Deshuffler {
+    /// Returns a new Deshuffler object.
+    ///
+    /// # Arguments
+    ///
+    /// * `element_size`: Size of each element in bytes.
+    /// * `len`: Size of the data in bytes, which must be a multiple of `element_size`.
+    pub fn new(element_size: usize, len: usize) -> Self {
+        assert_eq!(len % element_size, 0);
+        let mut result = maligned::align_first::<u8, maligned::A8>(len);
+        result.resize(len, 0);
+        Self {
+            element_size,
+            num_elements: len / element_size,
+            position: 0,
+            result,
+        }
+    }
+
+    /// Deshuffle a chunk of shuffled data.
+    ///
+    /// Returns false if the data exceeds the expected size, in which case the chunk and any
+    /// further chunks are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk`: Chunk of shuffled data
+    pub fn write(&mut self, mut chunk: &[u8]) -> bool {
+        if self.position + chunk.len() > self.result.len() {
+            self.position = self.position.saturating_add(chunk.len());
+            return false;
+        }
+        while !chunk.is_empty() {
+            // The shuffled data contains the same byte of each element in turn.
+            let byte = self.position / self.num_elements;
+            let element = self.position % self.num_elements;
+            let run = std::cmp::min(self.num_elements - element, chunk.len());
+            let dest = self.result[element * self.element_size + byte..]
+                .iter_mut()
+                .step_by(self.element_size);
+            for (dest, src) in dest.zip(&chunk[..run]) {
+                *dest = *src;
+            }
+            self.position += run;
+            chunk = &chunk[run..];
+        }
+        true
+    }
+
+    /// Returns the deshuffled data, or None if not all of the data has been received.
+    pub fn finish(self) -> Option<Bytes> {
+        (self.position == self.result.len()).then(|| self.result.into())
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
 
+    #[test]
+    fn test_deshuffler() {
+        let data: Vec<u8> = (0..24).collect();
+        let shuffled = test_utils::shuffle(&Bytes::copy_from_slice(&data), 4);
+        for chunk_size in [1, 5, 6, 24] {
+            let mut deshuffler = Deshuffler::new(4, shuffled.len());
+            for chunk in shuffled.chunks(chunk_size) {
+                assert!(deshuffler.write(chunk));
+            }
+            let result = deshuffler.finish().unwrap();
+            assert_eq!(data, result);
+            assert_eq!(0, result.as_ptr().align_offset(8));
+        }
+    }
+
+    #[test]
+    fn test_deshuffler_size_mismatch() {
+        let mut deshuffler = Deshuffler::new(2, 4);
+        assert!(deshuffler.write(&[0, 1, 2]));
+        assert!(!deshuffler.write(&[3, 4]));
+        assert!(!deshuffler.write(&[3]));
+        assert!(deshuffler.finish().is_none());
+    }
+
     #[test]
     fn test_deshuffle_2() {
         let shuffled = [0, 2, 4, 6, 1, 3, 5, 7];
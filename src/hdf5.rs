@@ -0,0 +1,2498 @@
+//! HDF5 chunk index
+//!
+//! Operations on a dataset in an [HDF5](https://www.hdfgroup.org/solutions/hdf5/) file, such as a
+//! netCDF4 file, are performed without the client describing the byte range of each chunk. The
+//! file's superblock, the object headers on the path to the dataset and the dataset's chunk index
+//! are read from the object store, and the operation is then performed on each chunk intersecting
+//! the selection in the same way as for a [Zarr array](crate::zarr).
+//!
+//! The following subset of the
+//! [HDF5 file format](https://docs.hdfgroup.org/hdf5/develop/_f_m_t3.html) is supported:
+//!
+//! * Superblock versions 0 to 3
+//! * Object header versions 1 and 2
+//! * Groups using a symbol table, and groups with compact or dense link storage
+//! * Contiguous datasets, and chunked datasets indexed by a version 1 B-tree, with a single chunk
+//!   or with implicit indexing
+//! * Fixed-point and IEEE floating-point data types
+//! * Deflate, shuffle, Fletcher32, Blosc, bit shuffle (without compression) and Zstandard
+//!   filters
+//!
+//! Metadata is read in pages, which are cached while the dataset is opened. Checksums of the
+//! metadata and chunks are not verified.
+
+use crate::error::ActiveStorageError;
+use crate::filters::bitshuffle;
+use crate::models::{Compression, DType, Filter, Hdf5RequestData, Order, RequestData};
+use crate::types::{ByteOrder, NATIVE_BYTE_ORDER};
+use crate::zarr::{ArrayMetadata, ChunkSelection};
+
+use axum::body::Bytes;
+use std::collections::HashMap;
+
+/// Signature at the start of the superblock
+const SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+
+/// Size in bytes of the pages in which metadata is read
+const PAGE_SIZE: u64 = 16 * 1024;
+
+/// Maximum number of pages of metadata cached while opening a dataset
+const MAX_CACHED_PAGES: usize = 1024;
+
+/// Maximum number of B-tree nodes visited while opening a dataset, bounding the work done for a
+/// corrupt B-tree
+const MAX_NODES: usize = 1 << 20;
+
+/// Maximum number of blocks in an object header
+const MAX_HEADER_BLOCKS: usize = 1024;
+
+/// Maximum size in bytes of a structure read from the file, such as a local heap or a block of
+/// object header messages
+const MAX_STRUCTURE_SIZE: usize = 64 << 20;
+
+// Object header message types
+const MSG_DATASPACE: u16 = 0x01;
+const MSG_LINK_INFO: u16 = 0x02;
+const MSG_DATATYPE: u16 = 0x03;
+const MSG_FILL_VALUE_OLD: u16 = 0x04;
+const MSG_FILL_VALUE: u16 = 0x05;
+const MSG_LINK: u16 = 0x06;
+const MSG_LAYOUT: u16 = 0x08;
+const MSG_FILTER_PIPELINE: u16 = 0x0b;
+const MSG_CONTINUATION: u16 = 0x10;
+const MSG_SYMBOL_TABLE: u16 = 0x11;
+
+// Filter identifiers
+const FILTER_DEFLATE: u16 = 1;
+const FILTER_SHUFFLE: u16 = 2;
+const FILTER_FLETCHER32: u16 = 3;
+const FILTER_BLOSC: u16 = 32001;
+const FILTER_BITSHUFFLE: u16 = 32008;
+const FILTER_ZSTD: u16 = 32015;
+
+/// Size in bytes of the Fletcher32 checksum at the end of a chunk
+const CHECKSUM_SIZE: u64 = 4;
+
+/// Size in bytes of the signature, version, type and checksum of a version 2 B-tree node
+const BTREE_V2_PREFIX_SIZE: usize = 10;
+
+/// Version 2 B-tree record type of the link name index of a group
+const BTREE_V2_LINK_NAME: u8 = 5;
+
+/// Returns an [ActiveStorageError::Hdf5Invalid] error.
+fn invalid(reason: impl Into<String>) -> ActiveStorageError {
+    ActiveStorageError::Hdf5Invalid {
+        reason: reason.into(),
+    }
+}
+
+/// Returns an address plus an offset, or an error if the sum overflows.
+///
+/// # Arguments
+///
+/// * `address`: Address in the file
+/// * `offset`: Offset from the address in bytes
+fn offset_address(address: u64, offset: u64) -> Result<u64, ActiveStorageError> {
+    address
+        .checked_add(offset)
+        .ok_or_else(|| invalid("address out of range"))
+}
+
+/// Returns the size in bytes of an array, or an error if it overflows.
+///
+/// # Arguments
+///
+/// * `shape`: Shape of the array
+/// * `element_size`: Size in bytes of each element
+fn array_size(shape: &[usize], element_size: u64) -> Result<u64, ActiveStorageError> {
+    shape
+        .iter()
+        .try_fold(element_size, |size, &length| {
+            size.checked_mul(length as u64)
+        })
+        .ok_or_else(|| invalid("array size out of range"))
+}
+
+/// A source of the bytes of an HDF5 file
+pub(crate) trait ReadAt {
+    /// Read a byte range of the file, which may be shorter than requested at the end of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset`: Offset of the range in bytes
+    /// * `len`: Length of the range in bytes
+    async fn read_at(&self, offset: u64, len: u64) -> Result<Bytes, ActiveStorageError>;
+}
+
+/// A cursor over the bytes of a structure in the file
+struct Cursor<'a> {
+    data: &'a [u8],
+    /// Size in bytes of addresses
+    offset_size: usize,
+    /// Size in bytes of lengths
+    length_size: usize,
+}
+
+impl<'a> Cursor<'a> {
+    /// Returns the next `len` bytes.
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ActiveStorageError> {
+        if len > self.data.len() {
+            return Err(invalid("truncated structure"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Skips the next `len` bytes.
+    fn skip(&mut self, len: usize) -> Result<(), ActiveStorageError> {
+        self.bytes(len).map(|_| ())
+    }
+
+    /// Returns the next byte.
+    fn u8(&mut self) -> Result<u8, ActiveStorageError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Returns the next little-endian 16-bit integer.
+    fn u16(&mut self) -> Result<u16, ActiveStorageError> {
+        Ok(self.uint(2)? as u16)
+    }
+
+    /// Returns the next little-endian 32-bit integer.
+    fn u32(&mut self) -> Result<u32, ActiveStorageError> {
+        Ok(self.uint(4)? as u32)
+    }
+
+    /// Returns the next little-endian integer of `size` bytes.
+    fn uint(&mut self, size: usize) -> Result<u64, ActiveStorageError> {
+        if size > 8 {
+            return Err(invalid(format!("unsupported integer size {}", size)));
+        }
+        let bytes = self.bytes(size)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| (value << 8) | byte as u64))
+    }
+
+    /// Returns the next address, or `None` if it is undefined.
+    fn address(&mut self) -> Result<Option<u64>, ActiveStorageError> {
+        let bytes = self.bytes(self.offset_size)?;
+        if bytes.iter().all(|&byte| byte == 0xff) {
+            return Ok(None);
+        }
+        Ok(Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, &byte| (value << 8) | byte as u64),
+        ))
+    }
+
+    /// Returns the next length.
+    fn length(&mut self) -> Result<u64, ActiveStorageError> {
+        self.uint(self.length_size)
+    }
+
+    /// Checks the signature of a structure.
+    fn signature(&mut self, signature: &[u8]) -> Result<(), ActiveStorageError> {
+        if self.bytes(signature.len())? != signature {
+            return Err(invalid(format!(
+                "missing {} signature",
+                String::from_utf8_lossy(signature)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of remaining bytes.
+    fn remaining(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// A message in an object header
+struct Message {
+    /// Message type
+    kind: u16,
+    /// Whether the message is stored in another object header or the shared message heap
+    shared: bool,
+    /// Message data
+    data: Bytes,
+}
+
+/// Returns the first message of a type in an object header.
+///
+/// # Arguments
+///
+/// * `messages`: Messages in the object header
+/// * `kind`: Message type
+fn find(messages: &[Message], kind: u16) -> Result<Option<&Message>, ActiveStorageError> {
+    match messages.iter().find(|message| message.kind == kind) {
+        Some(message) if message.shared => Err(invalid(format!(
+            "shared object header messages of type {} are not supported",
+            kind
+        ))),
+        message => Ok(message),
+    }
+}
+
+/// A link from a group to an object
+struct Link {
+    name: Vec<u8>,
+    /// Address of the object header of a hard link, or `None` for soft and external links
+    address: Option<u64>,
+}
+
+/// A fractal heap, used to store the links of groups with dense link storage
+struct FractalHeap {
+    /// Size in bytes of the offsets in heap IDs and block headers
+    offset_size: usize,
+    /// Size in bytes of the lengths in heap IDs
+    length_size: usize,
+    /// Number of blocks in each row of the doubling table
+    table_width: u64,
+    /// Size in bytes of the blocks in the first row of the doubling table
+    start_block_size: u64,
+    /// Maximum size in bytes of a direct block
+    max_direct_block_size: u64,
+    /// Address of the root block
+    root: Option<u64>,
+    /// Number of rows in the root indirect block, or zero if the root block is a direct block
+    root_rows: u64,
+}
+
+/// Location of a chunk within the object
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ChunkLocation {
+    /// Offset of the chunk in bytes
+    offset: u64,
+    /// Size of the chunk in bytes
+    size: u64,
+    /// Mask of the filters in the pipeline that were skipped for the chunk
+    filter_mask: u32,
+}
+
+/// Index of the chunks of a dataset
+#[derive(Debug, PartialEq)]
+enum ChunkIndex {
+    /// Location of each allocated chunk, keyed by its coordinates in the chunk grid
+    Map(HashMap<Vec<usize>, ChunkLocation>),
+    /// Unfiltered chunks stored contiguously in C order
+    Implicit {
+        /// Offset of the first chunk in bytes
+        offset: u64,
+        /// Number of chunks in each dimension
+        grid: Vec<usize>,
+        /// Size of each chunk in bytes
+        size: u64,
+    },
+}
+
+/// Storage layout of a dataset, from its data layout message
+enum Layout {
+    /// All elements stored contiguously
+    Contiguous { address: Option<u64>, size: u64 },
+    /// Elements stored in chunks
+    Chunked {
+        /// Shape of each chunk, followed by the element size
+        dimensions: Vec<u64>,
+        /// Index of the chunks
+        index: LayoutIndex,
+    },
+}
+
+/// Chunk index of a chunked dataset, from its data layout message
+enum LayoutIndex {
+    /// Version 1 B-tree
+    BTreeV1(Option<u64>),
+    /// A single chunk, the size of the dataset
+    Single {
+        address: Option<u64>,
+        /// Size of the chunk in bytes, if it is filtered
+        filtered_size: Option<u64>,
+        filter_mask: u32,
+    },
+    /// Chunks stored contiguously in C order
+    Implicit(Option<u64>),
+}
+
+/// A filter in a dataset's filter pipeline
+#[derive(Clone, Debug, PartialEq)]
+struct PipelineFilter {
+    /// Filter identifier
+    id: u16,
+    /// Parameters of the filter
+    client_data: Vec<u32>,
+}
+
+/// Compression and filters of a chunk
+#[derive(Debug, Default, PartialEq)]
+struct Codecs {
+    compression: Option<Compression>,
+    /// Filters applied before compression
+    filters: Vec<Filter>,
+    /// Whether a Fletcher32 checksum follows the chunk data
+    checksum: bool,
+}
+
+/// A dataset in an HDF5 file
+#[derive(Debug, PartialEq)]
+pub struct Dataset {
+    /// Shape, chunk shape, data type and fill value of the dataset, described in the same way as
+    /// a Zarr array
+    pub metadata: ArrayMetadata,
+    /// Location of each chunk
+    index: ChunkIndex,
+    /// Filters applied to each chunk, in the order in which they were applied when writing
+    pipeline: Vec<PipelineFilter>,
+}
+
+impl Dataset {
+    /// Returns the location of a chunk, or `None` if it has not been written.
+    ///
+    /// # Arguments
+    ///
+    /// * `coords`: Coordinates of the chunk in the chunk grid
+    fn chunk(&self, coords: &[usize]) -> Option<ChunkLocation> {
+        match &self.index {
+            ChunkIndex::Map(chunks) => chunks.get(coords).copied(),
+            ChunkIndex::Implicit { offset, grid, size } => {
+                let index = coords
+                    .iter()
+                    .zip(grid)
+                    .fold(0, |index, (&coord, &length)| index * length + coord);
+                Some(ChunkLocation {
+                    offset: offset + index as u64 * size,
+                    size: *size,
+                    filter_mask: 0,
+                })
+            }
+        }
+    }
+
+    /// Returns whether a chunk has been written.
+    ///
+    /// # Arguments
+    ///
+    /// * `coords`: Coordinates of the chunk in the chunk grid
+    pub fn is_allocated(&self, coords: &[usize]) -> bool {
+        self.chunk(coords).is_some()
+    }
+
+    /// Returns request data for an operation on the selection within a chunk.
+    ///
+    /// Chunks that have not been written have no byte range, and should be filled using
+    /// [ArrayMetadata::fill_chunk].
+    ///
+    /// # Arguments
+    ///
+    /// * `request`: Request data for the operation on the dataset
+    /// * `chunk`: Selection within the chunk
+    pub fn chunk_request(
+        &self,
+        request: &Hdf5RequestData,
+        chunk: &ChunkSelection,
+    ) -> Result<RequestData, ActiveStorageError> {
+        let metadata = &self.metadata;
+        let mut builder = RequestData::builder(
+            request.source.clone(),
+            request.bucket.clone(),
+            request.object.clone(),
+            metadata.dtype,
+        )
+        .requester_pays(request.requester_pays);
+        // Scalar datasets are treated as one-dimensional arrays with a single element.
+        if !metadata.shape.is_empty() {
+            builder = builder
+                .shape(metadata.chunks.clone())
+                .selection(chunk.selection.iter().map(|&slice| slice.into()).collect());
+        }
+        if let Some(byte_order) = metadata.byte_order {
+            builder = builder.byte_order(byte_order);
+        }
+        if let Some(location) = self.chunk(&chunk.coords) {
+            let codecs = codecs(
+                &self.pipeline,
+                location.filter_mask,
+                metadata.dtype.size_of(),
+            )?;
+            let size = if codecs.checksum {
+                location
+                    .size
+                    .checked_sub(CHECKSUM_SIZE)
+                    .ok_or_else(|| invalid("chunk is smaller than its checksum"))?
+            } else {
+                location.size
+            };
+            builder = builder
+                .offset(usize::try_from(location.offset)?)
+                .size(usize::try_from(size)?);
+            if let Some(compression) = codecs.compression {
+                builder = builder.compression(compression);
+            }
+            if !codecs.filters.is_empty() {
+                builder = builder.filters(codecs.filters);
+            }
+        }
+        if let Some(missing) = &request.missing {
+            builder = builder.missing(missing.clone());
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Open a dataset in an HDF5 file.
+///
+/// # Arguments
+///
+/// * `reader`: Source of the bytes of the file
+/// * `superblock_offset`: Offset of the superblock in bytes, which is the base address of the
+///   file
+/// * `path`: Path of the dataset within the file
+pub(crate) async fn open<R: ReadAt>(
+    reader: R,
+    superblock_offset: u64,
+    path: &str,
+) -> Result<Dataset, ActiveStorageError> {
+    let mut file = File::open(reader, superblock_offset).await?;
+    let mut address = file.root;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        address = file
+            .lookup(address, name)
+            .await?
+            .ok_or_else(|| invalid(format!("no object at path {}", path)))?;
+    }
+    file.dataset(address, path).await
+}
+
+/// An HDF5 file being read
+struct File<R> {
+    reader: R,
+    /// Cached pages of the file, keyed by their index
+    pages: HashMap<u64, Bytes>,
+    /// Number of B-tree nodes visited
+    nodes: usize,
+    /// Offset in bytes of the base address, to which addresses in the file are relative
+    base: u64,
+    /// Size in bytes of addresses
+    offset_size: usize,
+    /// Size in bytes of lengths
+    length_size: usize,
+    /// Address of the root group's object header
+    root: u64,
+}
+
+impl<R: ReadAt> File<R> {
+    /// Open a file by reading its superblock.
+    ///
+    /// The base address is taken to be the offset of the superblock, as it is by the HDF5
+    /// library when the two differ.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader`: Source of the bytes of the file
+    /// * `superblock_offset`: Offset of the superblock in bytes
+    async fn open(reader: R, superblock_offset: u64) -> Result<Self, ActiveStorageError> {
+        let mut file = File {
+            reader,
+            pages: HashMap::new(),
+            nodes: 0,
+            base: superblock_offset,
+            offset_size: 8,
+            length_size: 8,
+            root: 0,
+        };
+        let prefix = file.read(0, SIGNATURE.len() + 8).await?;
+        if !prefix.starts_with(SIGNATURE) {
+            return Err(invalid("no superblock at the superblock offset"));
+        }
+        let version = prefix[SIGNATURE.len()];
+        let (sizes, size) = match version {
+            // Version 0 and 1 superblocks contain the versions of other structures first.
+            0 | 1 => (&prefix[13..15], 24 + 4 * version as usize),
+            2 | 3 => (&prefix[9..11], 12),
+            _ => {
+                return Err(invalid(format!(
+                    "unsupported superblock version {}",
+                    version
+                )))
+            }
+        };
+        file.offset_size = sizes[0] as usize;
+        file.length_size = sizes[1] as usize;
+        for size in [file.offset_size, file.length_size] {
+            if ![2, 4, 8].contains(&size) {
+                return Err(invalid(format!(
+                    "unsupported address or length size {}",
+                    size
+                )));
+            }
+        }
+        let addresses = if version < 2 { 6 } else { 4 };
+        let data = file.read(0, size + addresses * file.offset_size).await?;
+        let mut cursor = file.cursor(&data);
+        cursor.skip(size)?;
+        // Skip the base, free-space or superblock extension, and end of file addresses.
+        cursor.skip(3 * file.offset_size)?;
+        if version < 2 {
+            // Skip the driver information block address, and the link name offset of the root
+            // group's symbol table entry.
+            cursor.skip(2 * file.offset_size)?;
+        }
+        file.root = cursor
+            .address()?
+            .ok_or_else(|| invalid("undefined root group address"))?;
+        Ok(file)
+    }
+
+    /// Returns a cursor over a structure in the file.
+    fn cursor<'a>(&self, data: &'a [u8]) -> Cursor<'a> {
+        Cursor {
+            data,
+            offset_size: self.offset_size,
+            length_size: self.length_size,
+        }
+    }
+
+    /// Read a structure from the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: Address of the structure
+    /// * `len`: Size of the structure in bytes
+    async fn read(&mut self, address: u64, len: usize) -> Result<Bytes, ActiveStorageError> {
+        if len > MAX_STRUCTURE_SIZE {
+            return Err(invalid(format!(
+                "structure of {} bytes exceeds the maximum size",
+                len
+            )));
+        }
+        let start = offset_address(self.base, address)?;
+        let end = offset_address(start, len as u64)?;
+        if len == 0 {
+            return Ok(Bytes::new());
+        }
+        let (first, last) = (start / PAGE_SIZE, (end - 1) / PAGE_SIZE);
+        // The data grows as each page is read, so that a structure extending beyond the end of
+        // the file is rejected before its size is allocated.
+        let mut data = Vec::new();
+        for index in first..=last {
+            let page = self.page(index).await?;
+            let page_start = index * PAGE_SIZE;
+            let from = start.saturating_sub(page_start) as usize;
+            let to = std::cmp::min(end - page_start, PAGE_SIZE) as usize;
+            if page.len() < to {
+                return Err(invalid("structure extends beyond the end of the file"));
+            }
+            if first == last {
+                return Ok(page.slice(from..to));
+            }
+            data.extend_from_slice(&page[from..to]);
+        }
+        Ok(data.into())
+    }
+
+    /// Returns a page of the file, reading it if it is not cached.
+    ///
+    /// # Arguments
+    ///
+    /// * `index`: Index of the page
+    async fn page(&mut self, index: u64) -> Result<Bytes, ActiveStorageError> {
+        if let Some(page) = self.pages.get(&index) {
+            return Ok(page.clone());
+        }
+        if self.pages.len() >= MAX_CACHED_PAGES {
+            self.pages.clear();
+        }
+        let page = self.reader.read_at(index * PAGE_SIZE, PAGE_SIZE).await?;
+        self.pages.insert(index, page.clone());
+        Ok(page)
+    }
+
+    /// Count a visit to a B-tree node.
+    fn visit(&mut self) -> Result<(), ActiveStorageError> {
+        self.nodes += 1;
+        if self.nodes > MAX_NODES {
+            return Err(invalid("too many B-tree nodes"));
+        }
+        Ok(())
+    }
+
+    /// Returns the messages of an object header.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: Address of the object header
+    async fn object_header(&mut self, address: u64) -> Result<Vec<Message>, ActiveStorageError> {
+        let prefix = self.read(address, 16).await?;
+        let mut cursor = self.cursor(&prefix);
+        // Blocks of messages, as the address and size of their messages.
+        let mut blocks = vec![];
+        let version = if prefix.starts_with(b"OHDR") {
+            cursor.skip(4)?;
+            let version = cursor.u8()?;
+            if version != 2 {
+                return Err(invalid(format!(
+                    "unsupported object header version {}",
+                    version
+                )));
+            }
+            let flags = cursor.u8()?;
+            let mut size = 6;
+            if flags & 0x20 != 0 {
+                // Access, modification, change and birth times
+                size += 16;
+            }
+            if flags & 0x10 != 0 {
+                // Maximum compact and minimum dense attribute counts
+                size += 4;
+            }
+            let size_size = 1 << (flags & 0x03);
+            let data = self.read(address, size + size_size).await?;
+            let mut cursor = self.cursor(&data);
+            cursor.skip(size)?;
+            let len = cursor.uint(size_size)?;
+            blocks.push((offset_address(address, (size + size_size) as u64)?, len));
+            // The creation order of each message is tracked.
+            let creation_order = flags & 0x04 != 0;
+            Some(creation_order)
+        } else {
+            let version = cursor.u8()?;
+            if version != 1 {
+                return Err(invalid(format!(
+                    "unsupported object header version {}",
+                    version
+                )));
+            }
+            // Skip the reserved byte, number of messages and reference count.
+            cursor.skip(7)?;
+            let len = cursor.u32()?;
+            // Messages are aligned to 8 bytes, following the 12 byte prefix.
+            blocks.push((offset_address(address, 16)?, len as u64));
+            None
+        };
+        let mut messages = vec![];
+        let mut index = 0;
+        while let Some(&(address, len)) = blocks.get(index) {
+            if index >= MAX_HEADER_BLOCKS {
+                return Err(invalid("too many object header blocks"));
+            }
+            let data = self.read(address, usize::try_from(len)?).await?;
+            let mut cursor = self.cursor(&data);
+            if index > 0 && version.is_some() {
+                // Continuation blocks have a signature, and include their checksum.
+                cursor.signature(b"OCHK")?;
+                cursor.data = &cursor.data[..cursor.remaining().saturating_sub(4)];
+            }
+            let header_size = match version {
+                Some(true) => 6,
+                Some(false) => 4,
+                None => 8,
+            };
+            // Any remaining space that is too small for a message is a gap.
+            while cursor.remaining() >= header_size {
+                let (kind, len, flags) = match version {
+                    Some(creation_order) => {
+                        let kind = cursor.u8()? as u16;
+                        let len = cursor.u16()?;
+                        let flags = cursor.u8()?;
+                        if creation_order {
+                            cursor.skip(2)?;
+                        }
+                        (kind, len, flags)
+                    }
+                    None => {
+                        let kind = cursor.u16()?;
+                        let len = cursor.u16()?;
+                        let flags = cursor.u8()?;
+                        cursor.skip(3)?;
+                        (kind, len, flags)
+                    }
+                };
+                let body = cursor.bytes(len as usize)?;
+                if kind == MSG_CONTINUATION {
+                    let mut cursor = self.cursor(body);
+                    let address = cursor
+                        .address()?
+                        .ok_or_else(|| invalid("undefined continuation block address"))?;
+                    let len = cursor.length()?;
+                    blocks.push((address, len));
+                } else {
+                    messages.push(Message {
+                        kind,
+                        shared: flags & 0x02 != 0,
+                        data: data.slice_ref(body),
+                    });
+                }
+            }
+            index += 1;
+        }
+        Ok(messages)
+    }
+
+    /// Returns the address of the object header of a link in a group, or `None` if the group has
+    /// no such link.
+    ///
+    /// # Arguments
+    ///
+    /// * `group`: Address of the group's object header
+    /// * `name`: Name of the link
+    async fn lookup(&mut self, group: u64, name: &str) -> Result<Option<u64>, ActiveStorageError> {
+        let messages = self.object_header(group).await?;
+        let link = if let Some(message) = find(&messages, MSG_SYMBOL_TABLE)? {
+            let mut cursor = self.cursor(&message.data);
+            let btree = cursor.address()?;
+            let heap = cursor.address()?;
+            match (btree, heap) {
+                (Some(btree), Some(heap)) => self.symbol_table_lookup(btree, heap, name).await?,
+                _ => return Err(invalid("undefined symbol table address")),
+            }
+        } else {
+            let dense = match find(&messages, MSG_LINK_INFO)? {
+                Some(message) => {
+                    let mut cursor = self.cursor(&message.data);
+                    cursor.skip(1)?;
+                    let flags = cursor.u8()?;
+                    if flags & 0x01 != 0 {
+                        // Maximum creation index
+                        cursor.skip(8)?;
+                    }
+                    let heap = cursor.address()?;
+                    let btree = cursor.address()?;
+                    heap.zip(btree)
+                }
+                None => None,
+            };
+            match dense {
+                Some((heap, btree)) => self.dense_lookup(heap, btree, name).await?,
+                None => {
+                    let mut found = None;
+                    for message in messages.iter().filter(|message| message.kind == MSG_LINK) {
+                        let link = parse_link(&mut self.cursor(&message.data))?;
+                        if link.name == name.as_bytes() {
+                            found = Some(link);
+                            break;
+                        }
+                    }
+                    found
+                }
+            }
+        };
+        match link {
+            Some(Link { address: None, .. }) => Err(invalid(format!(
+                "soft and external link {} is not supported",
+                name
+            ))),
+            link => Ok(link.and_then(|link| link.address)),
+        }
+    }
+
+    /// Returns a node of a version 1 B-tree, as its level, keys and child addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: Address of the node
+    /// * `node_type`: Expected type of the node
+    /// * `key_size`: Size in bytes of each key
+    /// * `level`: Expected level of the node, if known
+    async fn node_v1(
+        &mut self,
+        address: u64,
+        node_type: u8,
+        key_size: usize,
+        level: Option<u8>,
+    ) -> Result<(u8, Vec<Bytes>, Vec<u64>), ActiveStorageError> {
+        self.visit()?;
+        let header_size = 8 + 2 * self.offset_size;
+        let header = self.read(address, header_size).await?;
+        let mut cursor = self.cursor(&header);
+        cursor.signature(b"TREE")?;
+        if cursor.u8()? != node_type {
+            return Err(invalid("unexpected B-tree node type"));
+        }
+        let node_level = cursor.u8()?;
+        if level.is_some_and(|level| level != node_level) {
+            return Err(invalid("unexpected B-tree node level"));
+        }
+        let entries = cursor.u16()? as usize;
+        let len = entries * (key_size + self.offset_size) + key_size;
+        let data = self
+            .read(offset_address(address, header_size as u64)?, len)
+            .await?;
+        let mut cursor = self.cursor(&data);
+        let mut keys = vec![];
+        let mut children = vec![];
+        for _ in 0..entries {
+            keys.push(data.slice_ref(cursor.bytes(key_size)?));
+            let child = cursor
+                .address()?
+                .ok_or_else(|| invalid("undefined B-tree child address"))?;
+            children.push(child);
+        }
+        Ok((node_level, keys, children))
+    }
+
+    /// Returns the link with a name in a group using a symbol table.
+    ///
+    /// # Arguments
+    ///
+    /// * `btree`: Address of the symbol table's B-tree
+    /// * `heap`: Address of the local heap containing link names
+    /// * `name`: Name of the link
+    async fn symbol_table_lookup(
+        &mut self,
+        btree: u64,
+        heap: u64,
+        name: &str,
+    ) -> Result<Option<Link>, ActiveStorageError> {
+        let data = self
+            .read(heap, 8 + 2 * self.length_size + self.offset_size)
+            .await?;
+        let mut cursor = self.cursor(&data);
+        cursor.signature(b"HEAP")?;
+        cursor.skip(4)?;
+        let size = cursor.length()?;
+        // Skip the offset of the free list.
+        cursor.length()?;
+        let address = cursor
+            .address()?
+            .ok_or_else(|| invalid("undefined local heap data address"))?;
+        let names = self.read(address, usize::try_from(size)?).await?;
+        let entry_size = 2 * self.offset_size + 24;
+        let mut nodes = vec![(btree, None)];
+        while let Some((address, level)) = nodes.pop() {
+            let (level, _, children) = self.node_v1(address, 0, self.length_size, level).await?;
+            if level > 0 {
+                nodes.extend(children.into_iter().map(|child| (child, Some(level - 1))));
+                continue;
+            }
+            for child in children {
+                let header = self.read(child, 8).await?;
+                let mut cursor = self.cursor(&header);
+                cursor.signature(b"SNOD")?;
+                cursor.skip(2)?;
+                let count = cursor.u16()? as usize;
+                let data = self
+                    .read(offset_address(child, 8)?, count * entry_size)
+                    .await?;
+                let mut cursor = self.cursor(&data);
+                for _ in 0..count {
+                    let offset = cursor.uint(self.offset_size)? as usize;
+                    let address = cursor.address()?;
+                    // Skip the cache type, reserved bytes and scratch-pad.
+                    cursor.skip(24)?;
+                    let entry_name = names
+                        .get(offset..)
+                        .and_then(|names| names.split(|&byte| byte == 0).next())
+                        .ok_or_else(|| invalid("link name offset out of range"))?;
+                    if entry_name == name.as_bytes() {
+                        return Ok(Some(Link {
+                            name: entry_name.to_vec(),
+                            address,
+                        }));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the link with a name in a group with dense link storage.
+    ///
+    /// # Arguments
+    ///
+    /// * `heap`: Address of the fractal heap containing the links
+    /// * `btree`: Address of the version 2 B-tree indexing the links by name
+    /// * `name`: Name of the link
+    async fn dense_lookup(
+        &mut self,
+        heap: u64,
+        btree: u64,
+        name: &str,
+    ) -> Result<Option<Link>, ActiveStorageError> {
+        let heap = self.fractal_heap(heap).await?;
+        for record in self.records_v2(btree, BTREE_V2_LINK_NAME).await? {
+            // Skip the hash of the name.
+            let id = record
+                .get(4..)
+                .ok_or_else(|| invalid("truncated link name record"))?;
+            let object = self.heap_object(&heap, id).await?;
+            let link = parse_link(&mut self.cursor(&object))?;
+            if link.name == name.as_bytes() {
+                return Ok(Some(link));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the header of a fractal heap.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: Address of the fractal heap header
+    async fn fractal_heap(&mut self, address: u64) -> Result<FractalHeap, ActiveStorageError> {
+        let len = 22 + 12 * self.length_size + 3 * self.offset_size;
+        let data = self.read(address, len).await?;
+        let mut cursor = self.cursor(&data);
+        cursor.signature(b"FRHP")?;
+        cursor.skip(3)?;
+        if cursor.u16()? != 0 {
+            return Err(invalid("filtered fractal heaps are not supported"));
+        }
+        cursor.skip(1)?;
+        let max_managed_size = cursor.u32()? as u64;
+        // Skip the statistics and addresses of huge objects and free space.
+        cursor.skip(10 * self.length_size + 2 * self.offset_size)?;
+        let table_width = cursor.u16()? as u64;
+        let start_block_size = cursor.length()?;
+        let max_direct_block_size = cursor.length()?;
+        let max_heap_size = cursor.u16()? as usize;
+        cursor.skip(2)?;
+        let root = cursor.address()?;
+        let root_rows = cursor.u16()? as u64;
+        if table_width == 0
+            || !start_block_size.is_power_of_two()
+            || !max_direct_block_size.is_power_of_two()
+            || start_block_size > max_direct_block_size
+            || max_heap_size > 64
+        {
+            return Err(invalid("invalid fractal heap doubling table"));
+        }
+        Ok(FractalHeap {
+            offset_size: max_heap_size.div_ceil(8),
+            length_size: std::cmp::min(
+                (max_direct_block_size.trailing_zeros() as usize).div_ceil(8),
+                encoded_size(max_managed_size),
+            ),
+            table_width,
+            start_block_size,
+            max_direct_block_size,
+            root,
+            root_rows,
+        })
+    }
+
+    /// Returns an object in a fractal heap.
+    ///
+    /// # Arguments
+    ///
+    /// * `heap`: Header of the fractal heap
+    /// * `id`: Heap ID of the object
+    async fn heap_object(
+        &mut self,
+        heap: &FractalHeap,
+        id: &[u8],
+    ) -> Result<Bytes, ActiveStorageError> {
+        let mut cursor = self.cursor(id);
+        let flags = cursor.u8()?;
+        if flags & 0xc0 != 0 {
+            return Err(invalid("unsupported fractal heap ID version"));
+        }
+        match (flags >> 4) & 0x03 {
+            0 => (),
+            2 => {
+                // Tiny objects are stored in the heap ID.
+                let len = (flags & 0x0f) as usize + 1;
+                return Ok(Bytes::copy_from_slice(cursor.bytes(len)?));
+            }
+            _ => return Err(invalid("huge fractal heap objects are not supported")),
+        }
+        let offset = cursor.uint(heap.offset_size)?;
+        let len = cursor.uint(heap.length_size)?;
+        let root = heap
+            .root
+            .ok_or_else(|| invalid("fractal heap has no root block"))?;
+        if heap.root_rows == 0 {
+            return self
+                .read(offset_address(root, offset)?, usize::try_from(len)?)
+                .await;
+        }
+        // The blocks in each row of the doubling table are the same size, which doubles in each
+        // row after the second.
+        let max_direct_rows =
+            heap.max_direct_block_size
+                .trailing_zeros()
+                .checked_sub(heap.start_block_size.trailing_zeros())
+                .ok_or_else(|| invalid("invalid fractal heap doubling table"))? as u64
+                + 2;
+        let mut block_offset = 0;
+        for row in 0..std::cmp::min(heap.root_rows, max_direct_rows) {
+            let block_size = heap.start_block_size << row.saturating_sub(1);
+            for column in 0..heap.table_width {
+                let block_end = offset_address(block_offset, block_size)?;
+                if offset < block_end {
+                    let entry = 5
+                        + self.offset_size
+                        + heap.offset_size
+                        + (row * heap.table_width + column) as usize * self.offset_size;
+                    let data = self
+                        .read(offset_address(root, entry as u64)?, self.offset_size)
+                        .await?;
+                    let block = self
+                        .cursor(&data)
+                        .address()?
+                        .ok_or_else(|| invalid("fractal heap object in unallocated block"))?;
+                    return self
+                        .read(
+                            offset_address(block, offset - block_offset)?,
+                            usize::try_from(len)?,
+                        )
+                        .await;
+                }
+                block_offset = block_end;
+            }
+        }
+        Err(invalid(
+            "fractal heaps with nested indirect blocks are not supported",
+        ))
+    }
+
+    /// Returns the records of a version 2 B-tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: Address of the B-tree header
+    /// * `record_type`: Expected type of the records
+    async fn records_v2(
+        &mut self,
+        address: u64,
+        record_type: u8,
+    ) -> Result<Vec<Bytes>, ActiveStorageError> {
+        let len = 18 + self.offset_size + self.length_size;
+        let data = self.read(address, len).await?;
+        let mut cursor = self.cursor(&data);
+        cursor.signature(b"BTHD")?;
+        cursor.skip(1)?;
+        if cursor.u8()? != record_type {
+            return Err(invalid("unexpected B-tree record type"));
+        }
+        let node_size = cursor.u32()? as usize;
+        let record_size = cursor.u16()? as usize;
+        let depth = cursor.u16()? as usize;
+        cursor.skip(2)?;
+        let root = cursor.address()?;
+        let root_records = cursor.u16()? as usize;
+        let Some(root) = root else {
+            return Ok(vec![]);
+        };
+        if record_size == 0 || node_size < BTREE_V2_PREFIX_SIZE {
+            return Err(invalid("invalid B-tree node size"));
+        }
+        // The sizes of the record counts of child nodes depend on the maximum number of records
+        // below each node.
+        let max_records = (node_size - BTREE_V2_PREFIX_SIZE) / record_size;
+        let count_size = encoded_size(max_records as u64);
+        let mut total_sizes = vec![0];
+        let mut total_records = vec![max_records as u64];
+        for level in 1..=depth {
+            let pointer_size = self.offset_size + count_size + total_sizes[level - 1];
+            let records = node_size.saturating_sub(BTREE_V2_PREFIX_SIZE + pointer_size)
+                / (record_size + pointer_size);
+            let total = (records as u64 + 1)
+                .saturating_mul(total_records[level - 1])
+                .saturating_add(records as u64);
+            total_records.push(total);
+            total_sizes.push(encoded_size(total));
+        }
+        let mut records = vec![];
+        let mut nodes = vec![(root, root_records, depth)];
+        while let Some((address, count, depth)) = nodes.pop() {
+            self.visit()?;
+            let (signature, pointer_size) = if depth == 0 {
+                (b"BTLF", 0)
+            } else {
+                let total_size = if depth > 1 { total_sizes[depth - 1] } else { 0 };
+                (b"BTIN", self.offset_size + count_size + total_size)
+            };
+            let len = count
+                .checked_mul(record_size + pointer_size)
+                .and_then(|len| len.checked_add(6 + pointer_size))
+                .ok_or_else(|| invalid("B-tree node size out of range"))?;
+            let data = self.read(address, len).await?;
+            let mut cursor = self.cursor(&data);
+            cursor.signature(signature)?;
+            cursor.skip(2)?;
+            for _ in 0..count {
+                records.push(data.slice_ref(cursor.bytes(record_size)?));
+            }
+            if depth > 0 {
+                for _ in 0..=count {
+                    let child = cursor
+                        .address()?
+                        .ok_or_else(|| invalid("undefined B-tree child address"))?;
+                    let child_count = cursor.uint(count_size)? as usize;
+                    cursor.skip(pointer_size - self.offset_size - count_size)?;
+                    nodes.push((child, child_count, depth - 1));
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Returns the location of each chunk in a version 1 B-tree chunk index.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: Address of the root node of the B-tree
+    /// * `chunks`: Shape of each chunk
+    async fn chunk_btree(
+        &mut self,
+        address: u64,
+        chunks: &[usize],
+    ) -> Result<HashMap<Vec<usize>, ChunkLocation>, ActiveStorageError> {
+        // Each key contains the chunk size, filter mask and the offset of the chunk in each
+        // dimension, followed by a zero offset for the element size.
+        let key_size = 8 + 8 * (chunks.len() + 1);
+        let mut locations = HashMap::new();
+        let mut nodes = vec![(address, None)];
+        while let Some((address, level)) = nodes.pop() {
+            let (level, keys, children) = self.node_v1(address, 1, key_size, level).await?;
+            if level > 0 {
+                nodes.extend(children.into_iter().map(|child| (child, Some(level - 1))));
+                continue;
+            }
+            for (key, child) in std::iter::zip(keys, children) {
+                let mut cursor = self.cursor(&key);
+                let size = cursor.u32()? as u64;
+                let filter_mask = cursor.u32()?;
+                let mut coords = vec![];
+                for &chunk in chunks {
+                    let offset = usize::try_from(cursor.uint(8)?)?;
+                    if offset % chunk != 0 {
+                        return Err(invalid("chunk offset is not a multiple of the chunk shape"));
+                    }
+                    coords.push(offset / chunk);
+                }
+                let location = ChunkLocation {
+                    offset: offset_address(self.base, child)?,
+                    size,
+                    filter_mask,
+                };
+                locations.insert(coords, location);
+            }
+        }
+        Ok(locations)
+    }
+
+    /// Returns the dataset with an object header.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: Address of the dataset's object header
+    /// * `path`: Path of the dataset, for error messages
+    async fn dataset(&mut self, address: u64, path: &str) -> Result<Dataset, ActiveStorageError> {
+        let messages = self.object_header(address).await?;
+        let (Some(dataspace), Some(datatype), Some(layout)) = (
+            find(&messages, MSG_DATASPACE)?,
+            find(&messages, MSG_DATATYPE)?,
+            find(&messages, MSG_LAYOUT)?,
+        ) else {
+            return Err(invalid(format!("{} is not a dataset", path)));
+        };
+        let shape = parse_dataspace(&mut self.cursor(&dataspace.data))?;
+        let (dtype, byte_order) = parse_datatype(&mut self.cursor(&datatype.data))?;
+        let fill_value = match (
+            find(&messages, MSG_FILL_VALUE)?,
+            find(&messages, MSG_FILL_VALUE_OLD)?,
+        ) {
+            (Some(message), _) => parse_fill_value(&mut self.cursor(&message.data))?,
+            (None, Some(message)) => {
+                let mut cursor = self.cursor(&message.data);
+                let size = cursor.u32()? as usize;
+                Some(cursor.bytes(size)?.to_vec())
+            }
+            (None, None) => None,
+        };
+        let fill_value = native_fill_value(fill_value, dtype, byte_order)?;
+        let pipeline = match find(&messages, MSG_FILTER_PIPELINE)? {
+            Some(message) => parse_pipeline(&mut self.cursor(&message.data))?,
+            None => vec![],
+        };
+        // Check that the filters are supported before reading any chunks.
+        codecs(&pipeline, 0, dtype.size_of())?;
+        let element_size = dtype.size_of() as u64;
+        let (chunks, index) = match parse_layout(&mut self.cursor(&layout.data))? {
+            Layout::Contiguous { address, size } => {
+                // The dataset is a single chunk.
+                let chunks: Vec<_> = shape.iter().map(|&length| length.max(1)).collect();
+                let expected = array_size(&shape, element_size)?;
+                let mut locations = HashMap::new();
+                if let Some(address) = address {
+                    if size != expected {
+                        return Err(invalid("contiguous dataset size does not match its shape"));
+                    }
+                    let location = ChunkLocation {
+                        offset: offset_address(self.base, address)?,
+                        size,
+                        filter_mask: 0,
+                    };
+                    locations.insert(vec![0; shape.len()], location);
+                }
+                (chunks, ChunkIndex::Map(locations))
+            }
+            Layout::Chunked { dimensions, index } => {
+                let (element, chunks) = dimensions
+                    .split_last()
+                    .ok_or_else(|| invalid("chunk shape has no dimensions"))?;
+                if *element != element_size || chunks.len() != shape.len() {
+                    return Err(invalid("chunk shape does not match the dataspace"));
+                }
+                let chunks = chunks
+                    .iter()
+                    .map(|&chunk| usize::try_from(chunk))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if chunks.contains(&0) {
+                    return Err(invalid("chunk shape must not contain zeros"));
+                }
+                let chunk_size = array_size(&chunks, element_size)?;
+                let index = match index {
+                    LayoutIndex::BTreeV1(Some(address)) => {
+                        ChunkIndex::Map(self.chunk_btree(address, &chunks).await?)
+                    }
+                    LayoutIndex::Single {
+                        address: Some(address),
+                        filtered_size,
+                        filter_mask,
+                    } => {
+                        let location = ChunkLocation {
+                            offset: offset_address(self.base, address)?,
+                            size: filtered_size.unwrap_or(chunk_size),
+                            filter_mask,
+                        };
+                        ChunkIndex::Map(HashMap::from([(vec![0; shape.len()], location)]))
+                    }
+                    LayoutIndex::Implicit(Some(address)) => {
+                        if !pipeline.is_empty() {
+                            return Err(invalid("implicit chunk index with filters"));
+                        }
+                        let grid: Vec<_> = std::iter::zip(&shape, &chunks)
+                            .map(|(length, chunk)| length.div_ceil(*chunk))
+                            .collect();
+                        // Check that the offset of every chunk fits in an address.
+                        let offset = offset_address(self.base, address)?;
+                        offset_address(offset, array_size(&grid, chunk_size)?)?;
+                        ChunkIndex::Implicit {
+                            offset,
+                            grid,
+                            size: chunk_size,
+                        }
+                    }
+                    // No chunks have been written.
+                    _ => ChunkIndex::Map(HashMap::new()),
+                };
+                (chunks, index)
+            }
+        };
+        let metadata = ArrayMetadata {
+            shape,
+            chunks,
+            dtype,
+            byte_order,
+            order: Order::C,
+            compression: None,
+            filters: None,
+            fill_value,
+            key_prefix: None,
+            separator: String::new(),
+        };
+        Ok(Dataset {
+            metadata,
+            index,
+            pipeline,
+        })
+    }
+}
+
+/// Returns the number of bytes needed to encode a count, as in the HDF5 library.
+///
+/// # Arguments
+///
+/// * `count`: Maximum value of the count
+fn encoded_size(count: u64) -> usize {
+    (count.max(1).ilog2() / 8 + 1) as usize
+}
+
+/// Parse a link message.
+///
+/// # Arguments
+///
+/// * `cursor`: Cursor over the message
+fn parse_link(cursor: &mut Cursor) -> Result<Link, ActiveStorageError> {
+    if cursor.u8()? != 1 {
+        return Err(invalid("unsupported link message version"));
+    }
+    let flags = cursor.u8()?;
+    let link_type = if flags & 0x08 != 0 { cursor.u8()? } else { 0 };
+    if flags & 0x04 != 0 {
+        // Creation order
+        cursor.skip(8)?;
+    }
+    if flags & 0x10 != 0 {
+        // Character set of the name
+        cursor.skip(1)?;
+    }
+    let len = cursor.uint(1 << (flags & 0x03))? as usize;
+    let name = cursor.bytes(len)?.to_vec();
+    let address = match link_type {
+        0 => Some(
+            cursor
+                .address()?
+                .ok_or_else(|| invalid("undefined link address"))?,
+        ),
+        _ => None,
+    };
+    Ok(Link { name, address })
+}
+
+/// Parse a dataspace message, returning the shape of the dataspace.
+///
+/// # Arguments
+///
+/// * `cursor`: Cursor over the message
+fn parse_dataspace(cursor: &mut Cursor) -> Result<Vec<usize>, ActiveStorageError> {
+    let version = cursor.u8()?;
+    let rank = cursor.u8()? as usize;
+    cursor.skip(1)?;
+    match version {
+        1 => cursor.skip(5)?,
+        2 => {
+            if cursor.u8()? == 2 {
+                return Err(invalid("null dataspaces are not supported"));
+            }
+        }
+        _ => {
+            return Err(invalid(format!(
+                "unsupported dataspace message version {}",
+                version
+            )))
+        }
+    }
+    (0..rank)
+        .map(|_| Ok(usize::try_from(cursor.length()?)?))
+        .collect()
+}
+
+/// Parse a datatype message, returning the data type and byte order.
+///
+/// # Arguments
+///
+/// * `cursor`: Cursor over the message
+fn parse_datatype(cursor: &mut Cursor) -> Result<(DType, Option<ByteOrder>), ActiveStorageError> {
+    let class = cursor.u8()? & 0x0f;
+    let bits = cursor.bytes(3)?;
+    let size = cursor.u32()?;
+    let unsupported = || {
+        invalid(format!(
+            "unsupported datatype class {} size {}",
+            class, size
+        ))
+    };
+    let dtype = match class {
+        // Fixed-point
+        0 => {
+            let bit_offset = cursor.u16()?;
+            let precision = cursor.u16()?;
+            if bit_offset != 0 || precision as u32 != size * 8 {
+                return Err(invalid("fixed-point types with padding are not supported"));
+            }
+            match (bits[0] & 0x08 != 0, size) {
+                (true, 1) => DType::Int8,
+                (true, 2) => DType::Int16,
+                (true, 4) => DType::Int32,
+                (true, 8) => DType::Int64,
+                (false, 1) => DType::Uint8,
+                (false, 2) => DType::Uint16,
+                (false, 4) => DType::Uint32,
+                (false, 8) => DType::Uint64,
+                _ => return Err(unsupported()),
+            }
+        }
+        // Floating-point
+        1 => {
+            if bits[0] & 0x40 != 0 {
+                return Err(invalid("VAX byte order is not supported"));
+            }
+            match size {
+                4 => DType::Float32,
+                8 => DType::Float64,
+                _ => return Err(unsupported()),
+            }
+        }
+        _ => return Err(unsupported()),
+    };
+    let byte_order = if bits[0] & 0x01 != 0 {
+        ByteOrder::Big
+    } else {
+        ByteOrder::Little
+    };
+    // The byte order of single byte types is irrelevant.
+    Ok((dtype, Some(byte_order).filter(|_| size > 1)))
+}
+
+/// Parse a fill value message, returning the fill value if it is defined.
+///
+/// # Arguments
+///
+/// * `cursor`: Cursor over the message
+fn parse_fill_value(cursor: &mut Cursor) -> Result<Option<Vec<u8>>, ActiveStorageError> {
+    let version = cursor.u8()?;
+    let defined = match version {
+        1 | 2 => {
+            // Skip the space allocation and fill value write times.
+            cursor.skip(2)?;
+            let defined = cursor.u8()? != 0;
+            version == 1 || defined
+        }
+        3 => cursor.u8()? & 0x20 != 0,
+        _ => {
+            return Err(invalid(format!(
+                "unsupported fill value message version {}",
+                version
+            )))
+        }
+    };
+    if !defined {
+        return Ok(None);
+    }
+    let size = cursor.u32()? as usize;
+    Ok(Some(cursor.bytes(size)?.to_vec()))
+}
+
+/// Returns a fill value as a single element in native byte order, defaulting to zero.
+///
+/// # Arguments
+///
+/// * `fill_value`: Fill value in the byte order of the dataset, if defined
+/// * `dtype`: Data type of the dataset
+/// * `byte_order`: Byte order of the dataset
+fn native_fill_value(
+    fill_value: Option<Vec<u8>>,
+    dtype: DType,
+    byte_order: Option<ByteOrder>,
+) -> Result<Vec<u8>, ActiveStorageError> {
+    match fill_value {
+        Some(fill_value) if !fill_value.is_empty() => {
+            if fill_value.len() != dtype.size_of() {
+                return Err(invalid("fill value size does not match the datatype"));
+            }
+            let mut fill_value = fill_value;
+            if byte_order.is_some_and(|byte_order| byte_order != NATIVE_BYTE_ORDER) {
+                fill_value.reverse();
+            }
+            Ok(fill_value)
+        }
+        _ => Ok(vec![0; dtype.size_of()]),
+    }
+}
+
+/// Parse a data layout message.
+///
+/// # Arguments
+///
+/// * `cursor`: Cursor over the message
+fn parse_layout(cursor: &mut Cursor) -> Result<Layout, ActiveStorageError> {
+    let version = cursor.u8()?;
+    if !(3..=4).contains(&version) {
+        return Err(invalid(format!(
+            "unsupported data layout message version {}",
+            version
+        )));
+    }
+    match cursor.u8()? {
+        1 => Ok(Layout::Contiguous {
+            address: cursor.address()?,
+            size: cursor.length()?,
+        }),
+        2 if version == 3 => {
+            let rank = cursor.u8()? as usize;
+            let address = cursor.address()?;
+            let dimensions = (0..rank)
+                .map(|_| Ok(cursor.u32()? as u64))
+                .collect::<Result<_, ActiveStorageError>>()?;
+            Ok(Layout::Chunked {
+                dimensions,
+                index: LayoutIndex::BTreeV1(address),
+            })
+        }
+        2 => {
+            let flags = cursor.u8()?;
+            let rank = cursor.u8()? as usize;
+            let size = cursor.u8()? as usize;
+            let dimensions = (0..rank)
+                .map(|_| cursor.uint(size))
+                .collect::<Result<_, _>>()?;
+            if flags & 0x01 != 0 {
+                return Err(invalid("unfiltered partial edge chunks are not supported"));
+            }
+            let index = match cursor.u8()? {
+                1 => {
+                    let (filtered_size, filter_mask) = if flags & 0x02 != 0 {
+                        (Some(cursor.length()?), cursor.u32()?)
+                    } else {
+                        (None, 0)
+                    };
+                    LayoutIndex::Single {
+                        address: cursor.address()?,
+                        filtered_size,
+                        filter_mask,
+                    }
+                }
+                2 => LayoutIndex::Implicit(cursor.address()?),
+                index => {
+                    return Err(invalid(format!("unsupported chunk index type {}", index)));
+                }
+            };
+            Ok(Layout::Chunked { dimensions, index })
+        }
+        0 => Err(invalid("compact datasets are not supported")),
+        class => Err(invalid(format!("unsupported layout class {}", class))),
+    }
+}
+
+/// Parse a filter pipeline message.
+///
+/// # Arguments
+///
+/// * `cursor`: Cursor over the message
+fn parse_pipeline(cursor: &mut Cursor) -> Result<Vec<PipelineFilter>, ActiveStorageError> {
+    let version = cursor.u8()?;
+    if !(1..=2).contains(&version) {
+        return Err(invalid(format!(
+            "unsupported filter pipeline message version {}",
+            version
+        )));
+    }
+    let count = cursor.u8()?;
+    if version == 1 {
+        cursor.skip(6)?;
+    }
+    let mut pipeline = vec![];
+    for _ in 0..count {
+        let id = cursor.u16()?;
+        // Version 2 messages omit the name of predefined filters, and do not pad names.
+        let name_len = if version == 1 || id >= 256 {
+            cursor.u16()? as usize
+        } else {
+            0
+        };
+        cursor.skip(2)?;
+        let values = cursor.u16()? as usize;
+        cursor.skip(name_len)?;
+        let client_data = (0..values)
+            .map(|_| cursor.u32())
+            .collect::<Result<_, _>>()?;
+        if version == 1 && values % 2 == 1 {
+            cursor.skip(4)?;
+        }
+        pipeline.push(PipelineFilter { id, client_data });
+    }
+    Ok(pipeline)
+}
+
+/// Returns the compression and filters of a chunk.
+///
+/// # Arguments
+///
+/// * `pipeline`: Filter pipeline of the dataset
+/// * `filter_mask`: Mask of the filters that were skipped for the chunk
+/// * `element_size`: Size of each element in bytes
+fn codecs(
+    pipeline: &[PipelineFilter],
+    filter_mask: u32,
+    element_size: usize,
+) -> Result<Codecs, ActiveStorageError> {
+    let mut codecs = Codecs::default();
+    for (index, filter) in pipeline.iter().enumerate() {
+        if filter_mask.checked_shr(index as u32).unwrap_or(0) & 1 != 0 {
+            continue;
+        }
+        if codecs.checksum {
+            return Err(invalid(
+                "filters after the Fletcher32 filter are not supported",
+            ));
+        }
+        let compression = match filter.id {
+            FILTER_DEFLATE => Compression::Zlib,
+            FILTER_BLOSC => Compression::Blosc,
+            FILTER_ZSTD => Compression::Zstd,
+            FILTER_FLETCHER32 => {
+                codecs.checksum = true;
+                continue;
+            }
+            FILTER_SHUFFLE | FILTER_BITSHUFFLE => {
+                if codecs.compression.is_some() {
+                    return Err(invalid("filters after compression are not supported"));
+                }
+                let filter = if filter.id == FILTER_SHUFFLE {
+                    Filter::Shuffle { element_size }
+                } else {
+                    let block_size = filter.client_data.get(3).copied().unwrap_or(0) as usize;
+                    if block_size != 0 && block_size != bitshuffle::default_block_size(element_size)
+                    {
+                        return Err(invalid(
+                            "non-default bit shuffle block sizes are not supported",
+                        ));
+                    }
+                    if filter
+                        .client_data
+                        .get(4)
+                        .is_some_and(|&compression| compression != 0)
+                    {
+                        return Err(invalid("bit shuffle with compression is not supported"));
+                    }
+                    Filter::Bitshuffle { element_size }
+                };
+                codecs.filters.push(filter);
+                continue;
+            }
+            id => return Err(invalid(format!("unsupported filter {}", id))),
+        };
+        if codecs.compression.is_some() {
+            return Err(invalid("multiple compression filters are not supported"));
+        }
+        codecs.compression = Some(compression);
+    }
+    Ok(codecs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::Slice;
+
+    /// An undefined address
+    const UNDEFINED: [u8; 8] = [0xff; 8];
+
+    impl ReadAt for Bytes {
+        async fn read_at(&self, offset: u64, len: u64) -> Result<Bytes, ActiveStorageError> {
+            let start = std::cmp::min(offset as usize, self.len());
+            let end = std::cmp::min((offset + len) as usize, self.len());
+            Ok(self.slice(start..end))
+        }
+    }
+
+    /// Writes an HDF5 file with 8 byte addresses and lengths.
+    struct Writer {
+        data: Vec<u8>,
+        /// Offset of the superblock
+        base: usize,
+    }
+
+    impl Writer {
+        /// Returns a writer, reserving space for a superblock at `base`.
+        fn new(base: usize) -> Self {
+            Writer {
+                data: vec![0; base + 128],
+                base,
+            }
+        }
+
+        /// Appends a structure aligned to 8 bytes, returning its address.
+        fn append(&mut self, structure: &[u8]) -> u64 {
+            self.data.resize(self.data.len().next_multiple_of(8), 0);
+            let address = (self.data.len() - self.base) as u64;
+            self.data.extend_from_slice(structure);
+            address
+        }
+
+        /// Writes the superblock, returning the file.
+        fn finish(mut self, superblock: &[u8]) -> Bytes {
+            self.data[self.base..][..superblock.len()].copy_from_slice(superblock);
+            self.data.into()
+        }
+    }
+
+    fn superblock_v0(root: u64, btree: u64, heap: u64) -> Vec<u8> {
+        [
+            SIGNATURE,
+            &[0, 0, 0, 0, 0, 8, 8, 0],
+            &4_u16.to_le_bytes(),
+            &16_u16.to_le_bytes(),
+            &[0; 4],
+            &[0; 8],
+            &UNDEFINED,
+            &UNDEFINED,
+            &UNDEFINED,
+            // Root group symbol table entry
+            &[0; 8],
+            &root.to_le_bytes(),
+            &1_u32.to_le_bytes(),
+            &[0; 4],
+            &btree.to_le_bytes(),
+            &heap.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    fn superblock_v2(root: u64) -> Vec<u8> {
+        [
+            SIGNATURE,
+            &[2, 8, 8, 0],
+            &[0; 8],
+            &UNDEFINED,
+            &UNDEFINED,
+            &root.to_le_bytes(),
+            &[0; 4],
+        ]
+        .concat()
+    }
+
+    fn message_v1(kind: u16, body: &[u8]) -> Vec<u8> {
+        let len = body.len().next_multiple_of(8);
+        let mut message = [
+            &kind.to_le_bytes()[..],
+            &(len as u16).to_le_bytes(),
+            &[0; 4],
+        ]
+        .concat();
+        message.extend_from_slice(body);
+        message.resize(8 + len, 0);
+        message
+    }
+
+    fn header_v1(messages: &[Vec<u8>]) -> Vec<u8> {
+        let body = messages.concat();
+        [
+            &[1, 0][..],
+            &(messages.len() as u16).to_le_bytes(),
+            &1_u32.to_le_bytes(),
+            &(body.len() as u32).to_le_bytes(),
+            &[0; 4],
+            &body,
+        ]
+        .concat()
+    }
+
+    fn message_v2(kind: u16, body: &[u8]) -> Vec<u8> {
+        [
+            &[kind as u8][..],
+            &(body.len() as u16).to_le_bytes(),
+            &[0],
+            body,
+        ]
+        .concat()
+    }
+
+    fn header_v2(messages: &[Vec<u8>]) -> Vec<u8> {
+        let body = messages.concat();
+        // The flags select a 4 byte chunk size.
+        [
+            &b"OHDR"[..],
+            &[2, 0x02],
+            &(body.len() as u32).to_le_bytes(),
+            &body,
+            &[0; 4],
+        ]
+        .concat()
+    }
+
+    fn continuation(address: u64, len: usize) -> Vec<u8> {
+        [address.to_le_bytes(), (len as u64).to_le_bytes()].concat()
+    }
+
+    fn dataspace(version: u8, shape: &[u64]) -> Vec<u8> {
+        let mut message = match version {
+            1 => vec![1, shape.len() as u8, 0, 0, 0, 0, 0, 0],
+            _ => vec![2, shape.len() as u8, 0, 1],
+        };
+        for length in shape {
+            message.extend(length.to_le_bytes());
+        }
+        message
+    }
+
+    fn fixed_point(size: u32, signed: bool, big_endian: bool) -> Vec<u8> {
+        let bits = (signed as u8) << 3 | big_endian as u8;
+        [
+            &[0x10, bits, 0, 0][..],
+            &size.to_le_bytes(),
+            &0_u16.to_le_bytes(),
+            &(size as u16 * 8).to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    fn floating_point(size: u32, big_endian: bool) -> Vec<u8> {
+        [
+            &[0x11, 0x20 | big_endian as u8, 0, 0][..],
+            &size.to_le_bytes(),
+            &[0; 12],
+        ]
+        .concat()
+    }
+
+    fn fill_value(value: &[u8]) -> Vec<u8> {
+        [
+            &[2, 2, 2, 1][..],
+            &(value.len() as u32).to_le_bytes(),
+            value,
+        ]
+        .concat()
+    }
+
+    fn pipeline(filters: &[(u16, &[u32])]) -> Vec<u8> {
+        let mut message = vec![1, filters.len() as u8, 0, 0, 0, 0, 0, 0];
+        for (id, client_data) in filters {
+            message.extend(id.to_le_bytes());
+            message.extend([0, 0, 0, 0]);
+            message.extend((client_data.len() as u16).to_le_bytes());
+            for value in client_data.iter() {
+                message.extend(value.to_le_bytes());
+            }
+            if client_data.len() % 2 == 1 {
+                message.extend([0; 4]);
+            }
+        }
+        message
+    }
+
+    fn layout_chunked(address: u64, dimensions: &[u32]) -> Vec<u8> {
+        let mut message = vec![3, 2, dimensions.len() as u8];
+        message.extend(address.to_le_bytes());
+        for dimension in dimensions {
+            message.extend(dimension.to_le_bytes());
+        }
+        message
+    }
+
+    fn layout_contiguous(address: u64, size: u64) -> Vec<u8> {
+        [&[3, 1][..], &address.to_le_bytes(), &size.to_le_bytes()].concat()
+    }
+
+    fn btree_v1(node_type: u8, level: u8, keys: &[Vec<u8>], children: &[u64]) -> Vec<u8> {
+        let mut node = [
+            &b"TREE"[..],
+            &[node_type, level],
+            &(children.len() as u16).to_le_bytes(),
+            &UNDEFINED,
+            &UNDEFINED,
+        ]
+        .concat();
+        for (key, child) in std::iter::zip(keys, children) {
+            node.extend(key);
+            node.extend(child.to_le_bytes());
+        }
+        node.extend(keys.last().unwrap());
+        node
+    }
+
+    fn chunk_key(size: u32, filter_mask: u32, offsets: &[u64]) -> Vec<u8> {
+        let mut key = [size.to_le_bytes(), filter_mask.to_le_bytes()].concat();
+        for offset in offsets.iter().chain(&[0]) {
+            key.extend(offset.to_le_bytes());
+        }
+        key
+    }
+
+    fn link(name: &str, address: u64) -> Vec<u8> {
+        [
+            &[1, 0, name.len() as u8][..],
+            name.as_bytes(),
+            &address.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    /// Returns a file with a dataset named `data` in the root group.
+    fn file_with_dataset(writer: Writer, messages: &[Vec<u8>]) -> Bytes {
+        let mut writer = writer;
+        let dataset = writer.append(&header_v2(messages));
+        let root = writer.append(&header_v2(&[message_v2(MSG_LINK, &link("data", dataset))]));
+        writer.finish(&superblock_v2(root))
+    }
+
+    fn request() -> Hdf5RequestData {
+        serde_json::from_value(serde_json::json!({
+            "source": "http://example.com",
+            "bucket": "bar",
+            "object": "baz.nc",
+            "dataset": "data"
+        }))
+        .unwrap()
+    }
+
+    fn chunk_selection(coords: Vec<usize>, selection: Vec<Slice>) -> ChunkSelection {
+        ChunkSelection { coords, selection }
+    }
+
+    #[tokio::test]
+    async fn symbol_table_btree_v1() {
+        let mut writer = Writer::new(0);
+        let chunk_00 = writer.append(&[1; 20]);
+        let chunk_11 = writer.append(&[2; 32]);
+        let leaf_0 = writer.append(&btree_v1(
+            1,
+            0,
+            &[chunk_key(20, 0, &[0, 0]), chunk_key(0, 0, &[2, 4])],
+            &[chunk_00],
+        ));
+        let leaf_1 = writer.append(&btree_v1(
+            1,
+            0,
+            &[chunk_key(32, 0b10, &[2, 4]), chunk_key(0, 0, &[4, 8])],
+            &[chunk_11],
+        ));
+        let btree = writer.append(&btree_v1(
+            1,
+            1,
+            &[
+                chunk_key(0, 0, &[0, 0]),
+                chunk_key(0, 0, &[2, 4]),
+                chunk_key(0, 0, &[4, 8]),
+            ],
+            &[leaf_0, leaf_1],
+        ));
+        // The filter pipeline and layout are in a continuation block.
+        let block = [
+            message_v1(
+                MSG_FILTER_PIPELINE,
+                &pipeline(&[(FILTER_SHUFFLE, &[4]), (FILTER_DEFLATE, &[6])]),
+            ),
+            message_v1(MSG_LAYOUT, &layout_chunked(btree, &[2, 4, 4])),
+        ]
+        .concat();
+        let block_address = writer.append(&block);
+        let dataset = writer.append(&header_v1(&[
+            message_v1(MSG_DATASPACE, &dataspace(1, &[4, 6])),
+            message_v1(MSG_DATATYPE, &fixed_point(4, true, false)),
+            message_v1(MSG_FILL_VALUE, &fill_value(&(-1_i32).to_le_bytes())),
+            message_v1(MSG_CONTINUATION, &continuation(block_address, block.len())),
+        ]));
+        let names = writer.append(b"\0data\0other\0\0\0\0\0");
+        let heap = writer.append(
+            &[
+                &b"HEAP"[..],
+                &[0; 4],
+                &16_u64.to_le_bytes(),
+                &UNDEFINED,
+                &names.to_le_bytes(),
+            ]
+            .concat(),
+        );
+        let mut snod = [&b"SNOD"[..], &[1, 0], &2_u16.to_le_bytes()].concat();
+        for (offset, address) in [(1_u64, dataset), (6, dataset)] {
+            snod.extend(offset.to_le_bytes());
+            snod.extend(address.to_le_bytes());
+            snod.extend([0; 24]);
+        }
+        let snod = writer.append(&snod);
+        let group_btree = writer.append(&btree_v1(
+            0,
+            0,
+            &[0_u64.to_le_bytes().to_vec(), 6_u64.to_le_bytes().to_vec()],
+            &[snod],
+        ));
+        let root = writer.append(&header_v1(&[message_v1(
+            MSG_SYMBOL_TABLE,
+            &[group_btree.to_le_bytes(), heap.to_le_bytes()].concat(),
+        )]));
+        let file = writer.finish(&superblock_v0(root, group_btree, heap));
+
+        let dataset = open(file.clone(), 0, "data").await.unwrap();
+        let expected = ArrayMetadata {
+            shape: vec![4, 6],
+            chunks: vec![2, 4],
+            dtype: DType::Int32,
+            byte_order: Some(ByteOrder::Little),
+            order: Order::C,
+            compression: None,
+            filters: None,
+            fill_value: (-1_i32).to_ne_bytes().to_vec(),
+            key_prefix: None,
+            separator: String::new(),
+        };
+        assert_eq!(expected, dataset.metadata);
+        assert!(dataset.is_allocated(&[0, 0]));
+        assert!(!dataset.is_allocated(&[0, 1]));
+        assert!(dataset.is_allocated(&[1, 1]));
+
+        let request = request();
+        let selection = vec![Slice::new(0, 2, 1), Slice::new(1, 4, 2)];
+        let request_data = dataset
+            .chunk_request(&request, &chunk_selection(vec![0, 0], selection.clone()))
+            .unwrap();
+        assert_eq!("baz.nc", request_data.object);
+        assert_eq!(Some(chunk_00 as usize), request_data.offset);
+        assert_eq!(Some(20), request_data.size);
+        assert_eq!(Some(vec![2, 4]), request_data.shape);
+        assert_eq!(Some(Compression::Zlib), request_data.compression);
+        assert_eq!(
+            Some(vec![Filter::Shuffle { element_size: 4 }]),
+            request_data.filters
+        );
+        // Compression was skipped for the chunk.
+        let request_data = dataset
+            .chunk_request(&request, &chunk_selection(vec![1, 1], selection.clone()))
+            .unwrap();
+        assert_eq!(Some(chunk_11 as usize), request_data.offset);
+        assert_eq!(Some(32), request_data.size);
+        assert_eq!(None, request_data.compression);
+        assert_eq!(
+            Some(vec![Filter::Shuffle { element_size: 4 }]),
+            request_data.filters
+        );
+        let request_data = dataset
+            .chunk_request(&request, &chunk_selection(vec![0, 1], selection))
+            .unwrap();
+        assert_eq!(None, request_data.offset);
+        assert_eq!(None, request_data.size);
+
+        assert_eq!(dataset, open(file.clone(), 0, "/other").await.unwrap());
+        let err = open(file, 0, "missing").await.unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: no object at path missing",
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn compact_links_contiguous() {
+        // The file has a user block before the superblock.
+        let mut writer = Writer::new(512);
+        let data: Vec<u8> = [1.0_f64, 2.0, 3.0]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        let data = writer.append(&data);
+        let dataset = writer.append(&header_v2(&[
+            message_v2(MSG_DATASPACE, &dataspace(2, &[3])),
+            message_v2(MSG_DATATYPE, &floating_point(8, true)),
+            // The fill value is undefined.
+            message_v2(MSG_FILL_VALUE, &[3, 0x12]),
+            message_v2(MSG_LAYOUT, &layout_contiguous(data, 24)),
+        ]));
+        let group = writer.append(&header_v2(&[message_v2(
+            MSG_LINK,
+            &link("values", dataset),
+        )]));
+        let soft_link = [&[1, 0x08, 1, 4][..], b"soft", &3_u16.to_le_bytes(), b"grp"].concat();
+        let root = writer.append(&header_v2(&[
+            message_v2(MSG_LINK, &soft_link),
+            message_v2(MSG_LINK, &link("grp", group)),
+        ]));
+        let file = writer.finish(&superblock_v2(root));
+
+        let dataset = open(file.clone(), 512, "grp/values").await.unwrap();
+        assert_eq!(vec![3], dataset.metadata.shape);
+        assert_eq!(vec![3], dataset.metadata.chunks);
+        assert_eq!(DType::Float64, dataset.metadata.dtype);
+        assert_eq!(Some(ByteOrder::Big), dataset.metadata.byte_order);
+        assert_eq!(vec![0; 8], dataset.metadata.fill_value);
+        let chunks = dataset.metadata.chunk_selections(None, usize::MAX).unwrap();
+        assert_eq!(1, chunks.len());
+        let request_data = dataset.chunk_request(&request(), &chunks[0]).unwrap();
+        assert_eq!(Some(512 + data as usize), request_data.offset);
+        assert_eq!(Some(24), request_data.size);
+        assert_eq!(Some(ByteOrder::Big), request_data.byte_order);
+        assert_eq!(
+            &[1.0_f64.to_be_bytes(), 2.0_f64.to_be_bytes()].concat()[..],
+            &file[512 + data as usize..][..16]
+        );
+
+        let err = open(file.clone(), 512, "soft").await.unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: soft and external link soft is not supported",
+            err.to_string()
+        );
+        let err = open(file.clone(), 512, "grp").await.unwrap_err();
+        assert_eq!("invalid HDF5 file: grp is not a dataset", err.to_string());
+        let err = open(file, 0, "grp/values").await.unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: no superblock at the superblock offset",
+            err.to_string()
+        );
+    }
+
+    /// Returns a file whose root group stores links to a dataset named `a`, `b` and `c` in a
+    /// fractal heap.
+    ///
+    /// # Arguments
+    ///
+    /// * `indirect`: Whether the root block of the heap is an indirect block
+    /// * `depth`: Depth of the B-tree indexing the links
+    fn dense_file(indirect: bool, depth: u16) -> Bytes {
+        let mut writer = Writer::new(0);
+        let dataset = writer.append(&header_v2(&[
+            message_v2(MSG_DATASPACE, &dataspace(1, &[2])),
+            message_v2(MSG_DATATYPE, &fixed_point(1, false, false)),
+            message_v2(MSG_LAYOUT, &layout_contiguous(0, 2)),
+        ]));
+        // Objects are stored after the header of a direct block.
+        let block_offset: u32 = if indirect { 512 } else { 0 };
+        let mut block = [&b"FHDB"[..], &[0], &[0; 8], &block_offset.to_le_bytes()].concat();
+        let mut ids = vec![];
+        for name in ["a", "b", "c"] {
+            let object = link(name, dataset);
+            let offset = block_offset + block.len() as u32;
+            ids.push(
+                [
+                    &[0][..],
+                    &offset.to_le_bytes(),
+                    &(object.len() as u16).to_le_bytes(),
+                ]
+                .concat(),
+            );
+            block.extend(object);
+        }
+        let block = writer.append(&block);
+        let root = if indirect {
+            let mut entries = [UNDEFINED, block.to_le_bytes(), UNDEFINED, UNDEFINED].concat();
+            entries.extend([0; 4]);
+            writer.append(&[&b"FHIB"[..], &[0], &[0; 8], &[0; 4], &entries].concat())
+        } else {
+            block
+        };
+        let rows = indirect as u16;
+        let heap = writer.append(
+            &[
+                &b"FRHP"[..],
+                &[0],
+                &7_u16.to_le_bytes(),
+                &0_u16.to_le_bytes(),
+                &[0],
+                &4096_u32.to_le_bytes(),
+                &[0; 96],
+                &4_u16.to_le_bytes(),
+                &512_u64.to_le_bytes(),
+                &65536_u64.to_le_bytes(),
+                &32_u16.to_le_bytes(),
+                &rows.to_le_bytes(),
+                &root.to_le_bytes(),
+                &rows.to_le_bytes(),
+                &[0; 4],
+            ]
+            .concat(),
+        );
+        let records: Vec<Vec<u8>> = ids.iter().map(|id| [&[0; 4][..], id].concat()).collect();
+        let leaf =
+            |records: &[Vec<u8>]| [&b"BTLF"[..], &[0, 5], &records.concat(), &[0; 4]].concat();
+        let (btree_root, root_records) = if depth == 0 {
+            (writer.append(&leaf(&records)), 3_u16)
+        } else {
+            let left = writer.append(&leaf(&records[..1]));
+            let right = writer.append(&leaf(&records[2..]));
+            let node = [
+                &b"BTIN"[..],
+                &[0, 5],
+                &records[1],
+                &left.to_le_bytes(),
+                &[1],
+                &right.to_le_bytes(),
+                &[1],
+                &[0; 4],
+            ]
+            .concat();
+            (writer.append(&node), 1)
+        };
+        let btree = writer.append(
+            &[
+                &b"BTHD"[..],
+                &[0, 5],
+                &512_u32.to_le_bytes(),
+                &11_u16.to_le_bytes(),
+                &depth.to_le_bytes(),
+                &[100, 40],
+                &btree_root.to_le_bytes(),
+                &root_records.to_le_bytes(),
+                &3_u64.to_le_bytes(),
+                &[0; 4],
+            ]
+            .concat(),
+        );
+        let link_info = [&[0, 0][..], &heap.to_le_bytes(), &btree.to_le_bytes()].concat();
+        let root = writer.append(&header_v2(&[message_v2(MSG_LINK_INFO, &link_info)]));
+        writer.finish(&superblock_v2(root))
+    }
+
+    #[tokio::test]
+    async fn dense_links() {
+        for (indirect, depth) in [(false, 0), (true, 0), (false, 1), (true, 1)] {
+            let file = dense_file(indirect, depth);
+            for name in ["a", "b", "c"] {
+                let dataset = open(file.clone(), 0, name).await.unwrap();
+                assert_eq!(vec![2], dataset.metadata.shape);
+                assert_eq!(DType::Uint8, dataset.metadata.dtype);
+                assert_eq!(None, dataset.metadata.byte_order);
+            }
+            let err = open(file, 0, "d").await.unwrap_err();
+            assert_eq!("invalid HDF5 file: no object at path d", err.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn layout_v4_implicit() {
+        let layout = [
+            &[4, 2, 0, 2, 4][..],
+            &2_u32.to_le_bytes(),
+            &2_u32.to_le_bytes(),
+            &[2],
+            &1000_u64.to_le_bytes(),
+        ]
+        .concat();
+        let file = file_with_dataset(
+            Writer::new(0),
+            &[
+                message_v2(MSG_DATASPACE, &dataspace(1, &[5])),
+                message_v2(MSG_DATATYPE, &fixed_point(2, true, true)),
+                message_v2(MSG_LAYOUT, &layout),
+            ],
+        );
+        let dataset = open(file, 0, "data").await.unwrap();
+        assert_eq!(vec![2], dataset.metadata.chunks);
+        assert_eq!(Some(ByteOrder::Big), dataset.metadata.byte_order);
+        let chunks = dataset.metadata.chunk_selections(None, usize::MAX).unwrap();
+        assert_eq!(3, chunks.len());
+        let request_data = dataset.chunk_request(&request(), &chunks[2]).unwrap();
+        assert_eq!(Some(1008), request_data.offset);
+        assert_eq!(Some(4), request_data.size);
+        assert_eq!(Some(vec![2]), request_data.shape);
+    }
+
+    #[tokio::test]
+    async fn layout_v4_single_chunk() {
+        let layout = [
+            &[4, 2, 0x02, 2, 8][..],
+            &3_u64.to_le_bytes(),
+            &4_u64.to_le_bytes(),
+            &[1],
+            &10_u64.to_le_bytes(),
+            &0_u32.to_le_bytes(),
+            &2000_u64.to_le_bytes(),
+        ]
+        .concat();
+        let file = file_with_dataset(
+            Writer::new(0),
+            &[
+                message_v2(MSG_DATASPACE, &dataspace(2, &[3])),
+                message_v2(MSG_DATATYPE, &floating_point(4, false)),
+                message_v2(MSG_FILL_VALUE, &fill_value(&1.5_f32.to_le_bytes())),
+                message_v2(
+                    MSG_FILTER_PIPELINE,
+                    &pipeline(&[(FILTER_DEFLATE, &[1]), (FILTER_FLETCHER32, &[])]),
+                ),
+                message_v2(MSG_LAYOUT, &layout),
+            ],
+        );
+        let dataset = open(file, 0, "data").await.unwrap();
+        assert_eq!(1.5_f32.to_ne_bytes().to_vec(), dataset.metadata.fill_value);
+        let chunks = dataset.metadata.chunk_selections(None, usize::MAX).unwrap();
+        let request_data = dataset.chunk_request(&request(), &chunks[0]).unwrap();
+        assert_eq!(Some(2000), request_data.offset);
+        // The checksum is excluded.
+        assert_eq!(Some(6), request_data.size);
+        assert_eq!(Some(Compression::Zlib), request_data.compression);
+        assert_eq!(None, request_data.filters);
+    }
+
+    #[tokio::test]
+    async fn unsupported() {
+        let file = file_with_dataset(
+            Writer::new(0),
+            &[
+                message_v2(MSG_DATASPACE, &dataspace(1, &[4])),
+                message_v2(MSG_DATATYPE, &fixed_point(4, true, false)),
+                message_v2(MSG_FILTER_PIPELINE, &pipeline(&[(4, &[])])),
+                message_v2(MSG_LAYOUT, &layout_chunked(0, &[2, 4])),
+            ],
+        );
+        let err = open(file, 0, "data").await.unwrap_err();
+        assert_eq!("invalid HDF5 file: unsupported filter 4", err.to_string());
+        let file = file_with_dataset(
+            Writer::new(0),
+            &[
+                message_v2(MSG_DATASPACE, &dataspace(1, &[4])),
+                message_v2(MSG_DATATYPE, &fixed_point(4, true, false)),
+                message_v2(MSG_LAYOUT, &[3, 0, 0, 0]),
+            ],
+        );
+        let err = open(file, 0, "data").await.unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: compact datasets are not supported",
+            err.to_string()
+        );
+        let mut superblock = superblock_v2(0);
+        superblock[8] = 4;
+        let file = Writer::new(0).finish(&superblock);
+        let err = open(file, 0, "data").await.unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: unsupported superblock version 4",
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn hostile_lengths() {
+        // An object header claims a block of messages of 4 GiB.
+        let mut writer = Writer::new(0);
+        let header = [
+            &[1, 0][..],
+            &1_u16.to_le_bytes(),
+            &1_u32.to_le_bytes(),
+            &u32::MAX.to_le_bytes(),
+            &[0; 4],
+        ]
+        .concat();
+        let root = writer.append(&header);
+        let file = writer.finish(&superblock_v2(root));
+        let err = open(file, 0, "data").await.unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: structure of 4294967295 bytes exceeds the maximum size",
+            err.to_string()
+        );
+
+        // A local heap claims the largest possible size.
+        let mut writer = Writer::new(0);
+        let heap = writer.append(
+            &[
+                &b"HEAP"[..],
+                &[0; 4],
+                &u64::MAX.to_le_bytes(),
+                &UNDEFINED,
+                &0_u64.to_le_bytes(),
+            ]
+            .concat(),
+        );
+        let root = writer.append(&header_v1(&[message_v1(
+            MSG_SYMBOL_TABLE,
+            &[0_u64.to_le_bytes(), heap.to_le_bytes()].concat(),
+        )]));
+        let file = writer.finish(&superblock_v0(root, 0, heap));
+        let err = open(file, 0, "data").await.unwrap_err();
+        assert!(err.to_string().contains("exceeds the maximum size"));
+
+        // The superblock is at the end of the address space.
+        let file = Writer::new(0).finish(&superblock_v2(0));
+        let err = open(file, u64::MAX - 4, "data").await.unwrap_err();
+        assert_eq!("invalid HDF5 file: address out of range", err.to_string());
+    }
+
+    #[tokio::test]
+    async fn hostile_heap_offsets() {
+        let file = Writer::new(0).finish(&superblock_v2(0));
+        let mut file = File::open(file, 0).await.unwrap();
+        let mut heap = FractalHeap {
+            offset_size: 4,
+            length_size: 2,
+            table_width: 4,
+            start_block_size: 512,
+            max_direct_block_size: 65536,
+            root: Some(u64::MAX - 8),
+            root_rows: 0,
+        };
+        let id = [&[0][..], &u32::MAX.to_le_bytes(), &1_u16.to_le_bytes()].concat();
+        let err = file.heap_object(&heap, &id).await.unwrap_err();
+        assert_eq!("invalid HDF5 file: address out of range", err.to_string());
+        heap.root_rows = 1;
+        heap.max_direct_block_size = 256;
+        let err = file.heap_object(&heap, &id).await.unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: invalid fractal heap doubling table",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn filter_codecs() {
+        let filters = [
+            PipelineFilter {
+                id: FILTER_SHUFFLE,
+                client_data: vec![4],
+            },
+            PipelineFilter {
+                id: FILTER_DEFLATE,
+                client_data: vec![6],
+            },
+            PipelineFilter {
+                id: FILTER_FLETCHER32,
+                client_data: vec![],
+            },
+        ];
+        let expected = Codecs {
+            compression: Some(Compression::Zlib),
+            filters: vec![Filter::Shuffle { element_size: 4 }],
+            checksum: true,
+        };
+        assert_eq!(expected, codecs(&filters, 0, 4).unwrap());
+        let expected = Codecs {
+            compression: Some(Compression::Zlib),
+            filters: vec![],
+            checksum: false,
+        };
+        assert_eq!(expected, codecs(&filters, 0b101, 4).unwrap());
+        let err = codecs(&[filters[1].clone(), filters[0].clone()], 0, 4).unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: filters after compression are not supported",
+            err.to_string()
+        );
+        let err = codecs(&[filters[2].clone(), filters[0].clone()], 0, 4).unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: filters after the Fletcher32 filter are not supported",
+            err.to_string()
+        );
+        let bitshuffle = |client_data: Vec<u32>| {
+            codecs(
+                &[PipelineFilter {
+                    id: FILTER_BITSHUFFLE,
+                    client_data,
+                }],
+                0,
+                8,
+            )
+        };
+        assert_eq!(
+            vec![Filter::Bitshuffle { element_size: 8 }],
+            bitshuffle(vec![0, 3, 8, 0, 0]).unwrap().filters
+        );
+        let err = bitshuffle(vec![0, 3, 8, 0, 2]).unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: bit shuffle with compression is not supported",
+            err.to_string()
+        );
+        bitshuffle(vec![0, 3, 8, 64, 0]).unwrap_err();
+    }
+
+    #[test]
+    fn datatypes() {
+        let parse = |message: &[u8]| {
+            parse_datatype(&mut Cursor {
+                data: message,
+                offset_size: 8,
+                length_size: 8,
+            })
+        };
+        assert_eq!(
+            (DType::Uint16, Some(ByteOrder::Big)),
+            parse(&fixed_point(2, false, true)).unwrap()
+        );
+        assert_eq!(
+            (DType::Int8, None),
+            parse(&fixed_point(1, true, true)).unwrap()
+        );
+        assert_eq!(
+            (DType::Float32, Some(ByteOrder::Little)),
+            parse(&floating_point(4, false)).unwrap()
+        );
+        let mut vax = floating_point(4, false);
+        vax[1] |= 0x40;
+        parse(&vax).unwrap_err();
+        parse(&floating_point(2, false)).unwrap_err();
+        let string = [&[0x13, 0, 0, 0][..], &8_u32.to_le_bytes()].concat();
+        let err = parse(&string).unwrap_err();
+        assert_eq!(
+            "invalid HDF5 file: unsupported datatype class 3 size 8",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_encoded_size() {
+        assert_eq!(1, encoded_size(0));
+        assert_eq!(1, encoded_size(45));
+        assert_eq!(1, encoded_size(255));
+        assert_eq!(2, encoded_size(256));
+        assert_eq!(3, encoded_size(4096 * 16));
+    }
+}
@@ -0,0 +1,44 @@
+//! HTTP client configuration for connections to upstream object stores.
+
+pub mod proxy;
+pub mod tls;
+
+use aws_smithy_runtime::client::http::hyper_014::HyperClientBuilder;
+use aws_smithy_runtime_api::client::http::SharedHttpClient;
+use hyper::client::HttpConnector;
+
+/// Returns an HTTP client for the AWS SDK, or `None` to use the SDK's default client.
+///
+/// A custom client is only built when the default client's behaviour needs to be changed. It
+/// may be shared between all S3 clients.
+///
+/// # Arguments
+///
+/// * `proxy`: Optional HTTP proxy configuration
+/// * `tls_config`: Optional TLS client configuration. If `None`, the platform's native CA
+///   certificates are trusted.
+pub fn build(
+    proxy: Option<proxy::ProxyConfig>,
+    tls_config: Option<rustls::ClientConfig>,
+) -> Option<SharedHttpClient> {
+    if proxy.is_none() && tls_config.is_none() {
+        return None;
+    }
+    let builder = hyper_rustls::HttpsConnectorBuilder::new();
+    let builder = match tls_config {
+        Some(tls_config) => builder.with_tls_config(tls_config),
+        None => builder.with_native_roots(),
+    };
+    let builder = builder.https_or_http().enable_http1().enable_http2();
+    let client = match proxy {
+        Some(proxy) => HyperClientBuilder::new()
+            .build(builder.wrap_connector(proxy::ProxyConnector::new(proxy))),
+        None => {
+            let mut http = HttpConnector::new();
+            // HTTPS is handled by the wrapping TLS connector.
+            http.enforce_http(false);
+            HyperClientBuilder::new().build(builder.wrap_connector(http))
+        }
+    };
+    Some(client)
+}
@@ -0,0 +1,141 @@
+//! TLS configuration for upstream object store connections
+
+use expanduser::expanduser;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+use std::io::BufRead;
+use std::time::SystemTime;
+
+/// Returns a TLS client configuration, or `None` to use the default configuration.
+///
+/// The default configuration trusts the platform's native CA certificates.
+///
+/// # Arguments
+///
+/// * `ca_bundle`: Optional path to a PEM file containing CA certificates to trust in addition to
+///   the platform's native CA certificates
+/// * `insecure_skip_verify`: Whether to skip verification of server certificates. This should
+///   only be used in test environments.
+pub fn client_config(
+    ca_bundle: Option<&str>,
+    insecure_skip_verify: bool,
+) -> Result<Option<ClientConfig>, String> {
+    if insecure_skip_verify {
+        tracing::warn!("TLS certificate verification for S3 object stores is disabled");
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(std::sync::Arc::new(NoVerification {}))
+            .with_no_client_auth();
+        return Ok(Some(config));
+    }
+    let Some(ca_bundle) = ca_bundle else {
+        return Ok(None);
+    };
+    let path = expanduser(ca_bundle).map_err(|err| err.to_string())?;
+    let file = std::fs::File::open(&path)
+        .map_err(|err| format!("failed to open CA bundle {}: {}", path.display(), err))?;
+    let mut roots = native_root_store()?;
+    add_certificates(&mut roots, &mut std::io::BufReader::new(file))?;
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Some(config))
+}
+
+/// Returns a certificate store containing the platform's native CA certificates.
+fn native_root_store() -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    let certs = rustls_native_certs::load_native_certs()
+        .map_err(|err| format!("failed to load native CA certificates: {}", err))?;
+    for cert in certs {
+        // Ignore any invalid native certificates, in common with the AWS SDK's default client.
+        let _ = roots.add(&Certificate(cert.0));
+    }
+    Ok(roots)
+}
+
+/// Add PEM encoded certificates to a certificate store.
+///
+/// # Arguments
+///
+/// * `roots`: Certificate store
+/// * `pem`: Reader for PEM encoded certificates
+pub fn add_certificates(roots: &mut RootCertStore, pem: &mut dyn BufRead) -> Result<(), String> {
+    let certs =
+        rustls_pemfile::certs(pem).map_err(|err| format!("failed to parse CA bundle: {}", err))?;
+    if certs.is_empty() {
+        return Err("no certificates found in CA bundle".to_string());
+    }
+    for cert in certs {
+        roots
+            .add(&Certificate(cert))
+            .map_err(|err| format!("invalid certificate in CA bundle: {}", err))?;
+    }
+    Ok(())
+}
+
+/// A server certificate verifier that accepts any certificate.
+struct NoVerification {}
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_CA: &str = "-----BEGIN CERTIFICATE-----
+MIIBljCCATugAwIBAgIUL9Xqkkgky9SKRHW/guzj8pbgdC4wCgYIKoZIzj0EAwIw
+HzEdMBsGA1UEAwwUUmVkdWN0aW9uaXN0IFRlc3QgQ0EwIBcNMjYxMDE2MTc0NDEz
+WhgPMjEyNjA5MjIxNzQ0MTNaMB8xHTAbBgNVBAMMFFJlZHVjdGlvbmlzdCBUZXN0
+IENBMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEE1fiVFw0nDv8L7cs1zHO2XRV
+wX6MTdn2HU7JLjaweYYHOH0Iltza6FFRwJtnxS/X+SM2lHP3RUSnpenslrmPxKNT
+MFEwHQYDVR0OBBYEFB6v6wxWhCkEAJaFba3+xP1TeY9nMB8GA1UdIwQYMBaAFB6v
+6wxWhCkEAJaFba3+xP1TeY9nMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwID
+SQAwRgIhAKRVa4uOWfq/hANInJaaGWgt3CS1s90K8cwhIjoXW40mAiEA0O6AySV3
+WktpUt2UTVEE1FKzP4qWBD9JcMFK1eSFLr4=
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn client_config_default() {
+        assert!(client_config(None, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn client_config_insecure() {
+        assert!(client_config(None, true).unwrap().is_some());
+    }
+
+    #[test]
+    fn client_config_missing_ca_bundle() {
+        let err = client_config(Some("/nonexistent/ca.pem"), false).unwrap_err();
+        assert!(err.starts_with("failed to open CA bundle /nonexistent/ca.pem"));
+    }
+
+    #[test]
+    fn add_certificates_valid() {
+        let mut roots = RootCertStore::empty();
+        add_certificates(&mut roots, &mut TEST_CA.as_bytes()).unwrap();
+        assert_eq!(1, roots.len());
+    }
+
+    #[test]
+    fn add_certificates_empty() {
+        let mut roots = RootCertStore::empty();
+        let err = add_certificates(&mut roots, &mut "".as_bytes()).unwrap_err();
+        assert_eq!("no certificates found in CA bundle", err);
+    }
+}
@@ -21,14 +21,25 @@
 //!
 //! * HTTP(S) API with JSON request data
 //! * Access to data stored in S3-compatible storage
-//! * Basic numerical operations on multi-dimensional arrays (count, min, max, select, sum)
+//! * Basic numerical operations on multi-dimensional arrays (count, min, max, argmin, argmax, extrema, mean, select, std, sum, var)
+//! * Grouped reductions using a label array (groupby)
+//! * Downsampling by aggregating blocks of an array (coarsen)
+//! * Reductions across the members of an ensemble of objects (ensemble)
+//! * Experimental element-wise expressions followed by a reduction (expr)
 //! * Perform calculations on a selection/slice of an array
 //! * Perform calculations allowing for missing data
-//! * Compressed data (GZip, Zlib)
-//! * Filtered data (byte shuffle)
+//! * CF conventions mask-and-scale decoding
+//! * Reduced precision floating point results
+//! * Sparse encoding of mostly-missing array results
+//! * Compressed data (Blosc, GZip, LZ4, Zlib, Zstandard)
+//! * Filtered data (byte shuffle, bit shuffle)
+//! * Inner chunks of Zarr v3 shards
+//! * Reductions over whole Zarr v2 and v3 arrays
+//! * Reductions over datasets in HDF5 and netCDF4 files
 //! * Data with non-native byte order (endianness)
 //! * Server resource (CPU, memory, files) management
-//! * [Prometheus](https://prometheus.io/) metrics
+//! * Optional Landlock and seccomp sandboxing (Linux)
+//! * [Prometheus](https://prometheus.io/) metrics, optionally pushed to StatsD or OpenTelemetry collectors
 //! * Tracing with an option to send data to [Jaeger](https://www.jaegertracing.io/)
 //! * Ansible-based containerised deployment
 //!
@@ -48,20 +59,49 @@
 
 pub mod app;
 pub mod array;
+pub mod auth;
+pub mod autotune;
+pub mod cache_headers;
+pub mod cf;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod circuit_breaker;
 pub mod cli;
+pub mod coalesce;
 pub mod compression;
+pub mod deadline;
+pub mod demo;
 pub mod error;
+pub mod expression;
+pub mod failover;
 pub mod filter_pipeline;
 pub mod filters;
+pub mod hdf5;
+pub mod http_client;
+pub mod maintenance;
 pub mod metrics;
 pub mod models;
+pub mod numa;
+pub mod object_limit;
 pub mod operation;
+pub mod operation_limits;
 pub mod operations;
+pub mod precision;
+pub mod recorder;
+pub mod request_limit;
 pub mod resource_manager;
+pub mod retry_budget;
 pub mod s3_client;
+pub mod sandbox;
 pub mod server;
-#[cfg(test)]
+pub mod shard;
+pub mod sources;
+pub mod sparse;
+pub mod supervisor;
+#[cfg(any(test, feature = "testing"))]
 pub mod test_utils;
 pub mod tracing;
 pub mod types;
+pub mod usage;
 pub mod validated_json;
+pub mod zarr;
@@ -3,17 +3,41 @@
 use reductionist::app;
 use reductionist::cli;
 use reductionist::metrics;
+use reductionist::sandbox;
 use reductionist::server;
 use reductionist::tracing;
 
 /// Application entry point
-#[tokio::main]
-async fn main() {
+fn main() {
     let args = cli::parse();
+    // The sandbox must be applied before the Tokio runtime creates any threads, so that they
+    // inherit it.
+    let sandbox = args
+        .sandbox
+        .then(|| sandbox::apply(&args).expect("failed to apply sandbox"));
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build Tokio runtime")
+        .block_on(run(args, sandbox));
+}
+
+/// Run the Reductionist server
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+/// * `sandbox`: Enforcement of the sandbox, if applied
+async fn run(args: cli::CommandLineArgs, sandbox: Option<sandbox::Enforcement>) {
     tracing::init_tracing(&args);
+    match sandbox {
+        Some(sandbox::Enforcement::Full) => ::tracing::info!("Sandbox is fully enforced"),
+        Some(enforcement) => ::tracing::warn!("Sandbox is {} by the kernel", enforcement),
+        None => (),
+    }
     metrics::register_metrics();
     app::init(&args);
-    let service = app::service(&args);
-    server::serve(&args, service).await;
+    let (service, admin_service) = app::services(&args);
+    server::serve(&args, service, admin_service).await;
     tracing::shutdown_tracing();
 }
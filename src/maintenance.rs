@@ -0,0 +1,164 @@
+//! Maintenance mode
+//!
+//! In maintenance mode, new operation requests are rejected while in-flight requests are allowed to
+//! finish. Once no requests are in flight the server is drained, and may be stopped without
+//! affecting clients.
+
+use crate::error::ActiveStorageError;
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Maintenance mode state
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    /// Whether maintenance mode is enabled.
+    enabled: AtomicBool,
+
+    /// Number of operation requests in flight.
+    in_flight: AtomicUsize,
+}
+
+/// Maintenance mode status
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MaintenanceStatus {
+    /// Whether maintenance mode is enabled.
+    pub enabled: bool,
+
+    /// Number of operation requests in flight.
+    pub in_flight: usize,
+
+    /// Whether maintenance mode is enabled and no requests are in flight.
+    pub drained: bool,
+}
+
+/// Guard that records an in-flight request until dropped.
+pub struct InFlightGuard<'a> {
+    maintenance: &'a Maintenance,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.maintenance.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Maintenance {
+    /// Returns a new Maintenance object with maintenance mode disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable maintenance mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether maintenance mode should be enabled
+    pub fn set(&self, enabled: bool) {
+        let previous = self.enabled.swap(enabled, Ordering::SeqCst);
+        if previous != enabled {
+            tracing::info!(
+                "Maintenance mode {}",
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+    }
+
+    /// Toggle maintenance mode.
+    pub fn toggle(&self) {
+        self.set(!self.enabled.load(Ordering::SeqCst))
+    }
+
+    /// Returns the maintenance mode status.
+    pub fn status(&self) -> MaintenanceStatus {
+        let enabled = self.enabled.load(Ordering::SeqCst);
+        let in_flight = self.in_flight.load(Ordering::SeqCst);
+        MaintenanceStatus {
+            enabled,
+            in_flight,
+            drained: enabled && in_flight == 0,
+        }
+    }
+
+    /// Start a request.
+    ///
+    /// Returns a guard that records the request as in flight until dropped, or an
+    /// [ActiveStorageError::Maintenance] if maintenance mode is enabled.
+    pub fn start_request(&self) -> Result<InFlightGuard<'_>, ActiveStorageError> {
+        // Increment before checking to avoid a race with enabling maintenance mode and reading
+        // the drained status.
+        let guard = InFlightGuard { maintenance: self };
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if self.enabled.load(Ordering::SeqCst) {
+            return Err(ActiveStorageError::Maintenance);
+        }
+        Ok(guard)
+    }
+}
+
+/// Toggle maintenance mode whenever a SIGUSR1 signal is received.
+///
+/// # Arguments
+///
+/// * `maintenance`: Maintenance mode state
+#[cfg(unix)]
+pub async fn toggle_on_signal(maintenance: std::sync::Arc<Maintenance>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut signal =
+        signal(SignalKind::user_defined1()).expect("failed to install SIGUSR1 signal handler");
+    while signal.recv().await.is_some() {
+        maintenance.toggle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status() {
+        let maintenance = Maintenance::new();
+        let expected = MaintenanceStatus {
+            enabled: false,
+            in_flight: 0,
+            drained: false,
+        };
+        assert_eq!(expected, maintenance.status());
+    }
+
+    #[test]
+    fn start_request() {
+        let maintenance = Maintenance::new();
+        let guard = maintenance.start_request().unwrap();
+        assert_eq!(1, maintenance.status().in_flight);
+        maintenance.set(true);
+        let status = maintenance.status();
+        assert!(status.enabled);
+        assert!(!status.drained);
+        drop(guard);
+        let expected = MaintenanceStatus {
+            enabled: true,
+            in_flight: 0,
+            drained: true,
+        };
+        assert_eq!(expected, maintenance.status());
+    }
+
+    #[test]
+    fn start_request_maintenance() {
+        let maintenance = Maintenance::new();
+        maintenance.set(true);
+        let err = maintenance.start_request().err().unwrap();
+        assert_eq!("server is in maintenance mode", err.to_string());
+        assert_eq!(0, maintenance.status().in_flight);
+    }
+
+    #[test]
+    fn toggle() {
+        let maintenance = Maintenance::new();
+        maintenance.toggle();
+        assert!(maintenance.status().enabled);
+        maintenance.toggle();
+        assert!(!maintenance.status().enabled);
+    }
+}
@@ -1,10 +1,27 @@
 //! Prometheus metrics
+//!
+//! Metrics are collected in the Prometheus default registry, and served in the Prometheus text
+//! format. Sites not running Prometheus may also push them periodically to another backend by
+//! implementing the [Exporter] trait. The following exporters are available:
+//!
+//! * [statsd::StatsD]: Sends metrics to a StatsD server over UDP.
+//! * [otlp::Otlp]: Sends metrics to an OpenTelemetry collector using OTLP over HTTP.
 
-use std::time::Instant;
+pub mod otlp;
+pub mod statsd;
 
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cli::{CommandLineArgs, MetricsBackend};
+
+use async_trait::async_trait;
 use axum::{http::Request, middleware::Next, response::IntoResponse};
 use lazy_static::lazy_static;
-use prometheus::{self, Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts};
+use prometheus::{
+    self, proto::MetricFamily, Encoder, Gauge, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, Opts,
+};
 
 lazy_static! {
     // Simple request counter
@@ -25,6 +42,51 @@ lazy_static! {
         },
         &["status_code", "http_method", "path"],
     ).expect("Prometheus metric options should be valid");
+    // Histogram of the CPU time used by the synchronous part of each operation
+    pub static ref COMPUTE_CPU_TIME: Histogram = Histogram::with_opts(
+        HistogramOpts::new("compute_cpu_time", "The CPU time used by the synchronous part of each operation")
+    ).expect("Prometheus metric options should be valid");
+    // S3 endpoint failover counter
+    pub static ref S3_ENDPOINT_FAILOVERS: IntCounterVec = IntCounterVec::new(
+        Opts::new("s3_endpoint_failovers", "The number of S3 requests failed over to an equivalent endpoint"),
+        &["endpoint"]
+    ).expect("Prometheus metric options should be valid");
+    // S3 downloads coalesced with a download in flight
+    pub static ref S3_COALESCED_READS: IntCounter = IntCounter::with_opts(
+        Opts::new("s3_coalesced_reads", "The number of S3 downloads served by a concurrent download of the same object")
+    ).expect("Prometheus metric options should be valid");
+    // S3 requests rejected by an open circuit breaker
+    pub static ref S3_CIRCUIT_BREAKER_REJECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new("s3_circuit_breaker_rejections", "The number of S3 requests rejected because the circuit breaker for the endpoint was open"),
+        &["endpoint"]
+    ).expect("Prometheus metric options should be valid");
+    // Authenticated requests by user
+    pub static ref USER_REQUESTS: IntCounterVec = IntCounterVec::new(
+        Opts::new("user_requests", "The number of authenticated requests by user"),
+        &["user"]
+    ).expect("Prometheus metric options should be valid");
+    // Requests rejected by the authentication backend
+    pub static ref AUTHENTICATION_FAILURES: IntCounterVec = IntCounterVec::new(
+        Opts::new("authentication_failures", "The number of requests that failed authentication"),
+        &["backend"]
+    ).expect("Prometheus metric options should be valid");
+    // Ratio of observed to estimated memory usage
+    pub static ref MEMORY_ESTIMATE_RATIO: Gauge = Gauge::with_opts(
+        Opts::new("memory_estimate_ratio", "The moving average ratio of observed to estimated memory usage of requests")
+    ).expect("Prometheus metric options should be valid");
+    // Current limit on concurrent CPU-bound tasks
+    pub static ref TASK_LIMIT: IntGauge = IntGauge::with_opts(
+        Opts::new("task_limit", "The current limit on concurrent CPU-bound tasks")
+    ).expect("Prometheus metric options should be valid");
+    // Panics in supervised background tasks
+    pub static ref TASK_PANICS: IntCounterVec = IntCounterVec::new(
+        Opts::new("task_panics", "The number of panics in supervised background tasks"),
+        &["task"]
+    ).expect("Prometheus metric options should be valid");
+    // Panics while handling requests
+    pub static ref REQUEST_PANICS: IntCounter = IntCounter::with_opts(
+        Opts::new("request_panics", "The number of panics while handling requests")
+    ).expect("Prometheus metric options should be valid");
 }
 
 /// Registers various prometheus metrics with the global registry
@@ -39,6 +101,36 @@ pub fn register_metrics() {
     registry
         .register(Box::new(RESPONSE_TIME_COLLECTOR.clone()))
         .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(COMPUTE_CPU_TIME.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(S3_ENDPOINT_FAILOVERS.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(S3_COALESCED_READS.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(S3_CIRCUIT_BREAKER_REJECTIONS.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(USER_REQUESTS.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(AUTHENTICATION_FAILURES.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(MEMORY_ESTIMATE_RATIO.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(TASK_LIMIT.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(TASK_PANICS.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
+    registry
+        .register(Box::new(REQUEST_PANICS.clone()))
+        .expect("Prometheus metrics registration should not fail during initialization");
 }
 
 /// Returns currently gathered prometheus metrics
@@ -83,3 +175,55 @@ pub async fn track_metrics<B>(request: Request<B>, next: Next<B>) -> impl IntoRe
 
     response
 }
+
+/// A backend to which metrics are pushed periodically.
+#[async_trait]
+pub trait Exporter: Send + Sync {
+    /// Returns the name of the backend.
+    fn name(&self) -> &'static str;
+
+    /// Pushes the current values of metrics to the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `families`: Metric families gathered from the registry
+    async fn export(&self, families: &[MetricFamily]) -> Result<(), String>;
+}
+
+/// Returns the metrics exporter configured by the command line arguments, or `None` if metrics
+/// are only served for scraping.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+pub fn exporter(args: &CommandLineArgs) -> Result<Option<Arc<dyn Exporter>>, String> {
+    let endpoint = || {
+        args.metrics_endpoint
+            .as_deref()
+            .ok_or("the metrics backend requires a metrics endpoint")
+    };
+    match args.metrics_backend {
+        MetricsBackend::Prometheus => Ok(None),
+        MetricsBackend::Statsd => Ok(Some(Arc::new(statsd::StatsD::new(endpoint()?)))),
+        MetricsBackend::Otlp => Ok(Some(Arc::new(otlp::Otlp::new(endpoint()?)?))),
+    }
+}
+
+/// Push metrics to a backend periodically.
+///
+/// Failures are logged, and metrics are pushed again after the next interval.
+///
+/// # Arguments
+///
+/// * `exporter`: Metrics exporter
+/// * `interval`: Interval between pushes
+pub async fn export(exporter: Arc<dyn Exporter>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(err) = exporter.export(&prometheus::gather()).await {
+            tracing::warn!("failed to push metrics to {}: {}", exporter.name(), err);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! OpenTelemetry (OTLP) metrics exporter
+
+use super::Exporter;
+
+use async_trait::async_trait;
+use hyper::client::HttpConnector;
+use hyper::{header, Body, Client, Method, Request, Uri};
+use hyper_rustls::HttpsConnector;
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Cumulative aggregation temporality, as Prometheus metrics are never reset.
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+/// Exporter sending metrics to an OpenTelemetry collector using OTLP over HTTP.
+///
+/// Metrics are encoded as JSON. Counters are sent as cumulative monotonic sums, and histograms
+/// as cumulative explicit bucket histograms.
+pub struct Otlp {
+    /// URL of the OTLP metrics endpoint.
+    url: Uri,
+    /// HTTP client.
+    client: Client<HttpsConnector<HttpConnector>>,
+    /// Time from which cumulative metrics are collected.
+    start: SystemTime,
+}
+
+impl Otlp {
+    /// Returns a new OTLP exporter.
+    ///
+    /// # Arguments
+    ///
+    /// * `url`: URL of the OTLP metrics endpoint
+    pub fn new(url: &str) -> Result<Self, String> {
+        let url = url
+            .parse()
+            .map_err(|err| format!("invalid OTLP endpoint {}: {}", url, err))?;
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Ok(Self {
+            url,
+            client: Client::builder().build(connector),
+            start: SystemTime::now(),
+        })
+    }
+}
+
+#[async_trait]
+impl Exporter for Otlp {
+    fn name(&self) -> &'static str {
+        "otlp"
+    }
+
+    async fn export(&self, families: &[MetricFamily]) -> Result<(), String> {
+        let body = encode(families, self.start, SystemTime::now());
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(body.to_string()))
+            .map_err(|err| err.to_string())?;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|err| err.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "OTLP endpoint responded with {}",
+                response.status()
+            ))
+        }
+    }
+}
+
+/// Returns an OTLP `ExportMetricsServiceRequest` for the metric families, encoded as JSON.
+///
+/// # Arguments
+///
+/// * `families`: Metric families gathered from the registry
+/// * `start`: Time from which cumulative metrics are collected
+/// * `now`: Time at which the metrics were gathered
+fn encode(families: &[MetricFamily], start: SystemTime, now: SystemTime) -> Value {
+    let start = unix_nanos(start);
+    let now = unix_nanos(now);
+    let data_point = |metric: &Metric| {
+        json!({
+            "attributes": attributes(metric.get_label()),
+            "startTimeUnixNano": start,
+            "timeUnixNano": now,
+        })
+    };
+    let metrics: Vec<_> = families
+        .iter()
+        .map(|family| {
+            // Returns the data points of the family, with values added by a function.
+            let points = |add: &dyn Fn(&Metric, &mut Value)| -> Vec<Value> {
+                family
+                    .get_metric()
+                    .iter()
+                    .map(|metric| {
+                        let mut point = data_point(metric);
+                        add(metric, &mut point);
+                        point
+                    })
+                    .collect()
+            };
+            let mut value = json!({
+                "name": family.get_name(),
+                "description": family.get_help(),
+            });
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    value["sum"] = json!({
+                        "dataPoints": points(&|metric, point| {
+                            point["asDouble"] = json!(metric.get_counter().get_value())
+                        }),
+                        "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+                        "isMonotonic": true,
+                    })
+                }
+                MetricType::GAUGE => {
+                    value["gauge"] = json!({
+                        "dataPoints": points(&|metric, point| {
+                            point["asDouble"] = json!(metric.get_gauge().get_value())
+                        }),
+                    })
+                }
+                MetricType::UNTYPED => {
+                    value["gauge"] = json!({
+                        "dataPoints": points(&|metric, point| {
+                            point["asDouble"] = json!(metric.get_untyped().get_value())
+                        }),
+                    })
+                }
+                MetricType::HISTOGRAM => {
+                    value["histogram"] = json!({
+                        "dataPoints": points(&histogram),
+                        "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+                    })
+                }
+                MetricType::SUMMARY => {
+                    value["summary"] = json!({
+                        "dataPoints": points(&summary),
+                    })
+                }
+            }
+            value
+        })
+        .collect();
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [{
+                    "key": "service.name",
+                    "value": {"stringValue": "reductionist"},
+                }],
+            },
+            "scopeMetrics": [{
+                "scope": {
+                    "name": "reductionist",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
+
+/// Adds the count, sum and buckets of a histogram metric to an OTLP data point.
+///
+/// Prometheus buckets are cumulative, while OTLP buckets count the observations between
+/// consecutive bounds, with a final bucket for observations above the last bound.
+///
+/// # Arguments
+///
+/// * `metric`: Histogram metric
+/// * `point`: OTLP data point
+fn histogram(metric: &Metric, point: &mut Value) {
+    let histogram = metric.get_histogram();
+    let buckets: Vec<_> = histogram
+        .get_bucket()
+        .iter()
+        .filter(|bucket| bucket.get_upper_bound().is_finite())
+        .collect();
+    let mut counts = vec![];
+    let mut previous = 0;
+    for bucket in &buckets {
+        counts.push((bucket.get_cumulative_count() - previous).to_string());
+        previous = bucket.get_cumulative_count();
+    }
+    counts.push((histogram.get_sample_count() - previous).to_string());
+    point["count"] = json!(histogram.get_sample_count().to_string());
+    point["sum"] = json!(histogram.get_sample_sum());
+    point["bucketCounts"] = json!(counts);
+    point["explicitBounds"] = buckets
+        .iter()
+        .map(|bucket| json!(bucket.get_upper_bound()))
+        .collect();
+}
+
+/// Adds the count, sum and quantiles of a summary metric to an OTLP data point.
+///
+/// # Arguments
+///
+/// * `metric`: Summary metric
+/// * `point`: OTLP data point
+fn summary(metric: &Metric, point: &mut Value) {
+    let summary = metric.get_summary();
+    point["count"] = json!(summary.get_sample_count().to_string());
+    point["sum"] = json!(summary.get_sample_sum());
+    point["quantileValues"] = summary
+        .get_quantile()
+        .iter()
+        .map(|quantile| {
+            json!({
+                "quantile": quantile.get_quantile(),
+                "value": quantile.get_value(),
+            })
+        })
+        .collect();
+}
+
+/// Returns OTLP attributes for the labels of a metric.
+///
+/// # Arguments
+///
+/// * `labels`: Labels of the metric
+fn attributes(labels: &[LabelPair]) -> Value {
+    labels
+        .iter()
+        .map(|label| {
+            json!({
+                "key": label.get_name(),
+                "value": {"stringValue": label.get_value()},
+            })
+        })
+        .collect()
+}
+
+/// Returns a time as a string of nanoseconds since the Unix epoch, as OTLP encodes 64-bit
+/// integers in JSON as strings.
+///
+/// # Arguments
+///
+/// * `time`: Time to encode
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use prometheus::{Gauge, Histogram, HistogramOpts, IntCounterVec, Opts, Registry};
+    use std::time::Duration;
+
+    #[test]
+    fn encode_metrics() {
+        let registry = Registry::new();
+        let counter = IntCounterVec::new(Opts::new("requests", "Requests"), &["path"]).unwrap();
+        let gauge = Gauge::with_opts(Opts::new("ratio", "Ratio")).unwrap();
+        let histogram =
+            Histogram::with_opts(HistogramOpts::new("time", "Time").buckets(vec![1.0, 2.0]))
+                .unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        counter.with_label_values(&["/v1/sum"]).inc_by(3);
+        gauge.set(0.5);
+        histogram.observe(0.5);
+        histogram.observe(1.5);
+        histogram.observe(3.0);
+
+        let start = UNIX_EPOCH + Duration::from_secs(1);
+        let now = UNIX_EPOCH + Duration::from_secs(2);
+        let value = encode(&registry.gather(), start, now);
+        let metrics = &value["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+
+        assert_eq!("ratio", metrics[0]["name"]);
+        assert_eq!(0.5, metrics[0]["gauge"]["dataPoints"][0]["asDouble"]);
+
+        assert_eq!("requests", metrics[1]["name"]);
+        assert_eq!("Requests", metrics[1]["description"]);
+        let sum = &metrics[1]["sum"];
+        assert_eq!(true, sum["isMonotonic"]);
+        assert_eq!(2, sum["aggregationTemporality"]);
+        let point = &sum["dataPoints"][0];
+        assert_eq!(3.0, point["asDouble"]);
+        assert_eq!("1000000000", point["startTimeUnixNano"]);
+        assert_eq!("2000000000", point["timeUnixNano"]);
+        assert_eq!(
+            json!([{"key": "path", "value": {"stringValue": "/v1/sum"}}]),
+            point["attributes"]
+        );
+
+        assert_eq!("time", metrics[2]["name"]);
+        let point = &metrics[2]["histogram"]["dataPoints"][0];
+        assert_eq!("3", point["count"]);
+        assert_eq!(5.0, point["sum"]);
+        assert_eq!(json!(["1", "1", "1"]), point["bucketCounts"]);
+        assert_eq!(json!([1.0, 2.0]), point["explicitBounds"]);
+    }
+}
@@ -0,0 +1,222 @@
+//! StatsD metrics exporter
+
+use super::Exporter;
+
+use async_trait::async_trait;
+use hashbrown::HashMap;
+use prometheus::proto::{MetricFamily, MetricType};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use tokio::net::UdpSocket;
+
+/// Maximum size of a UDP packet, avoiding fragmentation on common networks.
+const MAX_PACKET_SIZE: usize = 1432;
+
+/// Exporter sending metrics to a StatsD server over UDP.
+///
+/// Labels are sent as DogStatsD-style tags, which are supported by most StatsD servers. Counters
+/// are sent as the increase since the previous push, and histograms as counters of the number and
+/// sum of their observations.
+pub struct StatsD {
+    /// `<host>:<port>` address of the StatsD server.
+    address: String,
+    /// Value of each counter at the previous push, keyed by its name and tags.
+    previous: Mutex<HashMap<String, f64>>,
+}
+
+impl StatsD {
+    /// Returns a new StatsD exporter.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: `<host>:<port>` address of the StatsD server
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            previous: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the StatsD lines for the metric families.
+    ///
+    /// # Arguments
+    ///
+    /// * `families`: Metric families gathered from the registry
+    fn lines(&self, families: &[MetricFamily]) -> Vec<String> {
+        let mut previous = self.previous.lock().unwrap();
+        let mut counter = |name: String, tags: &str, value: f64| {
+            let key = format!("{}{}", name, tags);
+            let delta = value - previous.insert(key, value).unwrap_or(0.0);
+            // Counters reset when the process restarts, but not while it is running.
+            (delta > 0.0).then(|| format!("{}:{}|c{}", name, delta, tags))
+        };
+        let mut lines = vec![];
+        for family in families {
+            let name = family.get_name();
+            for metric in family.get_metric() {
+                let tags = tags(metric.get_label());
+                match family.get_field_type() {
+                    MetricType::COUNTER => {
+                        lines.extend(counter(
+                            name.to_string(),
+                            &tags,
+                            metric.get_counter().get_value(),
+                        ));
+                    }
+                    MetricType::GAUGE => lines.push(format!(
+                        "{}:{}|g{}",
+                        name,
+                        metric.get_gauge().get_value(),
+                        tags
+                    )),
+                    MetricType::UNTYPED => lines.push(format!(
+                        "{}:{}|g{}",
+                        name,
+                        metric.get_untyped().get_value(),
+                        tags
+                    )),
+                    MetricType::HISTOGRAM => {
+                        let histogram = metric.get_histogram();
+                        lines.extend(counter(
+                            format!("{}_count", name),
+                            &tags,
+                            histogram.get_sample_count() as f64,
+                        ));
+                        lines.extend(counter(
+                            format!("{}_sum", name),
+                            &tags,
+                            histogram.get_sample_sum(),
+                        ));
+                    }
+                    MetricType::SUMMARY => {
+                        let summary = metric.get_summary();
+                        lines.extend(counter(
+                            format!("{}_count", name),
+                            &tags,
+                            summary.get_sample_count() as f64,
+                        ));
+                        lines.extend(counter(
+                            format!("{}_sum", name),
+                            &tags,
+                            summary.get_sample_sum(),
+                        ));
+                    }
+                }
+            }
+        }
+        lines
+    }
+}
+
+#[async_trait]
+impl Exporter for StatsD {
+    fn name(&self) -> &'static str {
+        "statsd"
+    }
+
+    async fn export(&self, families: &[MetricFamily]) -> Result<(), String> {
+        let lines = self.lines(families);
+        let address = tokio::net::lookup_host(&self.address)
+            .await
+            .map_err(|err| err.to_string())?
+            .next()
+            .ok_or_else(|| format!("{} did not resolve to an address", self.address))?;
+        let local = match address {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local)
+            .await
+            .map_err(|err| err.to_string())?;
+        for packet in packets(&lines) {
+            socket
+                .send_to(packet.as_bytes(), address)
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the DogStatsD tags for the labels of a metric, or an empty string if it has none.
+///
+/// # Arguments
+///
+/// * `labels`: Labels of the metric
+fn tags(labels: &[prometheus::proto::LabelPair]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let tags: Vec<_> = labels
+        .iter()
+        // Separators are not permitted in tags.
+        .map(|label| format!("{}:{}", label.get_name(), label.get_value()).replace([',', '|'], "_"))
+        .collect();
+    format!("|#{}", tags.join(","))
+}
+
+/// Returns UDP packets containing the lines, separated by newlines.
+///
+/// # Arguments
+///
+/// * `lines`: StatsD lines
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut packets: Vec<String> = vec![];
+    for line in lines {
+        match packets.last_mut() {
+            Some(packet) if packet.len() + 1 + line.len() <= MAX_PACKET_SIZE => {
+                packet.push('\n');
+                packet.push_str(line);
+            }
+            _ => packets.push(line.clone()),
+        }
+    }
+    packets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use prometheus::{Gauge, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+
+    #[test]
+    fn lines() {
+        let registry = Registry::new();
+        let counter =
+            IntCounterVec::new(Opts::new("requests", "Requests"), &["path", "code"]).unwrap();
+        let gauge = Gauge::with_opts(Opts::new("ratio", "Ratio")).unwrap();
+        let histogram = HistogramVec::new(HistogramOpts::new("time", "Time"), &["path"]).unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        counter.with_label_values(&["/v1/sum", "200"]).inc_by(3);
+        gauge.set(0.5);
+        histogram.with_label_values(&["/v1/sum"]).observe(1.5);
+
+        let statsd = StatsD::new("localhost:8125");
+        assert_eq!(
+            statsd.lines(&registry.gather()),
+            vec![
+                "ratio:0.5|g",
+                "requests:3|c|#code:200,path:/v1/sum",
+                "time_count:1|c|#path:/v1/sum",
+                "time_sum:1.5|c|#path:/v1/sum",
+            ]
+        );
+
+        // Counters are sent as the increase since the previous push.
+        counter.with_label_values(&["/v1/sum", "200"]).inc();
+        assert_eq!(
+            statsd.lines(&registry.gather()),
+            vec!["ratio:0.5|g", "requests:1|c|#code:200,path:/v1/sum"]
+        );
+    }
+
+    #[test]
+    fn packets_split() {
+        let line = "a".repeat(1000);
+        let lines = vec![line.clone(), "b:1|c".to_string(), line.clone()];
+        assert_eq!(packets(&lines), vec![format!("{}\nb:1|c", line), line]);
+    }
+}
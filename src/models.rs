@@ -2,20 +2,46 @@
 
 use axum::body::Bytes;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
 use strum_macros::Display;
 use url::Url;
 use validator::{Validate, ValidationError};
 
+use crate::expression;
+use crate::operation_limits;
 use crate::types::{ByteOrder, DValue, Missing};
 
+/// Maximum number of dimensions of an array
+pub const MAX_RANK: usize = 32;
+
+/// Names of the supported numerical data types
+pub const DTYPES: &[&str] = &[
+    "int8", "int16", "int32", "int64", "uint8", "uint16", "uint32", "uint64", "float32", "float64",
+];
+
+/// Names of the supported compression algorithms
+pub const COMPRESSIONS: &[&str] = &["blosc", "gzip", "lz4", "zlib", "zstd"];
+
+/// Names of the supported filter algorithms
+pub const FILTERS: &[&str] = &["bitshuffle", "shuffle"];
+
 /// Supported numerical data types
 #[derive(Clone, Copy, Debug, Deserialize, Display, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum DType {
+    /// [i8]
+    Int8,
+    /// [i16]
+    Int16,
     /// [i32]
     Int32,
     /// [i64]
     Int64,
+    /// [u8]
+    Uint8,
+    /// [u16]
+    Uint16,
     /// [u32]
     Uint32,
     /// [u64]
@@ -30,8 +56,12 @@ impl DType {
     /// Returns the size of the associated type in bytes.
     pub fn size_of(self) -> usize {
         match self {
+            Self::Int8 => std::mem::size_of::<i8>(),
+            Self::Int16 => std::mem::size_of::<i16>(),
             Self::Int32 => std::mem::size_of::<i32>(),
             Self::Int64 => std::mem::size_of::<i64>(),
+            Self::Uint8 => std::mem::size_of::<u8>(),
+            Self::Uint16 => std::mem::size_of::<u16>(),
             Self::Uint32 => std::mem::size_of::<u32>(),
             Self::Uint64 => std::mem::size_of::<u64>(),
             Self::Float32 => std::mem::size_of::<f32>(),
@@ -43,7 +73,7 @@ impl DType {
 /// Array ordering
 ///
 /// Defines an ordering for multi-dimensional arrays.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub enum Order {
     /// Row-major (C) ordering
     C,
@@ -87,15 +117,73 @@ impl Slice {
     }
 }
 
+/// A selection of elements along a single dimension of an array
+///
+/// Either a slice, or a list of indices. Index lists allow non-contiguous selections, such as
+/// every January of a monthly time axis, in a single request. Indices may be negative, in which
+/// case they count back from the end of the dimension, and may be repeated or in any order.
+// NOTE: Slices may be deserialised from sequences, so index lists are deserialised from maps of
+// the form {"indices": [...]} to avoid ambiguity.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Selector {
+    /// A slice of the dimension
+    Slice(Slice),
+    /// A list of indices in the dimension
+    Indices {
+        /// Indices of the selected elements
+        indices: Vec<isize>,
+    },
+}
+
+impl From<Slice> for Selector {
+    fn from(slice: Slice) -> Self {
+        Selector::Slice(slice)
+    }
+}
+
+impl Validate for Selector {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        match self {
+            Selector::Slice(slice) => slice.validate(),
+            Selector::Indices { indices } if indices.is_empty() => {
+                let mut errors = validator::ValidationErrors::new();
+                errors.add(
+                    "indices",
+                    ValidationError::new("Selection indices must not be empty"),
+                );
+                Err(errors)
+            }
+            Selector::Indices { .. } => Ok(()),
+        }
+    }
+}
+
 /// Compression algorithm
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 #[serde(tag = "id")]
 pub enum Compression {
+    /// Blosc
+    Blosc,
     /// Gzip
     Gzip,
+    /// LZ4 or LZ4HC, in the frame format or as a block with a size prefix
+    Lz4,
     /// Zlib
     Zlib,
+    /// Zstandard
+    Zstd,
+}
+
+impl Compression {
+    /// Returns whether data may be decompressed incrementally as it is downloaded.
+    pub fn is_streamable(&self) -> bool {
+        match self {
+            Self::Blosc | Self::Lz4 | Self::Zstd => false,
+            Self::Gzip | Self::Zlib => true,
+        }
+    }
 }
 
 /// Filter algorithm
@@ -103,18 +191,195 @@ pub enum Compression {
 #[serde(rename_all = "lowercase")]
 #[serde(tag = "id")]
 pub enum Filter {
+    /// Bit shuffle
+    Bitshuffle { element_size: usize },
     /// Byte shuffle
     Shuffle { element_size: usize },
 }
 
+/// Location of the index within a Zarr v3 shard
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexLocation {
+    /// Index precedes the chunks
+    Start,
+    /// Index follows the chunks
+    #[default]
+    End,
+}
+
+/// An inner chunk of a Zarr v3 shard
+///
+/// The shard index is read to locate the byte range of the inner chunk within the shard object,
+/// so that only the index and the chunk are downloaded.
+#[derive(Clone, Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+#[validate(schema(function = "validate_shard"))]
+pub struct Shard {
+    /// Number of inner chunks in each dimension of the shard
+    #[validate(
+        length(min = 1, message = "chunks_per_shard length must be greater than 0"),
+        custom = "validate_shape"
+    )]
+    pub chunks_per_shard: Vec<usize>,
+    /// Coordinates of the inner chunk within the shard
+    pub chunk: Vec<usize>,
+    /// Location of the shard index
+    #[serde(default)]
+    pub index_location: IndexLocation,
+    /// Whether the shard index is followed by a CRC32C checksum
+    #[serde(default = "default_index_crc32c")]
+    pub index_crc32c: bool,
+}
+
+/// Returns the default for [Shard::index_crc32c], which matches the Zarr default index codecs.
+fn default_index_crc32c() -> bool {
+    true
+}
+
+/// Aggregation computed for each group by the groupby operation, each block by the coarsen
+/// operation, or each element by the ensemble operation
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    /// Number of non-missing elements
+    Count,
+    /// Maximum of non-missing elements
+    Max,
+    /// Mean of non-missing elements, as float64
+    Mean,
+    /// Minimum of non-missing elements
+    Min,
+    /// Sum of non-missing elements
+    Sum,
+}
+
+/// Integer labels assigning each index along an axis of the array to a group
+///
+/// The labels are read from a second object in the same bucket as the data. Each group's label is
+/// its index in the result, and elements with negative labels do not belong to any group.
+#[derive(Clone, Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+#[validate(schema(function = "validate_group_by"))]
+pub struct GroupBy {
+    /// S3 object containing the labels
+    #[validate(length(min = 1, message = "labels must not be empty"))]
+    pub labels: String,
+    /// Data type of the labels
+    pub labels_dtype: DType,
+    /// Byte order of the labels
+    pub labels_byte_order: Option<ByteOrder>,
+    /// Offset in bytes of the labels within the object
+    pub labels_offset: Option<usize>,
+    /// Size in bytes of the labels from the offset
+    #[validate(range(min = 1, message = "labels_size must be greater than 0"))]
+    pub labels_size: Option<usize>,
+    /// Axis of the array along which the labels apply
+    pub axis: usize,
+    /// Aggregation to compute for each group
+    pub aggregation: Aggregation,
+    /// Label values, populated once the labels have been downloaded
+    #[serde(skip)]
+    pub label_values: Vec<i64>,
+}
+
+/// Block sizes for downsampling an array with the coarsen operation
+#[derive(Clone, Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct Coarsen {
+    /// Number of elements in each dimension of a block
+    #[validate(
+        length(min = 1, message = "block length must be greater than 0"),
+        custom = "validate_shape"
+    )]
+    pub block: Vec<usize>,
+    /// Aggregation to compute for each block
+    pub aggregation: Aggregation,
+}
+
+/// Further members of an ensemble for the ensemble operation
+///
+/// Each member is an object in the same bucket as the data, with the same layout, encoding and
+/// range. The ensemble is reduced across its members, including the request's object.
+#[derive(Clone, Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct Ensemble {
+    /// Objects containing the other members
+    #[validate(length(min = 1, message = "ensemble members must not be empty"))]
+    pub members: Vec<String>,
+    /// Aggregation to compute for each element across the members
+    pub aggregation: Aggregation,
+}
+
+/// Precision of floating point results
+#[derive(Clone, Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+#[validate(schema(function = "validate_precision"))]
+pub struct Precision {
+    /// Floating point data type to cast results to
+    pub dtype: Option<DType>,
+    /// Number of decimal places to round results to
+    #[validate(range(max = 15, message = "decimals must be at most 15"))]
+    pub decimals: Option<u32>,
+}
+
+/// Handling of NaN elements by reductions, following NumPy
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NanPolicy {
+    /// NaN elements are included, so that any NaN element makes the result NaN
+    Propagate,
+    /// NaN elements are excluded, and counted as missing
+    Ignore,
+    /// NaN elements are an error
+    Error,
+}
+
+/// S3-compatible object store
+///
+/// Deserialised from a string, which is treated as a URL if it contains `://`, or otherwise as
+/// the name of a source configured on the server.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Source {
+    /// URL of the object store
+    Url(Url),
+    /// Name of a source configured on the server
+    Name(String),
+}
+
+impl<'de> Deserialize<'de> for Source {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::{Error, Unexpected};
+        let s = String::deserialize(deserializer)?;
+        if s.contains("://") {
+            Url::parse(&s).map(Source::Url).map_err(|err| {
+                D::Error::invalid_value(Unexpected::Str(&s), &err.to_string().as_str())
+            })
+        } else if !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            Ok(Source::Name(s))
+        } else {
+            Err(D::Error::invalid_value(
+                Unexpected::Str(&s),
+                &"a URL or source name",
+            ))
+        }
+    }
+}
+
 /// Request data for operations
-#[derive(Debug, Deserialize, PartialEq, Validate)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Validate)]
 #[serde(deny_unknown_fields)]
 #[validate(schema(function = "validate_request_data"))]
 pub struct RequestData {
-    /// URL of the S3-compatible object store
+    /// URL or name of the S3-compatible object store
     // TODO: Investigate using lifetimes to enable zero-copy: https://serde.rs/lifetimes.html
-    pub source: Url,
+    pub source: Source,
     /// S3 bucket containing the object
     #[validate(length(min = 1, message = "bucket must not be empty"))]
     pub bucket: String,
@@ -141,17 +406,440 @@ pub struct RequestData {
     /// Subset of the data to operate on
     #[validate]
     #[validate(length(min = 1, message = "selection length must be greater than 0"))]
-    pub selection: Option<Vec<Slice>>,
+    pub selection: Option<Vec<Selector>>,
     /// Compression filter name
     pub compression: Option<Compression>,
     /// List of filter algorithms
     pub filters: Option<Vec<Filter>>,
     /// Missing data
     pub missing: Option<Missing<DValue>>,
+    /// Inner chunk of a Zarr v3 shard
+    #[validate]
+    pub shard: Option<Shard>,
+    /// Whether to decode the data following CF conventions before the operation
+    #[serde(default)]
+    pub cf_convention: bool,
+    /// CF scale factor for unpacking data
+    pub scale_factor: Option<f64>,
+    /// CF offset for unpacking data
+    pub add_offset: Option<f64>,
+    /// Labels for the groupby operation
+    #[validate]
+    pub group_by: Option<GroupBy>,
+    /// Block sizes for the coarsen operation
+    #[validate]
+    pub coarsen: Option<Coarsen>,
+    /// Further members for the ensemble operation
+    #[validate]
+    pub ensemble: Option<Ensemble>,
+    /// Expression for the expr operation
+    pub expression: Option<String>,
+    /// Precision of floating point results
+    #[validate]
+    pub precision: Option<Precision>,
+    /// Delta degrees of freedom for the var and std operations
+    pub ddof: Option<u32>,
+    /// Handling of NaN elements by the count, max, mean, min and sum operations
+    pub nan_policy: Option<NanPolicy>,
+    /// Whether to return partial aggregates for exact merging by the client
+    #[serde(default)]
+    pub partial: bool,
+    /// Whether the requester pays for requests to a requester pays bucket
+    #[serde(default)]
+    pub requester_pays: bool,
+}
+
+impl RequestData {
+    /// Returns a builder for request data with the required fields set.
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: URL or name of the S3-compatible object store
+    /// * `bucket`: S3 bucket containing the object
+    /// * `object`: S3 object containing the data
+    /// * `dtype`: Data type
+    pub fn builder(
+        source: Source,
+        bucket: impl Into<String>,
+        object: impl Into<String>,
+        dtype: DType,
+    ) -> RequestDataBuilder {
+        RequestDataBuilder {
+            request_data: RequestData {
+                source,
+                bucket: bucket.into(),
+                object: object.into(),
+                dtype,
+                byte_order: None,
+                offset: None,
+                size: None,
+                shape: None,
+                order: None,
+                selection: None,
+                compression: None,
+                filters: None,
+                missing: None,
+                shard: None,
+                cf_convention: false,
+                scale_factor: None,
+                add_offset: None,
+                group_by: None,
+                coarsen: None,
+                ensemble: None,
+                expression: None,
+                precision: None,
+                ddof: None,
+                nan_policy: None,
+                partial: false,
+                requester_pays: false,
+            },
+        }
+    }
+}
+
+/// Builder for [RequestData]
+///
+/// Allows Rust consumers of the crate to construct requests programmatically. The request data is
+/// validated when built, in the same way as JSON request data.
+#[derive(Debug)]
+pub struct RequestDataBuilder {
+    /// Request data being built.
+    request_data: RequestData,
+}
+
+impl RequestDataBuilder {
+    /// Set the byte order of data.
+    pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.request_data.byte_order = Some(byte_order);
+        self
+    }
+
+    /// Set the offset in bytes of the numerical data within the object.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.request_data.offset = Some(offset);
+        self
+    }
+
+    /// Set the size in bytes of the numerical data from the offset.
+    pub fn size(mut self, size: usize) -> Self {
+        self.request_data.size = Some(size);
+        self
+    }
+
+    /// Set the shape of the multi-dimensional array.
+    pub fn shape(mut self, shape: Vec<usize>) -> Self {
+        self.request_data.shape = Some(shape);
+        self
+    }
+
+    /// Set the order of the multi-dimensional array.
+    pub fn order(mut self, order: Order) -> Self {
+        self.request_data.order = Some(order);
+        self
+    }
+
+    /// Set the subset of the data to operate on.
+    pub fn selection(mut self, selection: Vec<Selector>) -> Self {
+        self.request_data.selection = Some(selection);
+        self
+    }
+
+    /// Set the compression filter name.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.request_data.compression = Some(compression);
+        self
+    }
+
+    /// Set the list of filter algorithms.
+    pub fn filters(mut self, filters: Vec<Filter>) -> Self {
+        self.request_data.filters = Some(filters);
+        self
+    }
+
+    /// Set the missing data.
+    pub fn missing(mut self, missing: Missing<DValue>) -> Self {
+        self.request_data.missing = Some(missing);
+        self
+    }
+
+    /// Set the inner chunk of a Zarr v3 shard.
+    pub fn shard(mut self, shard: Shard) -> Self {
+        self.request_data.shard = Some(shard);
+        self
+    }
+
+    /// Set whether to decode the data following CF conventions before the operation.
+    pub fn cf_convention(mut self, cf_convention: bool) -> Self {
+        self.request_data.cf_convention = cf_convention;
+        self
+    }
+
+    /// Set the CF scale factor for unpacking data.
+    pub fn scale_factor(mut self, scale_factor: f64) -> Self {
+        self.request_data.scale_factor = Some(scale_factor);
+        self
+    }
+
+    /// Set the CF offset for unpacking data.
+    pub fn add_offset(mut self, add_offset: f64) -> Self {
+        self.request_data.add_offset = Some(add_offset);
+        self
+    }
+
+    /// Set the labels for the groupby operation.
+    pub fn group_by(mut self, group_by: GroupBy) -> Self {
+        self.request_data.group_by = Some(group_by);
+        self
+    }
+
+    /// Set the block sizes for the coarsen operation.
+    pub fn coarsen(mut self, coarsen: Coarsen) -> Self {
+        self.request_data.coarsen = Some(coarsen);
+        self
+    }
+
+    /// Set the further members for the ensemble operation.
+    pub fn ensemble(mut self, ensemble: Ensemble) -> Self {
+        self.request_data.ensemble = Some(ensemble);
+        self
+    }
+
+    /// Set the expression for the expr operation.
+    pub fn expression(mut self, expression: impl Into<String>) -> Self {
+        self.request_data.expression = Some(expression.into());
+        self
+    }
+
+    /// Set the precision of floating point results.
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.request_data.precision = Some(precision);
+        self
+    }
+
+    /// Set the delta degrees of freedom for the var and std operations.
+    pub fn ddof(mut self, ddof: u32) -> Self {
+        self.request_data.ddof = Some(ddof);
+        self
+    }
+
+    /// Set the handling of NaN elements by reductions.
+    pub fn nan_policy(mut self, nan_policy: NanPolicy) -> Self {
+        self.request_data.nan_policy = Some(nan_policy);
+        self
+    }
+
+    /// Set whether to return partial aggregates for exact merging by the client.
+    pub fn partial(mut self, partial: bool) -> Self {
+        self.request_data.partial = partial;
+        self
+    }
+
+    /// Set whether the requester pays for requests to a requester pays bucket.
+    pub fn requester_pays(mut self, requester_pays: bool) -> Self {
+        self.request_data.requester_pays = requester_pays;
+        self
+    }
+
+    /// Returns the request data, once it has been validated.
+    pub fn build(self) -> Result<RequestData, validator::ValidationErrors> {
+        self.request_data.validate()?;
+        Ok(self.request_data)
+    }
+}
+
+/// Request data for listing objects
+#[derive(Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct ListRequestData {
+    /// URL or name of the S3-compatible object store
+    pub source: Source,
+    /// S3 bucket containing the objects
+    #[validate(length(min = 1, message = "bucket must not be empty"))]
+    pub bucket: String,
+    /// Prefix of the object keys to list
+    pub prefix: Option<String>,
+    /// Maximum number of object keys to return
+    #[validate(range(min = 1, max = 1000, message = "max_keys must be between 1 and 1000"))]
+    pub max_keys: Option<i32>,
+    /// Continuation token returned by a previous request
+    pub continuation_token: Option<String>,
+    /// Whether the requester pays for requests to a requester pays bucket
+    #[serde(default)]
+    pub requester_pays: bool,
+}
+
+/// An operation in a batch request
+#[derive(Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct BatchEntry {
+    /// Name of the operation
+    pub operation: String,
+    /// Request data for the operation
+    #[validate]
+    pub request: RequestData,
+}
+
+/// Request data for a batch of operations
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct BatchRequestData {
+    /// Operations in the batch
+    pub entries: Vec<BatchEntry>,
+}
+
+impl Validate for BatchRequestData {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        let mut errors = validator::ValidationErrors::new();
+        if self.entries.is_empty() {
+            let mut error = ValidationError::new("length");
+            error.message = Some("batch must not be empty".into());
+            errors.add("entries", error);
+        }
+        let entry_errors: BTreeMap<_, _> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| Some((index, Box::new(entry.validate().err()?))))
+            .collect();
+        if !entry_errors.is_empty() {
+            errors.errors_mut().insert(
+                "entries",
+                validator::ValidationErrorsKind::List(entry_errors),
+            );
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Request data for operations on a Zarr array
+///
+/// The array's metadata is read from the object store, and the operation is performed on each
+/// chunk intersecting the selection, with the results merged into a single response.
+#[derive(Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct ZarrRequestData {
+    /// URL or name of the S3-compatible object store
+    pub source: Source,
+    /// S3 bucket containing the array
+    #[validate(length(min = 1, message = "bucket must not be empty"))]
+    pub bucket: String,
+    /// Path of the array within the bucket
+    #[validate(length(min = 1, message = "array must not be empty"))]
+    pub array: String,
+    /// Subset of the array to operate on, in array coordinates
+    #[validate]
+    #[validate(length(min = 1, message = "selection length must be greater than 0"))]
+    pub selection: Option<Vec<Slice>>,
+    /// Missing data
+    pub missing: Option<Missing<DValue>>,
+    /// Whether the requester pays for requests to a requester pays bucket
+    #[serde(default)]
+    pub requester_pays: bool,
+}
+
+/// Request data for operations on a dataset in an HDF5 file, such as a netCDF4 file
+///
+/// The file's metadata and chunk index are read from the object store, and the operation is
+/// performed on each chunk intersecting the selection, with the results merged into a single
+/// response.
+#[derive(Debug, Deserialize, PartialEq, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct Hdf5RequestData {
+    /// URL or name of the S3-compatible object store
+    pub source: Source,
+    /// S3 bucket containing the file
+    #[validate(length(min = 1, message = "bucket must not be empty"))]
+    pub bucket: String,
+    /// Object within the bucket containing the file
+    #[validate(length(min = 1, message = "object must not be empty"))]
+    pub object: String,
+    /// Path of the dataset within the file
+    #[validate(length(min = 1, message = "dataset must not be empty"))]
+    pub dataset: String,
+    /// Offset in bytes of the superblock within the object
+    #[serde(default)]
+    pub superblock_offset: u64,
+    /// Subset of the dataset to operate on, in dataset coordinates
+    #[validate]
+    #[validate(length(min = 1, message = "selection length must be greater than 0"))]
+    pub selection: Option<Vec<Slice>>,
+    /// Missing data
+    pub missing: Option<Missing<DValue>>,
+    /// Whether the requester pays for requests to a requester pays bucket
+    #[serde(default)]
+    pub requester_pays: bool,
+}
+
+/// Header of an entry in a batch response, preceding the entry's body
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BatchFrame {
+    /// Index of the entry in the batch request
+    pub index: usize,
+    /// HTTP status of the entry
+    pub status: u16,
+    /// Headers of the entry, as they would be for a single operation request
+    pub headers: BTreeMap<String, String>,
+    /// Size of the entry's body in bytes
+    pub size: usize,
+}
+
+/// Response containing a page of object keys
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ListResponse {
+    /// Object keys, in lexicographical order
+    pub keys: Vec<String>,
+    /// Continuation token for retrieving the next page of keys, if there are more
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<String>,
+}
+
+/// Limits enforced by the server
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Limits {
+    /// Maximum number of dimensions of an array
+    pub max_rank: usize,
+    /// Maximum number of groups in a groupby operation
+    pub max_groups: usize,
+    /// Maximum size of a response body in bytes, if limited
+    pub max_response_size: Option<usize>,
+    /// Maximum ratio of decompressed to compressed data size, if limited
+    pub max_decompression_ratio: Option<usize>,
+    /// Memory available to requests in bytes, if limited
+    pub memory_limit: Option<usize>,
+    /// Maximum number of operations in a batch request
+    pub max_batch_size: usize,
+    /// Limits configured for individual operations, overriding the limits above
+    pub operations: BTreeMap<String, operation_limits::Limits>,
+}
+
+/// Capabilities and limits of the server
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Capabilities {
+    /// Version of the server
+    pub version: &'static str,
+    /// Supported operations
+    pub operations: Vec<&'static str>,
+    /// Supported numerical data types
+    pub dtypes: Vec<&'static str>,
+    /// Supported compression algorithms
+    pub compression: Vec<&'static str>,
+    /// Supported filter algorithms
+    pub filters: Vec<&'static str>,
+    /// Limits enforced by the server
+    pub limits: Limits,
 }
 
 /// Validate an array shape
 fn validate_shape(shape: &[usize]) -> Result<(), ValidationError> {
+    if shape.len() > MAX_RANK {
+        let mut error = ValidationError::new("shape length must not exceed the maximum rank");
+        error.add_param("max_rank".into(), &MAX_RANK);
+        return Err(error);
+    }
     if shape.iter().any(|index| *index == 0) {
         return Err(ValidationError::new("shape indices must be greater than 0"));
     }
@@ -169,13 +857,71 @@ fn validate_slice(slice: &Slice) -> Result<(), ValidationError> {
 }
 
 /// Validate that a shape and selection are consistent
-fn validate_shape_selection(shape: &[usize], selection: &[Slice]) -> Result<(), ValidationError> {
+fn validate_shape_selection(
+    shape: &[usize],
+    selection: &[Selector],
+) -> Result<(), ValidationError> {
     if shape.len() != selection.len() {
         let mut error = ValidationError::new("Shape and selection must have the same length");
         error.add_param("shape".into(), &shape.len());
         error.add_param("selection".into(), &selection.len());
         return Err(error);
     }
+    for (selector, length) in std::iter::zip(selection, shape) {
+        if let Selector::Indices { indices } = selector {
+            // Unlike slices, indices are not clamped.
+            let length = *length as isize;
+            if let Some(index) = indices
+                .iter()
+                .find(|&&index| index < -length || index >= length)
+            {
+                let mut error = ValidationError::new("Selection index out of bounds");
+                error.add_param("index".into(), index);
+                error.add_param("length".into(), &length);
+                return Err(error);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate that the inner chunk of a shard is within the shard
+fn validate_shard(shard: &Shard) -> Result<(), ValidationError> {
+    if shard.chunk.len() != shard.chunks_per_shard.len() {
+        let mut error =
+            ValidationError::new("Shard chunk and chunks_per_shard must have the same length");
+        error.add_param("chunk".into(), &shard.chunk.len());
+        error.add_param("chunks_per_shard".into(), &shard.chunks_per_shard.len());
+        return Err(error);
+    }
+    if shard
+        .chunk
+        .iter()
+        .zip(&shard.chunks_per_shard)
+        .any(|(index, len)| index >= len)
+    {
+        return Err(ValidationError::new(
+            "Shard chunk indices must be less than chunks_per_shard",
+        ));
+    }
+    Ok(())
+}
+
+/// Validate that groupby labels have an integer data type
+fn validate_group_by(group_by: &GroupBy) -> Result<(), ValidationError> {
+    if matches!(group_by.labels_dtype, DType::Float32 | DType::Float64) {
+        return Err(ValidationError::new("labels_dtype must be an integer type"));
+    }
+    Ok(())
+}
+
+/// Validate that the precision data type is a floating point type
+fn validate_precision(precision: &Precision) -> Result<(), ValidationError> {
+    if matches!(precision.dtype, Some(dtype) if !matches!(dtype, DType::Float32 | DType::Float64)) {
+        return Err(ValidationError::new(
+            "precision dtype must be a floating point type",
+        ));
+    }
     Ok(())
 }
 
@@ -236,9 +982,88 @@ fn validate_request_data(request_data: &RequestData) -> Result<(), ValidationErr
     if let Some(missing) = &request_data.missing {
         missing.validate(request_data.dtype)?;
     };
+    if !request_data.cf_convention
+        && (request_data.scale_factor.is_some() || request_data.add_offset.is_some())
+    {
+        return Err(ValidationError::new(
+            "scale_factor and add_offset require cf_convention",
+        ));
+    }
+    if request_data.partial && request_data.precision.is_some() {
+        return Err(ValidationError::new(
+            "partial and precision cannot both be specified",
+        ));
+    }
+    if let Some(group_by) = &request_data.group_by {
+        let ndim = request_data.shape.as_ref().map_or(1, Vec::len);
+        if group_by.axis >= ndim {
+            let mut error = ValidationError::new("group_by axis must be less than shape length");
+            error.add_param("axis".into(), &group_by.axis);
+            error.add_param("ndim".into(), &ndim);
+            return Err(error);
+        }
+    }
+    if let Some(coarsen) = &request_data.coarsen {
+        let ndim = request_data.shape.as_ref().map_or(1, Vec::len);
+        if coarsen.block.len() != ndim {
+            let mut error =
+                ValidationError::new("Coarsen block and shape must have the same length");
+            error.add_param("block".into(), &coarsen.block.len());
+            error.add_param("ndim".into(), &ndim);
+            return Err(error);
+        }
+    }
+    if request_data.ensemble.is_some() && request_data.shard.is_some() {
+        return Err(ValidationError::new(
+            "ensemble is not supported for sharded objects",
+        ));
+    }
+    if let Some(expression) = &request_data.expression {
+        if let Err(err) = expression::parse(expression) {
+            let mut error = ValidationError::new("Invalid expression");
+            error.add_param("reason".into(), &err.reason);
+            error.add_param("position".into(), &err.position);
+            return Err(error);
+        }
+    }
+    if request_data.shard.is_some()
+        && (request_data.offset.is_some() || request_data.size.is_some())
+    {
+        return Err(ValidationError::new(
+            "Shard cannot be specified with offset or size",
+        ));
+    }
     Ok(())
 }
 
+/// Fill value of a sparse response body.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fill {
+    /// Not a number
+    NaN,
+    /// A numeric value
+    Value(DValue),
+}
+
+impl std::fmt::Display for Fill {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fill::NaN => write!(f, "nan"),
+            Fill::Value(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Encoding of a response body.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Encoding {
+    /// All elements of the result, in C order
+    #[default]
+    Dense,
+    /// Flat indices of the elements that differ from the fill value, followed by their values
+    Sparse(Fill),
+}
+
 /// Response containing the result of a computation and associated metadata.
 pub struct Response {
     /// Response data. May be a scalar or multi-dimensional array.
@@ -249,6 +1074,14 @@ pub struct Response {
     pub shape: Vec<usize>,
     /// Number of non-missing elements operated on to generate response
     pub count: i64,
+    /// Number of missing elements in the selection that were not operated on
+    pub missing: i64,
+    /// Encoding of the response data
+    pub encoding: Encoding,
+    /// Optional multi-dimensional indices within the selection of the elements in the response
+    pub indices: Option<Vec<Vec<usize>>>,
+    /// Optional CPU time used to compute the response
+    pub cpu_time: Option<Duration>,
 }
 
 impl Response {
@@ -259,8 +1092,21 @@ impl Response {
             dtype,
             shape,
             count,
+            missing: 0,
+            encoding: Encoding::Dense,
+            indices: None,
+            cpu_time: None,
         }
     }
+
+    /// Return the Response with a number of missing elements
+    ///
+    /// # Arguments
+    ///
+    /// * `missing`: Number of missing elements in the selection
+    pub fn with_missing(self, missing: i64) -> Response {
+        Response { missing, ..self }
+    }
 }
 
 #[cfg(test)]
@@ -404,13 +1250,38 @@ mod tests {
                     len: 2,
                 },
                 Token::Str("source"),
-                Token::Str("foo"),
+                Token::Str("http://"),
+                Token::StructEnd,
+            ],
+            "invalid value: string \"http://\", expected empty host",
+        )
+    }
+
+    #[test]
+    fn test_invalid_source_name() {
+        assert_de_tokens_error::<RequestData>(
+            &[
+                Token::Struct {
+                    name: "RequestData",
+                    len: 2,
+                },
+                Token::Str("source"),
+                Token::Str("foo bar"),
                 Token::StructEnd,
             ],
-            "invalid value: string \"foo\", expected relative URL without a base",
+            "invalid value: string \"foo bar\", expected a URL or source name",
         )
     }
 
+    #[test]
+    fn test_json_source_name() {
+        let json = r#"{"source": "foo", "bucket": "bar", "object": "baz", "dtype": "int32"}"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.source = Source::Name("foo".to_string());
+        assert_eq!(request_data, expected);
+    }
+
     #[test]
     fn test_missing_bucket() {
         assert_de_tokens_error::<RequestData>(
@@ -490,7 +1361,7 @@ mod tests {
             Token::Str("foo"),
             Token::StructEnd
             ],
-            "unknown variant `foo`, expected one of `int32`, `int64`, `uint32`, `uint64`, `float32`, `float64`"
+            "unknown variant `foo`, expected one of `int8`, `int16`, `int32`, `int64`, `uint8`, `uint16`, `uint32`, `uint64`, `float32`, `float64`"
         )
     }
 
@@ -529,16 +1400,39 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "shape indices must be greater than 0")]
-    fn test_invalid_shape_indices() {
+    #[should_panic(expected = "shape length must not exceed the maximum rank")]
+    fn test_invalid_shape_rank() {
         let mut request_data = test_utils::get_test_request_data();
-        request_data.shape = Some(vec![0]);
+        request_data.shape = Some(vec![1; MAX_RANK + 1]);
         request_data.validate().unwrap()
     }
 
     #[test]
-    fn test_invalid_order() {
-        assert_de_tokens_error::<RequestData>(
+    fn test_capability_names() {
+        for dtype in DTYPES {
+            serde_json::from_value::<DType>(serde_json::json!(dtype)).unwrap();
+        }
+        for compression in COMPRESSIONS {
+            serde_json::from_value::<Compression>(serde_json::json!({ "id": compression }))
+                .unwrap();
+        }
+        for filter in FILTERS {
+            let json = serde_json::json!({ "id": filter, "element_size": 4 });
+            serde_json::from_value::<Filter>(json).unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "shape indices must be greater than 0")]
+    fn test_invalid_shape_indices() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![0]);
+        request_data.validate().unwrap()
+    }
+
+    #[test]
+    fn test_invalid_order() {
+        assert_de_tokens_error::<RequestData>(
             &[
                 Token::Struct {
                     name: "RequestData",
@@ -566,7 +1460,7 @@ mod tests {
     #[should_panic(expected = "Selection stride must not be equal to zero")]
     fn test_invalid_selection2() {
         let mut request_data = test_utils::get_test_request_data();
-        request_data.selection = Some(vec![Slice::new(1, 2, 0)]);
+        request_data.selection = Some(vec![Slice::new(1, 2, 0).into()]);
         request_data.validate().unwrap()
     }
 
@@ -575,7 +1469,7 @@ mod tests {
         // Numpy sementics: start >= end yields an empty array
         let mut request_data = test_utils::get_test_request_data();
         request_data.shape = Some(vec![1]);
-        request_data.selection = Some(vec![Slice::new(1, 0, 1)]);
+        request_data.selection = Some(vec![Slice::new(1, 0, 1).into()]);
         request_data.validate().unwrap()
     }
 
@@ -583,7 +1477,7 @@ mod tests {
     fn test_selection_negative_stride() {
         let mut request_data = test_utils::get_test_request_data();
         request_data.shape = Some(vec![1]);
-        request_data.selection = Some(vec![Slice::new(1, 0, -1)]);
+        request_data.selection = Some(vec![Slice::new(1, 0, -1).into()]);
         request_data.validate().unwrap()
     }
 
@@ -611,7 +1505,7 @@ mod tests {
     fn test_shape_selection_mismatch() {
         let mut request_data = test_utils::get_test_request_data();
         request_data.shape = Some(vec![1, 2]);
-        request_data.selection = Some(vec![Slice::new(1, 2, 1)]);
+        request_data.selection = Some(vec![Slice::new(1, 2, 1).into()]);
         request_data.validate().unwrap()
     }
 
@@ -620,7 +1514,7 @@ mod tests {
         // Numpy sementics: start > length yields an empty array
         let mut request_data = test_utils::get_test_request_data();
         request_data.shape = Some(vec![4]);
-        request_data.selection = Some(vec![Slice::new(5, 5, 1)]);
+        request_data.selection = Some(vec![Slice::new(5, 5, 1).into()]);
         request_data.validate().unwrap()
     }
 
@@ -629,7 +1523,7 @@ mod tests {
         // Numpy sementics: start < -length gets clamped to zero
         let mut request_data = test_utils::get_test_request_data();
         request_data.shape = Some(vec![4]);
-        request_data.selection = Some(vec![Slice::new(-5, 5, 1)]);
+        request_data.selection = Some(vec![Slice::new(-5, 5, 1).into()]);
         request_data.validate().unwrap()
     }
 
@@ -638,7 +1532,7 @@ mod tests {
         // Numpy semantics: end > length gets clamped to length
         let mut request_data = test_utils::get_test_request_data();
         request_data.shape = Some(vec![4]);
-        request_data.selection = Some(vec![Slice::new(1, 5, 1)]);
+        request_data.selection = Some(vec![Slice::new(1, 5, 1).into()]);
         request_data.validate().unwrap()
     }
 
@@ -647,7 +1541,50 @@ mod tests {
         // Numpy semantics: end < -length gets clamped to zero
         let mut request_data = test_utils::get_test_request_data();
         request_data.shape = Some(vec![4]);
-        request_data.selection = Some(vec![Slice::new(1, -5, 1)]);
+        request_data.selection = Some(vec![Slice::new(1, -5, 1).into()]);
+        request_data.validate().unwrap()
+    }
+
+    #[test]
+    fn test_json_selection_indices() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "shape": [12, 4],
+                        "selection": [{"indices": [0, -12, 11]}, [0, 4, 1]]
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.shape = Some(vec![12, 4]);
+        expected.selection = Some(vec![
+            Selector::Indices {
+                indices: vec![0, -12, 11],
+            },
+            Slice::new(0, 4, 1).into(),
+        ]);
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Selection index out of bounds")]
+    fn test_selection_index_out_of_bounds() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![4]);
+        request_data.selection = Some(vec![Selector::Indices {
+            indices: vec![0, -5],
+        }]);
+        request_data.validate().unwrap()
+    }
+
+    #[test]
+    #[should_panic(expected = "Selection indices must not be empty")]
+    fn test_selection_indices_empty() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![4]);
+        request_data.selection = Some(vec![Selector::Indices { indices: vec![] }]);
         request_data.validate().unwrap()
     }
 
@@ -655,7 +1592,7 @@ mod tests {
     #[should_panic(expected = "Selection requires shape to be specified")]
     fn test_selection_without_shape() {
         let mut request_data = test_utils::get_test_request_data();
-        request_data.selection = Some(vec![Slice::new(1, 2, 1)]);
+        request_data.selection = Some(vec![Slice::new(1, 2, 1).into()]);
         request_data.validate().unwrap()
     }
 
@@ -674,7 +1611,7 @@ mod tests {
                 Token::Str("foo"),
                 Token::MapEnd,
             ],
-            "unknown variant `foo`, expected `gzip` or `zlib`",
+            "unknown variant `foo`, expected one of `blosc`, `gzip`, `lz4`, `zlib`, `zstd`",
         )
     }
 
@@ -694,7 +1631,7 @@ mod tests {
                 Token::Str("foo"),
                 Token::MapEnd,
             ],
-            "unknown variant `foo`, expected `shuffle`",
+            "unknown variant `foo`, expected `bitshuffle` or `shuffle`",
         )
     }
 
@@ -731,7 +1668,7 @@ mod tests {
             Token::Str("foo"),
             Token::StructEnd
             ],
-            "unknown field `foo`, expected one of `source`, `bucket`, `object`, `dtype`, `byte_order`, `offset`, `size`, `shape`, `order`, `selection`, `compression`, `filters`, `missing`"
+            "unknown field `foo`, expected one of `source`, `bucket`, `object`, `dtype`, `byte_order`, `offset`, `size`, `shape`, `order`, `selection`, `compression`, `filters`, `missing`, `shard`, `cf_convention`, `scale_factor`, `add_offset`, `group_by`, `coarsen`, `ensemble`, `expression`, `precision`, `ddof`, `nan_policy`, `partial`, `requester_pays`"
         )
     }
 
@@ -789,9 +1726,9 @@ mod tests {
         expected.shape = Some(vec![2, 5, 10]);
         expected.order = Some(Order::F);
         expected.selection = Some(vec![
-            Slice::new(1, 2, 3),
-            Slice::new(4, 5, 6),
-            Slice::new(7, 8, 9),
+            Slice::new(1, 2, 3).into(),
+            Slice::new(4, 5, 6).into(),
+            Slice::new(7, 8, 9).into(),
         ]);
         expected.compression = Some(Compression::Zlib);
         expected.filters = Some(vec![Filter::Shuffle { element_size: 8 }]);
@@ -827,4 +1764,499 @@ mod tests {
         ]));
         assert_eq!(request_data, expected);
     }
+
+    #[test]
+    fn test_json_list_request() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "prefix": "baz/",
+                        "max_keys": 100,
+                        "continuation_token": "qux",
+                        "requester_pays": true
+                      }"#;
+        let request_data = serde_json::from_str::<ListRequestData>(json).unwrap();
+        let expected = ListRequestData {
+            source: Source::Url(Url::parse("http://example.com").unwrap()),
+            bucket: "bar".to_string(),
+            prefix: Some("baz/".to_string()),
+            max_keys: Some(100),
+            continuation_token: Some("qux".to_string()),
+            requester_pays: true,
+        };
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_list_request_invalid_max_keys() {
+        let json = r#"{"source": "http://example.com", "bucket": "bar", "max_keys": 1001}"#;
+        let request_data = serde_json::from_str::<ListRequestData>(json).unwrap();
+        let err = request_data.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "max_keys: max_keys must be between 1 and 1000"
+        );
+    }
+
+    #[test]
+    fn test_json_batch_request() {
+        let json = r#"[{
+                        "operation": "sum",
+                        "request": {
+                          "source": "http://example.com",
+                          "bucket": "bar",
+                          "object": "baz",
+                          "dtype": "int32"
+                        }
+                      }]"#;
+        let request_data = serde_json::from_str::<BatchRequestData>(json).unwrap();
+        let expected = BatchRequestData {
+            entries: vec![BatchEntry {
+                operation: "sum".to_string(),
+                request: test_utils::get_test_request_data(),
+            }],
+        };
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_batch_request_empty() {
+        let request_data = serde_json::from_str::<BatchRequestData>("[]").unwrap();
+        let err = request_data.validate().unwrap_err();
+        assert_eq!(err.to_string(), "entries: batch must not be empty");
+    }
+
+    #[test]
+    fn test_batch_request_invalid_entry() {
+        let json = r#"[{
+                        "operation": "sum",
+                        "request": {
+                          "source": "http://example.com",
+                          "bucket": "",
+                          "object": "baz",
+                          "dtype": "int32"
+                        }
+                      }]"#;
+        let request_data = serde_json::from_str::<BatchRequestData>(json).unwrap();
+        let err = request_data.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "entries[0].request.bucket: bucket must not be empty"
+        );
+    }
+
+    #[test]
+    fn test_list_response() {
+        let response = ListResponse {
+            keys: vec!["foo".to_string()],
+            continuation_token: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"keys":["foo"]}"#
+        );
+    }
+
+    #[test]
+    fn test_json_shard() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "shard": {"chunks_per_shard": [2, 3], "chunk": [1, 2]}
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.shard = Some(Shard {
+            chunks_per_shard: vec![2, 3],
+            chunk: vec![1, 2],
+            index_location: IndexLocation::End,
+            index_crc32c: true,
+        });
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_shard_chunk_out_of_bounds() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shard = Some(Shard {
+            chunks_per_shard: vec![2, 3],
+            chunk: vec![1, 3],
+            index_location: IndexLocation::Start,
+            index_crc32c: false,
+        });
+        let err = request_data.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "shard.__all__: Validation error: Shard chunk indices must be less than chunks_per_shard [{}]"
+        );
+    }
+
+    #[test]
+    fn test_shard_with_offset() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.offset = Some(4);
+        request_data.shard = Some(Shard {
+            chunks_per_shard: vec![2],
+            chunk: vec![1],
+            index_location: IndexLocation::End,
+            index_crc32c: true,
+        });
+        let err = request_data.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "__all__: Validation error: Shard cannot be specified with offset or size [{}]"
+        );
+    }
+
+    #[test]
+    fn test_json_cf_convention() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "cf_convention": true,
+                        "scale_factor": 0.5,
+                        "add_offset": 273.15
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.cf_convention = true;
+        expected.scale_factor = Some(0.5);
+        expected.add_offset = Some(273.15);
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_scale_factor_without_cf_convention() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.scale_factor = Some(0.5);
+        let err = request_data.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "__all__: Validation error: scale_factor and add_offset require cf_convention [{}]"
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let request_data = RequestData::builder(
+            Source::Url(Url::parse("http://example.com").unwrap()),
+            "bar",
+            "baz",
+            DType::Int32,
+        )
+        .build()
+        .unwrap();
+        assert_eq!(test_utils::get_test_request_data(), request_data);
+        let request_data = RequestData::builder(
+            Source::Name("foo".to_string()),
+            "bar",
+            "baz",
+            DType::Float32,
+        )
+        .shape(vec![2, 5])
+        .selection(vec![Slice::new(0, 2, 1).into(), Slice::new(1, 5, 2).into()])
+        .missing(Missing::ValidMin(0.into()))
+        .cf_convention(true)
+        .scale_factor(0.5)
+        .build()
+        .unwrap();
+        assert_eq!(Source::Name("foo".to_string()), request_data.source);
+        assert_eq!(Some(vec![2, 5]), request_data.shape);
+        assert!(request_data.cf_convention);
+        assert_eq!(Some(0.5), request_data.scale_factor);
+    }
+
+    #[test]
+    fn test_builder_invalid() {
+        let err = RequestData::builder(
+            Source::Url(Url::parse("http://example.com").unwrap()),
+            "",
+            "baz",
+            DType::Int32,
+        )
+        .selection(vec![Slice::new(0, 2, 1).into()])
+        .build()
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("bucket must not be empty"));
+        assert!(err.contains("Selection requires shape to be specified"));
+    }
+
+    #[test]
+    fn test_json_group_by() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "shape": [2, 5],
+                        "group_by": {
+                            "labels": "qux",
+                            "labels_dtype": "int64",
+                            "axis": 1,
+                            "aggregation": "mean"
+                        }
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.shape = Some(vec![2, 5]);
+        expected.group_by = Some(GroupBy {
+            labels: "qux".to_string(),
+            labels_dtype: DType::Int64,
+            labels_byte_order: None,
+            labels_offset: None,
+            labels_size: None,
+            axis: 1,
+            aggregation: Aggregation::Mean,
+            label_values: vec![],
+        });
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_json_coarsen() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "shape": [4, 4],
+                        "coarsen": {"block": [2, 3], "aggregation": "max"}
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.shape = Some(vec![4, 4]);
+        expected.coarsen = Some(Coarsen {
+            block: vec![2, 3],
+            aggregation: Aggregation::Max,
+        });
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_coarsen_invalid() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "coarsen": {"block": [2, 0], "aggregation": "min"}
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let err = request_data.validate().unwrap_err().to_string();
+        assert!(err.contains("Coarsen block and shape must have the same length"));
+        assert!(err.contains("shape indices must be greater than 0"));
+    }
+
+    #[test]
+    fn test_json_ensemble() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "float32",
+                        "ensemble": {"members": ["qux", "quux"], "aggregation": "mean"}
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.dtype = DType::Float32;
+        expected.ensemble = Some(Ensemble {
+            members: vec!["qux".to_string(), "quux".to_string()],
+            aggregation: Aggregation::Mean,
+        });
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_ensemble_invalid() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "ensemble": {"members": [], "aggregation": "sum"}
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let err = request_data.validate().unwrap_err().to_string();
+        assert!(err.contains("ensemble members must not be empty"));
+    }
+
+    #[test]
+    fn test_ensemble_shard_invalid() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "shape": [4],
+                        "shard": {"chunks_per_shard": [2], "chunk": [0]},
+                        "ensemble": {"members": ["qux"], "aggregation": "sum"}
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let err = request_data.validate().unwrap_err().to_string();
+        assert!(err.contains("ensemble is not supported for sharded objects"));
+    }
+
+    #[test]
+    fn test_json_expression() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "expression": "sum((x - 273.15) * 2)"
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.expression = Some("sum((x - 273.15) * 2)".to_string());
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_expression_invalid() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "expression": "sum(x * weights)"
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let err = request_data.validate().unwrap_err().to_string();
+        assert!(err.contains("Invalid expression"));
+        assert!(err.contains("unknown variable or function"));
+    }
+
+    #[test]
+    fn test_json_precision() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "float64",
+                        "precision": {"dtype": "float32", "decimals": 2}
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.dtype = DType::Float64;
+        expected.precision = Some(Precision {
+            dtype: Some(DType::Float32),
+            decimals: Some(2),
+        });
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_precision_invalid() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "float64",
+                        "precision": {"dtype": "int32", "decimals": 16}
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let err = request_data.validate().unwrap_err().to_string();
+        assert!(err.contains("precision dtype must be a floating point type"));
+        assert!(err.contains("decimals must be at most 15"));
+    }
+
+    #[test]
+    fn test_json_nan_policy() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "float64",
+                        "nan_policy": "ignore"
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.dtype = DType::Float64;
+        expected.nan_policy = Some(NanPolicy::Ignore);
+        assert_eq!(request_data, expected);
+        let json = json.replace("ignore", "omit");
+        let err = serde_json::from_str::<RequestData>(&json)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unknown variant `omit`"));
+    }
+
+    #[test]
+    fn test_json_partial() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "float64",
+                        "partial": true
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.dtype = DType::Float64;
+        expected.partial = true;
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_json_requester_pays() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "requester_pays": true
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let mut expected = test_utils::get_test_request_data();
+        expected.requester_pays = true;
+        assert_eq!(request_data, expected);
+        request_data.validate().unwrap();
+    }
+
+    #[test]
+    fn test_partial_with_precision() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.partial = true;
+        request_data.precision = Some(Precision {
+            dtype: Some(DType::Float32),
+            decimals: None,
+        });
+        let err = request_data.validate().unwrap_err().to_string();
+        assert!(err.contains("partial and precision cannot both be specified"));
+    }
+
+    #[test]
+    fn test_group_by_invalid() {
+        let json = r#"{
+                        "source": "http://example.com",
+                        "bucket": "bar",
+                        "object": "baz",
+                        "dtype": "int32",
+                        "group_by": {
+                            "labels": "qux",
+                            "labels_dtype": "float32",
+                            "axis": 1,
+                            "aggregation": "sum"
+                        }
+                      }"#;
+        let request_data = serde_json::from_str::<RequestData>(json).unwrap();
+        let err = request_data.validate().unwrap_err().to_string();
+        assert!(err.contains("group_by axis must be less than shape length"));
+        assert!(err.contains("labels_dtype must be an integer type"));
+    }
 }
@@ -0,0 +1,214 @@
+//! NUMA-aware Rayon thread pools
+//!
+//! On systems with multiple NUMA nodes, accessing memory attached to a remote node is slower than
+//! accessing local memory. Linux allocates memory on the node of the CPU that first writes to it,
+//! so downloaded object data resides on the node of the Tokio worker that received it. To avoid
+//! cross-node memory traffic, a Rayon thread pool is created for each NUMA node with its workers
+//! pinned to the node's CPUs, and CPU-bound work is dispatched to the pool local to the CPU that
+//! submits it.
+//!
+//! Node-local allocation of download buffers is out of scope. Memory is not explicitly allocated
+//! on a node with `mbind` or `set_mempolicy`, and downloads are not performed on pinned threads.
+//! Placement relies on the first-touch policy, and Tokio workers are not pinned, so a download
+//! buffer is only local to the pool that processes it if the worker that filled it has not
+//! migrated to another node. Buffers written by a Rayon worker, such as decompressed data, reside
+//! on the worker's node.
+
+use rayon::ThreadPool;
+
+/// Path to the NUMA node information in sysfs.
+#[cfg(target_os = "linux")]
+const SYSFS_NODE_PATH: &str = "/sys/devices/system/node";
+
+/// Parse a Linux CPU list, e.g. `0-3,8-11`.
+///
+/// # Arguments
+///
+/// * `cpulist`: CPU list
+fn parse_cpulist(cpulist: &str) -> Result<Vec<usize>, String> {
+    let parse = |cpu: &str| {
+        cpu.parse::<usize>()
+            .map_err(|err| format!("invalid CPU list {}: {}", cpulist, err))
+    };
+    let mut cpus = Vec::new();
+    for range in cpulist.trim().split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => cpus.extend(parse(start)?..=parse(end)?),
+            None => cpus.push(parse(range)?),
+        }
+    }
+    Ok(cpus)
+}
+
+/// Returns the CPUs of each NUMA node with CPUs, ordered by node number.
+#[cfg(target_os = "linux")]
+fn numa_nodes() -> Result<Vec<Vec<usize>>, String> {
+    let mut nodes = std::fs::read_dir(SYSFS_NODE_PATH)
+        .map_err(|err| format!("failed to read {}: {}", SYSFS_NODE_PATH, err))?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            let node = name.strip_prefix("node")?.parse::<usize>().ok()?;
+            Some((node, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    nodes.sort();
+    let mut node_cpus = Vec::new();
+    for (_, path) in nodes {
+        let cpulist = std::fs::read_to_string(path.join("cpulist"))
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let cpus = parse_cpulist(&cpulist)?;
+        // Skip memory-only nodes.
+        if !cpus.is_empty() {
+            node_cpus.push(cpus);
+        }
+    }
+    Ok(node_cpus)
+}
+
+/// Pin the current thread to a set of CPUs.
+///
+/// Fails without changing the affinity of the thread if a CPU does not fit in a `cpu_set_t`.
+///
+/// # Arguments
+///
+/// * `cpus`: CPUs to pin to
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpus: &[usize]) -> std::io::Result<()> {
+    let set_size = 8 * std::mem::size_of::<libc::cpu_set_t>();
+    if let Some(cpu) = cpus.iter().find(|cpu| **cpu >= set_size) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("CPU {} exceeds the maximum of {} CPUs", cpu, set_size),
+        ));
+    }
+    // SAFETY: cpu_set_t is a plain bitmask, for which all zeroes is a valid (empty) value, and
+    // the pointer passed to sched_setaffinity refers to it for the duration of the call.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for cpu in cpus {
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Returns the CPU on which the current thread is running, if known.
+#[cfg(target_os = "linux")]
+fn current_cpu() -> Option<usize> {
+    // SAFETY: sched_getcpu has no preconditions.
+    let cpu = unsafe { libc::sched_getcpu() };
+    usize::try_from(cpu).ok()
+}
+
+/// A Rayon thread pool for each NUMA node.
+pub struct NumaPools {
+    /// Map from CPU to the index of its node's pool.
+    cpu_pool: Vec<Option<usize>>,
+
+    /// Thread pool for each NUMA node.
+    pools: Vec<ThreadPool>,
+}
+
+impl NumaPools {
+    /// Returns a new NumaPools object.
+    ///
+    /// Each pool has one thread per CPU in its node, except that the first pool has one fewer
+    /// thread, leaving one CPU free for handling asynchronous tasks.
+    #[cfg(target_os = "linux")]
+    pub fn new() -> Result<Self, String> {
+        let nodes = numa_nodes()?;
+        if nodes.is_empty() {
+            return Err("no NUMA nodes with CPUs found".to_string());
+        }
+        let max_cpu = nodes.iter().flatten().max().copied().unwrap_or(0);
+        let mut cpu_pool = vec![None; max_cpu + 1];
+        let mut pools = Vec::new();
+        for (index, cpus) in nodes.into_iter().enumerate() {
+            for cpu in &cpus {
+                cpu_pool[*cpu] = Some(index);
+            }
+            let num_threads = if index == 0 {
+                std::cmp::max(cpus.len() - 1, 1)
+            } else {
+                cpus.len()
+            };
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .thread_name(move |i| format!("rayon-node{}-{}", index, i))
+                .start_handler(move |_| {
+                    if let Err(err) = pin_current_thread(&cpus) {
+                        tracing::warn!("failed to pin Rayon thread to NUMA node: {}", err);
+                    }
+                })
+                .build()
+                .map_err(|err| err.to_string())?;
+            pools.push(pool);
+        }
+        tracing::info!("Created Rayon thread pools for {} NUMA nodes", pools.len());
+        Ok(Self { cpu_pool, pools })
+    }
+
+    /// Returns a new NumaPools object.
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> Result<Self, String> {
+        Err("NUMA pinning is only supported on Linux".to_string())
+    }
+
+    /// Returns the thread pool local to the CPU on which the current thread is running.
+    pub fn local(&self) -> &ThreadPool {
+        #[cfg(target_os = "linux")]
+        let index = current_cpu()
+            .and_then(|cpu| self.cpu_pool.get(cpu).copied().flatten())
+            .unwrap_or(0);
+        #[cfg(not(target_os = "linux"))]
+        let index = 0;
+        &self.pools[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cpulist_single() {
+        assert_eq!(vec![3], parse_cpulist("3\n").unwrap());
+    }
+
+    #[test]
+    fn parse_cpulist_ranges() {
+        assert_eq!(
+            vec![0, 1, 2, 3, 8, 10, 11],
+            parse_cpulist("0-3,8,10-11").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_cpulist_empty() {
+        assert!(parse_cpulist("\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_cpulist_invalid() {
+        let err = parse_cpulist("0-a").unwrap_err();
+        assert_eq!("invalid CPU list 0-a: invalid digit found in string", err);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn pin_current_thread_cpu_too_large() {
+        let err = pin_current_thread(&[0, 1 << 20]).unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn numa_pools() {
+        let pools = NumaPools::new().unwrap();
+        assert_eq!(42, pools.local().install(|| 42));
+    }
+}
@@ -0,0 +1,213 @@
+//! Limit on concurrent distinct objects per user
+//!
+//! A single user sweeping a whole variable may submit requests for thousands of chunks at once,
+//! monopolising the server. When a limit is configured, each user may have requests in flight for
+//! at most that many distinct objects, with further requests for new objects waiting until one of
+//! the user's objects has no requests in flight. Requests for an object that the user already has
+//! in flight are not limited, allowing high parallelism within each object.
+//!
+//! Users are identified by the authentication backend, with anonymous requests sharing a single
+//! limit.
+
+use crate::models::Source;
+
+use hashbrown::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// An object in a request, identified by its source, bucket and key.
+type ObjectKey = (Source, String, String);
+
+/// In-flight objects of a user.
+#[derive(Debug)]
+struct User {
+    /// Semaphore for distinct objects.
+    semaphore: Arc<Semaphore>,
+    /// Number of requests in flight and permit for each object.
+    objects: HashMap<ObjectKey, (usize, OwnedSemaphorePermit)>,
+}
+
+/// Limits the number of distinct objects that each user may have in flight.
+#[derive(Debug)]
+pub struct ObjectLimiter {
+    /// Maximum number of distinct objects in flight per user.
+    limit: usize,
+    /// In-flight objects of each user with requests in flight or waiting.
+    users: Mutex<HashMap<String, User>>,
+}
+
+impl ObjectLimiter {
+    /// Returns a new ObjectLimiter object.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit`: Maximum number of distinct objects in flight per user
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit: limit.max(1),
+            users: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Acquire a slot for a request to an object, waiting if the user has reached their limit.
+    ///
+    /// The slot is released when the returned guard is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`: User making the request
+    /// * `object`: Source, bucket and key of the object
+    pub async fn acquire(self: &Arc<Self>, user: &str, object: ObjectKey) -> ObjectGuard {
+        let semaphore = {
+            let mut users = self.users.lock().unwrap();
+            let entry = users.entry(user.to_string()).or_insert_with(|| User {
+                semaphore: Arc::new(Semaphore::new(self.limit)),
+                objects: HashMap::new(),
+            });
+            if let Some((count, _)) = entry.objects.get_mut(&object) {
+                *count += 1;
+                return self.guard(user, object);
+            }
+            entry.semaphore.clone()
+        };
+        let permit = semaphore
+            .acquire_owned()
+            .await
+            .expect("semaphore should not be closed");
+        let mut users = self.users.lock().unwrap();
+        let entry = users
+            .get_mut(user)
+            .expect("user with a waiting request should not be removed");
+        // Another request for the same object may have acquired a permit while waiting, in which
+        // case this permit is released.
+        entry
+            .objects
+            .entry(object.clone())
+            .and_modify(|(count, _)| *count += 1)
+            .or_insert((1, permit));
+        self.guard(user, object)
+    }
+
+    /// Returns a guard for a request to an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`: User making the request
+    /// * `object`: Source, bucket and key of the object
+    fn guard(self: &Arc<Self>, user: &str, object: ObjectKey) -> ObjectGuard {
+        ObjectGuard {
+            limiter: self.clone(),
+            user: user.to_string(),
+            object,
+        }
+    }
+
+    /// Release a slot for a request to an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`: User that made the request
+    /// * `object`: Source, bucket and key of the object
+    fn release(&self, user: &str, object: &ObjectKey) {
+        let mut users = self.users.lock().unwrap();
+        let Some(entry) = users.get_mut(user) else {
+            return;
+        };
+        if let Some((count, _)) = entry.objects.get_mut(object) {
+            *count -= 1;
+            if *count == 0 {
+                // Dropping the permit allows another object to proceed.
+                entry.objects.remove(object);
+            }
+        }
+        // Forget users with no requests in flight or waiting.
+        if entry.objects.is_empty() && Arc::strong_count(&entry.semaphore) == 1 {
+            users.remove(user);
+        }
+    }
+
+    /// Returns the number of distinct objects a user has in flight.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`: User
+    pub fn in_flight(&self, user: &str) -> usize {
+        self.users
+            .lock()
+            .unwrap()
+            .get(user)
+            .map_or(0, |entry| entry.objects.len())
+    }
+}
+
+/// Slot for a request to an object, which is released when dropped.
+#[derive(Debug)]
+pub struct ObjectGuard {
+    /// Limiter that the slot was acquired from.
+    limiter: Arc<ObjectLimiter>,
+    /// User that made the request.
+    user: String,
+    /// Source, bucket and key of the object.
+    object: ObjectKey,
+}
+
+impl Drop for ObjectGuard {
+    fn drop(&mut self) {
+        self.limiter.release(&self.user, &self.object);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn object(key: &str) -> ObjectKey {
+        (
+            Source::Name("source".to_string()),
+            "bucket".to_string(),
+            key.to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn same_object_not_limited() {
+        let limiter = ObjectLimiter::new(1);
+        let _guard1 = limiter.acquire("user", object("a")).await;
+        let _guard2 = limiter.acquire("user", object("a")).await;
+        assert_eq!(1, limiter.in_flight("user"));
+    }
+
+    #[tokio::test]
+    async fn users_limited_separately() {
+        let limiter = ObjectLimiter::new(1);
+        let _guard1 = limiter.acquire("user1", object("a")).await;
+        let _guard2 = limiter.acquire("user2", object("b")).await;
+        assert_eq!(1, limiter.in_flight("user1"));
+        assert_eq!(1, limiter.in_flight("user2"));
+    }
+
+    #[tokio::test]
+    async fn distinct_objects_limited() {
+        let limiter = ObjectLimiter::new(1);
+        let guard1 = limiter.acquire("user", object("a")).await;
+        let guard2 = limiter.acquire("user", object("a")).await;
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire("user", object("b")).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+        drop(guard1);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // Object a still has a request in flight.
+        assert!(!waiting.is_finished());
+        drop(guard2);
+        let guard3 = waiting.await.unwrap();
+        assert_eq!(1, limiter.in_flight("user"));
+        drop(guard3);
+        assert_eq!(0, limiter.in_flight("user"));
+        assert!(limiter.users.lock().unwrap().is_empty());
+    }
+}
@@ -12,8 +12,8 @@ pub trait Element:
     + num_traits::FromBytes<Bytes = <Self as num_traits::ToBytes>::Bytes>
     + num_traits::FromPrimitive
     + num_traits::ToBytes
+    + num_traits::ToPrimitive
     + num_traits::Zero
-    + std::convert::From<u16>
     + std::fmt::Debug
     + std::iter::Sum
     + std::ops::Add<Output = Self>
@@ -33,8 +33,8 @@ impl<T> Element for T where
         + num_traits::FromPrimitive
         + num_traits::One
         + num_traits::ToBytes
+        + num_traits::ToPrimitive
         + num_traits::Zero
-        + std::convert::From<u16>
         + std::fmt::Debug
         + std::iter::Sum
         + std::ops::Add<Output = Self>
@@ -49,6 +49,37 @@ impl<T> Element for T where
 ///
 /// This forms the contract between the API layer and operations.
 pub trait Operation {
+    /// Name of the operation, as used in the API.
+    const NAME: &'static str;
+
+    /// Whether the operation aggregates groups of elements using labels in the request's
+    /// [group_by](models::RequestData::group_by).
+    const GROUPED: bool = false;
+
+    /// Whether the operation aggregates blocks of elements using block sizes in the request's
+    /// [coarsen](models::RequestData::coarsen).
+    const COARSENED: bool = false;
+
+    /// Whether the operation aggregates elements across the members in the request's
+    /// [ensemble](models::RequestData::ensemble).
+    const ENSEMBLE: bool = false;
+
+    /// Whether the operation evaluates the request's
+    /// [expression](models::RequestData::expression).
+    const EXPRESSION: bool = false;
+
+    /// Whether the operation uses the delta degrees of freedom in the request's
+    /// [ddof](models::RequestData::ddof).
+    const DDOF: bool = false;
+
+    /// Whether the operation handles NaN elements according to the request's
+    /// [nan_policy](models::RequestData::nan_policy).
+    const NAN_POLICY: bool = false;
+
+    /// Whether the operation returns partial aggregates if the request's
+    /// [partial](models::RequestData::partial) is set.
+    const PARTIAL: bool = false;
+
     /// Execute the operation.
     ///
     /// Returns a [models::Response] object with response data.
@@ -67,6 +98,17 @@ pub trait Operation {
 ///
 /// This trait provides an entry point into the type system based on the runtime `dtype` value.
 pub trait NumOperation: Operation {
+    /// Name of the operation, as used in the API.
+    const NAME: &'static str;
+
+    /// Whether the operation handles NaN elements according to the request's
+    /// [nan_policy](models::RequestData::nan_policy).
+    const NAN_POLICY: bool = false;
+
+    /// Whether the operation returns partial aggregates if the request's
+    /// [partial](models::RequestData::partial) is set.
+    const PARTIAL: bool = false;
+
     fn execute_t<T: Element>(
         request_data: &models::RequestData,
         data: Vec<u8>,
@@ -74,6 +116,12 @@ pub trait NumOperation: Operation {
 }
 
 impl<T: NumOperation> Operation for T {
+    const NAME: &'static str = <T as NumOperation>::NAME;
+
+    const NAN_POLICY: bool = <T as NumOperation>::NAN_POLICY;
+
+    const PARTIAL: bool = <T as NumOperation>::PARTIAL;
+
     /// Execute the operation.
     ///
     /// This method dispatches to `execute_t` based on the `dtype`.
@@ -83,8 +131,12 @@ impl<T: NumOperation> Operation for T {
     ) -> Result<models::Response, ActiveStorageError> {
         // Convert runtime data type into concrete types.
         match request_data.dtype {
+            models::DType::Int8 => Self::execute_t::<i8>(request_data, data),
+            models::DType::Int16 => Self::execute_t::<i16>(request_data, data),
             models::DType::Int32 => Self::execute_t::<i32>(request_data, data),
             models::DType::Int64 => Self::execute_t::<i64>(request_data, data),
+            models::DType::Uint8 => Self::execute_t::<u8>(request_data, data),
+            models::DType::Uint16 => Self::execute_t::<u16>(request_data, data),
             models::DType::Uint32 => Self::execute_t::<u32>(request_data, data),
             models::DType::Uint64 => Self::execute_t::<u64>(request_data, data),
             models::DType::Float32 => Self::execute_t::<f32>(request_data, data),
@@ -93,6 +145,16 @@ impl<T: NumOperation> Operation for T {
     }
 }
 
+/// Trait for code that is generic over the type of an operation, allowing it to be called for an
+/// operation named at runtime using [visit](crate::operations::visit).
+pub trait OperationVisitor {
+    /// Result of visiting an operation.
+    type Output;
+
+    /// Visit the operation `T`.
+    fn visit<T: Operation + 'static>(self) -> Self::Output;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -102,6 +164,8 @@ mod tests {
     struct TestOp {}
 
     impl Operation for TestOp {
+        const NAME: &'static str = "test";
+
         fn execute(
             request_data: &models::RequestData,
             data: Vec<u8>,
@@ -131,6 +195,8 @@ mod tests {
     struct TestNumOp {}
 
     impl NumOperation for TestNumOp {
+        const NAME: &'static str = "test_num";
+
         fn execute_t<T: Element>(
             request_data: &models::RequestData,
             _data: Vec<u8>,
@@ -157,4 +223,13 @@ mod tests {
         assert_eq!(vec![1, 2], response.shape);
         assert_eq!(2, response.count);
     }
+
+    #[test]
+    fn num_operation_i8() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Int8;
+        let response = TestNumOp::execute(&request_data, vec![1, 2]).unwrap();
+        assert_eq!("i8", response.body);
+        assert_eq!(models::DType::Int8, response.dtype);
+    }
 }
@@ -0,0 +1,165 @@
+//! Per-operation limits
+//!
+//! The responses of some operations can be orders of magnitude larger than others: a select
+//! returns every selected element, while a reduction returns a scalar. Limits may be configured
+//! for individual operations, overriding the server-wide limits, for example to give select
+//! operations a tighter response size cap and compute time budget. The memory limit of an
+//! operation is checked against the request data before any data is downloaded.
+
+use hashbrown::HashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Limits for an operation.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Limits {
+    /// Maximum memory in bytes reserved for a single request, if limited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_request_memory: Option<usize>,
+    /// Maximum size of a response body in bytes, overriding the server-wide limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_response_size: Option<usize>,
+    /// Compute time budget in seconds, overriding the server-wide budget
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_timeout: Option<f64>,
+}
+
+impl Limits {
+    /// Returns the compute time budget, if configured.
+    pub fn compute_budget(&self) -> Option<Duration> {
+        self.compute_timeout.map(Duration::from_secs_f64)
+    }
+}
+
+/// Limits configured for individual operations.
+#[derive(Debug, Default)]
+pub struct OperationLimits {
+    /// Map from the name of an operation to its limits.
+    limits: HashMap<String, Limits>,
+}
+
+impl OperationLimits {
+    /// Returns a new OperationLimits object.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits`: List of limits, each of the form `<operation>:<limit>=<value>[,...]`, where
+    ///   `<limit>` is one of `max_request_memory`, `max_response_size` or `compute_timeout`
+    /// * `operations`: Names of the supported operations
+    pub fn new(limits: &[String], operations: &[&str]) -> Result<Self, String> {
+        let mut map = HashMap::new();
+        for entry in limits {
+            let (operation, values) = entry
+                .split_once(':')
+                .ok_or(format!("invalid operation limits {}", entry))?;
+            let operation = operation.trim();
+            if !operations.contains(&operation) {
+                return Err(format!(
+                    "unknown operation {} in operation limits",
+                    operation
+                ));
+            }
+            let limits: &mut Limits = map.entry(operation.to_string()).or_default();
+            for value in values.split(',') {
+                let (name, value) = value
+                    .split_once('=')
+                    .ok_or(format!("invalid operation limit {}", value))?;
+                let value = value.trim();
+                let invalid = |err: &dyn std::fmt::Display| format!("{}: {}", value, err);
+                match name.trim() {
+                    "max_request_memory" => {
+                        limits.max_request_memory =
+                            Some(value.parse().map_err(|err| invalid(&err))?)
+                    }
+                    "max_response_size" => {
+                        limits.max_response_size = Some(value.parse().map_err(|err| invalid(&err))?)
+                    }
+                    "compute_timeout" => {
+                        let timeout: f64 = value.parse().map_err(|err| invalid(&err))?;
+                        Duration::try_from_secs_f64(timeout).map_err(|err| invalid(&err))?;
+                        limits.compute_timeout = Some(timeout)
+                    }
+                    name => return Err(format!("unknown operation limit {}", name)),
+                }
+            }
+        }
+        Ok(Self { limits: map })
+    }
+
+    /// Returns the limits for an operation, if any are configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation`: Name of the operation
+    pub fn get(&self, operation: &str) -> Option<&Limits> {
+        self.limits.get(operation)
+    }
+
+    /// Returns the limits configured for each operation, ordered by name.
+    pub fn all(&self) -> BTreeMap<&str, &Limits> {
+        self.limits
+            .iter()
+            .map(|(operation, limits)| (operation.as_str(), limits))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATIONS: &[&str] = &["select", "sum"];
+
+    fn limits(limits: &[&str]) -> Result<OperationLimits, String> {
+        let limits: Vec<String> = limits.iter().map(|limit| limit.to_string()).collect();
+        OperationLimits::new(&limits, OPERATIONS)
+    }
+
+    #[test]
+    fn new() {
+        let limits = limits(&[
+            "select:max_request_memory=1024, max_response_size=512",
+            "select:compute_timeout=0.5",
+        ])
+        .unwrap();
+        let expected = Limits {
+            max_request_memory: Some(1024),
+            max_response_size: Some(512),
+            compute_timeout: Some(0.5),
+        };
+        assert_eq!(Some(&expected), limits.get("select"));
+        assert_eq!(Some(Duration::from_millis(500)), expected.compute_budget());
+        assert_eq!(None, limits.get("sum"));
+    }
+
+    #[test]
+    fn new_empty() {
+        let limits = limits(&[]).unwrap();
+        assert!(limits.all().is_empty());
+    }
+
+    #[test]
+    fn new_unknown_operation() {
+        assert_eq!(
+            "unknown operation foo in operation limits",
+            limits(&["foo:max_response_size=1"]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn new_unknown_limit() {
+        assert_eq!(
+            "unknown operation limit foo",
+            limits(&["sum:foo=1"]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn new_invalid() {
+        limits(&["sum"]).unwrap_err();
+        limits(&["sum:max_response_size"]).unwrap_err();
+        limits(&["sum:max_response_size=-1"]).unwrap_err();
+        limits(&["sum:compute_timeout=-1"]).unwrap_err();
+    }
+}
@@ -1,27 +1,34 @@
 //! Numerical operations.
 //!
 //! Each operation is implemented as a struct that implements the
-//! [Operation](crate::operation::Operation) trait.
+//! [Operation] trait.
 
 use crate::array;
+use crate::deadline;
 use crate::error::ActiveStorageError;
+use crate::expression;
 use crate::models;
-use crate::operation::{Element, NumOperation};
-use crate::types::Missing;
+use crate::operation::{Element, NumOperation, Operation, OperationVisitor};
+use crate::types::{Missing, NON_NATIVE_BYTE_ORDER};
 
 use axum::body::Bytes;
-use ndarray::ArrayView;
-use ndarray_stats::{errors::MinMaxError, QuantileExt};
+use ndarray::{ArrayView, ArrayView1, Axis, Dimension, IxDyn};
 // Bring trait into scope to use as_bytes method.
 use zerocopy::AsBytes;
 
+/// Maximum number of groups for the groupby operation.
+pub const MAX_GROUPS: usize = 1 << 20;
+
+/// Filter function returning whether to include an element.
+pub(crate) type ElementFilter<'a, T> = Box<dyn Fn(&T) -> bool + 'a>;
+
 /// Returns a filter function that can be used with the Iterator trait's filter() method to filter
 /// out missing data.
 ///
 /// # Arguments
 ///
 /// * `missing`: Missing data description.
-fn missing_filter<'a, T: Element>(missing: &'a Missing<T>) -> Box<dyn Fn(&T) -> bool + 'a> {
+pub(crate) fn missing_filter<'a, T: Element>(missing: &'a Missing<T>) -> ElementFilter<'a, T> {
     match missing {
         Missing::MissingValue(value) => Box::new(move |x: &T| *x != *value),
         Missing::MissingValues(values) => Box::new(move |x: &T| !values.contains(x)),
@@ -42,13 +49,279 @@ fn count_non_missing<T: Element>(
     missing: &Missing<T>,
 ) -> Result<usize, ActiveStorageError> {
     let filter = missing_filter(missing);
-    Ok(array.iter().copied().filter(filter).count())
+    let count = deadline::checkpoints(array.iter().copied())
+        .filter(filter)
+        .count();
+    deadline::check()?;
+    Ok(count)
+}
+
+/// Returns whether an element is NaN.
+fn is_nan<T: Element>(value: &T) -> bool {
+    // Only NaN is unordered with respect to itself.
+    value.partial_cmp(value).is_none()
+}
+
+/// Returns a filter for the elements to include in a reduction, if any are excluded.
+///
+/// Missing elements are excluded, as are NaN elements if the NaN policy is `ignore`. Returns
+/// [crate::error::ActiveStorageError::NanValue] if the NaN policy is `error` and an element that
+/// is not missing is NaN.
+///
+/// # Arguments
+///
+/// * `array`: Selected elements of the array
+/// * `missing`: Optional missing data description
+/// * `nan_policy`: Optional NaN policy, which defaults to propagating NaN elements
+/// * `operation`: Name of the operation
+fn reduction_filter<'a, T: Element>(
+    array: &ArrayView<T, IxDyn>,
+    missing: Option<&'a Missing<T>>,
+    nan_policy: Option<models::NanPolicy>,
+    operation: &'static str,
+) -> Result<Option<ElementFilter<'a, T>>, ActiveStorageError> {
+    let valid = missing.map(missing_filter);
+    match nan_policy {
+        Some(models::NanPolicy::Ignore) => Ok(Some(match valid {
+            Some(valid) => Box::new(move |x: &T| !is_nan(x) && valid(x)),
+            None => Box::new(|x: &T| !is_nan(x)),
+        })),
+        Some(models::NanPolicy::Error) => {
+            let found = deadline::checkpoints(array.iter())
+                .any(|x| is_nan(x) && valid.as_ref().map_or(true, |valid| valid(x)));
+            deadline::check()?;
+            if found {
+                return Err(ActiveStorageError::NanValue { operation });
+            }
+            Ok(valid)
+        }
+        Some(models::NanPolicy::Propagate) | None => Ok(valid),
+    }
+}
+
+/// Returns the extremum of some elements and the number of elements.
+///
+/// NaN elements are propagated, so that the extremum is the first NaN element if there is one.
+///
+/// # Arguments
+///
+/// * `values`: Iterator over the elements
+/// * `ordering`: Ordering with respect to the extremum of an element that replaces it
+fn extremum<T: Element>(
+    values: impl Iterator<Item = T>,
+    ordering: std::cmp::Ordering,
+) -> (Option<T>, usize) {
+    values.fold((None, 0), |(extremum, count), value| {
+        let extremum = match extremum {
+            Some(current) if is_nan(&current) => current,
+            Some(current) if !is_nan(&value) && value.partial_cmp(&current) != Some(ordering) => {
+                current
+            }
+            _ => value,
+        };
+        (Some(extremum), count + 1)
+    })
+}
+
+/// Fold the non-missing elements of each bucket of an array, counting them.
+///
+/// Returns the accumulator and count for each bucket, and the number of missing elements.
+///
+/// # Arguments
+///
+/// * `buckets`: Iterator over the index of each bucket and some of its elements. Each bucket may
+///   appear more than once.
+/// * `num_buckets`: Number of buckets
+/// * `missing`: Optional missing data description
+/// * `init`: Initial accumulator for each bucket
+/// * `f`: Function that folds an element into an accumulator
+fn fold_buckets<'a, T: Element + 'a, A: Clone>(
+    buckets: impl Iterator<Item = (usize, impl Iterator<Item = &'a T>)>,
+    num_buckets: usize,
+    missing: Option<&Missing<T>>,
+    init: A,
+    f: impl Fn(&mut A, T),
+) -> Result<(Vec<A>, Vec<i64>, i64), ActiveStorageError> {
+    let valid = missing.map(missing_filter);
+    let mut accumulators = vec![init; num_buckets];
+    let mut counts = vec![0_i64; num_buckets];
+    let mut num_missing = 0;
+    for (bucket, values) in deadline::checkpoints(buckets) {
+        for value in deadline::checkpoints(values.copied()) {
+            if valid.as_ref().map_or(true, |valid| valid(&value)) {
+                f(&mut accumulators[bucket], value);
+                counts[bucket] += 1;
+            } else {
+                num_missing += 1;
+            }
+        }
+    }
+    deadline::check()?;
+    Ok((accumulators, counts, num_missing))
+}
+
+/// Aggregate the non-missing elements of each bucket of an array.
+///
+/// Returns the response body, its data type and the numbers of non-missing and missing elements. The `count`
+/// aggregation returns int64 results, `mean` returns float64 results with NaN for empty buckets,
+/// and `max`, `min` and `sum` return results of the same type as the data. Empty buckets are NaN
+/// for `max` and `min` of floating point data, and are an error for integer data.
+///
+/// # Arguments
+///
+/// * `buckets`: Iterator over the index of each bucket and some of its elements
+/// * `num_buckets`: Number of buckets
+/// * `missing`: Optional missing data description
+/// * `aggregation`: Aggregation to compute for each bucket
+/// * `request_data`: RequestData object for the request
+/// * `operation`: Name of the operation
+fn aggregate_buckets<'a, T: Element + 'a>(
+    buckets: impl Iterator<Item = (usize, impl Iterator<Item = &'a T>)>,
+    num_buckets: usize,
+    missing: Option<&Missing<T>>,
+    aggregation: models::Aggregation,
+    request_data: &models::RequestData,
+    operation: &'static str,
+) -> Result<(Bytes, models::DType, i64, i64), ActiveStorageError> {
+    match aggregation {
+        models::Aggregation::Count => {
+            let (_, counts, missing) = fold_buckets(buckets, num_buckets, missing, (), |_, _| ())?;
+            let body = Bytes::copy_from_slice(counts.as_bytes());
+            Ok((body, models::DType::Int64, counts.iter().sum(), missing))
+        }
+        models::Aggregation::Max | models::Aggregation::Min => {
+            // Replace the extremum only if it is ordered before the element, so that unordered
+            // elements such as NaN are kept if they occur first.
+            let replace = if aggregation == models::Aggregation::Max {
+                std::cmp::Ordering::Less
+            } else {
+                std::cmp::Ordering::Greater
+            };
+            let f = |extremum: &mut Option<T>, value: T| match extremum {
+                Some(current) if (*current).partial_cmp(&value) != Some(replace) => (),
+                _ => *extremum = Some(value),
+            };
+            let (extrema, counts, missing) = fold_buckets(buckets, num_buckets, missing, None, f)?;
+            // Empty buckets are NaN if the data type allows it.
+            let extrema = extrema
+                .into_iter()
+                .map(|extremum| {
+                    extremum
+                        .or_else(|| T::from_f64(f64::NAN))
+                        .ok_or(ActiveStorageError::EmptyArray { operation })
+                })
+                .collect::<Result<Vec<T>, _>>()?;
+            let body = Bytes::copy_from_slice(extrema.as_bytes());
+            Ok((body, request_data.dtype, counts.iter().sum(), missing))
+        }
+        models::Aggregation::Mean => {
+            let f = |sum: &mut f64, value: T| *sum += value.to_f64().unwrap_or(f64::NAN);
+            let (sums, counts, missing) = fold_buckets(buckets, num_buckets, missing, 0.0, f)?;
+            let means: Vec<f64> = std::iter::zip(&sums, &counts)
+                .map(|(sum, count)| sum / *count as f64)
+                .collect();
+            let body = Bytes::copy_from_slice(means.as_bytes());
+            Ok((body, models::DType::Float64, counts.iter().sum(), missing))
+        }
+        models::Aggregation::Sum => {
+            let f = |sum: &mut T, value: T| *sum = *sum + value;
+            let (sums, counts, missing) =
+                fold_buckets(buckets, num_buckets, missing, T::zero(), f)?;
+            let body = Bytes::copy_from_slice(sums.as_bytes());
+            Ok((body, request_data.dtype, counts.iter().sum(), missing))
+        }
+    }
+}
+
+/// Return an aggregation of the selected elements in each block of the array.
+///
+/// Blocks are defined by the block sizes in the request's
+/// [coarsen](models::RequestData::coarsen), and are applied to the selection. Blocks at the end of
+/// an axis are smaller if the block size does not divide the length of the axis.
+pub struct Coarsen {}
+
+impl Operation for Coarsen {
+    const NAME: &'static str = "coarsen";
+
+    const COARSENED: bool = true;
+
+    fn execute(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        // Convert runtime data type into concrete types.
+        match request_data.dtype {
+            models::DType::Int8 => Self::execute_t::<i8>(request_data, data),
+            models::DType::Int16 => Self::execute_t::<i16>(request_data, data),
+            models::DType::Int32 => Self::execute_t::<i32>(request_data, data),
+            models::DType::Int64 => Self::execute_t::<i64>(request_data, data),
+            models::DType::Uint8 => Self::execute_t::<u8>(request_data, data),
+            models::DType::Uint16 => Self::execute_t::<u16>(request_data, data),
+            models::DType::Uint32 => Self::execute_t::<u32>(request_data, data),
+            models::DType::Uint64 => Self::execute_t::<u64>(request_data, data),
+            models::DType::Float32 => Self::execute_t::<f32>(request_data, data),
+            models::DType::Float64 => Self::execute_t::<f64>(request_data, data),
+        }
+    }
+}
+
+impl Coarsen {
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        mut data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let coarsen = request_data
+            .coarsen
+            .as_ref()
+            .expect("coarsen should be validated for coarsen");
+        let array = array::build_array::<T>(request_data, &mut data)?;
+        let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
+        let sliced = array.slice(slice_info);
+        let shape: Vec<usize> = std::iter::zip(sliced.shape(), &coarsen.block)
+            .map(|(len, block)| len.div_ceil(*block))
+            .collect();
+        let num_blocks = shape.iter().product();
+        // Number the blocks in the same order as the data, as for the select operation.
+        let fortran = !array.is_standard_layout();
+        let blocks = ndarray::indices(IxDyn(&shape)).into_iter().map(|index| {
+            let index = index.slice();
+            let dims: Box<dyn Iterator<Item = (&usize, &usize)>> = if fortran {
+                Box::new(std::iter::zip(index, &shape).rev())
+            } else {
+                Box::new(std::iter::zip(index, &shape))
+            };
+            let bucket = dims.fold(0, |bucket, (i, len)| bucket * len + i);
+            let block = sliced.slice_each_axis(|axis| {
+                let (i, size) = (index[axis.axis.index()], coarsen.block[axis.axis.index()]);
+                ndarray::Slice::from(i * size..((i + 1) * size).min(axis.len))
+            });
+            (bucket, block.into_iter())
+        });
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let (body, dtype, count, missing) = aggregate_buckets(
+            blocks,
+            num_blocks,
+            missing.as_ref(),
+            coarsen.aggregation,
+            request_data,
+            "coarsen",
+        )?;
+        Ok(models::Response::new(body, dtype, shape, count).with_missing(missing))
+    }
 }
 
 /// Return the number of selected elements in the array.
 pub struct Count {}
 
 impl NumOperation for Count {
+    const NAME: &'static str = "count";
+
+    const NAN_POLICY: bool = true;
+
     fn execute_t<T: Element>(
         request_data: &models::RequestData,
         mut data: Vec<u8>,
@@ -56,9 +329,18 @@ impl NumOperation for Count {
         let array = array::build_array::<T>(request_data, &mut data)?;
         let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
         let sliced = array.slice(slice_info);
-        let count = if let Some(missing) = &request_data.missing {
-            let missing = Missing::<T>::try_from(missing)?;
-            count_non_missing(&sliced, &missing)?
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let valid = reduction_filter(&sliced, missing.as_ref(), request_data.nan_policy, "count")?;
+        let count = if let Some(valid) = valid {
+            let count = deadline::checkpoints(sliced.iter().copied())
+                .filter(valid)
+                .count();
+            deadline::check()?;
+            count
         } else {
             sliced.len()
         };
@@ -66,12 +348,464 @@ impl NumOperation for Count {
         let body = count.to_ne_bytes();
         // Need to copy to provide ownership to caller.
         let body = Bytes::copy_from_slice(&body);
-        Ok(models::Response::new(
-            body,
-            models::DType::Int64,
-            vec![],
-            count,
-        ))
+        Ok(
+            models::Response::new(body, models::DType::Int64, vec![], count)
+                .with_missing(i64::try_from(sliced.len())? - count),
+        )
+    }
+}
+
+/// Return an aggregation of each selected element across the members of an ensemble.
+///
+/// The data contains the decoded data of each member in turn, starting with the request's object
+/// followed by the members in the request's [ensemble](models::RequestData::ensemble). The result
+/// has the shape of the selection, with its elements in the same order as for the select
+/// operation.
+pub struct Ensemble {}
+
+impl Operation for Ensemble {
+    const NAME: &'static str = "ensemble";
+
+    const ENSEMBLE: bool = true;
+
+    fn execute(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        // Convert runtime data type into concrete types.
+        match request_data.dtype {
+            models::DType::Int8 => Self::execute_t::<i8>(request_data, data),
+            models::DType::Int16 => Self::execute_t::<i16>(request_data, data),
+            models::DType::Int32 => Self::execute_t::<i32>(request_data, data),
+            models::DType::Int64 => Self::execute_t::<i64>(request_data, data),
+            models::DType::Uint8 => Self::execute_t::<u8>(request_data, data),
+            models::DType::Uint16 => Self::execute_t::<u16>(request_data, data),
+            models::DType::Uint32 => Self::execute_t::<u32>(request_data, data),
+            models::DType::Uint64 => Self::execute_t::<u64>(request_data, data),
+            models::DType::Float32 => Self::execute_t::<f32>(request_data, data),
+            models::DType::Float64 => Self::execute_t::<f64>(request_data, data),
+        }
+    }
+}
+
+impl Ensemble {
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        mut data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let ensemble = request_data
+            .ensemble
+            .as_ref()
+            .expect("ensemble should be validated for ensemble");
+        let num_members = ensemble.members.len() + 1;
+        let member_size = data.len() / num_members;
+        let mut shape = vec![];
+        let mut members = Vec::with_capacity(num_members);
+        let mut rest = &mut data[..];
+        for _ in 0..num_members {
+            let (member, tail) = rest.split_at_mut(member_size);
+            rest = tail;
+            let array = array::build_array::<T>(request_data, member)?;
+            let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
+            let sliced = array.slice(slice_info);
+            shape = sliced.shape().to_vec();
+            // Transpose Fortran ordered arrays before iterating.
+            let values = if !array.is_standard_layout() {
+                deadline::checkpoints(sliced.t().iter().copied()).collect::<Vec<T>>()
+            } else {
+                deadline::checkpoints(sliced.iter().copied()).collect::<Vec<T>>()
+            };
+            deadline::check()?;
+            members.push(values);
+        }
+        let num_elements = shape.iter().product();
+        let buckets = (0..num_elements).map(|i| (i, members.iter().map(move |member| &member[i])));
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let (body, dtype, count, missing) = aggregate_buckets(
+            buckets,
+            num_elements,
+            missing.as_ref(),
+            ensemble.aggregation,
+            request_data,
+            "ensemble",
+        )?;
+        Ok(models::Response::new(body, dtype, shape, count).with_missing(missing))
+    }
+}
+
+/// Return a reduction of an expression evaluated for each selected element in the array.
+///
+/// This operation is experimental. The request's [expression](models::RequestData::expression)
+/// is evaluated in float64 with `x` bound to each non-missing element. Elements for which the
+/// expression evaluates to NaN are counted as missing. Returns a float64 scalar.
+pub struct Expr {}
+
+impl Operation for Expr {
+    const NAME: &'static str = "expr";
+
+    const EXPRESSION: bool = true;
+
+    fn execute(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        // Convert runtime data type into concrete types.
+        match request_data.dtype {
+            models::DType::Int8 => Self::execute_t::<i8>(request_data, data),
+            models::DType::Int16 => Self::execute_t::<i16>(request_data, data),
+            models::DType::Int32 => Self::execute_t::<i32>(request_data, data),
+            models::DType::Int64 => Self::execute_t::<i64>(request_data, data),
+            models::DType::Uint8 => Self::execute_t::<u8>(request_data, data),
+            models::DType::Uint16 => Self::execute_t::<u16>(request_data, data),
+            models::DType::Uint32 => Self::execute_t::<u32>(request_data, data),
+            models::DType::Uint64 => Self::execute_t::<u64>(request_data, data),
+            models::DType::Float32 => Self::execute_t::<f32>(request_data, data),
+            models::DType::Float64 => Self::execute_t::<f64>(request_data, data),
+        }
+    }
+}
+
+impl Expr {
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        mut data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let expression = request_data
+            .expression
+            .as_deref()
+            .and_then(|expression| expression::parse(expression).ok())
+            .expect("expression should be validated for expr");
+        let array = array::build_array::<T>(request_data, &mut data)?;
+        let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
+        let sliced = array.slice(slice_info);
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let valid = missing.as_ref().map(missing_filter);
+        let values = deadline::checkpoints(sliced.iter())
+            .filter(|value| valid.as_ref().map_or(true, |valid| valid(value)))
+            .map(|value| expression.node.evaluate(value.to_f64().unwrap_or(f64::NAN)))
+            .filter(|value| !value.is_nan());
+        let (result, count) = match expression.reduction {
+            expression::Reduction::Sum | expression::Reduction::Mean => {
+                let (sum, compensation, count) = compensated_sum(values);
+                (Some(sum + compensation), count)
+            }
+            expression::Reduction::Max => values.fold((None, 0), |(max, count), value| {
+                (Some(f64::max(max.unwrap_or(value), value)), count + 1)
+            }),
+            expression::Reduction::Min => values.fold((None, 0), |(min, count), value| {
+                (Some(f64::min(min.unwrap_or(value), value)), count + 1)
+            }),
+        };
+        deadline::check()?;
+        let result = match expression.reduction {
+            expression::Reduction::Sum => result,
+            expression::Reduction::Mean if count > 0 => result.map(|sum| sum / count as f64),
+            _ if count > 0 => result,
+            _ => None,
+        }
+        .ok_or(ActiveStorageError::EmptyArray { operation: "expr" })?;
+        let body = Bytes::copy_from_slice(result.as_bytes());
+        let count = i64::try_from(count)?;
+        Ok(
+            models::Response::new(body, models::DType::Float64, vec![], count)
+                .with_missing(i64::try_from(sliced.len())? - count),
+        )
+    }
+}
+
+/// Return an aggregation of the selected elements in each group of the array.
+///
+/// Groups are defined by the labels in the request's [group_by](models::RequestData::group_by),
+/// which must have been populated with the label values.
+pub struct GroupBy {}
+
+impl Operation for GroupBy {
+    const NAME: &'static str = "groupby";
+
+    const GROUPED: bool = true;
+
+    fn execute(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        // Convert runtime data type into concrete types.
+        match request_data.dtype {
+            models::DType::Int8 => Self::execute_t::<i8>(request_data, data),
+            models::DType::Int16 => Self::execute_t::<i16>(request_data, data),
+            models::DType::Int32 => Self::execute_t::<i32>(request_data, data),
+            models::DType::Int64 => Self::execute_t::<i64>(request_data, data),
+            models::DType::Uint8 => Self::execute_t::<u8>(request_data, data),
+            models::DType::Uint16 => Self::execute_t::<u16>(request_data, data),
+            models::DType::Uint32 => Self::execute_t::<u32>(request_data, data),
+            models::DType::Uint64 => Self::execute_t::<u64>(request_data, data),
+            models::DType::Float32 => Self::execute_t::<f32>(request_data, data),
+            models::DType::Float64 => Self::execute_t::<f64>(request_data, data),
+        }
+    }
+}
+
+impl GroupBy {
+    /// Returns the label values from the data of a labels object.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_by`: Labels for the request
+    /// * `data`: Labels object data
+    pub fn labels(group_by: &models::GroupBy, data: &[u8]) -> Result<Vec<i64>, ActiveStorageError> {
+        fn decode<T, const N: usize>(
+            data: &[u8],
+            reverse: bool,
+            from_ne_bytes: fn([u8; N]) -> T,
+        ) -> Result<Vec<i64>, ActiveStorageError>
+        where
+            i64: TryFrom<T>,
+        {
+            data.chunks_exact(N)
+                .map(|chunk| {
+                    let mut bytes: [u8; N] = chunk.try_into().expect("chunk should have N bytes");
+                    if reverse {
+                        bytes.reverse();
+                    }
+                    i64::try_from(from_ne_bytes(bytes)).map_err(|_| {
+                        ActiveStorageError::GroupByLabelsInvalid {
+                            reason: "label too large",
+                        }
+                    })
+                })
+                .collect()
+        }
+        if data.len() % group_by.labels_dtype.size_of() != 0 {
+            return Err(ActiveStorageError::GroupByLabelsInvalid {
+                reason: "size must be a multiple of labels_dtype size",
+            });
+        }
+        let reverse = group_by.labels_byte_order == Some(NON_NATIVE_BYTE_ORDER);
+        match group_by.labels_dtype {
+            models::DType::Int8 => decode(data, reverse, i8::from_ne_bytes),
+            models::DType::Int16 => decode(data, reverse, i16::from_ne_bytes),
+            models::DType::Int32 => decode(data, reverse, i32::from_ne_bytes),
+            models::DType::Int64 => decode(data, reverse, i64::from_ne_bytes),
+            models::DType::Uint8 => decode(data, reverse, u8::from_ne_bytes),
+            models::DType::Uint16 => decode(data, reverse, u16::from_ne_bytes),
+            models::DType::Uint32 => decode(data, reverse, u32::from_ne_bytes),
+            models::DType::Uint64 => decode(data, reverse, u64::from_ne_bytes),
+            models::DType::Float32 | models::DType::Float64 => {
+                unreachable!("labels_dtype should be validated as an integer type")
+            }
+        }
+    }
+
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        mut data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let group_by = request_data
+            .group_by
+            .as_ref()
+            .expect("group_by should be validated for groupby");
+        let array = array::build_array::<T>(request_data, &mut data)?;
+        let labels = ArrayView1::from(&group_by.label_values[..]).into_dyn();
+        if labels.len() != array.shape()[group_by.axis] {
+            return Err(ActiveStorageError::GroupByLabelsInvalid {
+                reason: "number of labels must match the length of the axis",
+            });
+        }
+        // The number of groups does not depend on the selection.
+        let num_groups = labels
+            .iter()
+            .max()
+            .and_then(|&max| usize::try_from(max).ok())
+            .map_or(0, |max| max.saturating_add(1));
+        if num_groups > MAX_GROUPS {
+            return Err(ActiveStorageError::GroupByLabelsInvalid {
+                reason: "too many groups",
+            });
+        }
+        let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
+        let sliced = array.slice(slice_info);
+        // Apply the selection for the axis to the labels.
+        let label_selection = request_data
+            .selection
+            .as_ref()
+            .map(|selection| vec![selection[group_by.axis].clone()]);
+        let slice_info = array::build_slice_info::<i64>(&label_selection, labels.shape());
+        let labels = labels.slice(slice_info);
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let groups = sliced
+            .axis_iter(Axis(group_by.axis))
+            .zip(labels.iter())
+            // Negative labels do not belong to any group.
+            .filter_map(|(lane, &label)| Some((usize::try_from(label).ok()?, lane.into_iter())));
+        let (body, dtype, count, missing) = aggregate_buckets(
+            groups,
+            num_groups,
+            missing.as_ref(),
+            group_by.aggregation,
+            request_data,
+            "groupby",
+        )?;
+        Ok(models::Response::new(body, dtype, vec![num_groups], count).with_missing(missing))
+    }
+}
+
+/// Return the index of the first occurrence of an extreme value of selected elements in the array.
+///
+/// The result is an int64 array containing the index of the extreme value in each dimension of
+/// the selection. Missing data and NaN values are ignored, and are both counted as missing.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `data`: Data to operate on
+/// * `operation`: Name of the operation
+/// * `replaces`: Function returning whether a value replaces the current extreme value
+fn arg_extreme<T: Element>(
+    request_data: &models::RequestData,
+    mut data: Vec<u8>,
+    operation: &'static str,
+    replaces: impl Fn(&T, &T) -> bool,
+) -> Result<models::Response, ActiveStorageError> {
+    let array = array::build_array::<T>(request_data, &mut data)?;
+    let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
+    let sliced = array.slice(slice_info);
+    let missing = request_data
+        .missing
+        .as_ref()
+        .map(Missing::<T>::try_from)
+        .transpose()?;
+    let valid = missing.as_ref().map(missing_filter);
+    let ((_, index), count) = deadline::checkpoints(sliced.indexed_iter())
+        .filter(|(_, value)| valid.as_ref().map_or(true, |valid| valid(value)))
+        .filter(|(_, value)| !value.to_f64().is_some_and(f64::is_nan))
+        .fold(None, |extreme, (index, &value)| match extreme {
+            None => Some(((value, index), 1usize)),
+            // Keep the first occurrence of the extreme value.
+            Some(((extreme, extreme_index), count)) => {
+                let extreme = if replaces(&value, &extreme) {
+                    (value, index)
+                } else {
+                    (extreme, extreme_index)
+                };
+                Some((extreme, count + 1))
+            }
+        })
+        .ok_or(ActiveStorageError::EmptyArray { operation })?;
+    deadline::check()?;
+    let index = index
+        .as_array_view()
+        .iter()
+        .map(|&index| i64::try_from(index))
+        .collect::<Result<Vec<_>, _>>()?;
+    let body = Bytes::copy_from_slice(index.as_bytes());
+    let count = i64::try_from(count)?;
+    Ok(
+        models::Response::new(body, models::DType::Int64, vec![index.len()], count)
+            .with_missing(i64::try_from(sliced.len())? - count),
+    )
+}
+
+/// Return the index of the first occurrence of the maximum of selected elements in the array.
+///
+/// The result is an int64 array containing the index of the maximum in each dimension of the
+/// selection. Missing data and NaN values are ignored.
+pub struct ArgMax {}
+
+impl NumOperation for ArgMax {
+    const NAME: &'static str = "argmax";
+
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        arg_extreme::<T>(request_data, data, "argmax", |value, max| value > max)
+    }
+}
+
+/// Return the index of the first occurrence of the minimum of selected elements in the array.
+///
+/// The result is an int64 array containing the index of the minimum in each dimension of the
+/// selection. Missing data and NaN values are ignored.
+pub struct ArgMin {}
+
+impl NumOperation for ArgMin {
+    const NAME: &'static str = "argmin";
+
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        arg_extreme::<T>(request_data, data, "argmin", |value, min| value < min)
+    }
+}
+
+/// Return the minimum and maximum of selected elements in the array, and their indices.
+///
+/// The result is an array containing the minimum and maximum, in that order, and the indices of
+/// their first occurrences within the selection. Missing data and NaN values are ignored, and are
+/// both counted as missing.
+pub struct Extrema {}
+
+impl NumOperation for Extrema {
+    const NAME: &'static str = "extrema";
+
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        mut data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let array = array::build_array::<T>(request_data, &mut data)?;
+        let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
+        let sliced = array.slice(slice_info);
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let valid = missing.as_ref().map(missing_filter);
+        let ((min, argmin), (max, argmax), count) = deadline::checkpoints(sliced.indexed_iter())
+            .filter(|(_, value)| valid.as_ref().map_or(true, |valid| valid(value)))
+            .filter(|(_, value)| !value.to_f64().is_some_and(f64::is_nan))
+            .fold(None, |extrema, (index, &value)| match extrema {
+                None => Some(((value, index.clone()), (value, index), 1usize)),
+                Some(((min, argmin), (max, argmax), count)) => {
+                    // Keep the first occurrence of each extreme value.
+                    let min = if value < min {
+                        (value, index.clone())
+                    } else {
+                        (min, argmin)
+                    };
+                    let max = if value > max {
+                        (value, index)
+                    } else {
+                        (max, argmax)
+                    };
+                    Some((min, max, count + 1))
+                }
+            })
+            .ok_or(ActiveStorageError::EmptyArray {
+                operation: "extrema",
+            })?;
+        deadline::check()?;
+        let body = Bytes::copy_from_slice([min, max].as_bytes());
+        let count = i64::try_from(count)?;
+        let mut response = models::Response::new(body, request_data.dtype, vec![2], count)
+            .with_missing(i64::try_from(sliced.len())? - count);
+        response.indices = Some(vec![
+            argmin.as_array_view().to_vec(),
+            argmax.as_array_view().to_vec(),
+        ]);
+        Ok(response)
     }
 }
 
@@ -79,6 +813,10 @@ impl NumOperation for Count {
 pub struct Max {}
 
 impl NumOperation for Max {
+    const NAME: &'static str = "max";
+
+    const NAN_POLICY: bool = true;
+
     fn execute_t<T: Element>(
         request_data: &models::RequestData,
         mut data: Vec<u8>,
@@ -86,44 +824,69 @@ impl NumOperation for Max {
         let array = array::build_array::<T>(request_data, &mut data)?;
         let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
         let sliced = array.slice(slice_info);
-        let (max, count) = if let Some(missing) = &request_data.missing {
-            let missing = Missing::<T>::try_from(missing)?;
-            // Use a fold to simultaneously max and count the non-missing data.
-            // TODO: separate float impl?
-            // TODO: inifinite/NaN
-            let (max, count) = sliced
-                .iter()
-                .copied()
-                .filter(missing_filter(&missing))
-                .fold((None, 0), |(a, count), b| {
-                    let max = match (a, b) {
-                        (None, b) => Some(b), //FIXME: if b.is_finite() { Some(b) } else { None },
-                        (Some(a), b) => Some(std::cmp::max_by(a, b, |x, y| {
-                            x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Greater)
-                        })),
-                    };
-                    (max, count + 1)
-                });
-            let max = max.ok_or(ActiveStorageError::EmptyArray { operation: "max" })?;
-            (max, count)
-        } else {
-            let max = *sliced.max().map_err(|err| match err {
-                MinMaxError::EmptyInput => ActiveStorageError::EmptyArray { operation: "max" },
-                MinMaxError::UndefinedOrder => panic!("unexpected undefined order error for max"),
-            })?;
-            let count = sliced.len();
-            (max, count)
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let valid = reduction_filter(&sliced, missing.as_ref(), request_data.nan_policy, "max")?;
+        let values = deadline::checkpoints(sliced.iter().copied());
+        let (max, count) = match valid {
+            Some(valid) => extremum(values.filter(valid), std::cmp::Ordering::Greater),
+            None => extremum(values, std::cmp::Ordering::Greater),
         };
+        deadline::check()?;
+        let max = max.ok_or(ActiveStorageError::EmptyArray { operation: "max" })?;
         let count = i64::try_from(count)?;
         let body = max.as_bytes();
         // Need to copy to provide ownership to caller.
         let body = Bytes::copy_from_slice(body);
-        Ok(models::Response::new(
-            body,
-            request_data.dtype,
-            vec![],
-            count,
-        ))
+        Ok(
+            models::Response::new(body, request_data.dtype, vec![], count)
+                .with_missing(i64::try_from(sliced.len())? - count),
+        )
+    }
+}
+
+/// Return the mean of selected elements in the array.
+///
+/// The mean is computed in float64 and returned as a float64 scalar, which is NaN if all of the
+/// selected elements are missing. This avoids separate sum and count requests, which would each
+/// download the data.
+pub struct Mean {}
+
+impl NumOperation for Mean {
+    const NAME: &'static str = "mean";
+
+    const NAN_POLICY: bool = true;
+
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        mut data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let array = array::build_array::<T>(request_data, &mut data)?;
+        let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
+        let sliced = array.slice(slice_info);
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let valid = reduction_filter(&sliced, missing.as_ref(), request_data.nan_policy, "mean")?;
+        // The selection is aggregated as a single bucket, excluding elements that are not valid.
+        let values = sliced
+            .iter()
+            .filter(|value| valid.as_ref().map_or(true, |valid| valid(value)));
+        let (body, dtype, count, _) = aggregate_buckets(
+            std::iter::once((0, values)),
+            1,
+            None,
+            models::Aggregation::Mean,
+            request_data,
+            "mean",
+        )?;
+        Ok(models::Response::new(body, dtype, vec![], count)
+            .with_missing(i64::try_from(sliced.len())? - count))
     }
 }
 
@@ -131,6 +894,10 @@ impl NumOperation for Max {
 pub struct Min {}
 
 impl NumOperation for Min {
+    const NAME: &'static str = "min";
+
+    const NAN_POLICY: bool = true;
+
     fn execute_t<T: Element>(
         request_data: &models::RequestData,
         mut data: Vec<u8>,
@@ -138,58 +905,51 @@ impl NumOperation for Min {
         let array = array::build_array::<T>(request_data, &mut data)?;
         let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
         let sliced = array.slice(slice_info);
-        let (min, count) = if let Some(missing) = &request_data.missing {
-            let missing = Missing::<T>::try_from(missing)?;
-            // Use a fold to simultaneously min and count the non-missing data.
-            // TODO: separate float impl?
-            // TODO: inifinite/NaN
-            let (min, count) = sliced
-                .iter()
-                .copied()
-                .filter(missing_filter(&missing))
-                .fold((None, 0), |(a, count), b| {
-                    let min = match (a, b) {
-                        (None, b) => Some(b), //FIXME: if b.is_finite() { Some(b) } else { None },
-                        (Some(a), b) => Some(std::cmp::min_by(a, b, |x, y| {
-                            x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Less)
-                        })),
-                    };
-                    (min, count + 1)
-                });
-            let min = min.ok_or(ActiveStorageError::EmptyArray { operation: "min" })?;
-            (min, count)
-        } else {
-            let min = *sliced.min().map_err(|err| match err {
-                MinMaxError::EmptyInput => ActiveStorageError::EmptyArray { operation: "min" },
-                MinMaxError::UndefinedOrder => panic!("unexpected undefined order error for min"),
-            })?;
-            let count = sliced.len();
-            (min, count)
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let valid = reduction_filter(&sliced, missing.as_ref(), request_data.nan_policy, "min")?;
+        let values = deadline::checkpoints(sliced.iter().copied());
+        let (min, count) = match valid {
+            Some(valid) => extremum(values.filter(valid), std::cmp::Ordering::Less),
+            None => extremum(values, std::cmp::Ordering::Less),
         };
+        deadline::check()?;
+        let min = min.ok_or(ActiveStorageError::EmptyArray { operation: "min" })?;
         let count = i64::try_from(count)?;
         let body = min.as_bytes();
         // Need to copy to provide ownership to caller.
         let body = Bytes::copy_from_slice(body);
-        Ok(models::Response::new(
-            body,
-            request_data.dtype,
-            vec![],
-            count,
-        ))
+        Ok(
+            models::Response::new(body, request_data.dtype, vec![], count)
+                .with_missing(i64::try_from(sliced.len())? - count),
+        )
     }
 }
 
 /// Return all selected elements in the array.
 pub struct Select {}
 
-impl NumOperation for Select {
-    fn execute_t<T: Element>(
+/// Maximum size in bytes of each chunk of a streamed select response body.
+pub const STREAM_CHUNK_SIZE: usize = 1 << 20;
+
+impl Select {
+    /// Returns a response for the selection with an empty body, and a view of the selected
+    /// elements that yields them in the order of the response body when iterated.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_data`: RequestData object for the request
+    /// * `array`: Array to select from
+    fn select<'a, T: Element>(
         request_data: &models::RequestData,
-        mut data: Vec<u8>,
-    ) -> Result<models::Response, ActiveStorageError> {
-        let array = array::build_array::<T>(request_data, &mut data)?;
+        array: ArrayView<'a, T, IxDyn>,
+    ) -> Result<(models::Response, ArrayView<'a, T, IxDyn>), ActiveStorageError> {
+        let standard_layout = array.is_standard_layout();
         let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
-        let sliced = array.slice(slice_info);
+        let sliced = array.slice_move(slice_info);
         let count = if let Some(missing) = &request_data.missing {
             let missing = Missing::<T>::try_from(missing)?;
             count_non_missing(&sliced, &missing)?
@@ -197,30 +957,293 @@ impl NumOperation for Select {
             sliced.len()
         };
         let count = i64::try_from(count)?;
+        let missing = i64::try_from(sliced.len())? - count;
         let shape = sliced.shape().to_vec();
+        let response = models::Response::new(Bytes::new(), request_data.dtype, shape, count)
+            .with_missing(missing);
         // Transpose Fortran ordered arrays before iterating.
-        let body = if !array.is_standard_layout() {
-            let sliced_ordered = sliced.t();
-            sliced_ordered.iter().copied().collect::<Vec<T>>()
+        let elements = if standard_layout {
+            sliced
         } else {
-            sliced.iter().copied().collect::<Vec<T>>()
+            sliced.reversed_axes()
         };
-        let body = body.as_bytes();
+        Ok((response, elements))
+    }
+
+    /// Returns the body of a select response containing the selected elements.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements`: View of the selected elements, as returned by [Select::select]
+    fn body<T: Element>(elements: ArrayView<T, IxDyn>) -> Result<Bytes, ActiveStorageError> {
+        let body = deadline::checkpoints(elements.iter().copied()).collect::<Vec<T>>();
+        deadline::check()?;
         // Need to copy to provide ownership to caller.
-        let body = Bytes::copy_from_slice(body);
-        Ok(models::Response::new(
-            body,
-            request_data.dtype,
-            shape,
-            count,
-        ))
+        Ok(Bytes::copy_from_slice(body.as_bytes()))
+    }
+
+    /// Execute a select operation, streaming the response body if it is larger than a threshold.
+    ///
+    /// The response is passed to `respond` once the shape and counts of the selection are known.
+    /// If the response body would be larger than `threshold` bytes, the response has an empty
+    /// body, and the body is instead passed to `send` in chunks of up to [STREAM_CHUNK_SIZE]
+    /// bytes. This avoids holding both the data and the selected elements in memory. Streaming
+    /// stops early if either function returns false, indicating that the client has gone away.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_data`: RequestData object for the request
+    /// * `data`: [`Vec<u8>`] containing data to operate on
+    /// * `threshold`: Size in bytes above which the response body is streamed
+    /// * `respond`: Function called with the response
+    /// * `send`: Function called with each chunk of a streamed response body
+    pub fn stream(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+        threshold: usize,
+        respond: impl FnOnce(models::Response) -> bool,
+        send: impl FnMut(Bytes) -> bool,
+    ) -> Result<(), ActiveStorageError> {
+        match request_data.dtype {
+            models::DType::Int8 => {
+                Self::stream_t::<i8>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Int16 => {
+                Self::stream_t::<i16>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Int32 => {
+                Self::stream_t::<i32>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Int64 => {
+                Self::stream_t::<i64>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Uint8 => {
+                Self::stream_t::<u8>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Uint16 => {
+                Self::stream_t::<u16>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Uint32 => {
+                Self::stream_t::<u32>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Uint64 => {
+                Self::stream_t::<u64>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Float32 => {
+                Self::stream_t::<f32>(request_data, data, threshold, respond, send)
+            }
+            models::DType::Float64 => {
+                Self::stream_t::<f64>(request_data, data, threshold, respond, send)
+            }
+        }
+    }
+
+    /// Execute a select operation on elements of type `T`, streaming large response bodies.
+    ///
+    /// See [Select::stream].
+    fn stream_t<T: Element>(
+        request_data: &models::RequestData,
+        mut data: Vec<u8>,
+        threshold: usize,
+        respond: impl FnOnce(models::Response) -> bool,
+        mut send: impl FnMut(Bytes) -> bool,
+    ) -> Result<(), ActiveStorageError> {
+        let array = array::build_array::<T>(request_data, &mut data)?;
+        let (response, elements) = Self::select(request_data, array)?;
+        if elements.len() * std::mem::size_of::<T>() <= threshold {
+            let body = Self::body(elements)?;
+            respond(models::Response { body, ..response });
+            return Ok(());
+        }
+        if !respond(response) {
+            return Ok(());
+        }
+        let chunk_len = (STREAM_CHUNK_SIZE / std::mem::size_of::<T>()).max(1);
+        let mut chunk = Vec::with_capacity(chunk_len);
+        for element in elements.iter() {
+            chunk.push(*element);
+            if chunk.len() == chunk_len {
+                if !send(Bytes::copy_from_slice(chunk.as_bytes())) {
+                    return Ok(());
+                }
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            send(Bytes::copy_from_slice(chunk.as_bytes()));
+        }
+        Ok(())
+    }
+}
+
+impl NumOperation for Select {
+    const NAME: &'static str = "select";
+
+    fn execute_t<T: Element>(
+        request_data: &models::RequestData,
+        mut data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let array = array::build_array::<T>(request_data, &mut data)?;
+        let (response, elements) = Self::select(request_data, array)?;
+        Ok(models::Response {
+            body: Self::body(elements)?,
+            ..response
+        })
+    }
+}
+
+/// Running mean and sum of squared deviations from the mean, updated using Welford's algorithm.
+#[derive(Clone, Copy, Debug, Default)]
+struct Moments {
+    /// Number of values
+    count: f64,
+    /// Mean of the values
+    mean: f64,
+    /// Sum of squared deviations of the values from their mean
+    m2: f64,
+}
+
+impl Moments {
+    /// Add a value.
+    fn push(&mut self, value: f64) {
+        self.count += 1.0;
+        let delta = value - self.mean;
+        self.mean += delta / self.count;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Returns the variance, which is NaN if there are no more values than degrees of freedom.
+    ///
+    /// # Arguments
+    ///
+    /// * `ddof`: Delta degrees of freedom
+    fn variance(&self, ddof: u32) -> f64 {
+        let divisor = self.count - f64::from(ddof);
+        if divisor > 0.0 {
+            self.m2 / divisor
+        } else {
+            f64::NAN
+        }
+    }
+}
+
+/// Returns the variance of the selected elements in the array and the numbers of non-missing and
+/// missing elements.
+///
+/// The variance is computed in float64 in a single pass using Welford's algorithm.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `data`: Data to operate on
+fn variance(
+    request_data: &models::RequestData,
+    data: Vec<u8>,
+) -> Result<(f64, i64, i64), ActiveStorageError> {
+    // Convert runtime data type into concrete types.
+    match request_data.dtype {
+        models::DType::Int8 => variance_t::<i8>(request_data, data),
+        models::DType::Int16 => variance_t::<i16>(request_data, data),
+        models::DType::Int32 => variance_t::<i32>(request_data, data),
+        models::DType::Int64 => variance_t::<i64>(request_data, data),
+        models::DType::Uint8 => variance_t::<u8>(request_data, data),
+        models::DType::Uint16 => variance_t::<u16>(request_data, data),
+        models::DType::Uint32 => variance_t::<u32>(request_data, data),
+        models::DType::Uint64 => variance_t::<u64>(request_data, data),
+        models::DType::Float32 => variance_t::<f32>(request_data, data),
+        models::DType::Float64 => variance_t::<f64>(request_data, data),
+    }
+}
+
+/// Returns the variance of the selected elements in the array, for a concrete element type.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `data`: Data to operate on
+fn variance_t<T: Element>(
+    request_data: &models::RequestData,
+    mut data: Vec<u8>,
+) -> Result<(f64, i64, i64), ActiveStorageError> {
+    let array = array::build_array::<T>(request_data, &mut data)?;
+    let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
+    let sliced = array.slice(slice_info);
+    let missing = request_data
+        .missing
+        .as_ref()
+        .map(Missing::<T>::try_from)
+        .transpose()?;
+    let f = |moments: &mut Moments, value: T| moments.push(value.to_f64().unwrap_or(f64::NAN));
+    let (moments, counts, missing) = fold_buckets(
+        std::iter::once((0, sliced.iter())),
+        1,
+        missing.as_ref(),
+        Moments::default(),
+        f,
+    )?;
+    let variance = moments[0].variance(request_data.ddof.unwrap_or(0));
+    Ok((variance, counts[0], missing))
+}
+
+/// Return the standard deviation of selected elements in the array.
+///
+/// The standard deviation is the square root of the variance computed by [Var], and is returned
+/// as a float64 scalar.
+pub struct Std {}
+
+impl Operation for Std {
+    const NAME: &'static str = "std";
+
+    const DDOF: bool = true;
+
+    fn execute(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let (variance, count, missing) = variance(request_data, data)?;
+        let body = Bytes::copy_from_slice(variance.sqrt().as_bytes());
+        Ok(
+            models::Response::new(body, models::DType::Float64, vec![], count)
+                .with_missing(missing),
+        )
     }
 }
 
+/// Returns the compensated sum of some values, and the number of values.
+///
+/// Uses Neumaier's variant of Kahan summation in float64. Returns the sum and the accumulated
+/// rounding error, which should be added to the sum after summing any other partial sums.
+///
+/// # Arguments
+///
+/// * `values`: Values to sum
+fn compensated_sum<T: Element>(values: impl Iterator<Item = T>) -> (f64, f64, usize) {
+    values.fold((0.0, 0.0, 0), |(sum, compensation, count), value| {
+        let value = value.to_f64().unwrap_or(f64::NAN);
+        let total = sum + value;
+        let error = if f64::abs(sum) >= f64::abs(value) {
+            (sum - total) + value
+        } else {
+            (value - total) + sum
+        };
+        (total, compensation + error, count + 1)
+    })
+}
+
 /// Return the sum of selected elements in the array.
+///
+/// If partial aggregates are requested for floating point data, returns a float64 array
+/// containing the sum and the compensation for its rounding error, allowing the client to merge
+/// sums exactly.
 pub struct Sum {}
 
 impl NumOperation for Sum {
+    const NAME: &'static str = "sum";
+
+    const NAN_POLICY: bool = true;
+
+    const PARTIAL: bool = true;
+
     fn execute_t<T: Element>(
         request_data: &models::RequestData,
         mut data: Vec<u8>,
@@ -228,14 +1251,41 @@ impl NumOperation for Sum {
         let array = array::build_array::<T>(request_data, &mut data)?;
         let slice_info = array::build_slice_info::<T>(&request_data.selection, array.shape());
         let sliced = array.slice(slice_info);
-        let (sum, count) = if let Some(missing) = &request_data.missing {
-            let missing = Missing::<T>::try_from(missing)?;
-            // Use a fold to simultaneously sum and count the non-missing data.
-            sliced
-                .iter()
-                .copied()
-                .filter(missing_filter(&missing))
-                .fold((T::zero(), 0), |(a, count), b| (a + b, count + 1))
+        let missing = request_data
+            .missing
+            .as_ref()
+            .map(Missing::<T>::try_from)
+            .transpose()?;
+        let valid = reduction_filter(&sliced, missing.as_ref(), request_data.nan_policy, "sum")?;
+        if request_data.partial
+            && matches!(
+                request_data.dtype,
+                models::DType::Float32 | models::DType::Float64
+            )
+        {
+            let values = deadline::checkpoints(sliced.iter().copied());
+            let (sum, compensation, count) = match valid {
+                Some(valid) => compensated_sum(values.filter(valid)),
+                None => compensated_sum(values),
+            };
+            deadline::check()?;
+            let body = [sum, compensation];
+            let count = i64::try_from(count)?;
+            return Ok(models::Response::new(
+                Bytes::copy_from_slice(body.as_bytes()),
+                models::DType::Float64,
+                vec![2],
+                count,
+            )
+            .with_missing(i64::try_from(sliced.len())? - count));
+        }
+        let (sum, count) = if let Some(valid) = valid {
+            // Use a fold to simultaneously sum and count the valid data.
+            let (sum, count) = deadline::checkpoints(sliced.iter().copied())
+                .filter(valid)
+                .fold((T::zero(), 0), |(a, count), b| (a + b, count + 1));
+            deadline::check()?;
+            (sum, count)
         } else {
             (sliced.sum(), sliced.len())
         };
@@ -243,12 +1293,65 @@ impl NumOperation for Sum {
         let body = sum.as_bytes();
         // Need to copy to provide ownership to caller.
         let body = Bytes::copy_from_slice(body);
-        Ok(models::Response::new(
-            body,
-            request_data.dtype,
-            vec![],
-            count,
-        ))
+        Ok(
+            models::Response::new(body, request_data.dtype, vec![], count)
+                .with_missing(i64::try_from(sliced.len())? - count),
+        )
+    }
+}
+
+/// Return the variance of selected elements in the array.
+///
+/// The variance is computed in float64 using a numerically stable single pass, and returned as a
+/// float64 scalar. The divisor is the number of non-missing elements minus the request's
+/// [ddof](models::RequestData::ddof), which defaults to zero. The result is NaN if the divisor is
+/// not positive.
+pub struct Var {}
+
+impl Operation for Var {
+    const NAME: &'static str = "var";
+
+    const DDOF: bool = true;
+
+    fn execute(
+        request_data: &models::RequestData,
+        data: Vec<u8>,
+    ) -> Result<models::Response, ActiveStorageError> {
+        let (variance, count, missing) = variance(request_data, data)?;
+        let body = Bytes::copy_from_slice(variance.as_bytes());
+        Ok(
+            models::Response::new(body, models::DType::Float64, vec![], count)
+                .with_missing(missing),
+        )
+    }
+}
+
+/// Visit the operation with a name, as used in the API.
+///
+/// Returns `None` if there is no operation with the name.
+///
+/// # Arguments
+///
+/// * `name`: Name of the operation
+/// * `visitor`: Visitor to call with the type of the operation
+pub fn visit<V: OperationVisitor>(name: &str, visitor: V) -> Option<V::Output> {
+    match name {
+        "argmax" => Some(visitor.visit::<ArgMax>()),
+        "argmin" => Some(visitor.visit::<ArgMin>()),
+        "coarsen" => Some(visitor.visit::<Coarsen>()),
+        "count" => Some(visitor.visit::<Count>()),
+        "ensemble" => Some(visitor.visit::<Ensemble>()),
+        "expr" => Some(visitor.visit::<Expr>()),
+        "extrema" => Some(visitor.visit::<Extrema>()),
+        "groupby" => Some(visitor.visit::<GroupBy>()),
+        "max" => Some(visitor.visit::<Max>()),
+        "mean" => Some(visitor.visit::<Mean>()),
+        "min" => Some(visitor.visit::<Min>()),
+        "select" => Some(visitor.visit::<Select>()),
+        "std" => Some(visitor.visit::<Std>()),
+        "sum" => Some(visitor.visit::<Sum>()),
+        "var" => Some(visitor.visit::<Var>()),
+        _ => None,
     }
 }
 
@@ -273,6 +1376,7 @@ mod tests {
         assert_eq!(models::DType::Int64, response.dtype);
         assert_eq!(vec![0; 0], response.shape);
         assert_eq!(expected, response.count);
+        assert_eq!(0, response.missing);
     }
 
     #[test]
@@ -290,6 +1394,139 @@ mod tests {
         assert_eq!(models::DType::Int64, response.dtype);
         assert_eq!(vec![0; 0], response.shape);
         assert_eq!(expected, response.count);
+        assert_eq!(1, response.missing);
+    }
+
+    #[test]
+    fn argmax_i64_1d() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Int64;
+        let data = [3_i64, 1, 4, 1, 5, 9, 2, 9].as_bytes();
+        let response = ArgMax::execute(&request_data, data.into()).unwrap();
+        // Ties resolve to the first occurrence.
+        assert_eq!([5_i64].as_bytes(), response.body);
+        assert_eq!(models::DType::Int64, response.dtype);
+        assert_eq!(vec![1], response.shape);
+        assert_eq!(8, response.count);
+        assert_eq!(0, response.missing);
+    }
+
+    #[test]
+    fn argmin_u32_2d_missing_selection() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint32;
+        request_data.shape = Some(vec![3, 3]);
+        request_data.missing = Some(Missing::MissingValue(0.into()));
+        request_data.selection = Some(vec![
+            models::Slice::new(1, 3, 1).into(),
+            models::Slice::new(0, 3, 1).into(),
+        ]);
+        let data = [1_u32, 100, 2, 5, 0, 7, 6, 3, 0].as_bytes();
+        let response = ArgMin::execute(&request_data, data.into()).unwrap();
+        // Indices are relative to the selection.
+        assert_eq!([1_i64, 1].as_bytes(), response.body);
+        assert_eq!(models::DType::Int64, response.dtype);
+        assert_eq!(vec![2], response.shape);
+        assert_eq!(4, response.count);
+        assert_eq!(2, response.missing);
+    }
+
+    #[test]
+    fn argmax_f64_2d_f_order() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        request_data.shape = Some(vec![2, 3]);
+        request_data.order = Some(models::Order::F);
+        // Stored in F order, the array is [[1, 6, 3], [4, 2, 6]].
+        let data = [1.0_f64, 4.0, 6.0, 2.0, 3.0, 6.0].as_bytes();
+        let response = ArgMax::execute(&request_data, data.into()).unwrap();
+        // The index is of the first occurrence in C order of the array.
+        assert_eq!([0_i64, 1].as_bytes(), response.body);
+    }
+
+    #[test]
+    fn argmin_f32_1d_nan() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        let data = [f32::NAN, 2.0, -1.0, f32::NAN].as_bytes();
+        let response = ArgMin::execute(&request_data, data.into()).unwrap();
+        assert_eq!([2_i64].as_bytes(), response.body);
+        assert_eq!(2, response.count);
+        assert_eq!(2, response.missing);
+    }
+
+    #[test]
+    fn argmax_empty() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        let data = [f64::NAN].as_bytes();
+        let result = ArgMax::execute(&request_data, data.into());
+        assert!(matches!(
+            result,
+            Err(ActiveStorageError::EmptyArray {
+                operation: "argmax"
+            })
+        ));
+    }
+
+    #[test]
+    fn extrema_i64_1d() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Int64;
+        let data = [3_i64, 1, 4, 1, 5, 9, 2, 9].as_bytes();
+        let response = Extrema::execute(&request_data, data.into()).unwrap();
+        assert_eq!([1_i64, 9].as_bytes(), response.body);
+        assert_eq!(models::DType::Int64, response.dtype);
+        assert_eq!(vec![2], response.shape);
+        assert_eq!(8, response.count);
+        // Ties resolve to the first occurrence.
+        assert_eq!(Some(vec![vec![1], vec![5]]), response.indices);
+    }
+
+    #[test]
+    fn extrema_u32_2d_missing_selection() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint32;
+        request_data.shape = Some(vec![3, 3]);
+        request_data.missing = Some(Missing::MissingValue(0.into()));
+        request_data.selection = Some(vec![
+            models::Slice::new(1, 3, 1).into(),
+            models::Slice::new(0, 3, 1).into(),
+        ]);
+        let data = [1_u32, 100, 2, 5, 0, 7, 6, 3, 0].as_bytes();
+        let response = Extrema::execute(&request_data, data.into()).unwrap();
+        assert_eq!([3_u32, 7].as_bytes(), response.body);
+        assert_eq!(vec![2], response.shape);
+        assert_eq!(4, response.count);
+        assert_eq!(2, response.missing);
+        // Indices are relative to the selection.
+        assert_eq!(Some(vec![vec![1, 1], vec![0, 2]]), response.indices);
+    }
+
+    #[test]
+    fn extrema_f32_1d_nan() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        let data = [f32::NAN, 2.0, -1.0, f32::NAN].as_bytes();
+        let response = Extrema::execute(&request_data, data.into()).unwrap();
+        assert_eq!([-1.0_f32, 2.0].as_bytes(), response.body);
+        assert_eq!(2, response.count);
+        assert_eq!(2, response.missing);
+        assert_eq!(Some(vec![vec![2], vec![1]]), response.indices);
+    }
+
+    #[test]
+    fn extrema_empty() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        let data = [f64::NAN].as_bytes();
+        let result = Extrema::execute(&request_data, data.into());
+        assert!(matches!(
+            result,
+            Err(ActiveStorageError::EmptyArray {
+                operation: "extrema"
+            })
+        ));
     }
 
     #[test]
@@ -361,6 +1598,61 @@ mod tests {
         assert_eq!(2, response.count);
     }
 
+    #[test]
+    fn mean_i32_1d() {
+        let request_data = test_utils::get_test_request_data();
+        let data = [1_i32, 2, 3, 6].as_bytes();
+        let response = Mean::execute(&request_data, data.into()).unwrap();
+        assert_eq!(3.0_f64.as_bytes(), response.body);
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(4, response.count);
+        assert_eq!(0, response.missing);
+    }
+
+    #[test]
+    fn mean_i16_1d_with_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Int16;
+        request_data.missing = Some(Missing::MissingValue((-32768).into()));
+        let data = [-300_i16, -32768, 100, 500].as_bytes();
+        let response = Mean::execute(&request_data, data.into()).unwrap();
+        assert_eq!(100.0_f64.as_bytes(), response.body);
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(3, response.count);
+        assert_eq!(1, response.missing);
+    }
+
+    #[test]
+    fn mean_f32_2d_with_selection_and_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.shape = Some(vec![2, 3]);
+        request_data.selection = Some(vec![
+            models::Slice::new(0, 2, 1).into(),
+            models::Slice::new(1, 3, 1).into(),
+        ]);
+        request_data.missing = Some(Missing::MissingValue((-1).into()));
+        let data = [9_f32, 1.0, 2.0, 9.0, -1.0, 6.0].as_bytes();
+        let response = Mean::execute(&request_data, data.into()).unwrap();
+        assert_eq!(3.0_f64.as_bytes(), response.body);
+        assert_eq!(3, response.count);
+        assert_eq!(1, response.missing);
+    }
+
+    #[test]
+    fn mean_u64_all_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint64;
+        request_data.missing = Some(Missing::MissingValue(0.into()));
+        let data = [0_u64, 0].as_bytes();
+        let response = Mean::execute(&request_data, data.into()).unwrap();
+        let mean = f64::from_ne_bytes(response.body[..].try_into().unwrap());
+        assert!(mean.is_nan());
+        assert_eq!(0, response.count);
+        assert_eq!(2, response.missing);
+    }
+
     #[test]
     fn min_u64_1d() {
         let mut request_data = test_utils::get_test_request_data();
@@ -427,70 +1719,182 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "unexpected undefined order error for min")]
-    fn min_f32_1d_nan() {
+    fn min_f32_1d_nan() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        let floats = [1.0, f32::NAN];
+        let data = floats.as_bytes();
+        let response = Min::execute(&request_data, data.into()).unwrap();
+        let expected = f32::NAN;
+        assert_eq!(expected.as_bytes(), response.body);
+        assert_eq!(4, response.body.len());
+        assert_eq!(models::DType::Float32, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(2, response.count);
+    }
+
+    #[test]
+    fn min_f32_1d_nan_first() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        let floats = [f32::NAN, 1.0];
+        let data = floats.as_bytes();
+        let response = Min::execute(&request_data, data.into()).unwrap();
+        let expected = f32::NAN;
+        assert_eq!(expected.as_bytes(), response.body);
+        assert_eq!(4, response.body.len());
+        assert_eq!(models::DType::Float32, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(2, response.count);
+    }
+
+    #[test]
+    fn min_f32_1d_nan_missing_value() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.missing = Some(Missing::MissingValue(DValue::from_f64(42.0).unwrap()));
+        let floats = [1.0, f32::NAN];
+        let data = floats.as_bytes();
+        let response = Min::execute(&request_data, data.into()).unwrap();
+        let expected = f32::NAN;
+        assert_eq!(expected.as_bytes(), response.body);
+        assert_eq!(4, response.body.len());
+        assert_eq!(models::DType::Float32, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(2, response.count);
+    }
+
+    #[test]
+    fn min_f32_1d_nan_first_missing_value() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.missing = Some(Missing::MissingValue(DValue::from_f64(42.0).unwrap()));
+        let floats = [f32::NAN, 1.0];
+        let data = floats.as_bytes();
+        let response = Min::execute(&request_data, data.into()).unwrap();
+        let expected = f32::NAN;
+        assert_eq!(expected.as_bytes(), response.body);
+        assert_eq!(4, response.body.len());
+        assert_eq!(models::DType::Float32, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(2, response.count);
+    }
+
+    #[test]
+    fn min_f32_1d_nan_ignore() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.nan_policy = Some(models::NanPolicy::Ignore);
+        let floats = [f32::NAN, 1.0, f32::NAN, 2.0];
+        let data = floats.as_bytes();
+        let response = Min::execute(&request_data, data.into()).unwrap();
+        assert_eq!(1.0_f32.as_bytes(), response.body);
+        assert_eq!(2, response.count);
+        assert_eq!(2, response.missing);
+    }
+
+    #[test]
+    fn max_f64_1d_nan_propagate() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        request_data.nan_policy = Some(models::NanPolicy::Propagate);
+        let floats = [1.0, f64::NAN, 2.0];
+        let data = floats.as_bytes();
+        let response = Max::execute(&request_data, data.into()).unwrap();
+        let max = f64::from_ne_bytes(response.body[..].try_into().unwrap());
+        assert!(max.is_nan());
+        assert_eq!(3, response.count);
+    }
+
+    #[test]
+    fn max_f64_1d_nan_ignore_all() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        request_data.nan_policy = Some(models::NanPolicy::Ignore);
+        let floats = [f64::NAN, f64::NAN];
+        let data = floats.as_bytes();
+        assert!(matches!(
+            Max::execute(&request_data, data.into()),
+            Err(ActiveStorageError::EmptyArray { operation: "max" })
+        ));
+    }
+
+    #[test]
+    fn max_f32_1d_nan_error() {
         let mut request_data = test_utils::get_test_request_data();
         request_data.dtype = models::DType::Float32;
+        request_data.nan_policy = Some(models::NanPolicy::Error);
         let floats = [1.0, f32::NAN];
         let data = floats.as_bytes();
-        let response = Min::execute(&request_data, data.into()).unwrap();
-        let expected = 1.0_f32;
-        assert_eq!(expected.as_bytes(), response.body);
-        assert_eq!(4, response.body.len());
-        assert_eq!(models::DType::Float32, response.dtype);
-        assert_eq!(vec![0; 0], response.shape);
-        assert_eq!(2, response.count);
+        assert!(matches!(
+            Max::execute(&request_data, data.into()),
+            Err(ActiveStorageError::NanValue { operation: "max" })
+        ));
+        // NaN elements that are missing are not an error.
+        request_data.missing = Some(Missing::ValidMin(DValue::from_f64(0.0).unwrap()));
+        let response = Max::execute(&request_data, data.into()).unwrap();
+        assert_eq!(1.0_f32.as_bytes(), response.body);
+        assert_eq!(1, response.missing);
     }
 
     #[test]
-    #[should_panic(expected = "unexpected undefined order error for min")]
-    fn min_f32_1d_nan_first() {
+    fn count_f32_1d_nan_ignore_with_missing() {
         let mut request_data = test_utils::get_test_request_data();
         request_data.dtype = models::DType::Float32;
-        let floats = [f32::NAN, 1.0];
+        request_data.missing = Some(Missing::MissingValue(DValue::from_f64(42.0).unwrap()));
+        let floats = [1.0, f32::NAN, 42.0, 2.0];
         let data = floats.as_bytes();
-        let response = Min::execute(&request_data, data.into()).unwrap();
-        let expected = 1.0_f32;
-        assert_eq!(expected.as_bytes(), response.body);
-        assert_eq!(4, response.body.len());
-        assert_eq!(models::DType::Float32, response.dtype);
-        assert_eq!(vec![0; 0], response.shape);
+        let response = Count::execute(&request_data, data.into()).unwrap();
+        assert_eq!(3_i64.as_bytes(), response.body);
+        request_data.nan_policy = Some(models::NanPolicy::Ignore);
+        let response = Count::execute(&request_data, data.into()).unwrap();
+        assert_eq!(2_i64.as_bytes(), response.body);
         assert_eq!(2, response.count);
+        assert_eq!(2, response.missing);
     }
 
     #[test]
-    fn min_f32_1d_nan_missing_value() {
+    fn mean_f64_1d_nan_ignore() {
         let mut request_data = test_utils::get_test_request_data();
-        request_data.dtype = models::DType::Float32;
-        request_data.missing = Some(Missing::MissingValue(DValue::from_f64(42.0).unwrap()));
-        let floats = [1.0, f32::NAN];
+        request_data.dtype = models::DType::Float64;
+        let floats = [1.0, f64::NAN, 3.0];
         let data = floats.as_bytes();
-        let response = Min::execute(&request_data, data.into()).unwrap();
-        let expected = 1.0_f32;
-        assert_eq!(expected.as_bytes(), response.body);
-        assert_eq!(4, response.body.len());
-        assert_eq!(models::DType::Float32, response.dtype);
-        assert_eq!(vec![0; 0], response.shape);
+        let response = Mean::execute(&request_data, data.into()).unwrap();
+        let mean = f64::from_ne_bytes(response.body[..].try_into().unwrap());
+        assert!(mean.is_nan());
+        request_data.nan_policy = Some(models::NanPolicy::Ignore);
+        let response = Mean::execute(&request_data, data.into()).unwrap();
+        assert_eq!(2.0_f64.as_bytes(), response.body);
         assert_eq!(2, response.count);
+        assert_eq!(1, response.missing);
     }
 
     #[test]
-    fn min_f32_1d_nan_first_missing_value() {
+    fn sum_f32_1d_nan_ignore_partial() {
         let mut request_data = test_utils::get_test_request_data();
         request_data.dtype = models::DType::Float32;
-        request_data.missing = Some(Missing::MissingValue(DValue::from_f64(42.0).unwrap()));
-        let floats = [f32::NAN, 1.0];
+        request_data.nan_policy = Some(models::NanPolicy::Ignore);
+        let floats = [1.0, f32::NAN, 2.0];
         let data = floats.as_bytes();
-        let response = Min::execute(&request_data, data.into()).unwrap();
-        // FIXME: Ignore NANs?
-        let expected = f32::NAN; //1.0_f32;
-        assert_eq!(expected.as_bytes(), response.body);
-        assert_eq!(4, response.body.len());
-        assert_eq!(models::DType::Float32, response.dtype);
-        assert_eq!(vec![0; 0], response.shape);
+        let response = Sum::execute(&request_data, data.into()).unwrap();
+        assert_eq!(3.0_f32.as_bytes(), response.body);
+        assert_eq!(1, response.missing);
+        request_data.partial = true;
+        let response = Sum::execute(&request_data, data.into()).unwrap();
+        assert_eq!([3.0_f64, 0.0].as_bytes(), response.body);
         assert_eq!(2, response.count);
     }
 
+    #[test]
+    fn sum_i32_1d_nan_error() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.nan_policy = Some(models::NanPolicy::Error);
+        let integers = [1_i32, 2, 3];
+        let data = integers.as_bytes();
+        let response = Sum::execute(&request_data, data.into()).unwrap();
+        assert_eq!(6_i32.as_bytes(), response.body);
+    }
+
     #[test]
     fn select_f32_1d() {
         let mut request_data = test_utils::get_test_request_data();
@@ -526,8 +1930,8 @@ mod tests {
         request_data.dtype = models::DType::Float32;
         request_data.shape = Some(vec![2, 2]);
         request_data.selection = Some(vec![
-            models::Slice::new(0, 2, 1),
-            models::Slice::new(1, 2, 1),
+            models::Slice::new(0, 2, 1).into(),
+            models::Slice::new(1, 2, 1).into(),
         ]);
         // 2x2 array, select second row of each column.
         // [[0x04030201, 0x08070605], [0x12111009, 0x16151413]]
@@ -542,6 +1946,166 @@ mod tests {
         assert_eq!(2, response.count);
     }
 
+    #[test]
+    fn select_stream_below_threshold() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let mut responses = vec![];
+        Select::stream(
+            &request_data,
+            data.clone(),
+            8,
+            |response| {
+                responses.push(response);
+                true
+            },
+            |_| panic!("body should not be streamed"),
+        )
+        .unwrap();
+        let expected = Select::execute(&request_data, data).unwrap();
+        assert_eq!(1, responses.len());
+        assert_eq!(expected.body, responses[0].body);
+        assert_eq!(expected.shape, responses[0].shape);
+        assert_eq!(expected.count, responses[0].count);
+    }
+
+    #[test]
+    fn select_stream_f_order() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint32;
+        request_data.shape = Some(vec![2, 2]);
+        request_data.order = Some(models::Order::F);
+        request_data.missing = Some(Missing::MissingValue(0x04030201.into()));
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+        let expected = Select::execute(&request_data, data.clone()).unwrap();
+        let mut responses = vec![];
+        let mut body = vec![];
+        Select::stream(
+            &request_data,
+            data,
+            0,
+            |response| {
+                responses.push(response);
+                true
+            },
+            |chunk| {
+                body.extend_from_slice(&chunk);
+                true
+            },
+        )
+        .unwrap();
+        assert_eq!(1, responses.len());
+        assert!(responses[0].body.is_empty());
+        assert_eq!(expected.shape, responses[0].shape);
+        assert_eq!(3, responses[0].count);
+        assert_eq!(1, responses[0].missing);
+        assert_eq!(expected.body, body);
+    }
+
+    #[test]
+    fn select_stream_chunks() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        let len = STREAM_CHUNK_SIZE / 8 * 2 + 1;
+        let data: Vec<u8> = (0..len as u64).flat_map(u64::to_ne_bytes).collect();
+        let mut chunks = vec![];
+        Select::stream(
+            &request_data,
+            data.clone(),
+            0,
+            |_| true,
+            |chunk| {
+                chunks.push(chunk);
+                true
+            },
+        )
+        .unwrap();
+        let sizes: Vec<usize> = chunks.iter().map(Bytes::len).collect();
+        assert_eq!(vec![STREAM_CHUNK_SIZE, STREAM_CHUNK_SIZE, 8], sizes);
+        assert_eq!(data, chunks.concat());
+    }
+
+    #[test]
+    fn select_stream_stops() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        Select::stream(
+            &request_data,
+            data,
+            0,
+            |_| false,
+            |_| panic!("body should not be streamed"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn std_f32_2d_with_selection_and_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.shape = Some(vec![2, 3]);
+        request_data.selection = Some(vec![
+            models::Slice::new(0, 2, 1).into(),
+            models::Slice::new(1, 3, 1).into(),
+        ]);
+        request_data.missing = Some(Missing::MissingValue((-1).into()));
+        let data = [9_f32, 1.0, 3.0, 9.0, -1.0, 5.0].as_bytes();
+        let response = Std::execute(&request_data, data.into()).unwrap();
+        // Variance of [1, 3, 5] is 8 / 3.
+        assert_eq!((8.0_f64 / 3.0).sqrt().as_bytes(), response.body);
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(3, response.count);
+        assert_eq!(1, response.missing);
+    }
+
+    #[test]
+    fn var_i32_1d() {
+        let request_data = test_utils::get_test_request_data();
+        let data = [1_i32, 2, 3, 4].as_bytes();
+        let response = Var::execute(&request_data, data.into()).unwrap();
+        assert_eq!(1.25_f64.as_bytes(), response.body);
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(4, response.count);
+        assert_eq!(0, response.missing);
+    }
+
+    #[test]
+    fn var_i64_1d_ddof() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Int64;
+        request_data.ddof = Some(1);
+        let data = [1_i64, 2, 3, 4].as_bytes();
+        let response = Var::execute(&request_data, data.into()).unwrap();
+        assert_eq!((5.0_f64 / 3.0).as_bytes(), response.body);
+        assert_eq!(4, response.count);
+    }
+
+    #[test]
+    fn var_f64_1d_large_offset() {
+        // A naive sum of squares loses the variance of values with a large mean.
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        let data = [1e9_f64 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0].as_bytes();
+        let response = Var::execute(&request_data, data.into()).unwrap();
+        assert_eq!(22.5_f64.as_bytes(), response.body);
+    }
+
+    #[test]
+    fn var_u32_ddof_too_large() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint32;
+        request_data.ddof = Some(2);
+        let data = [1_u32, 2].as_bytes();
+        let response = Var::execute(&request_data, data.into()).unwrap();
+        let variance = f64::from_ne_bytes(response.body[..].try_into().unwrap());
+        assert!(variance.is_nan());
+        assert_eq!(2, response.count);
+    }
+
     #[test]
     fn sum_u32_1d() {
         let mut request_data = test_utils::get_test_request_data();
@@ -556,6 +2120,18 @@ mod tests {
         assert_eq!(2, response.count);
     }
 
+    #[test]
+    fn sum_u8_1d() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint8;
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let response = Sum::execute(&request_data, data).unwrap();
+        assert_eq!([36_u8].as_bytes(), response.body);
+        assert_eq!(models::DType::Uint8, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(8, response.count);
+    }
+
     #[test]
     fn sum_u32_1d_valid_max() {
         let mut request_data = test_utils::get_test_request_data();
@@ -571,6 +2147,18 @@ mod tests {
         assert_eq!(1, response.count);
     }
 
+    #[test]
+    fn sum_u32_1d_valid_max_compute_timeout() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint32;
+        request_data.missing = Some(Missing::ValidMax((0x08070605 - 1).into()));
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let result = deadline::run(Some(std::time::Duration::ZERO), || {
+            Sum::execute(&request_data, data)
+        });
+        assert!(matches!(result, Err(ActiveStorageError::ComputeTimeout)));
+    }
+
     #[test]
     fn sum_f32_1d_infinity() {
         let mut request_data = test_utils::get_test_request_data();
@@ -601,6 +2189,376 @@ mod tests {
         assert_eq!(2, response.count);
     }
 
+    fn make_group_by(
+        axis: usize,
+        aggregation: models::Aggregation,
+        label_values: Vec<i64>,
+    ) -> models::GroupBy {
+        models::GroupBy {
+            labels: "labels".to_string(),
+            labels_dtype: models::DType::Int64,
+            labels_byte_order: None,
+            labels_offset: None,
+            labels_size: None,
+            axis,
+            aggregation,
+            label_values,
+        }
+    }
+
+    #[test]
+    fn group_by_labels() {
+        let group_by = make_group_by(0, models::Aggregation::Sum, vec![]);
+        let data = [1_i64, -1, 2].as_bytes();
+        assert_eq!(vec![1, -1, 2], GroupBy::labels(&group_by, data).unwrap());
+        let mut group_by = group_by;
+        group_by.labels_dtype = models::DType::Uint32;
+        group_by.labels_byte_order = Some(NON_NATIVE_BYTE_ORDER);
+        let data = [1_u32.swap_bytes(), 2_u32.swap_bytes()];
+        assert_eq!(
+            vec![1, 2],
+            GroupBy::labels(&group_by, data.as_bytes()).unwrap()
+        );
+        let err = GroupBy::labels(&group_by, &[0; 3]).unwrap_err();
+        assert_eq!(
+            "invalid groupby labels: size must be a multiple of labels_dtype size",
+            err.to_string()
+        );
+        group_by.labels_dtype = models::DType::Uint64;
+        group_by.labels_byte_order = None;
+        let err = GroupBy::labels(&group_by, u64::MAX.as_bytes()).unwrap_err();
+        assert_eq!("invalid groupby labels: label too large", err.to_string());
+    }
+
+    #[test]
+    fn group_by_sum_i32_2d() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![4, 2]);
+        request_data.group_by = Some(make_group_by(
+            0,
+            models::Aggregation::Sum,
+            vec![1, 0, 1, -1],
+        ));
+        let data = [1_i32, 2, 3, 4, 5, 6, 7, 8].as_bytes();
+        let response = GroupBy::execute(&request_data, data.into()).unwrap();
+        assert_eq!([7_i32, 14].as_bytes(), response.body);
+        assert_eq!(models::DType::Int32, response.dtype);
+        assert_eq!(vec![2], response.shape);
+        assert_eq!(6, response.count);
+    }
+
+    #[test]
+    fn group_by_mean_f32_2d_axis_1_with_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.shape = Some(vec![2, 3]);
+        request_data.missing = Some(Missing::MissingValue(6.into()));
+        request_data.group_by = Some(make_group_by(1, models::Aggregation::Mean, vec![0, 2, 0]));
+        let data = [1_f32, 2.0, 3.0, 4.0, 5.0, 6.0].as_bytes();
+        let response = GroupBy::execute(&request_data, data.into()).unwrap();
+        let means: Vec<f64> = response
+            .body
+            .chunks_exact(8)
+            .map(|chunk| f64::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(8.0 / 3.0, means[0]);
+        // Group 1 has no elements.
+        assert!(means[1].is_nan());
+        assert_eq!(3.5, means[2]);
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(vec![3], response.shape);
+        assert_eq!(5, response.count);
+        assert_eq!(1, response.missing);
+    }
+
+    #[test]
+    fn group_by_sum_with_indices() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![4]);
+        request_data.selection = Some(vec![models::Selector::Indices {
+            indices: vec![3, 0, -1],
+        }]);
+        request_data.group_by = Some(make_group_by(0, models::Aggregation::Sum, vec![0, 1, 1, 1]));
+        let data = [1_i32, 2, 3, 4].as_bytes().to_vec();
+        let data = array::gather(&mut request_data, data).unwrap();
+        let response = GroupBy::execute(&request_data, data).unwrap();
+        // Element 0 is in group 0, and element 3 is selected twice in group 1.
+        assert_eq!([1_i32, 8].as_bytes(), response.body);
+        assert_eq!(3, response.count);
+    }
+
+    #[test]
+    fn group_by_count_with_selection() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![4]);
+        request_data.selection = Some(vec![models::Slice::new(1, 4, 2).into()]);
+        request_data.group_by = Some(make_group_by(
+            0,
+            models::Aggregation::Count,
+            vec![0, 1, 2, 1],
+        ));
+        let data = [1_i32, 2, 3, 4].as_bytes();
+        let response = GroupBy::execute(&request_data, data.into()).unwrap();
+        // Elements 1 and 3 are selected, both in group 1.
+        assert_eq!([0_i64, 2, 0].as_bytes(), response.body);
+        assert_eq!(models::DType::Int64, response.dtype);
+        assert_eq!(2, response.count);
+    }
+
+    #[test]
+    fn group_by_wrong_number_of_labels() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.group_by = Some(make_group_by(0, models::Aggregation::Sum, vec![0]));
+        let data = [1_i32, 2].as_bytes();
+        let result = GroupBy::execute(&request_data, data.into());
+        assert!(matches!(
+            result,
+            Err(ActiveStorageError::GroupByLabelsInvalid {
+                reason: "number of labels must match the length of the axis"
+            })
+        ));
+    }
+
+    #[test]
+    fn group_by_too_many_groups() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.group_by = Some(make_group_by(0, models::Aggregation::Sum, vec![i64::MAX]));
+        let data = [1_i32].as_bytes();
+        let result = GroupBy::execute(&request_data, data.into());
+        assert!(matches!(
+            result,
+            Err(ActiveStorageError::GroupByLabelsInvalid {
+                reason: "too many groups"
+            })
+        ));
+    }
+
+    #[test]
+    fn group_by_max_f64_with_nan_group() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        request_data.group_by = Some(make_group_by(0, models::Aggregation::Max, vec![0, 2, 0]));
+        let data = [1.0_f64, 2.0, 3.0].as_bytes();
+        let response = GroupBy::execute(&request_data, data.into()).unwrap();
+        let maxes: Vec<f64> = response
+            .body
+            .chunks_exact(8)
+            .map(|chunk| f64::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(3.0, maxes[0]);
+        // Group 1 has no elements.
+        assert!(maxes[1].is_nan());
+        assert_eq!(2.0, maxes[2]);
+        assert_eq!(models::DType::Float64, response.dtype);
+    }
+
+    fn make_coarsen(block: Vec<usize>, aggregation: models::Aggregation) -> models::Coarsen {
+        models::Coarsen { block, aggregation }
+    }
+
+    #[test]
+    fn coarsen_sum_i32_2d_partial_blocks() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![3, 4]);
+        request_data.coarsen = Some(make_coarsen(vec![2, 2], models::Aggregation::Sum));
+        let data: Vec<i32> = (1..=12).collect();
+        let response = Coarsen::execute(&request_data, data.as_bytes().into()).unwrap();
+        // The last row of blocks contains a single row of elements.
+        assert_eq!([14_i32, 22, 19, 23].as_bytes(), response.body);
+        assert_eq!(models::DType::Int32, response.dtype);
+        assert_eq!(vec![2, 2], response.shape);
+        assert_eq!(12, response.count);
+    }
+
+    #[test]
+    fn coarsen_mean_f32_with_selection_and_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.shape = Some(vec![6]);
+        request_data.selection = Some(vec![models::Slice::new(1, 6, 1).into()]);
+        request_data.missing = Some(Missing::MissingValue(3.into()));
+        request_data.coarsen = Some(make_coarsen(vec![2], models::Aggregation::Mean));
+        let data = [0_f32, 1.0, 2.0, 3.0, 4.0, 5.0].as_bytes();
+        let response = Coarsen::execute(&request_data, data.into()).unwrap();
+        assert_eq!([1.5_f64, 4.0, 5.0].as_bytes(), response.body);
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(vec![3], response.shape);
+        assert_eq!(4, response.count);
+    }
+
+    #[test]
+    fn coarsen_min_u32_fortran_order() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint32;
+        request_data.shape = Some(vec![2, 4]);
+        request_data.order = Some(models::Order::F);
+        request_data.coarsen = Some(make_coarsen(vec![1, 2], models::Aggregation::Min));
+        // Logical array is [[8, 6, 4, 2], [7, 5, 3, 1]].
+        let data = [8_u32, 7, 6, 5, 4, 3, 2, 1].as_bytes();
+        let response = Coarsen::execute(&request_data, data.into()).unwrap();
+        // Result is [[6, 2], [5, 1]] in Fortran order.
+        assert_eq!([6_u32, 5, 2, 1].as_bytes(), response.body);
+        assert_eq!(vec![2, 2], response.shape);
+    }
+
+    #[test]
+    fn coarsen_count_with_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.missing = Some(Missing::ValidMin(3.into()));
+        request_data.coarsen = Some(make_coarsen(vec![3], models::Aggregation::Count));
+        let data = [1_i32, 2, 3, 4, 5].as_bytes();
+        let response = Coarsen::execute(&request_data, data.into()).unwrap();
+        assert_eq!([1_i64, 2].as_bytes(), response.body);
+        assert_eq!(models::DType::Int64, response.dtype);
+        assert_eq!(3, response.count);
+        assert_eq!(2, response.missing);
+    }
+
+    #[test]
+    fn coarsen_max_i64_empty_block() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Int64;
+        request_data.missing = Some(Missing::MissingValue(0.into()));
+        request_data.coarsen = Some(make_coarsen(vec![2], models::Aggregation::Max));
+        let data = [1_i64, 2, 0, 0].as_bytes();
+        let result = Coarsen::execute(&request_data, data.into());
+        assert!(matches!(
+            result,
+            Err(ActiveStorageError::EmptyArray {
+                operation: "coarsen"
+            })
+        ));
+    }
+
+    fn make_ensemble(members: usize, aggregation: models::Aggregation) -> models::Ensemble {
+        let members = (1..=members).map(|i| format!("member{}", i)).collect();
+        models::Ensemble {
+            members,
+            aggregation,
+        }
+    }
+
+    #[test]
+    fn ensemble_mean_i32_with_selection() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![2, 2]);
+        request_data.selection = Some(vec![
+            models::Slice::new(0, 2, 1).into(),
+            models::Slice::new(1, 2, 1).into(),
+        ]);
+        request_data.ensemble = Some(make_ensemble(2, models::Aggregation::Mean));
+        let data = [1_i32, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12].as_bytes();
+        let response = Ensemble::execute(&request_data, data.into()).unwrap();
+        assert_eq!([6.0_f64, 8.0].as_bytes(), response.body);
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(vec![2, 1], response.shape);
+        assert_eq!(6, response.count);
+    }
+
+    #[test]
+    fn ensemble_max_f32_fortran_order_with_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.shape = Some(vec![2, 2]);
+        request_data.order = Some(models::Order::F);
+        request_data.missing = Some(Missing::MissingValue(9.into()));
+        request_data.ensemble = Some(make_ensemble(1, models::Aggregation::Max));
+        let data = [1_f32, 5.0, 2.0, 9.0, 4.0, 9.0, 3.0, 9.0].as_bytes();
+        let response = Ensemble::execute(&request_data, data.into()).unwrap();
+        // The last element is missing in both members.
+        let maxes: Vec<f32> = response
+            .body
+            .chunks_exact(4)
+            .map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!([4.0, 5.0, 3.0], maxes[..3]);
+        assert!(maxes[3].is_nan());
+        assert_eq!(models::DType::Float32, response.dtype);
+        assert_eq!(vec![2, 2], response.shape);
+        assert_eq!(5, response.count);
+        assert_eq!(3, response.missing);
+    }
+
+    #[test]
+    fn ensemble_min_u64_empty_element() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint64;
+        request_data.missing = Some(Missing::MissingValue(0.into()));
+        request_data.ensemble = Some(make_ensemble(1, models::Aggregation::Min));
+        let data = [1_u64, 0, 2, 0].as_bytes();
+        let result = Ensemble::execute(&request_data, data.into());
+        assert!(matches!(
+            result,
+            Err(ActiveStorageError::EmptyArray {
+                operation: "ensemble"
+            })
+        ));
+    }
+
+    #[test]
+    fn expr_sum_i32_with_selection() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.shape = Some(vec![2, 2]);
+        request_data.selection = Some(vec![
+            models::Slice::new(0, 2, 1).into(),
+            models::Slice::new(1, 2, 1).into(),
+        ]);
+        request_data.expression = Some("sum((x - 1) * 0.5)".to_string());
+        let data = [1_i32, 2, 3, 4].as_bytes();
+        let response = Expr::execute(&request_data, data.into()).unwrap();
+        assert_eq!(2.0_f64.as_bytes(), response.body);
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(vec![0; 0], response.shape);
+        assert_eq!(2, response.count);
+        assert_eq!(0, response.missing);
+    }
+
+    #[test]
+    fn expr_mean_f32_with_missing_and_nan() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.missing = Some(Missing::MissingValue(9.into()));
+        // The logarithm of -1 is NaN, so that element is also missing.
+        request_data.expression = Some("mean(log10(x))".to_string());
+        let data = [10_f32, 9.0, 1000.0, -1.0].as_bytes();
+        let response = Expr::execute(&request_data, data.into()).unwrap();
+        assert_eq!(2.0_f64.as_bytes(), response.body);
+        assert_eq!(2, response.count);
+        assert_eq!(2, response.missing);
+    }
+
+    #[test]
+    fn expr_max_min_u64() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Uint64;
+        let data = [3_u64, 1, 4, 1, 5].as_bytes();
+        request_data.expression = Some("max(abs(x - 3))".to_string());
+        let response = Expr::execute(&request_data, data.into()).unwrap();
+        assert_eq!(2.0_f64.as_bytes(), response.body);
+        request_data.expression = Some("min(abs(x - 3) + 1)".to_string());
+        let response = Expr::execute(&request_data, data.into()).unwrap();
+        assert_eq!(1.0_f64.as_bytes(), response.body);
+        assert_eq!(5, response.count);
+    }
+
+    #[test]
+    fn expr_empty() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        let data = [f64::NAN].as_bytes();
+        request_data.expression = Some("sum(x)".to_string());
+        let response = Expr::execute(&request_data, data.into()).unwrap();
+        assert_eq!(0.0_f64.as_bytes(), response.body);
+        assert_eq!(0, response.count);
+        assert_eq!(1, response.missing);
+        request_data.expression = Some("mean(x)".to_string());
+        let result = Expr::execute(&request_data, data.into());
+        assert!(matches!(
+            result,
+            Err(ActiveStorageError::EmptyArray { operation: "expr" })
+        ));
+    }
+
     #[test]
     fn partial_cmp_behaviour() {
         assert_eq!(
@@ -613,4 +2571,49 @@ mod tests {
             Some(std::cmp::Ordering::Greater)
         );
     }
+
+    #[test]
+    fn sum_f64_partial() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float64;
+        request_data.partial = true;
+        let values = [1.0e16_f64, 1.0, -1.0e16, 1.0];
+        let response = Sum::execute(&request_data, values.as_bytes().to_vec()).unwrap();
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(vec![2], response.shape);
+        assert_eq!(4, response.count);
+        let body: Vec<f64> = response
+            .body
+            .chunks_exact(8)
+            .map(|chunk| f64::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+        // The rounding error of the naive sum is recovered by the compensation.
+        assert_eq!(2.0, body[0] + body[1]);
+    }
+
+    #[test]
+    fn sum_f32_partial_missing() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.dtype = models::DType::Float32;
+        request_data.partial = true;
+        request_data.missing = Some(Missing::MissingValue(DValue::from_f64(-1.0).unwrap()));
+        let values = [1.5_f32, -1.0, 2.5];
+        let response = Sum::execute(&request_data, values.as_bytes().to_vec()).unwrap();
+        assert_eq!(models::DType::Float64, response.dtype);
+        assert_eq!(2, response.count);
+        assert_eq!(1, response.missing);
+        let expected = [4.0_f64, 0.0];
+        assert_eq!(expected.as_bytes(), response.body);
+    }
+
+    #[test]
+    fn sum_i32_partial() {
+        // Integer sums are exact, so are returned as normal.
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.partial = true;
+        let values = [1_i32, 2, 3];
+        let response = Sum::execute(&request_data, values.as_bytes().to_vec()).unwrap();
+        assert_eq!(models::DType::Int32, response.dtype);
+        assert_eq!(6_i32.as_bytes(), response.body);
+    }
 }
@@ -0,0 +1,122 @@
+//! Precision control for floating point results
+//!
+//! Clients that do not need full precision, such as dashboards, may request that floating point
+//! results are rounded to a number of decimal places and cast to float32, reducing the size of
+//! responses. Rounding is performed in float64 before any cast, and integer results are returned
+//! unchanged.
+
+use crate::models::{DType, Precision, Response};
+
+use axum::body::Bytes;
+// Bring trait into scope to use as_bytes method.
+use zerocopy::AsBytes;
+
+/// Round a value to a number of decimal places, as numpy.round does.
+///
+/// # Arguments
+///
+/// * `value`: Value to round
+/// * `decimals`: Number of decimal places
+fn round(value: f64, decimals: u32) -> f64 {
+    let scale = 10_f64.powi(decimals as i32);
+    (value * scale).round() / scale
+}
+
+/// Apply the requested precision to the floating point results of a response.
+///
+/// # Arguments
+///
+/// * `precision`: Requested precision
+/// * `response`: Response to the operation
+pub fn apply(precision: &Precision, response: Response) -> Response {
+    let values: Vec<f64> = match response.dtype {
+        DType::Float32 => response
+            .body
+            .chunks_exact(4)
+            .map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap()).into())
+            .collect(),
+        DType::Float64 => response
+            .body
+            .chunks_exact(8)
+            .map(|chunk| f64::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect(),
+        _ => return response,
+    };
+    let values = values.into_iter().map(|value| match precision.decimals {
+        Some(decimals) => round(value, decimals),
+        None => value,
+    });
+    let dtype = precision.dtype.unwrap_or(response.dtype);
+    let body = match dtype {
+        DType::Float32 => Bytes::copy_from_slice(
+            values
+                .map(|value| value as f32)
+                .collect::<Vec<_>>()
+                .as_bytes(),
+        ),
+        DType::Float64 => Bytes::copy_from_slice(values.collect::<Vec<_>>().as_bytes()),
+        _ => unreachable!("precision dtype should be validated as a floating point type"),
+    };
+    Response {
+        body,
+        dtype,
+        ..response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_precision(dtype: Option<DType>, decimals: Option<u32>) -> Precision {
+        Precision { dtype, decimals }
+    }
+
+    #[test]
+    fn round_decimals() {
+        assert_eq!(1.23, round(1.2345, 2));
+        assert_eq!(-2.0, round(-1.5, 0));
+        assert!(round(f64::NAN, 2).is_nan());
+    }
+
+    #[test]
+    fn apply_cast_to_float32() {
+        let body = [1.5_f64, f64::NAN].as_bytes();
+        let response = Response::new(body.into(), DType::Float64, vec![2], 2).with_missing(1);
+        let response = apply(&make_precision(Some(DType::Float32), None), response);
+        assert_eq!(DType::Float32, response.dtype);
+        assert_eq!(8, response.body.len());
+        assert_eq!(1.5_f32.as_bytes(), &response.body[..4]);
+        assert!(f32::from_ne_bytes(response.body[4..].try_into().unwrap()).is_nan());
+        assert_eq!(vec![2], response.shape);
+        assert_eq!(2, response.count);
+        assert_eq!(1, response.missing);
+    }
+
+    #[test]
+    fn apply_round_float32() {
+        let body = [1.26_f32, 4.04321].as_bytes();
+        let response = Response::new(body.into(), DType::Float32, vec![2], 2);
+        let response = apply(&make_precision(None, Some(1)), response);
+        assert_eq!(DType::Float32, response.dtype);
+        assert_eq!([1.3_f32, 4.0].as_bytes(), response.body);
+    }
+
+    #[test]
+    fn apply_round_and_cast() {
+        let body = 1.23456_f64.as_bytes();
+        let response = Response::new(body.into(), DType::Float64, vec![], 1);
+        let response = apply(&make_precision(Some(DType::Float32), Some(3)), response);
+        assert_eq!(DType::Float32, response.dtype);
+        assert_eq!(1.235_f32.as_bytes(), response.body);
+    }
+
+    #[test]
+    fn apply_integer_unchanged() {
+        let body = 42_i64.as_bytes();
+        let response = Response::new(body.into(), DType::Int64, vec![], 1);
+        let response = apply(&make_precision(Some(DType::Float32), Some(0)), response);
+        assert_eq!(DType::Int64, response.dtype);
+        assert_eq!(42_i64.as_bytes(), response.body);
+    }
+}
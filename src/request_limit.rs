@@ -0,0 +1,201 @@
+//! Limits on in-flight requests
+//!
+//! The number of operation requests in flight may be limited across the server and for each
+//! client connection. Requests beyond either limit are rejected immediately with 429 Too Many
+//! Requests rather than queued, providing a simple first line of defence in front of the resource
+//! manager, for example against a client that accidentally opens many concurrent requests.
+//! Connections are identified by the address of the peer, which is only known when the service
+//! is served with connection information.
+
+use crate::error::ActiveStorageError;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hashbrown::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Limits on in-flight requests
+#[derive(Debug, Default)]
+pub struct RequestLimiter {
+    /// Optional maximum number of requests in flight across the server.
+    max_requests: Option<usize>,
+
+    /// Optional maximum number of requests in flight on each connection.
+    max_connection_requests: Option<usize>,
+
+    /// Number of requests in flight across the server.
+    in_flight: AtomicUsize,
+
+    /// Number of requests in flight on each connection with requests in flight.
+    connections: Mutex<HashMap<SocketAddr, usize>>,
+}
+
+/// Guard that records an in-flight request until dropped.
+pub struct RequestGuard<'a> {
+    limiter: &'a RequestLimiter,
+    connection: Option<SocketAddr>,
+}
+
+impl Drop for RequestGuard<'_> {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::SeqCst);
+        if let Some(connection) = self.connection {
+            let mut connections = self.limiter.connections.lock().unwrap();
+            if let Some(count) = connections.get_mut(&connection) {
+                *count -= 1;
+                if *count == 0 {
+                    connections.remove(&connection);
+                }
+            }
+        }
+    }
+}
+
+impl RequestLimiter {
+    /// Returns a new RequestLimiter object.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_requests`: Optional maximum number of requests in flight across the server
+    /// * `max_connection_requests`: Optional maximum number of requests in flight on each
+    ///   connection
+    pub fn new(max_requests: Option<usize>, max_connection_requests: Option<usize>) -> Self {
+        Self {
+            max_requests,
+            max_connection_requests,
+            ..Default::default()
+        }
+    }
+
+    /// Start a request.
+    ///
+    /// Returns a guard that records the request as in flight until dropped, or an
+    /// [ActiveStorageError::TooManyRequests] if a limit would be exceeded.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection`: Address of the peer of the request's connection, if known
+    pub fn start_request(
+        &self,
+        connection: Option<SocketAddr>,
+    ) -> Result<RequestGuard<'_>, ActiveStorageError> {
+        let mut guard = RequestGuard {
+            limiter: self,
+            connection: None,
+        };
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if let Some(limit) = self.max_requests {
+            if in_flight >= limit {
+                return Err(ActiveStorageError::TooManyRequests {
+                    scope: "server",
+                    limit,
+                });
+            }
+        }
+        if let (Some(limit), Some(connection)) = (self.max_connection_requests, connection) {
+            let mut connections = self.connections.lock().unwrap();
+            let count = connections.entry(connection).or_insert(0);
+            if *count >= limit {
+                return Err(ActiveStorageError::TooManyRequests {
+                    scope: "connection",
+                    limit,
+                });
+            }
+            *count += 1;
+            guard.connection = Some(connection);
+        }
+        Ok(guard)
+    }
+
+    /// Returns the number of requests in flight across the server.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// Middleware that rejects requests beyond the limits on in-flight requests.
+///
+/// # Arguments
+///
+/// * `limiter`: Limits on in-flight requests
+/// * `request`: HTTP request
+/// * `next`: Next middleware
+pub async fn limit<B>(
+    State(limiter): State<Arc<RequestLimiter>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let connection = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+    match limiter.start_request(connection) {
+        Ok(_guard) => next.run(request).await,
+        Err(err) => err.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> Option<SocketAddr> {
+        Some(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    #[test]
+    fn unlimited() {
+        let limiter = RequestLimiter::new(None, None);
+        let guards: Vec<_> = (0..10)
+            .map(|_| limiter.start_request(addr(1)).unwrap())
+            .collect();
+        assert_eq!(10, limiter.in_flight());
+        drop(guards);
+        assert_eq!(0, limiter.in_flight());
+    }
+
+    #[test]
+    fn max_requests() {
+        let limiter = RequestLimiter::new(Some(2), None);
+        let guard1 = limiter.start_request(addr(1)).unwrap();
+        let _guard2 = limiter.start_request(addr(2)).unwrap();
+        let err = limiter.start_request(None).err().unwrap();
+        assert_eq!(
+            "too many requests in flight on this server (limit 2)",
+            err.to_string()
+        );
+        assert_eq!(2, limiter.in_flight());
+        drop(guard1);
+        limiter.start_request(None).unwrap();
+    }
+
+    #[test]
+    fn max_connection_requests() {
+        let limiter = RequestLimiter::new(None, Some(1));
+        let guard = limiter.start_request(addr(1)).unwrap();
+        let err = limiter.start_request(addr(1)).err().unwrap();
+        assert_eq!(
+            "too many requests in flight on this connection (limit 1)",
+            err.to_string()
+        );
+        // Other connections and requests without connection information are not limited.
+        let _guard2 = limiter.start_request(addr(2)).unwrap();
+        let _guard3 = limiter.start_request(None).unwrap();
+        drop(guard);
+        let _guard4 = limiter.start_request(addr(1)).unwrap();
+        assert_eq!(2, limiter.connections.lock().unwrap().len());
+    }
+
+    #[test]
+    fn connections_removed() {
+        let limiter = RequestLimiter::new(None, Some(2));
+        let guard = limiter.start_request(addr(1)).unwrap();
+        assert_eq!(1, limiter.connections.lock().unwrap().len());
+        drop(guard);
+        assert!(limiter.connections.lock().unwrap().is_empty());
+    }
+}
@@ -1,9 +1,33 @@
 //! Resource management
 
 use crate::error::ActiveStorageError;
+use crate::metrics::MEMORY_ESTIMATE_RATIO;
 
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::{Semaphore, SemaphorePermit};
 
+/// Weight of each observation in the moving average of observed to estimated memory usage.
+const MEMORY_RATIO_WEIGHT: f64 = 0.1;
+
+/// Available quantity of each type of resource. `None` means that a resource is not limited.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ResourceStatus {
+    /// Available S3 connections.
+    pub s3_connections: Option<usize>,
+
+    /// Available memory (bytes).
+    pub memory: Option<usize>,
+
+    /// Available tasks.
+    pub tasks: Option<usize>,
+
+    /// Ratio of observed to estimated memory usage, if memory estimates are adaptive.
+    pub memory_ratio: Option<f64>,
+}
+
 /// [crate::resource_manager::ResourceManager] provides a simple way to allocate various resources
 /// to tasks. Resource management is performed using a Tokio Semaphore for each type of resource.
 pub struct ResourceManager {
@@ -11,36 +35,79 @@ pub struct ResourceManager {
     s3_connections: Option<Semaphore>,
 
     /// Optional semaphore for memory (bytes).
-    memory: Option<Semaphore>,
+    memory: Option<Arc<Semaphore>>,
 
     /// Optional total memory pool in bytes.
     total_memory: Option<usize>,
 
+    /// Number of bytes of memory per permit. At most u32::MAX permits may be acquired at once, so
+    /// memory limits that exceed this are accounted in larger units.
+    memory_unit: usize,
+
     /// Optional semaphore for tasks.
-    tasks: Option<Semaphore>,
+    tasks: Option<Arc<Semaphore>>,
+
+    /// Current task limit, which may be changed at runtime.
+    task_limit: AtomicUsize,
+
+    /// Number of task permits still to be removed following a reduction of the task limit.
+    task_debt: AtomicUsize,
+
+    /// Total time spent waiting for task permits since the last call to take_task_wait, in
+    /// nanoseconds.
+    task_wait_nanos: AtomicU64,
+
+    /// Number of task permits acquired since the last call to take_task_wait.
+    task_waits: AtomicU64,
+
+    /// Optional moving average of the ratio of observed to estimated memory usage, stored as the
+    /// bits of an f64. Present if memory estimates are adaptive.
+    memory_ratio: Option<AtomicU64>,
 }
 
 impl ResourceManager {
     /// Returns a new ResourceManager object.
+    ///
+    /// # Arguments
+    ///
+    /// * `s3_connection_limit`: Optional S3 connection limit
+    /// * `memory_limit`: Optional memory limit in bytes
+    /// * `task_limit`: Optional task limit
+    /// * `adaptive_memory`: Whether to scale memory estimates by the observed memory usage
     pub fn new(
         s3_connection_limit: Option<usize>,
         memory_limit: Option<usize>,
         task_limit: Option<usize>,
+        adaptive_memory: bool,
     ) -> Self {
+        let memory_unit = memory_limit.map_or(1, |limit| limit.div_ceil(u32::MAX as usize).max(1));
         Self {
             s3_connections: s3_connection_limit.map(Semaphore::new),
-            memory: memory_limit.map(Semaphore::new),
+            memory: memory_limit.map(|limit| Arc::new(Semaphore::new(limit / memory_unit))),
             total_memory: memory_limit,
-            tasks: task_limit.map(Semaphore::new),
+            memory_unit,
+            tasks: task_limit.map(|limit| Arc::new(Semaphore::new(limit))),
+            task_limit: AtomicUsize::new(task_limit.unwrap_or(0)),
+            task_debt: AtomicUsize::new(0),
+            task_wait_nanos: AtomicU64::new(0),
+            task_waits: AtomicU64::new(0),
+            memory_ratio: adaptive_memory.then(|| AtomicU64::new(1.0_f64.to_bits())),
         }
     }
 
     /// Acquire an S3 connection resource.
     pub async fn s3_connection(&self) -> Result<Option<SemaphorePermit>, ActiveStorageError> {
-        optional_acquire(&self.s3_connections, 1).await
+        optional_acquire(self.s3_connections.as_ref(), 1).await
     }
 
     /// Acquire memory resource.
+    ///
+    /// If memory estimates are adaptive, the memory acquired is scaled by the ratio of observed
+    /// to estimated memory usage, up to the total memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes`: Estimated memory usage in bytes
     pub async fn memory(
         &self,
         bytes: usize,
@@ -53,18 +120,173 @@ impl ResourceManager {
                 });
             };
         };
-        optional_acquire(&self.memory, bytes).await
+        let bytes = match self.memory_ratio() {
+            Some(ratio) => {
+                let scaled = (bytes as f64 * ratio).ceil() as usize;
+                self.total_memory.map_or(scaled, |total| scaled.min(total))
+            }
+            None => bytes,
+        };
+        // Round up to whole units, without exceeding the total.
+        let units = self.total_memory.map_or(0, |total| {
+            bytes
+                .div_ceil(self.memory_unit)
+                .min(total / self.memory_unit)
+        });
+        optional_acquire(self.memory.as_deref(), units).await
+    }
+
+    /// Returns the ratio of observed to estimated memory usage, if memory estimates are adaptive.
+    fn memory_ratio(&self) -> Option<f64> {
+        self.memory_ratio
+            .as_ref()
+            .map(|ratio| f64::from_bits(ratio.load(Ordering::Relaxed)))
+    }
+
+    /// Record the observed memory usage of a request, to improve future memory estimates.
+    ///
+    /// Has no effect unless memory estimates are adaptive.
+    ///
+    /// # Arguments
+    ///
+    /// * `estimated`: Estimated memory usage in bytes, before scaling
+    /// * `observed`: Observed memory usage in bytes
+    pub fn observe_memory(&self, estimated: usize, observed: usize) {
+        let Some(ratio) = &self.memory_ratio else {
+            return;
+        };
+        if estimated == 0 {
+            return;
+        }
+        let sample = observed as f64 / estimated as f64;
+        let update = |bits| {
+            let average = f64::from_bits(bits);
+            Some((average + MEMORY_RATIO_WEIGHT * (sample - average)).to_bits())
+        };
+        // The update function always returns Some.
+        let previous = ratio
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, update)
+            .unwrap();
+        if let Some(bits) = update(previous) {
+            MEMORY_ESTIMATE_RATIO.set(f64::from_bits(bits));
+        }
     }
 
     /// Acquire a task resource.
     pub async fn task(&self) -> Result<Option<SemaphorePermit>, ActiveStorageError> {
-        optional_acquire(&self.tasks, 1).await
+        let start = Instant::now();
+        let permit = optional_acquire(self.tasks.as_deref(), 1).await?;
+        let waited = start.elapsed().as_nanos().try_into().unwrap_or(u64::MAX);
+        self.task_wait_nanos.fetch_add(waited, Ordering::Relaxed);
+        self.task_waits.fetch_add(1, Ordering::Relaxed);
+        Ok(permit)
+    }
+
+    /// Detach memory permits from the lifetime of the resource manager, so that they may be held by
+    /// a task that outlives the request handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `permit`: Memory permit acquired from this resource manager
+    pub fn own_memory(&self, permit: Option<SemaphorePermit>) -> Option<OwnedPermit> {
+        OwnedPermit::new(self.memory.as_ref(), permit)
+    }
+
+    /// Detach a task permit from the lifetime of the resource manager, so that it may be held by a
+    /// task that outlives the request handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `permit`: Task permit acquired from this resource manager
+    pub fn own_task(&self, permit: Option<SemaphorePermit>) -> Option<OwnedPermit> {
+        OwnedPermit::new(self.tasks.as_ref(), permit)
+    }
+
+    /// Returns the current task limit, if tasks are limited.
+    pub fn task_limit(&self) -> Option<usize> {
+        self.tasks
+            .as_ref()
+            .map(|_| self.task_limit.load(Ordering::Relaxed))
+    }
+
+    /// Change the task limit.
+    ///
+    /// Permits that are in use when the limit is reduced are removed as they become available, on
+    /// this or subsequent calls. Has no effect if tasks are not limited. Must not be called
+    /// concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit`: New task limit
+    pub fn set_task_limit(&self, limit: usize) {
+        let Some(tasks) = &self.tasks else {
+            return;
+        };
+        let current = self.task_limit.swap(limit, Ordering::Relaxed);
+        let mut debt = self.task_debt.load(Ordering::Relaxed);
+        if limit > current {
+            // Cancel any outstanding removals before adding permits.
+            let increase = limit - current;
+            let cancelled = increase.min(debt);
+            debt -= cancelled;
+            tasks.add_permits(increase - cancelled);
+        } else {
+            debt += current - limit;
+        }
+        debt -= tasks.forget_permits(debt);
+        self.task_debt.store(debt, Ordering::Relaxed);
+    }
+
+    /// Returns the mean time spent waiting for task permits since the last call, and resets it.
+    ///
+    /// Returns `None` if no task permits have been acquired since the last call.
+    pub fn take_task_wait(&self) -> Option<Duration> {
+        let waits = self.task_waits.swap(0, Ordering::Relaxed);
+        let nanos = self.task_wait_nanos.swap(0, Ordering::Relaxed);
+        (waits > 0).then(|| Duration::from_nanos(nanos / waits))
+    }
+
+    /// Returns the available quantity of each type of resource.
+    pub fn status(&self) -> ResourceStatus {
+        let available = |sem: Option<&Semaphore>| sem.map(Semaphore::available_permits);
+        ResourceStatus {
+            s3_connections: available(self.s3_connections.as_ref()),
+            memory: available(self.memory.as_deref()).map(|units| units * self.memory_unit),
+            tasks: available(self.tasks.as_deref()),
+            memory_ratio: self.memory_ratio(),
+        }
+    }
+}
+
+/// Permits that do not borrow the resource manager. The permits are returned to the semaphore when
+/// dropped.
+pub struct OwnedPermit {
+    semaphore: Arc<Semaphore>,
+    permits: usize,
+}
+
+impl OwnedPermit {
+    /// Returns an owned permit in place of a permit acquired from `semaphore`.
+    fn new(semaphore: Option<&Arc<Semaphore>>, permit: Option<SemaphorePermit>) -> Option<Self> {
+        let (semaphore, permit) = semaphore.zip(permit)?;
+        let permits = permit.num_permits();
+        permit.forget();
+        Some(Self {
+            semaphore: semaphore.clone(),
+            permits,
+        })
+    }
+}
+
+impl Drop for OwnedPermit {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
     }
 }
 
 /// Acquire permits on an optional Semaphore, if present.
 async fn optional_acquire(
-    sem: &Option<Semaphore>,
+    sem: Option<&Semaphore>,
     n: usize,
 ) -> Result<Option<SemaphorePermit>, ActiveStorageError> {
     let n = n.try_into()?;
@@ -86,13 +308,22 @@ mod tests {
 
     #[tokio::test]
     async fn no_resource_management() {
-        let rm = ResourceManager::new(None, None, None);
+        let rm = ResourceManager::new(None, None, None, false);
         assert!(rm.s3_connections.is_none());
         assert!(rm.memory.is_none());
         assert!(rm.tasks.is_none());
         let _c = rm.s3_connection().await.unwrap();
         let _m = rm.memory(1).await.unwrap();
         let _t = rm.task().await.unwrap();
+        assert_eq!(
+            ResourceStatus {
+                s3_connections: None,
+                memory: None,
+                tasks: None,
+                memory_ratio: None,
+            },
+            rm.status()
+        );
         assert!(_c.is_none());
         assert!(_m.is_none());
         assert!(_t.is_none());
@@ -100,13 +331,31 @@ mod tests {
 
     #[tokio::test]
     async fn full_resource_management() {
-        let rm = ResourceManager::new(Some(1), Some(1), Some(1));
+        let rm = ResourceManager::new(Some(1), Some(1), Some(1), false);
         assert!(rm.s3_connections.is_some());
         assert!(rm.memory.is_some());
         assert!(rm.tasks.is_some());
+        assert_eq!(
+            ResourceStatus {
+                s3_connections: Some(1),
+                memory: Some(1),
+                tasks: Some(1),
+                memory_ratio: None,
+            },
+            rm.status()
+        );
         let _c = rm.s3_connection().await.unwrap();
         let _m = rm.memory(1).await.unwrap();
         let _t = rm.task().await.unwrap();
+        assert_eq!(
+            ResourceStatus {
+                s3_connections: Some(0),
+                memory: Some(0),
+                tasks: Some(0),
+                memory_ratio: None,
+            },
+            rm.status()
+        );
         assert!(_c.is_some());
         assert!(_m.is_some());
         assert!(_t.is_some());
@@ -124,4 +373,92 @@ mod tests {
             Some(TryAcquireError::NoPermits)
         );
     }
+
+    #[tokio::test]
+    async fn adaptive_memory() {
+        let rm = ResourceManager::new(None, Some(100), None, true);
+        assert_eq!(Some(1.0), rm.status().memory_ratio);
+        // Estimates for compressed data are too low.
+        for _ in 0..100 {
+            rm.observe_memory(10, 40);
+        }
+        let ratio = rm.status().memory_ratio.unwrap();
+        assert!((ratio - 4.0).abs() < 0.01, "{}", ratio);
+        {
+            let _m = rm.memory(10).await.unwrap();
+            assert_eq!(Some(60), rm.status().memory);
+        }
+        // Scaled estimates are limited to the total memory.
+        let _m = rm.memory(50).await.unwrap();
+        assert_eq!(Some(0), rm.status().memory);
+    }
+
+    #[tokio::test]
+    async fn adaptive_memory_disabled() {
+        let rm = ResourceManager::new(None, Some(100), None, false);
+        rm.observe_memory(10, 40);
+        let _m = rm.memory(10).await.unwrap();
+        assert_eq!(Some(90), rm.status().memory);
+        assert_eq!(None, rm.status().memory_ratio);
+    }
+
+    #[tokio::test]
+    async fn owned_permits() {
+        let rm = ResourceManager::new(None, Some(100), Some(2), false);
+        let memory = rm.own_memory(rm.memory(10).await.unwrap());
+        let task = rm.own_task(rm.task().await.unwrap());
+        assert_eq!(Some(90), rm.status().memory);
+        assert_eq!(Some(1), rm.status().tasks);
+        drop(memory);
+        drop(task);
+        assert_eq!(Some(100), rm.status().memory);
+        assert_eq!(Some(2), rm.status().tasks);
+        // Nothing is held if resources are not limited.
+        let rm = ResourceManager::new(None, None, None, false);
+        assert!(rm.own_memory(rm.memory(10).await.unwrap()).is_none());
+        assert!(rm.own_task(rm.task().await.unwrap()).is_none());
+    }
+
+    #[tokio::test]
+    async fn set_task_limit() {
+        let rm = ResourceManager::new(None, None, Some(2), false);
+        assert_eq!(Some(2), rm.task_limit());
+        rm.set_task_limit(4);
+        assert_eq!(Some(4), rm.status().tasks);
+        let _t1 = rm.task().await.unwrap();
+        let t2 = rm.task().await.unwrap();
+        // Permits that are in use are removed once released.
+        rm.set_task_limit(1);
+        assert_eq!(Some(1), rm.task_limit());
+        assert_eq!(Some(0), rm.status().tasks);
+        drop(t2);
+        assert_eq!(Some(1), rm.status().tasks);
+        rm.set_task_limit(1);
+        assert_eq!(Some(0), rm.status().tasks);
+        // Increases cancel outstanding removals.
+        rm.set_task_limit(2);
+        assert_eq!(Some(1), rm.status().tasks);
+        assert!(rm.take_task_wait().is_some());
+        assert!(rm.take_task_wait().is_none());
+    }
+
+    #[tokio::test]
+    async fn set_task_limit_unlimited() {
+        let rm = ResourceManager::new(None, None, None, false);
+        rm.set_task_limit(4);
+        assert_eq!(None, rm.task_limit());
+        assert_eq!(None, rm.status().tasks);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[tokio::test]
+    async fn memory_large() {
+        // Memory beyond u32::MAX bytes is accounted in larger units.
+        let rm = ResourceManager::new(None, Some(8 << 30), None, false);
+        let _m = rm.memory(5 << 30).await.unwrap();
+        let available = rm.status().memory.unwrap();
+        assert!(available.abs_diff(3 << 30) <= 2 * rm.memory_unit);
+        let _m = rm.memory(available).await.unwrap();
+        assert_eq!(Some(0), rm.status().memory);
+    }
 }
@@ -0,0 +1,327 @@
+//! Retry budget for S3 requests
+//!
+//! S3 requests may be retried at several layers: by the S3 client after transient errors, by
+//! Reductionist after a response body does not match its Content-Length header, and on another
+//! endpoint after failover. Without a shared limit, these retries multiply into long delays that
+//! are hidden from the client. Each request to the server has a retry budget, shared by all of
+//! its S3 requests, limiting the number of retries and the time after which no further retries
+//! are started.
+//!
+//! As with the compute time budget in [crate::deadline], the budget is held in a task-local
+//! variable rather than passed to every download. The S3 client counts its attempts using an
+//! [AttemptCounter] interceptor, and stops retrying once the budget is exhausted using a
+//! [BudgetClassifier]. Outside of a request, such as during warm-up, retries are unlimited.
+
+use aws_sdk_s3::config::interceptors::{BeforeTransmitInterceptorContextRef, InterceptorContext};
+use aws_sdk_s3::config::retry::{ClassifyRetry, RetryAction};
+use aws_sdk_s3::config::{ConfigBag, Intercept, RuntimeComponents};
+use aws_smithy_runtime_api::box_error::BoxError;
+use aws_smithy_runtime_api::client::retries::RequestAttempts;
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// `x-activestorage-attempts` header definition
+static HEADER_ATTEMPTS: header::HeaderName =
+    header::HeaderName::from_static("x-activestorage-attempts");
+
+tokio::task_local! {
+    /// Retry budget for the request handled by the current task.
+    static BUDGET: Arc<RetryBudget>;
+}
+
+/// Limits on the retries of S3 requests for each request to the server.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetryLimits {
+    /// Maximum number of retries, or `None` for no limit.
+    pub max_retries: Option<usize>,
+    /// Time after the start of the request after which no retries are started, or `None` for no
+    /// limit.
+    pub timeout: Option<Duration>,
+}
+
+/// Retry budget for a request to the server.
+#[derive(Debug)]
+pub struct RetryBudget {
+    /// Maximum number of retries, or `None` for no limit.
+    max_retries: Option<usize>,
+    /// Time after which no retries are started, or `None` for no limit.
+    deadline: Option<Instant>,
+    /// Number of S3 request attempts made, including retries.
+    attempts: AtomicUsize,
+    /// Number of retries made.
+    retries: AtomicUsize,
+}
+
+impl RetryBudget {
+    /// Returns a new RetryBudget object, starting now.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits`: Limits on the retries
+    pub fn new(limits: RetryLimits) -> Self {
+        Self {
+            max_retries: limits.max_retries,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            attempts: AtomicUsize::new(0),
+            retries: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of S3 request attempts made, including retries.
+    pub fn attempts(&self) -> usize {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether no further retries may be started.
+    fn exhausted(&self) -> bool {
+        self.max_retries
+            .is_some_and(|max_retries| self.retries.load(Ordering::Relaxed) >= max_retries)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Records a retry if the budget allows it, returning whether the retry may be started.
+    fn try_retry(&self) -> bool {
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return false;
+        }
+        self.retries
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |retries| {
+                match self.max_retries {
+                    Some(max_retries) if retries >= max_retries => None,
+                    _ => Some(retries + 1),
+                }
+            })
+            .is_ok()
+    }
+
+    /// Records an attempt of an S3 request.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry`: Whether the attempt is a retry by the S3 client
+    fn record_attempt(&self, retry: bool) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+        if retry {
+            self.retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Run a future with a retry budget for the current task.
+///
+/// # Arguments
+///
+/// * `budget`: Retry budget
+/// * `f`: Future to run
+pub async fn scope<F: Future>(budget: Arc<RetryBudget>, f: F) -> F::Output {
+    BUDGET.scope(budget, f).await
+}
+
+/// Returns the retry budget for the current task, if it has one.
+///
+/// The budget may be shared with tasks spawned to perform part of a request by running them with
+/// [scope].
+pub fn current() -> Option<Arc<RetryBudget>> {
+    BUDGET.try_with(Arc::clone).ok()
+}
+
+/// Records a retry of an S3 request by Reductionist if the budget for the current task allows
+/// it, returning whether the retry may be started.
+///
+/// Retries are always allowed outside of a request.
+pub fn try_retry() -> bool {
+    BUDGET.try_with(|budget| budget.try_retry()).unwrap_or(true)
+}
+
+/// S3 client interceptor that counts the attempts of each S3 request against the budget for the
+/// current task.
+#[derive(Debug)]
+pub struct AttemptCounter;
+
+impl Intercept for AttemptCounter {
+    fn name(&self) -> &'static str {
+        "AttemptCounter"
+    }
+
+    fn read_before_attempt(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let retry = cfg
+            .load::<RequestAttempts>()
+            .is_some_and(|attempts| attempts.attempts() > 1);
+        let _ = BUDGET.try_with(|budget| budget.record_attempt(retry));
+        Ok(())
+    }
+}
+
+/// S3 client retry classifier that forbids retries once the budget for the current task is
+/// exhausted.
+#[derive(Debug)]
+pub struct BudgetClassifier;
+
+impl ClassifyRetry for BudgetClassifier {
+    fn classify_retry(&self, _ctx: &InterceptorContext) -> RetryAction {
+        match BUDGET.try_with(|budget| budget.exhausted()) {
+            Ok(true) => RetryAction::RetryForbidden,
+            _ => RetryAction::NoActionIndicated,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "BudgetClassifier"
+    }
+}
+
+/// Middleware that applies a retry budget to each request.
+///
+/// The number of S3 request attempts made is returned in the `x-activestorage-attempts` header.
+///
+/// # Arguments
+///
+/// * `limits`: Limits on the retries of each request
+/// * `request`: HTTP request
+/// * `next`: Next middleware
+pub async fn track(
+    State(limits): State<RetryLimits>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    apply(limits, next.run(request)).await
+}
+
+/// Run a future returning a response with a new retry budget.
+///
+/// The number of S3 request attempts made is returned in the `x-activestorage-attempts` header.
+///
+/// # Arguments
+///
+/// * `limits`: Limits on the retries
+/// * `f`: Future to run
+pub async fn apply<F: Future<Output = Response>>(limits: RetryLimits, f: F) -> Response {
+    let budget = Arc::new(RetryBudget::new(limits));
+    let mut response = scope(budget.clone(), f).await;
+    response
+        .headers_mut()
+        .insert(&HEADER_ATTEMPTS, budget.attempts().into());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::demo;
+    use crate::resource_manager::ResourceManager;
+    use crate::s3_client::{S3ClientMap, S3Credentials};
+    use crate::supervisor::Supervisor;
+
+    fn limits(max_retries: Option<usize>, timeout: Option<Duration>) -> RetryLimits {
+        RetryLimits {
+            max_retries,
+            timeout,
+        }
+    }
+
+    #[test]
+    fn try_retry_max_retries() {
+        let budget = RetryBudget::new(limits(Some(2), None));
+        assert!(budget.try_retry());
+        assert!(!budget.exhausted());
+        assert!(budget.try_retry());
+        assert!(budget.exhausted());
+        assert!(!budget.try_retry());
+    }
+
+    #[test]
+    fn try_retry_unlimited() {
+        let budget = RetryBudget::new(RetryLimits::default());
+        for _ in 0..100 {
+            assert!(budget.try_retry());
+        }
+        assert!(!budget.exhausted());
+    }
+
+    #[test]
+    fn try_retry_timeout() {
+        let budget = RetryBudget::new(limits(None, Some(Duration::ZERO)));
+        assert!(budget.exhausted());
+        assert!(!budget.try_retry());
+    }
+
+    #[test]
+    fn record_attempt() {
+        let budget = RetryBudget::new(limits(Some(1), None));
+        budget.record_attempt(false);
+        assert!(!budget.exhausted());
+        budget.record_attempt(true);
+        assert!(budget.exhausted());
+        assert_eq!(2, budget.attempts());
+    }
+
+    #[tokio::test]
+    async fn scoped_try_retry() {
+        assert!(try_retry());
+        let budget = Arc::new(RetryBudget::new(limits(Some(0), None)));
+        assert!(!scope(budget, async { try_retry() }).await);
+    }
+
+    #[tokio::test]
+    async fn scoped_current() {
+        assert!(current().is_none());
+        let budget = Arc::new(RetryBudget::new(limits(Some(0), None)));
+        let current = scope(budget.clone(), async { current() }).await;
+        assert!(Arc::ptr_eq(&budget, &current.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn s3_client_attempts() {
+        let supervisor = Supervisor::new();
+        let url = demo::start(&supervisor).unwrap();
+        let client = S3ClientMap::new(None).get(&url, S3Credentials::None).await;
+        let resource_manager = ResourceManager::new(None, None, None, false);
+        let budget = Arc::new(RetryBudget::new(RetryLimits::default()));
+        scope(budget.clone(), async {
+            client
+                .download_object(
+                    demo::BUCKET,
+                    "int32.dat",
+                    None,
+                    &resource_manager,
+                    &mut None,
+                )
+                .await
+                .unwrap();
+            // Missing objects are not retried.
+            client
+                .download_object(
+                    demo::BUCKET,
+                    "missing.dat",
+                    None,
+                    &resource_manager,
+                    &mut None,
+                )
+                .await
+                .unwrap_err();
+        })
+        .await;
+        assert_eq!(2, budget.attempts());
+        supervisor.abort_all();
+    }
+}
@@ -1,15 +1,24 @@
-//! A simplified S3 client that supports downloading objects.
+//! A simplified S3 client that supports downloading and listing objects.
 //! It attempts to hide the complexities of working with the AWS SDK for S3.
 
 use crate::error::ActiveStorageError;
+use crate::models::ListResponse;
 use crate::resource_manager::ResourceManager;
+use crate::retry_budget;
 
 use aws_credential_types::Credentials;
 use aws_sdk_s3::config::BehaviorVersion;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::operation::head_bucket::HeadBucketError;
+use aws_sdk_s3::types::RequestPayer;
 use aws_sdk_s3::Client;
+use aws_smithy_runtime_api::client::http::SharedHttpClient;
+use aws_smithy_types::byte_stream::error::Error as ByteStreamError;
+use aws_smithy_types::byte_stream::ByteStream;
 use aws_types::region::Region;
 use axum::body::Bytes;
 use hashbrown::HashMap;
+use std::future::Future;
 use tokio::sync::{RwLock, SemaphorePermit};
 use tracing::Instrument;
 use url::Url;
@@ -33,17 +42,42 @@ impl S3Credentials {
     }
 }
 
+/// Options for connecting to an object store.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct S3ClientOptions {
+    /// Region of the object store.
+    pub region: String,
+    /// Whether to use path-style addressing rather than virtual-hosted-style addressing.
+    pub force_path_style: bool,
+    /// Whether the requester pays for requests to requester pays buckets.
+    pub requester_pays: bool,
+}
+
+impl Default for S3ClientOptions {
+    /// Returns the default options, which are suitable for most S3-compatible object stores.
+    fn default() -> Self {
+        Self {
+            region: "us-east-1".to_string(),
+            force_path_style: true,
+            requester_pays: false,
+        }
+    }
+}
+
 /// A map containing initialised S3Client objects.
 ///
 /// The [aws_sdk_s3::Client] object is relatively expensive to create, so we reuse them where
 /// possible. This type provides a map for storing the clients objects.
 ///
-/// The map's key is a 2-tuple of the S3 URL and credentials.
+/// The map's key is a 3-tuple of the S3 URL, client options and credentials.
 /// The value is the corresponding client object.
 pub struct S3ClientMap {
     /// A [hashbrown::HashMap] for storing the S3 clients. A read-write lock synchronises access to
     /// the map, optimised for reads.
-    map: RwLock<HashMap<(Url, S3Credentials), S3Client>>,
+    map: RwLock<HashMap<(Url, S3ClientOptions, S3Credentials), S3Client>>,
+
+    /// Optional HTTP client shared by all S3 clients. If `None`, the SDK's default is used.
+    http_client: Option<SharedHttpClient>,
 }
 
 // FIXME: Currently clients are never removed from the map. If a large number of endpoints or
@@ -51,21 +85,42 @@ pub struct S3ClientMap {
 // clients. An ageing mechanism should be implemented
 impl S3ClientMap {
     /// Create and return an [crate::s3_client::S3ClientMap].
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client`: Optional HTTP client to use for all S3 clients
+    pub fn new(http_client: Option<SharedHttpClient>) -> Self {
         S3ClientMap {
             map: RwLock::new(HashMap::new()),
+            http_client,
         }
     }
 
-    /// Get or create an [crate::s3_client::S3Client] object from the map.
+    /// Get or create an [crate::s3_client::S3Client] object from the map using default options.
     ///
     /// # Arguments
     ///
     /// * `url`: Object storage API URL
     /// * `credentials`: Object storage account credentials
     pub async fn get(&self, url: &Url, credentials: S3Credentials) -> S3Client {
-        let key = (url.clone(), credentials.clone());
+        self.get_with_options(url, &S3ClientOptions::default(), credentials)
+            .await
+    }
+
+    /// Get or create an [crate::s3_client::S3Client] object from the map.
+    ///
+    /// # Arguments
+    ///
+    /// * `url`: Object storage API URL
+    /// * `options`: Object storage client options
+    /// * `credentials`: Object storage account credentials
+    pub async fn get_with_options(
+        &self,
+        url: &Url,
+        options: &S3ClientOptions,
+        credentials: S3Credentials,
+    ) -> S3Client {
+        let key = (url.clone(), options.clone(), credentials.clone());
         // Common case: return an existing client from the map.
         {
             let map = self.map.read().await;
@@ -80,11 +135,19 @@ impl S3ClientMap {
             client.clone()
         } else {
             tracing::info!("Creating new S3 client for {}", url);
-            let client = S3Client::new(url, credentials).await;
+            let client =
+                S3Client::new_with_options(url, options, credentials, self.http_client.clone())
+                    .await;
             let (_, client) = map.insert_unique_unchecked(key, client);
             client.clone()
         }
     }
+
+    /// Returns the number of clients in the map.
+    #[cfg(test)]
+    pub(crate) async fn len(&self) -> usize {
+        self.map.read().await.len()
+    }
 }
 
 /// S3 client object.
@@ -92,17 +155,41 @@ impl S3ClientMap {
 pub struct S3Client {
     /// Underlying AWS SDK S3 client object.
     client: Client,
+    /// Value of the x-amz-request-payer header for requests, if any.
+    request_payer: Option<RequestPayer>,
 }
 
 impl S3Client {
+    /// Creates an S3Client object using default options
+    ///
+    /// # Arguments
+    ///
+    /// * `url`: Object storage API URL
+    /// * `credentials`: Object storage account credentials
+    /// * `http_client`: Optional HTTP client. If `None`, the SDK's default is used.
+    pub async fn new(
+        url: &Url,
+        credentials: S3Credentials,
+        http_client: Option<SharedHttpClient>,
+    ) -> Self {
+        Self::new_with_options(url, &S3ClientOptions::default(), credentials, http_client).await
+    }
+
     /// Creates an S3Client object
     ///
     /// # Arguments
     ///
     /// * `url`: Object storage API URL
+    /// * `options`: Object storage client options
     /// * `credentials`: Object storage account credentials
-    pub async fn new(url: &Url, credentials: S3Credentials) -> Self {
-        let region = Region::new("us-east-1");
+    /// * `http_client`: Optional HTTP client. If `None`, the SDK's default is used.
+    pub async fn new_with_options(
+        url: &Url,
+        options: &S3ClientOptions,
+        credentials: S3Credentials,
+        http_client: Option<SharedHttpClient>,
+    ) -> Self {
+        let region = Region::new(options.region.clone());
         let builder = aws_sdk_s3::Config::builder().behavior_version(BehaviorVersion::latest());
         let builder = match credentials {
             S3Credentials::AccessKey {
@@ -114,13 +201,73 @@ impl S3Client {
             }
             S3Credentials::None => builder,
         };
+        let builder = match http_client {
+            Some(http_client) => builder.http_client(http_client),
+            None => builder,
+        };
+        // Count attempts and limit retries using the retry budget of the current request.
         let s3_config = builder
+            .interceptor(retry_budget::AttemptCounter)
+            .retry_classifier(retry_budget::BudgetClassifier)
             .region(Some(region))
             .endpoint_url(url.to_string())
-            .force_path_style(true)
+            .force_path_style(options.force_path_style)
             .build();
         let client = Client::from_conf(s3_config);
-        Self { client }
+        let request_payer = options.requester_pays.then_some(RequestPayer::Requester);
+        Self {
+            client,
+            request_payer,
+        }
+    }
+
+    /// Sends a HEAD request for a bucket
+    ///
+    /// This may be used to establish a connection to the object store in advance of requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket`: Name of the bucket
+    pub async fn head_bucket(&self, bucket: &str) -> Result<(), SdkError<HeadBucketError>> {
+        self.client.head_bucket().bucket(bucket).send().await?;
+        Ok(())
+    }
+
+    /// Lists objects in a bucket and returns a page of their keys
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket`: Name of the bucket
+    /// * `prefix`: Optional prefix of the keys to list
+    /// * `max_keys`: Optional maximum number of keys to return
+    /// * `continuation_token`: Optional continuation token from a previous page
+    pub async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: Option<String>,
+        max_keys: Option<i32>,
+        continuation_token: Option<String>,
+    ) -> Result<ListResponse, ActiveStorageError> {
+        let response = self
+            .client
+            .list_objects_v2()
+            .bucket(bucket)
+            .set_prefix(prefix)
+            .set_max_keys(max_keys)
+            .set_continuation_token(continuation_token)
+            .set_request_payer(self.request_payer.clone())
+            .send()
+            .instrument(tracing::Span::current())
+            .await?;
+        let keys = response
+            .contents()
+            .iter()
+            .filter_map(|object| object.key().map(str::to_string))
+            .collect();
+        Ok(ListResponse {
+            keys,
+            continuation_token: response.next_continuation_token().map(str::to_string),
+        })
     }
 
     /// Downloads an object from object storage and returns the data as Bytes
@@ -140,15 +287,56 @@ impl S3Client {
         resource_manager: &'a ResourceManager,
         mem_permits: &mut Option<SemaphorePermit<'a>>,
     ) -> Result<Bytes, ActiveStorageError> {
-        let mut response = self
+        self.download_object_to(
+            bucket,
+            key,
+            range,
+            resource_manager,
+            mem_permits,
+            AlignedBuffer::new,
+        )
+        .await
+    }
+
+    /// Downloads an object from object storage, writing the data to a [BodySink] as it arrives,
+    /// and returns the data produced by the sink as Bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket`: Name of the bucket
+    /// * `key`: Name of the object in the bucket
+    /// * `range`: Optional byte range
+    /// * `resource_manager`: ResourceManager object
+    /// * `mem_permits`: Optional SemaphorePermit for any memory resources reserved
+    /// * `sink`: Function returning a sink for the data, given the length of the body
+    ///
+    /// Returns an [ActiveStorageError::S3RequesterPays] if access is denied and the requester
+    /// does not pay, since requester pays buckets deny such requests.
+    pub async fn download_object_to<'a, S: BodySink>(
+        self: &S3Client,
+        bucket: &str,
+        key: &str,
+        range: Option<String>,
+        resource_manager: &'a ResourceManager,
+        mem_permits: &mut Option<SemaphorePermit<'a>>,
+        sink: impl FnOnce(usize) -> S,
+    ) -> Result<Bytes, ActiveStorageError> {
+        let response = self
             .client
             .get_object()
             .bucket(bucket)
             .key(key)
             .set_range(range)
+            .set_request_payer(self.request_payer.clone())
             .send()
             .instrument(tracing::Span::current())
-            .await?;
+            .await
+            .map_err(|err| match err.code() {
+                Some("AccessDenied") if self.request_payer.is_none() => {
+                    ActiveStorageError::S3RequesterPays(err)
+                }
+                _ => err.into(),
+            })?;
         // Fail if the content length header is missing.
         let content_length: usize = response
             .content_length()
@@ -159,26 +347,154 @@ impl S3Client {
         if mem_permits.is_none() {
             *mem_permits = resource_manager.memory(content_length).await?;
         };
+        read_body(response.body, content_length, sink(content_length)).await
+    }
+}
+
+/// Destination for the data in a streaming response body
+///
+/// Allows data to be transformed as it arrives, rather than once the whole body has been read.
+/// The methods are asynchronous so that sinks may hand CPU-bound work to other threads.
+pub trait BodySink: Send {
+    /// Write a chunk of the body.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk`: Chunk of the body
+    fn write(
+        &mut self,
+        chunk: &[u8],
+    ) -> impl Future<Output = Result<(), ActiveStorageError>> + Send;
+
+    /// Returns the data once the whole body has been written.
+    fn finish(self) -> impl Future<Output = Result<Bytes, ActiveStorageError>> + Send;
+}
+
+/// Body sink that copies the data into an 8-byte aligned buffer
+pub struct AlignedBuffer {
+    /// Aligned buffer with capacity for the whole body.
+    buf: Vec<u8>,
+}
+
+impl AlignedBuffer {
+    /// Returns a new AlignedBuffer object.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_length`: Length of the body
+    pub fn new(content_length: usize) -> Self {
         // The data returned by the S3 client does not have any alignment guarantees. In order to
         // reinterpret the data as an array of numbers with a higher alignment than 1, we need to
         // return the data in Bytes object in which the underlying data has a higher alignment.
         // For now we're hard-coding an alignment of 8 bytes, although this should depend on the
         // data type, and potentially whether there are any SIMD requirements.
         // Create an 8-byte aligned Vec<u8>.
-        let mut buf = maligned::align_first::<u8, maligned::A8>(content_length);
+        Self {
+            buf: maligned::align_first::<u8, maligned::A8>(content_length),
+        }
+    }
+}
 
-        // Iterate over the streaming response, copying data into the aligned Vec<u8>.
-        while let Some(bytes) = response
-            .body
-            .try_next()
-            .instrument(tracing::Span::current())
-            .await?
-        {
-            buf.extend_from_slice(&bytes)
+impl AlignedBuffer {
+    /// Returns an aligned copy of some data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: Data to copy
+    pub fn copy(data: &[u8]) -> Bytes {
+        let mut buffer = Self::new(data.len());
+        buffer.buf.extend_from_slice(data);
+        buffer.buf.into()
+    }
+}
+
+impl BodySink for AlignedBuffer {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), ActiveStorageError> {
+        // The body length is checked against the capacity by read_body, so this does not
+        // reallocate and lose the alignment guarantee.
+        self.buf.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    async fn finish(self) -> Result<Bytes, ActiveStorageError> {
+        Ok(self.buf.into())
+    }
+}
+
+/// Reads a streaming response body into a sink and returns the data produced by the sink
+///
+/// Returns an [ActiveStorageError::S3ContentLengthMismatch] if the length of the body does not
+/// match the Content-Length header, or an [ActiveStorageError::S3ByteStream] if the body fails for
+/// another reason.
+///
+/// # Arguments
+///
+/// * `body`: Streaming response body
+/// * `content_length`: Value of the response's Content-Length header
+/// * `sink`: Sink for the data
+async fn read_body<S: BodySink>(
+    mut body: ByteStream,
+    content_length: usize,
+    mut sink: S,
+) -> Result<Bytes, ActiveStorageError> {
+    let mut received = 0;
+    // Iterate over the streaming response, writing data to the sink.
+    loop {
+        match body.try_next().instrument(tracing::Span::current()).await {
+            Ok(Some(bytes)) => {
+                if received + bytes.len() > content_length {
+                    return Err(ActiveStorageError::S3ContentLengthMismatch {
+                        expected: content_length,
+                        received: received + bytes.len(),
+                        source: None,
+                    });
+                }
+                received += bytes.len();
+                sink.write(&bytes).await?;
+            }
+            Ok(None) => break,
+            // A body shorter than the Content-Length header typically ends in an error.
+            Err(err) if is_short_body(&err) => {
+                return Err(ActiveStorageError::S3ContentLengthMismatch {
+                    expected: content_length,
+                    received,
+                    source: Some(err),
+                })
+            }
+            Err(err) => return Err(err.into()),
         }
-        // Return as Bytes.
-        Ok(buf.into())
     }
+    if received != content_length {
+        return Err(ActiveStorageError::S3ContentLengthMismatch {
+            expected: content_length,
+            received,
+            source: None,
+        });
+    }
+    sink.finish().await
+}
+
+/// Returns whether a body error indicates that the body ended before its Content-Length
+///
+/// # Arguments
+///
+/// * `err`: Error reading the body
+fn is_short_body(err: &ByteStreamError) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+            if io_err.kind() == std::io::ErrorKind::UnexpectedEof {
+                return true;
+            }
+        }
+        if let Some(hyper_err) = err.downcast_ref::<hyper::Error>() {
+            if hyper_err.is_incomplete_message() {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
 }
 
 /// Return an optional byte range string based on the offset and size.
@@ -206,6 +522,7 @@ pub fn get_range(offset: Option<usize>, size: Option<usize>) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http_client::{self, proxy::ProxyConfig, tls};
     use url::Url;
 
     fn make_access_key() -> S3Credentials {
@@ -219,7 +536,7 @@ mod tests {
     #[tokio::test]
     async fn s3_client_map() {
         let url = Url::parse("http://example.com").unwrap();
-        let map = S3ClientMap::new();
+        let map = S3ClientMap::new(None);
         map.get(&url, make_access_key()).await;
         map.get(&url, make_access_key()).await;
         assert_eq!(map.map.read().await.len(), 1);
@@ -230,16 +547,71 @@ mod tests {
         assert_eq!(map.map.read().await.len(), 3);
     }
 
+    #[tokio::test]
+    async fn s3_client_map_options() {
+        let url = Url::parse("http://example.com").unwrap();
+        let map = S3ClientMap::new(None);
+        map.get(&url, S3Credentials::None).await;
+        map.get_with_options(&url, &S3ClientOptions::default(), S3Credentials::None)
+            .await;
+        assert_eq!(map.map.read().await.len(), 1);
+        let options = S3ClientOptions {
+            region: "eu-west-2".to_string(),
+            force_path_style: false,
+            requester_pays: false,
+        };
+        map.get_with_options(&url, &options, S3Credentials::None)
+            .await;
+        assert_eq!(map.map.read().await.len(), 2);
+        let options = S3ClientOptions {
+            requester_pays: true,
+            ..Default::default()
+        };
+        map.get_with_options(&url, &options, S3Credentials::None)
+            .await;
+        assert_eq!(map.map.read().await.len(), 3);
+    }
+
     #[tokio::test]
     async fn new() {
         let url = Url::parse("http://example.com").unwrap();
-        S3Client::new(&url, make_access_key()).await;
+        S3Client::new(&url, make_access_key(), None).await;
     }
 
     #[tokio::test]
     async fn new_no_auth() {
         let url = Url::parse("http://example.com").unwrap();
-        S3Client::new(&url, S3Credentials::None).await;
+        S3Client::new(&url, S3Credentials::None, None).await;
+    }
+
+    #[tokio::test]
+    async fn new_requester_pays() {
+        let url = Url::parse("http://example.com").unwrap();
+        let options = S3ClientOptions {
+            requester_pays: true,
+            ..Default::default()
+        };
+        let client = S3Client::new_with_options(&url, &options, S3Credentials::None, None).await;
+        assert_eq!(Some(RequestPayer::Requester), client.request_payer);
+    }
+
+    #[tokio::test]
+    async fn new_with_proxy() {
+        let url = Url::parse("http://example.com").unwrap();
+        let proxy_url = Url::parse("http://proxy.example.com:3128").unwrap();
+        let proxy = ProxyConfig::new(&proxy_url, &[]).unwrap();
+        let http_client = http_client::build(Some(proxy), None);
+        assert!(http_client.is_some());
+        S3Client::new(&url, S3Credentials::None, http_client).await;
+    }
+
+    #[tokio::test]
+    async fn new_with_tls_config() {
+        let url = Url::parse("https://example.com").unwrap();
+        let tls_config = tls::client_config(None, true).unwrap();
+        let http_client = http_client::build(None, tls_config);
+        assert!(http_client.is_some());
+        S3Client::new(&url, S3Credentials::None, http_client).await;
     }
 
     #[test]
@@ -261,4 +633,47 @@ mod tests {
     fn get_range_size() {
         assert_eq!(Some("bytes=0-1".to_string()), get_range(None, Some(2)));
     }
+
+    #[tokio::test]
+    async fn read_body() {
+        let body = ByteStream::from(vec![1_u8, 2, 3, 4]);
+        let data = super::read_body(body, 4, AlignedBuffer::new(4))
+            .await
+            .unwrap();
+        assert_eq!(&[1, 2, 3, 4], &data[..]);
+        // Check alignment.
+        assert_eq!(0, data.as_ptr() as usize % 8);
+    }
+
+    #[tokio::test]
+    async fn read_body_short() {
+        let body = ByteStream::from(vec![1_u8, 2, 3]);
+        let err = super::read_body(body, 4, AlignedBuffer::new(4))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            "S3 response length mismatch: expected 4 bytes, received 3 bytes",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn short_body() {
+        let err = std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into();
+        assert!(is_short_body(&err));
+        let err = std::io::Error::from(std::io::ErrorKind::ConnectionReset).into();
+        assert!(!is_short_body(&err));
+    }
+
+    #[tokio::test]
+    async fn read_body_long() {
+        let body = ByteStream::from(vec![1_u8, 2, 3, 4, 5]);
+        let err = super::read_body(body, 4, AlignedBuffer::new(4))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            "S3 response length mismatch: expected 4 bytes, received 5 bytes",
+            err.to_string()
+        );
+    }
 }
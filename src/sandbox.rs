@@ -0,0 +1,412 @@
+//! Process sandboxing
+//!
+//! Reductionist processes untrusted data, including compressed data from arbitrary S3 endpoints.
+//! To limit the impact of any vulnerability exploited by such data, the process may optionally be
+//! sandboxed at startup using the following Linux security features:
+//!
+//! * [Landlock](https://landlock.io) restricts filesystem access to read-only access to system
+//!   directories and configured files, with write access only to the request recording file. TCP
+//!   sockets may only bind to the configured listening ports, and connect to the configured S3
+//!   ports and the OTLP metrics endpoint.
+//! * [seccomp](https://www.kernel.org/doc/html/latest/userspace-api/seccomp_filter.html) denies
+//!   system calls that are not required by the service, such as executing programs, tracing
+//!   processes and loading kernel modules.
+//!
+//! The sandbox is applied before any threads are created, so that all threads inherit it.
+//! Restrictions that are not supported by the running kernel are not enforced: Landlock requires
+//! Linux 5.13, and Linux 6.7 for TCP restrictions.
+
+use crate::cli::{CommandLineArgs, MetricsBackend};
+
+use strum_macros::Display;
+
+/// Extent to which the sandbox is enforced by the running kernel.
+#[derive(Clone, Copy, Debug, Display, PartialEq)]
+pub enum Enforcement {
+    /// All restrictions are enforced.
+    #[strum(serialize = "fully enforced")]
+    Full,
+    /// Some restrictions are not supported by the kernel.
+    #[strum(serialize = "partially enforced")]
+    Partial,
+}
+
+/// System directories that may be read, e.g. for name resolution, TLS root certificates and
+/// process metrics.
+#[cfg(target_os = "linux")]
+const SYSTEM_READ_PATHS: &[&str] = &["/etc", "/usr", "/lib", "/lib64", "/proc", "/sys"];
+
+/// Returns the paths of configured files that may be read.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+fn read_paths(args: &CommandLineArgs) -> Vec<&str> {
+    let mut paths = Vec::new();
+    if args.https {
+        paths.push(args.cert_file.as_str());
+        paths.push(args.key_file.as_str());
+        paths.extend(args.client_ca_file.as_deref());
+    }
+    paths.extend(args.s3_ca_bundle.as_deref());
+    paths.extend(args.sources_file.as_deref());
+    paths
+}
+
+/// Returns the TCP ports to which the process may connect.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+fn connect_ports(args: &CommandLineArgs) -> Vec<u16> {
+    let mut ports = args.sandbox_connect_ports.clone();
+    ports.extend(
+        args.s3_proxy
+            .as_ref()
+            .and_then(|proxy| proxy.port_or_known_default()),
+    );
+    // StatsD uses UDP, which is not restricted.
+    if args.metrics_backend == MetricsBackend::Otlp {
+        ports.extend(
+            args.metrics_endpoint
+                .as_deref()
+                .and_then(|endpoint| url::Url::parse(endpoint).ok())
+                .and_then(|endpoint| endpoint.port_or_known_default()),
+        );
+    }
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+/// Returns the TCP ports to which the process may bind.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+fn bind_ports(args: &CommandLineArgs) -> Vec<u16> {
+    std::iter::once(args.port).chain(args.admin_port).collect()
+}
+
+/// Apply the sandbox to the current process.
+///
+/// Must be called before any threads are created.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments
+#[cfg(target_os = "linux")]
+pub fn apply(args: &CommandLineArgs) -> Result<Enforcement, String> {
+    let landlock = access::restrict(args)?;
+    // The seccomp filter is only defined for some architectures.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    let seccomp = {
+        seccomp::restrict()?;
+        true
+    };
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let seccomp = false;
+    if landlock && seccomp {
+        Ok(Enforcement::Full)
+    } else {
+        Ok(Enforcement::Partial)
+    }
+}
+
+/// Apply the sandbox to the current process.
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_args: &CommandLineArgs) -> Result<Enforcement, String> {
+    Err("sandboxing is only supported on Linux".to_string())
+}
+
+/// Landlock filesystem and network access restrictions.
+#[cfg(target_os = "linux")]
+mod access {
+    use super::{bind_ports, connect_ports, read_paths, SYSTEM_READ_PATHS};
+    use crate::cli::CommandLineArgs;
+
+    use expanduser::expanduser;
+    use landlock::{
+        Access, AccessFs, AccessNet, NetPort, PathBeneath, PathFd, Ruleset, RulesetAttr,
+        RulesetCreatedAttr, RulesetStatus, ABI,
+    };
+
+    /// Landlock ABI version used to determine the access rights to restrict.
+    const LANDLOCK_ABI: ABI = ABI::V4;
+
+    /// Restrict filesystem and network access using Landlock.
+    ///
+    /// Returns whether all restrictions are enforced.
+    ///
+    /// # Arguments
+    ///
+    /// * `args`: Command line arguments
+    pub fn restrict(args: &CommandLineArgs) -> Result<bool, String> {
+        let error = |err: &dyn std::fmt::Display| format!("failed to apply Landlock: {}", err);
+        let path_fd = |path: &str| {
+            let path = expanduser(path).map_err(|err| error(&err))?;
+            PathFd::new(&path).map_err(|err| format!("{}: {}", path.display(), err))
+        };
+        let mut ruleset = Ruleset::default()
+            .handle_access(AccessFs::from_all(LANDLOCK_ABI))
+            .and_then(|ruleset| ruleset.handle_access(AccessNet::from_all(LANDLOCK_ABI)))
+            .and_then(|ruleset| ruleset.create())
+            .map_err(|err| error(&err))?;
+        // System directories may not all exist.
+        for path in SYSTEM_READ_PATHS {
+            if let Ok(fd) = PathFd::new(path) {
+                ruleset = ruleset
+                    .add_rule(PathBeneath::new(fd, AccessFs::from_read(LANDLOCK_ABI)))
+                    .map_err(|err| error(&err))?;
+            }
+        }
+        for path in read_paths(args) {
+            ruleset = ruleset
+                .add_rule(PathBeneath::new(
+                    path_fd(path)?,
+                    AccessFs::from_read(LANDLOCK_ABI),
+                ))
+                .map_err(|err| error(&err))?;
+        }
+        if let Some(path) = &args.record_requests {
+            // Create the file if necessary, since files cannot be created once restricted.
+            let expanded = expanduser(path).map_err(|err| error(&err))?;
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&expanded)
+                .map_err(|err| format!("failed to open {}: {}", expanded.display(), err))?;
+            let access = AccessFs::ReadFile | AccessFs::WriteFile;
+            ruleset = ruleset
+                .add_rule(PathBeneath::new(path_fd(path)?, access))
+                .map_err(|err| error(&err))?;
+        }
+        for port in bind_ports(args) {
+            ruleset = ruleset
+                .add_rule(NetPort::new(port, AccessNet::BindTcp))
+                .map_err(|err| error(&err))?;
+        }
+        for port in connect_ports(args) {
+            ruleset = ruleset
+                .add_rule(NetPort::new(port, AccessNet::ConnectTcp))
+                .map_err(|err| error(&err))?;
+        }
+        let status = ruleset.restrict_self().map_err(|err| error(&err))?;
+        Ok(status.ruleset == RulesetStatus::FullyEnforced)
+    }
+}
+
+/// seccomp system call restrictions.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod seccomp {
+    use libc::{sock_filter, sock_fprog};
+
+    /// Audit architecture of the current target, as defined in `linux/audit.h`.
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    /// Offset of the system call number in `struct seccomp_data`.
+    const SECCOMP_DATA_NR: u32 = 0;
+    /// Offset of the architecture in `struct seccomp_data`.
+    const SECCOMP_DATA_ARCH: u32 = 4;
+
+    /// Bit set in the system call numbers of the x32 ABI on x86_64.
+    #[cfg(target_arch = "x86_64")]
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// System calls that are denied with `EPERM`.
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_add_key,
+        libc::SYS_bpf,
+        libc::SYS_chroot,
+        libc::SYS_delete_module,
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_finit_module,
+        libc::SYS_init_module,
+        libc::SYS_kexec_load,
+        libc::SYS_keyctl,
+        libc::SYS_mount,
+        libc::SYS_perf_event_open,
+        libc::SYS_pivot_root,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_ptrace,
+        libc::SYS_reboot,
+        libc::SYS_request_key,
+        libc::SYS_setns,
+        libc::SYS_swapoff,
+        libc::SYS_swapon,
+        libc::SYS_umount2,
+        libc::SYS_unshare,
+        libc::SYS_userfaultfd,
+    ];
+
+    /// Returns a BPF statement.
+    const fn stmt(code: u32, k: u32) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    /// Returns a BPF jump.
+    const fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    /// Returns a seccomp BPF program that denies the [DENIED_SYSCALLS].
+    ///
+    /// System calls for other architectures kill the process.
+    pub(super) fn filter() -> Vec<sock_filter> {
+        let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+        let jeq = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+        let ret = libc::BPF_RET | libc::BPF_K;
+        let mut filter = vec![
+            stmt(load, SECCOMP_DATA_ARCH),
+            jump(jeq, AUDIT_ARCH, 1, 0),
+            stmt(ret, libc::SECCOMP_RET_KILL_PROCESS),
+            stmt(load, SECCOMP_DATA_NR),
+        ];
+        let denied = DENIED_SYSCALLS.len() as u8;
+        // Each jump skips the remaining jumps and the allow statement.
+        #[cfg(target_arch = "x86_64")]
+        filter.push(jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            X32_SYSCALL_BIT,
+            denied + 1,
+            0,
+        ));
+        for (i, nr) in DENIED_SYSCALLS.iter().enumerate() {
+            filter.push(jump(jeq, *nr as u32, denied - i as u8, 0));
+        }
+        filter.push(stmt(ret, libc::SECCOMP_RET_ALLOW));
+        filter.push(stmt(ret, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        filter
+    }
+
+    /// Deny system calls using a seccomp filter.
+    pub fn restrict() -> Result<(), String> {
+        let filter = filter();
+        let program = sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_ptr() as *mut sock_filter,
+        };
+        // SAFETY: The program is valid for the duration of the call, and the kernel copies it.
+        let result = unsafe {
+            // Landlock has already set no_new_privs, but it is required for an unprivileged
+            // process to install a filter.
+            libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0);
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                libc::SECCOMP_FILTER_FLAG_TSYNC,
+                &program as *const sock_fprog,
+            )
+        };
+        if result != 0 {
+            return Err(format!(
+                "failed to apply seccomp filter: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn parse(args: &[&str]) -> CommandLineArgs {
+        CommandLineArgs::parse_from(std::iter::once("reductionist").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn read_paths_default() {
+        assert!(read_paths(&parse(&[])).is_empty());
+    }
+
+    #[test]
+    fn read_paths_configured() {
+        let args = parse(&[
+            "--https",
+            "--cert-file=cert.pem",
+            "--key-file=key.pem",
+            "--client-ca-file=client-ca.pem",
+            "--s3-ca-bundle=ca.pem",
+            "--sources-file=sources.json",
+        ]);
+        assert_eq!(
+            vec![
+                "cert.pem",
+                "key.pem",
+                "client-ca.pem",
+                "ca.pem",
+                "sources.json"
+            ],
+            read_paths(&args)
+        );
+    }
+
+    #[test]
+    fn connect_ports_default() {
+        assert_eq!(vec![80, 443], connect_ports(&parse(&[])));
+    }
+
+    #[test]
+    fn connect_ports_configured() {
+        let args = parse(&[
+            "--sandbox-connect-ports=9000,443",
+            "--s3-proxy=http://proxy:3128",
+        ]);
+        assert_eq!(vec![443, 3128, 9000], connect_ports(&args));
+    }
+
+    #[test]
+    fn connect_ports_otlp() {
+        let args = parse(&[
+            "--metrics-backend=otlp",
+            "--metrics-endpoint=http://collector:4318/v1/metrics",
+        ]);
+        assert_eq!(vec![80, 443, 4318], connect_ports(&args));
+    }
+
+    #[test]
+    fn bind_ports_admin() {
+        assert_eq!(vec![8080], bind_ports(&parse(&[])));
+        let args = parse(&["--port=8000", "--admin-port=8081"]);
+        assert_eq!(vec![8000, 8081], bind_ports(&args));
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn seccomp_filter() {
+        let filter = seccomp::filter();
+        // Each jump to the deny statement lands on it.
+        let deny = filter.len() - 1;
+        for (i, statement) in filter.iter().enumerate().skip(4) {
+            if statement.jt != 0 {
+                assert_eq!(deny, i + 1 + statement.jt as usize);
+            }
+        }
+        assert_eq!(libc::SECCOMP_RET_ERRNO | libc::EPERM as u32, filter[deny].k);
+        assert_eq!(libc::SECCOMP_RET_ALLOW, filter[deny - 1].k);
+    }
+}
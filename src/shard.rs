@@ -0,0 +1,168 @@
+//! Zarr v3 sharding support
+//!
+//! A Zarr v3 shard is a single object containing multiple inner chunks, with an index that
+//! records the byte range of each chunk within the shard. The index is an array of
+//! `(offset, nbytes)` pairs of little-endian `uint64`s, one for each chunk in C order, optionally
+//! followed by a CRC32C checksum. Empty chunks have an offset and size of `2^64 - 1`.
+//!
+//! See the [sharding codec specification](https://zarr-specs.readthedocs.io/en/latest/v3/codecs/sharding-indexed/v1.0.html).
+
+use crate::error::ActiveStorageError;
+use crate::models::{IndexLocation, Shard};
+
+/// Size in bytes of each entry in the shard index.
+const INDEX_ENTRY_SIZE: usize = 16;
+
+/// Size in bytes of the shard index checksum.
+const CHECKSUM_SIZE: usize = 4;
+
+/// Offset and size of an empty chunk in the shard index.
+const EMPTY: u64 = u64::MAX;
+
+/// Returns the size in bytes of the shard index.
+///
+/// # Arguments
+///
+/// * `shard`: Inner chunk of the shard
+pub fn index_size(shard: &Shard) -> usize {
+    let entries: usize = shard.chunks_per_shard.iter().product();
+    let checksum = if shard.index_crc32c { CHECKSUM_SIZE } else { 0 };
+    entries * INDEX_ENTRY_SIZE + checksum
+}
+
+/// Returns the byte range of the shard index, compatible with the HTTP Range header.
+///
+/// # Arguments
+///
+/// * `shard`: Inner chunk of the shard
+pub fn index_range(shard: &Shard) -> String {
+    match shard.index_location {
+        IndexLocation::Start => format!("bytes=0-{}", index_size(shard) - 1),
+        IndexLocation::End => format!("bytes=-{}", index_size(shard)),
+    }
+}
+
+/// Returns the offset and size in bytes of the inner chunk within the shard.
+///
+/// # Arguments
+///
+/// * `shard`: Inner chunk of the shard
+/// * `index`: Shard index data
+pub fn chunk_range(shard: &Shard, index: &[u8]) -> Result<(usize, usize), ActiveStorageError> {
+    let invalid = |reason| ActiveStorageError::ShardIndexInvalid { reason };
+    if index.len() != index_size(shard) {
+        return Err(invalid("unexpected index size"));
+    }
+    if shard.index_crc32c {
+        let (entries, checksum) = index.split_at(index.len() - CHECKSUM_SIZE);
+        let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+        if crc32c::crc32c(entries) != checksum {
+            return Err(invalid("checksum mismatch"));
+        }
+    }
+    // Linear index of the chunk in C order.
+    let position = shard
+        .chunk
+        .iter()
+        .zip(&shard.chunks_per_shard)
+        .fold(0, |position, (index, len)| position * len + index);
+    let entry = &index[position * INDEX_ENTRY_SIZE..(position + 1) * INDEX_ENTRY_SIZE];
+    let offset = u64::from_le_bytes(entry[..8].try_into().unwrap());
+    let size = u64::from_le_bytes(entry[8..].try_into().unwrap());
+    if (offset, size) == (EMPTY, EMPTY) || size == 0 {
+        return Err(ActiveStorageError::ShardChunkEmpty {
+            chunk: shard.chunk.clone(),
+        });
+    }
+    if offset.checked_add(size).is_none() {
+        return Err(invalid("chunk range out of bounds"));
+    }
+    Ok((offset.try_into()?, size.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_shard(index_location: IndexLocation, index_crc32c: bool) -> Shard {
+        Shard {
+            chunks_per_shard: vec![2, 3],
+            chunk: vec![1, 2],
+            index_location,
+            index_crc32c,
+        }
+    }
+
+    fn make_index(entries: &[(u64, u64)], checksum: bool) -> Vec<u8> {
+        let mut index: Vec<u8> = entries
+            .iter()
+            .flat_map(|(offset, size)| [offset.to_le_bytes(), size.to_le_bytes()])
+            .flatten()
+            .collect();
+        if checksum {
+            let checksum = crc32c::crc32c(&index);
+            index.extend_from_slice(&checksum.to_le_bytes());
+        }
+        index
+    }
+
+    #[test]
+    fn index_size_and_range() {
+        let shard = make_shard(IndexLocation::End, true);
+        assert_eq!(100, index_size(&shard));
+        assert_eq!("bytes=-100", index_range(&shard));
+        let shard = make_shard(IndexLocation::Start, false);
+        assert_eq!(96, index_size(&shard));
+        assert_eq!("bytes=0-95", index_range(&shard));
+    }
+
+    #[test]
+    fn chunk_range_ok() {
+        let entries: Vec<(u64, u64)> = (0..6).map(|i| (i * 10, 10)).collect();
+        for checksum in [false, true] {
+            let shard = make_shard(IndexLocation::End, checksum);
+            let index = make_index(&entries, checksum);
+            assert_eq!((50, 10), chunk_range(&shard, &index).unwrap());
+        }
+    }
+
+    #[test]
+    fn chunk_range_empty() {
+        let mut entries = vec![(0, 10); 6];
+        entries[5] = (EMPTY, EMPTY);
+        let shard = make_shard(IndexLocation::End, true);
+        let err = chunk_range(&shard, &make_index(&entries, true)).unwrap_err();
+        assert_eq!("shard chunk [1, 2] is empty", err.to_string());
+    }
+
+    #[test]
+    fn chunk_range_bad_checksum() {
+        let shard = make_shard(IndexLocation::End, true);
+        let mut index = make_index(&[(0, 10); 6], true);
+        index[0] ^= 1;
+        let err = chunk_range(&shard, &index).unwrap_err();
+        assert_eq!("invalid shard index: checksum mismatch", err.to_string());
+    }
+
+    #[test]
+    fn chunk_range_bad_size() {
+        let shard = make_shard(IndexLocation::End, true);
+        let err = chunk_range(&shard, &make_index(&[(0, 10); 6], false)).unwrap_err();
+        assert_eq!(
+            "invalid shard index: unexpected index size",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn chunk_range_out_of_bounds() {
+        let mut entries = vec![(0, 10); 6];
+        entries[5] = (u64::MAX - 1, 10);
+        let shard = make_shard(IndexLocation::End, false);
+        let err = chunk_range(&shard, &make_index(&entries, false)).unwrap_err();
+        assert_eq!(
+            "invalid shard index: chunk range out of bounds",
+            err.to_string()
+        );
+    }
+}
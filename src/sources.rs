@@ -0,0 +1,555 @@
+//! Named S3 sources
+//!
+//! Sources may be configured on the server in a JSON file, allowing requests to refer to a source
+//! by name rather than by URL. This centralises the policy for each source. For example:
+//!
+//! ```json
+//! {
+//!     "defaults": {"region": "eu-west-2"},
+//!     "sources": {
+//!         "public": {
+//!             "url": "https://s3.example.com",
+//!             "addressing_style": "virtual",
+//!             "credentials": {"mode": "anonymous"},
+//!             "connection_limit": 16,
+//!             "cache_control": "public, max-age=86400",
+//!             "warmup_bucket": "data"
+//!         },
+//!         "aws-open-data": {
+//!             "url": "https://s3.us-east-1.amazonaws.com",
+//!             "region": "us-east-1",
+//!             "requester_pays": true
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Settings not specified for a source are taken from `defaults`.
+//!
+//! S3 clients for named sources are created at startup rather than on first use. If a source has a
+//! `warmup_bucket`, a HEAD request is also sent for the bucket to establish a connection.
+//!
+//! If a source has `requester_pays` set, requests to it accept the charges for requester pays
+//! buckets, such as some public datasets on AWS.
+//!
+//! Sources with `static` credentials lend the server's credentials to clients, so they may only be
+//! used by users verified by the `static` authentication backend, and may be restricted to a list
+//! of users:
+//!
+//! ```json
+//! {"mode": "static", "access_key": "...", "secret_key": "...", "users": ["alice", "bob"]}
+//! ```
+//!
+//! The passthrough authentication backend accepts any user name, so the server refuses to start
+//! with sources that have `static` credentials unless the `static` backend is used.
+
+use crate::auth::Identity;
+use crate::cli::AuthBackend;
+use crate::error::ActiveStorageError;
+use crate::s3_client::{S3ClientMap, S3ClientOptions, S3Credentials};
+
+use aws_smithy_types::error::display::DisplayErrorContext;
+use axum::http::HeaderValue;
+use expanduser::expanduser;
+use hashbrown::HashMap;
+use serde::Deserialize;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use url::Url;
+
+/// Addressing style for S3 requests
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressingStyle {
+    /// Bucket name in the path of the URL
+    Path,
+    /// Bucket name in the host name of the URL
+    Virtual,
+}
+
+/// How to determine the credentials used to access a source
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[serde(tag = "mode")]
+pub enum CredentialsMode {
+    /// Use credentials from the request's basic auth header, if present
+    Passthrough,
+    /// Always access the source anonymously
+    Anonymous,
+    /// Use credentials configured on the server, for verified users
+    ///
+    /// Any user verified by the authentication backend may use the credentials, unless `users`
+    /// lists the users who may. Anonymous and unverified requests are rejected.
+    Static {
+        access_key: String,
+        secret_key: String,
+        users: Option<Vec<String>>,
+    },
+}
+
+/// Settings for a source in the configuration file
+///
+/// All settings are optional, allowing them to be inherited from the defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourceSettings {
+    /// URL of the object store
+    url: Option<Url>,
+    /// Region of the object store
+    region: Option<String>,
+    /// Addressing style
+    addressing_style: Option<AddressingStyle>,
+    /// Credentials mode
+    credentials: Option<CredentialsMode>,
+    /// Maximum number of concurrent connections to the source
+    connection_limit: Option<usize>,
+    /// Value of the Cache-Control header for responses
+    cache_control: Option<String>,
+    /// Bucket for a HEAD request at startup
+    warmup_bucket: Option<String>,
+    /// Whether the requester pays for requests to requester pays buckets
+    requester_pays: Option<bool>,
+}
+
+impl SourceSettings {
+    /// Returns settings with any unspecified values taken from `defaults`.
+    fn or(self, defaults: &SourceSettings) -> Self {
+        let defaults = defaults.clone();
+        Self {
+            url: self.url.or(defaults.url),
+            region: self.region.or(defaults.region),
+            addressing_style: self.addressing_style.or(defaults.addressing_style),
+            credentials: self.credentials.or(defaults.credentials),
+            connection_limit: self.connection_limit.or(defaults.connection_limit),
+            cache_control: self.cache_control.or(defaults.cache_control),
+            warmup_bucket: self.warmup_bucket.or(defaults.warmup_bucket),
+            requester_pays: self.requester_pays.or(defaults.requester_pays),
+        }
+    }
+}
+
+/// Sources configuration file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourcesConfig {
+    /// Default settings for all sources
+    #[serde(default)]
+    defaults: SourceSettings,
+    /// Map of source names to settings
+    sources: std::collections::HashMap<String, SourceSettings>,
+}
+
+/// A named source
+#[derive(Debug)]
+pub struct NamedSource {
+    /// URL of the object store.
+    pub url: Url,
+    /// Object store client options.
+    pub options: S3ClientOptions,
+    /// Credentials mode.
+    pub credentials: CredentialsMode,
+    /// Optional semaphore for connections to the source.
+    connections: Option<Semaphore>,
+    /// Optional value of the Cache-Control header for responses.
+    pub cache_control: Option<HeaderValue>,
+    /// Optional bucket for a HEAD request at startup.
+    warmup_bucket: Option<String>,
+}
+
+impl NamedSource {
+    /// Returns a new NamedSource object.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the source
+    /// * `settings`: Settings for the source, including defaults
+    fn new(name: &str, settings: SourceSettings) -> Result<Self, String> {
+        let url = settings
+            .url
+            .ok_or(format!("source {} must have a URL", name))?;
+        let default_options = S3ClientOptions::default();
+        let options = S3ClientOptions {
+            region: settings.region.unwrap_or(default_options.region),
+            force_path_style: settings
+                .addressing_style
+                .map_or(default_options.force_path_style, |style| {
+                    style == AddressingStyle::Path
+                }),
+            requester_pays: settings
+                .requester_pays
+                .unwrap_or(default_options.requester_pays),
+        };
+        let cache_control = settings
+            .cache_control
+            .map(|value| HeaderValue::from_str(&value))
+            .transpose()
+            .map_err(|err| format!("source {} has invalid cache_control: {}", name, err))?;
+        Ok(Self {
+            url,
+            options,
+            credentials: settings.credentials.unwrap_or(CredentialsMode::Passthrough),
+            connections: settings.connection_limit.map(Semaphore::new),
+            cache_control,
+            warmup_bucket: settings.warmup_bucket,
+        })
+    }
+
+    /// Returns the credentials to use for a request to the source.
+    ///
+    /// Returns `None` if the user may not use the static credentials of the source.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity`: Identity of the authenticated user
+    pub fn credentials(&self, identity: &Identity) -> Option<S3Credentials> {
+        match &self.credentials {
+            CredentialsMode::Passthrough => Some(identity.credentials.clone()),
+            CredentialsMode::Static { users, .. } => {
+                let permitted = identity.verified
+                    && users
+                        .as_ref()
+                        .map_or(true, |users| users.contains(&identity.user));
+                permitted.then(|| self.server_credentials())
+            }
+            CredentialsMode::Anonymous => Some(S3Credentials::None),
+        }
+    }
+
+    /// Returns the credentials used by the server itself, such as to warm up connections.
+    ///
+    /// Sources with passthrough credentials are accessed anonymously.
+    fn server_credentials(&self) -> S3Credentials {
+        match &self.credentials {
+            CredentialsMode::Static {
+                access_key,
+                secret_key,
+                ..
+            } => S3Credentials::access_key(access_key, secret_key),
+            CredentialsMode::Passthrough | CredentialsMode::Anonymous => S3Credentials::None,
+        }
+    }
+
+    /// Acquire a connection to the source, if connections are limited.
+    pub async fn connection(&self) -> Result<Option<SemaphorePermit<'_>>, ActiveStorageError> {
+        match &self.connections {
+            Some(connections) => Ok(Some(connections.acquire().await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Named sources configured on the server.
+#[derive(Debug, Default)]
+pub struct Sources {
+    /// Map of source names to sources.
+    sources: HashMap<String, NamedSource>,
+}
+
+impl Sources {
+    /// Returns a new Sources object, read from a configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: Optional path to a JSON configuration file. If `None`, no sources are configured.
+    /// * `auth_backend`: Authentication backend used by the server
+    pub fn new(path: Option<&str>, auth_backend: AuthBackend) -> Result<Self, String> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let path = expanduser(path).map_err(|err| err.to_string())?;
+        let json = std::fs::read_to_string(&path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let sources = Self::from_json(&json)?;
+        sources.check_auth_backend(auth_backend)?;
+        Ok(sources)
+    }
+
+    /// Checks that the authentication backend verifies the users of sources with static
+    /// credentials.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_backend`: Authentication backend used by the server
+    fn check_auth_backend(&self, auth_backend: AuthBackend) -> Result<(), String> {
+        if auth_backend == AuthBackend::Static {
+            return Ok(());
+        }
+        let mut names = self
+            .sources
+            .iter()
+            .filter(|(_, source)| matches!(source.credentials, CredentialsMode::Static { .. }))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        match names.first() {
+            Some(name) => Err(format!(
+                "source {} has static credentials, which require the static authentication backend",
+                name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a new Sources object, parsed from JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `json`: JSON configuration
+    fn from_json(json: &str) -> Result<Self, String> {
+        let config: SourcesConfig = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let sources = config
+            .sources
+            .into_iter()
+            .map(|(name, settings)| {
+                let source = NamedSource::new(&name, settings.or(&config.defaults))?;
+                Ok((name, source))
+            })
+            .collect::<Result<HashMap<String, NamedSource>, String>>()?;
+        Ok(Self { sources })
+    }
+
+    /// Warm up S3 clients for all named sources.
+    ///
+    /// A client is created for each source, using the credentials for anonymous requests if the
+    /// source uses passthrough credentials. If the source has a warmup bucket, a HEAD request is
+    /// sent for the bucket. Failures are logged but otherwise ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `s3_client_map`: Map of S3 clients
+    pub async fn warm_up(&self, s3_client_map: &S3ClientMap) {
+        for (name, source) in &self.sources {
+            let credentials = source.server_credentials();
+            let client = s3_client_map
+                .get_with_options(&source.url, &source.options, credentials)
+                .await;
+            if let Some(bucket) = &source.warmup_bucket {
+                match client.head_bucket(bucket).await {
+                    Ok(()) => tracing::info!("Warmed up connection to source {}", name),
+                    Err(err) => tracing::warn!(
+                        "Failed to warm up connection to source {}: {}",
+                        name,
+                        DisplayErrorContext(err)
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Add a source that is accessed anonymously, replacing any source with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the source
+    /// * `url`: URL of the object store
+    pub fn insert_anonymous(&mut self, name: &str, url: Url) {
+        let settings = SourceSettings {
+            url: Some(url),
+            credentials: Some(CredentialsMode::Anonymous),
+            ..Default::default()
+        };
+        let source = NamedSource::new(name, settings).expect("source with a URL should be valid");
+        self.sources.insert(name.to_string(), source);
+    }
+
+    /// Returns the URLs of all sources.
+    pub fn urls(&self) -> impl Iterator<Item = &Url> {
+        self.sources.values().map(|source| &source.url)
+    }
+
+    /// Returns a named source.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the source
+    pub fn get(&self, name: &str) -> Result<&NamedSource, ActiveStorageError> {
+        self.sources
+            .get(name)
+            .ok_or_else(|| ActiveStorageError::UnknownSource {
+                name: name.to_string(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"{
+        "defaults": {"region": "eu-west-2", "connection_limit": 2},
+        "sources": {
+            "minimal": {"url": "http://minimal.example.com"},
+            "full": {
+                "url": "https://full.example.com",
+                "region": "us-west-1",
+                "addressing_style": "virtual",
+                "credentials": {
+                    "mode": "static", "access_key": "foo", "secret_key": "bar", "users": ["alice"]
+                },
+                "connection_limit": 1,
+                "cache_control": "public",
+                "requester_pays": true
+            }
+        }
+    }"#;
+
+    #[test]
+    fn from_json_minimal() {
+        let sources = Sources::from_json(CONFIG).unwrap();
+        let source = sources.get("minimal").unwrap();
+        assert_eq!("http://minimal.example.com/", source.url.as_str());
+        assert_eq!("eu-west-2", source.options.region);
+        assert!(source.options.force_path_style);
+        assert_eq!(CredentialsMode::Passthrough, source.credentials);
+        assert_eq!(2, source.connections.as_ref().unwrap().available_permits());
+        assert_eq!(None, source.cache_control);
+        assert!(!source.options.requester_pays);
+    }
+
+    #[test]
+    fn from_json_full() {
+        let sources = Sources::from_json(CONFIG).unwrap();
+        let source = sources.get("full").unwrap();
+        assert_eq!("https://full.example.com/", source.url.as_str());
+        assert_eq!("us-west-1", source.options.region);
+        assert!(!source.options.force_path_style);
+        let alice = Identity {
+            user: "alice".to_string(),
+            verified: true,
+            credentials: S3Credentials::None,
+        };
+        assert!(Some(S3Credentials::access_key("foo", "bar")) == source.credentials(&alice));
+        assert_eq!(1, source.connections.as_ref().unwrap().available_permits());
+        assert_eq!("public", source.cache_control.as_ref().unwrap());
+        assert!(source.options.requester_pays);
+    }
+
+    #[test]
+    fn from_json_warmup_bucket() {
+        let json = r#"{"defaults": {"warmup_bucket": "foo"}, "sources": {"bar": {"url": "http://example.com"}}}"#;
+        let sources = Sources::from_json(json).unwrap();
+        let source = sources.get("bar").unwrap();
+        assert_eq!(Some("foo"), source.warmup_bucket.as_deref());
+    }
+
+    #[test]
+    fn from_json_missing_url() {
+        let err = Sources::from_json(r#"{"sources": {"foo": {}}}"#).unwrap_err();
+        assert_eq!("source foo must have a URL", err);
+    }
+
+    #[test]
+    fn from_json_unknown_field() {
+        let json = r#"{"sources": {"foo": {"url": "http://example.com", "bar": 1}}}"#;
+        let err = Sources::from_json(json).unwrap_err();
+        assert!(err.starts_with("unknown field `bar`"));
+    }
+
+    #[test]
+    fn new_none() {
+        let sources = Sources::new(None, AuthBackend::Passthrough).unwrap();
+        assert!(sources.sources.is_empty());
+    }
+
+    #[test]
+    fn check_auth_backend() {
+        let sources = Sources::from_json(CONFIG).unwrap();
+        sources.check_auth_backend(AuthBackend::Static).unwrap();
+        let err = sources
+            .check_auth_backend(AuthBackend::Passthrough)
+            .unwrap_err();
+        assert_eq!(
+            "source full has static credentials, which require the static authentication backend",
+            err
+        );
+        let json = r#"{"sources": {"foo": {"url": "http://example.com"}}}"#;
+        let sources = Sources::from_json(json).unwrap();
+        sources
+            .check_auth_backend(AuthBackend::Passthrough)
+            .unwrap();
+    }
+
+    #[test]
+    fn get_unknown() {
+        let sources = Sources::from_json(CONFIG).unwrap();
+        let err = sources.get("foo").unwrap_err();
+        assert_eq!("unknown source foo", err.to_string());
+    }
+
+    #[test]
+    fn credentials() {
+        let request_credentials = S3Credentials::access_key("user", "password");
+        let mut source = NamedSource::new(
+            "foo",
+            SourceSettings {
+                url: Some(Url::parse("http://example.com").unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let identity = Identity {
+            user: "user".to_string(),
+            verified: false,
+            credentials: request_credentials.clone(),
+        };
+        assert!(Some(request_credentials) == source.credentials(&identity));
+        source.credentials = CredentialsMode::Anonymous;
+        assert!(Some(S3Credentials::None) == source.credentials(&identity));
+    }
+
+    #[test]
+    fn credentials_static() {
+        let static_credentials = S3Credentials::access_key("foo", "bar");
+        let mut source = NamedSource::new(
+            "foo",
+            SourceSettings {
+                url: Some(Url::parse("http://example.com").unwrap()),
+                credentials: Some(CredentialsMode::Static {
+                    access_key: "foo".to_string(),
+                    secret_key: "bar".to_string(),
+                    users: None,
+                }),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let identity = Identity {
+            user: "user".to_string(),
+            verified: true,
+            credentials: S3Credentials::None,
+        };
+        // Any verified user may use the static credentials, but anonymous users may not.
+        assert!(Some(static_credentials.clone()) == source.credentials(&identity));
+        assert!(source.credentials(&Identity::anonymous()).is_none());
+        // User names from the passthrough backend are not verified.
+        let passthrough = Identity {
+            verified: false,
+            ..identity.clone()
+        };
+        assert!(source.credentials(&passthrough).is_none());
+        // Only listed users may use the static credentials.
+        source.credentials = CredentialsMode::Static {
+            access_key: "foo".to_string(),
+            secret_key: "bar".to_string(),
+            users: Some(vec!["other".to_string()]),
+        };
+        assert!(source.credentials(&identity).is_none());
+        assert!(static_credentials == source.server_credentials());
+    }
+
+    #[tokio::test]
+    async fn warm_up() {
+        let sources = Sources::from_json(CONFIG).unwrap();
+        let s3_client_map = S3ClientMap::new(None);
+        sources.warm_up(&s3_client_map).await;
+        assert_eq!(2, s3_client_map.len().await);
+    }
+
+    #[tokio::test]
+    async fn connection() {
+        let sources = Sources::from_json(CONFIG).unwrap();
+        let source = sources.get("full").unwrap();
+        let permit = source.connection().await.unwrap();
+        assert!(permit.is_some());
+        assert!(source.connections.as_ref().unwrap().try_acquire().is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Sparse encoding of array results
+//!
+//! Array results of operations on mostly-missing data, such as selections of land-masked ocean
+//! fields, consist mostly of a single fill value. Clients may request a sparse encoding of these
+//! results by sending an `x-activestorage-encoding: sparse` header. The fill value is the missing
+//! value of the request if the result has the same data type as the data, or otherwise NaN for
+//! floating point results.
+//!
+//! A sparse body contains the flat C order indices of the elements that differ from the fill value
+//! as uint64, followed by the values of those elements, all in the native byte order. If the sparse
+//! encoding would not be smaller than the dense encoding, the response is returned unchanged.
+
+use crate::models::{DType, Encoding, Fill, RequestData, Response};
+use crate::operation::Element;
+use crate::types::Missing;
+
+use axum::body::Bytes;
+// Bring trait into scope to use as_bytes method.
+use zerocopy::AsBytes;
+
+/// Returns the fill value for the result of a request, if there is one.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `response`: Response to the operation
+fn fill(request_data: &RequestData, response: &Response) -> Option<Fill> {
+    match &request_data.missing {
+        Some(Missing::MissingValue(value)) if response.dtype == request_data.dtype => {
+            Some(Fill::Value(value.clone()))
+        }
+        _ if matches!(response.dtype, DType::Float32 | DType::Float64) => Some(Fill::NaN),
+        _ => None,
+    }
+}
+
+/// Encode the array result of an operation sparsely if this reduces its size.
+///
+/// Scalar results, and results without a fill value, are returned unchanged.
+///
+/// # Arguments
+///
+/// * `request_data`: RequestData object for the request
+/// * `response`: Response to the operation
+pub fn encode(request_data: &RequestData, response: Response) -> Response {
+    if response.shape.is_empty() || response.encoding != Encoding::Dense {
+        return response;
+    }
+    let Some(fill) = fill(request_data, &response) else {
+        return response;
+    };
+    match response.dtype {
+        DType::Int8 => encode_t::<i8>(fill, response),
+        DType::Int16 => encode_t::<i16>(fill, response),
+        DType::Int32 => encode_t::<i32>(fill, response),
+        DType::Int64 => encode_t::<i64>(fill, response),
+        DType::Uint8 => encode_t::<u8>(fill, response),
+        DType::Uint16 => encode_t::<u16>(fill, response),
+        DType::Uint32 => encode_t::<u32>(fill, response),
+        DType::Uint64 => encode_t::<u64>(fill, response),
+        DType::Float32 => encode_t::<f32>(fill, response),
+        DType::Float64 => encode_t::<f64>(fill, response),
+    }
+}
+
+/// Encode the array result of an operation of a specific type sparsely if this reduces its size.
+///
+/// # Arguments
+///
+/// * `fill`: Fill value
+/// * `response`: Response to the operation
+fn encode_t<T: Element>(fill: Fill, response: Response) -> Response {
+    let is_fill: Box<dyn Fn(&T) -> bool> = match &fill {
+        Fill::NaN => Box::new(|value: &T| value.to_f64().is_some_and(f64::is_nan)),
+        Fill::Value(value) => match T::try_from_dvalue(value.clone()) {
+            Ok(fill) => Box::new(move |value: &T| *value == fill),
+            Err(_) => return response,
+        },
+    };
+    let size = std::mem::size_of::<T>();
+    let (indices, values): (Vec<u64>, Vec<T>) = response
+        .body
+        .chunks_exact(size)
+        .map(|chunk| T::read_from(chunk).expect("chunk should have the size of T"))
+        .enumerate()
+        .filter(|(_, value)| !is_fill(value))
+        .map(|(index, value)| (index as u64, value))
+        .unzip();
+    if indices.len() * (std::mem::size_of::<u64>() + size) >= response.body.len() {
+        return response;
+    }
+    let mut body = Vec::with_capacity(indices.len() * (std::mem::size_of::<u64>() + size));
+    body.extend_from_slice(indices.as_bytes());
+    body.extend_from_slice(values.as_bytes());
+    Response {
+        body: Bytes::from(body),
+        encoding: Encoding::Sparse(fill),
+        ..response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils;
+
+    fn make_response<T: Element>(values: &[T], dtype: DType) -> Response {
+        Response::new(
+            Bytes::copy_from_slice(values.as_bytes()),
+            dtype,
+            vec![values.len()],
+            values.len() as i64,
+        )
+    }
+
+    #[test]
+    fn encode_nan() {
+        let request_data = test_utils::get_test_request_data();
+        let values = [f64::NAN, f64::NAN, 1.5, f64::NAN, f64::NAN, f64::NAN];
+        let response = encode(&request_data, make_response(&values, DType::Float64));
+        assert_eq!(Encoding::Sparse(Fill::NaN), response.encoding);
+        assert_eq!(vec![6], response.shape);
+        let mut expected = 2_u64.as_bytes().to_vec();
+        expected.extend_from_slice(1.5_f64.as_bytes());
+        assert_eq!(expected, response.body);
+    }
+
+    #[test]
+    fn encode_missing_value() {
+        let mut request_data = test_utils::get_test_request_data();
+        request_data.missing = Some(Missing::MissingValue((-1).into()));
+        let values = [-1_i32, -1, -1, 7, -1, -1, -1, -1];
+        let response = encode(&request_data, make_response(&values, DType::Int32));
+        assert_eq!(
+            Encoding::Sparse(Fill::Value((-1).into())),
+            response.encoding
+        );
+        let mut expected = 3_u64.as_bytes().to_vec();
+        expected.extend_from_slice(7_i32.as_bytes());
+        assert_eq!(expected, response.body);
+    }
+
+    #[test]
+    fn encode_dense_when_smaller() {
+        let request_data = test_utils::get_test_request_data();
+        let values = [1.0_f32, f32::NAN, 2.0];
+        let response = encode(&request_data, make_response(&values, DType::Float32));
+        assert_eq!(Encoding::Dense, response.encoding);
+        assert_eq!(values.as_bytes(), response.body);
+    }
+
+    #[test]
+    fn encode_no_fill() {
+        // Integer results without a missing value have no fill value.
+        let request_data = test_utils::get_test_request_data();
+        let values = [0_i64; 8];
+        let response = encode(&request_data, make_response(&values, DType::Int64));
+        assert_eq!(Encoding::Dense, response.encoding);
+    }
+
+    #[test]
+    fn encode_scalar() {
+        let request_data = test_utils::get_test_request_data();
+        let response = Response::new(
+            Bytes::copy_from_slice(f64::NAN.as_bytes()),
+            DType::Float64,
+            vec![],
+            0,
+        );
+        let response = encode(&request_data, response);
+        assert_eq!(Encoding::Dense, response.encoding);
+    }
+
+    #[test]
+    fn fill_display() {
+        assert_eq!("nan", Fill::NaN.to_string());
+        assert_eq!("-1", Fill::Value((-1).into()).to_string());
+    }
+}
@@ -0,0 +1,302 @@
+//! Supervision of background tasks
+//!
+//! Background tasks, such as the maintenance signal handler and the thread limit autotuner, are
+//! spawned via a [Supervisor] rather than detached with `tokio::spawn`. The supervisor keeps a
+//! registry of its tasks, with their names, start times and abort handles. If a task panics, the
+//! panic is logged with the name and uptime of the task and counted in the `task_panics` metric.
+//! Restartable tasks are then restarted after a delay, which doubles after each consecutive panic
+//! up to a limit, so that a task that fails persistently does not spin. Each run of a task is
+//! traced in a root span linked to the span from which the task was spawned.
+
+use crate::metrics::TASK_PANICS;
+use crate::tracing::{background_span, current_span_context};
+
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::AbortHandle;
+use tracing::Instrument;
+
+/// Initial delay before restarting a task that panicked.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Maximum delay before restarting a task that panicked.
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// A supervised task.
+#[derive(Debug)]
+struct Task {
+    /// Name of the task.
+    name: &'static str,
+    /// State of the current run of the task.
+    state: Mutex<TaskState>,
+}
+
+/// State of the current run of a supervised task.
+#[derive(Debug)]
+struct TaskState {
+    /// Time at which the current run started.
+    started: Instant,
+    /// Number of times the task has been restarted.
+    restarts: usize,
+    /// Abort handle for the current run.
+    abort_handle: Option<AbortHandle>,
+}
+
+/// Status of a supervised task.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TaskStatus {
+    /// Name of the task
+    pub name: &'static str,
+    /// Whether the task is running
+    pub running: bool,
+    /// Time since the current run of the task started, in seconds
+    pub uptime: f64,
+    /// Number of times the task has been restarted
+    pub restarts: usize,
+}
+
+/// Registry of supervised background tasks.
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    /// Supervised tasks.
+    tasks: Mutex<Vec<Arc<Task>>>,
+}
+
+impl Supervisor {
+    /// Returns a new Supervisor object.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn a supervised task that is not restarted.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the task
+    /// * `future`: Future to run
+    pub fn spawn<F>(&self, name: &'static str, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut future = Some(future);
+        self.spawn_restartable_with_limit(name, Some(0), move || {
+            future.take().expect("task should not be restarted")
+        });
+    }
+
+    /// Spawn a supervised task that is restarted if it panics.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the task
+    /// * `factory`: Function returning a new future to run each time the task is started
+    pub fn spawn_restartable<M, F>(&self, name: &'static str, factory: M)
+    where
+        M: FnMut() -> F + Send + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_restartable_with_limit(name, None, factory);
+    }
+
+    /// Spawn a supervised task that is restarted if it panics, up to a limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Name of the task
+    /// * `max_restarts`: Optional maximum number of restarts
+    /// * `factory`: Function returning a new future to run each time the task is started
+    fn spawn_restartable_with_limit<M, F>(
+        &self,
+        name: &'static str,
+        max_restarts: Option<usize>,
+        mut factory: M,
+    ) where
+        M: FnMut() -> F + Send + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let origin = current_span_context();
+        let mut factory = move || factory().instrument(background_span(name, &origin));
+        let task = Arc::new(Task {
+            name,
+            state: Mutex::new(TaskState {
+                started: Instant::now(),
+                restarts: 0,
+                abort_handle: None,
+            }),
+        });
+        self.tasks.lock().unwrap().push(task.clone());
+        // Hold the lock while spawning so that the abort handle is set before the task is
+        // observed by the supervising task.
+        let mut state = task.state.lock().unwrap();
+        let handle = tokio::spawn(factory());
+        state.abort_handle = Some(handle.abort_handle());
+        drop(state);
+        tokio::spawn(async move {
+            let mut handle = handle;
+            let mut delay = RESTART_DELAY;
+            loop {
+                let result = handle.await;
+                let (uptime, restarts) = {
+                    let state = task.state.lock().unwrap();
+                    (state.started.elapsed(), state.restarts)
+                };
+                match result {
+                    Ok(()) => {
+                        tracing::debug!("Task {} finished after {:?}", task.name, uptime);
+                        return;
+                    }
+                    Err(err) if err.is_panic() => {
+                        TASK_PANICS.with_label_values(&[task.name]).inc();
+                        tracing::error!(
+                            "Task {} panicked after {:?} and {} restarts: {}",
+                            task.name,
+                            uptime,
+                            restarts,
+                            panic_message(err.into_panic())
+                        );
+                    }
+                    // The task was aborted.
+                    Err(_) => return,
+                }
+                if max_restarts.is_some_and(|max_restarts| restarts >= max_restarts) {
+                    return;
+                }
+                // A task that ran for a while before panicking is not failing persistently.
+                if uptime >= MAX_RESTART_DELAY {
+                    delay = RESTART_DELAY;
+                }
+                tracing::info!("Restarting task {} in {:?}", task.name, delay);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RESTART_DELAY);
+                let mut state = task.state.lock().unwrap();
+                handle = tokio::spawn(factory());
+                state.started = Instant::now();
+                state.restarts += 1;
+                state.abort_handle = Some(handle.abort_handle());
+            }
+        });
+    }
+
+    /// Returns the status of each supervised task.
+    pub fn status(&self) -> Vec<TaskStatus> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|task| {
+                let state = task.state.lock().unwrap();
+                TaskStatus {
+                    name: task.name,
+                    running: state
+                        .abort_handle
+                        .as_ref()
+                        .is_some_and(|handle| !handle.is_finished()),
+                    uptime: state.started.elapsed().as_secs_f64(),
+                    restarts: state.restarts,
+                }
+            })
+            .collect()
+    }
+
+    /// Abort all supervised tasks.
+    pub fn abort_all(&self) {
+        for task in self.tasks.lock().unwrap().iter() {
+            if let Some(handle) = &task.state.lock().unwrap().abort_handle {
+                handle.abort();
+            }
+        }
+    }
+}
+
+/// Returns the message of a panic payload, if it has one.
+///
+/// # Arguments
+///
+/// * `payload`: Panic payload
+pub fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn wait_for(supervisor: &Supervisor, condition: impl Fn(&TaskStatus) -> bool) {
+        for _ in 0..100 {
+            if supervisor.status().iter().all(&condition) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition not met: {:?}", supervisor.status());
+    }
+
+    #[tokio::test]
+    async fn spawn_finishes() {
+        let supervisor = Supervisor::new();
+        supervisor.spawn("test", async {});
+        wait_for(&supervisor, |status| !status.running).await;
+        let status = supervisor.status();
+        assert_eq!(1, status.len());
+        assert_eq!("test", status[0].name);
+        assert_eq!(0, status[0].restarts);
+    }
+
+    #[tokio::test]
+    async fn spawn_panic_not_restarted() {
+        let supervisor = Supervisor::new();
+        supervisor.spawn("test", async { panic!("oops") });
+        wait_for(&supervisor, |status| !status.running).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(0, supervisor.status()[0].restarts);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawn_restartable_panic() {
+        let supervisor = Supervisor::new();
+        let starts = Arc::new(AtomicUsize::new(0));
+        let task_starts = starts.clone();
+        supervisor.spawn_restartable("test", move || {
+            let starts = task_starts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if starts < 2 {
+                    panic!("oops");
+                }
+                std::future::pending::<()>().await
+            }
+        });
+        // Restarts are delayed by 1s, then 2s.
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(3, starts.load(Ordering::SeqCst));
+        let status = supervisor.status();
+        assert_eq!(2, status[0].restarts);
+        assert!(status[0].running);
+    }
+
+    #[tokio::test]
+    async fn abort_all() {
+        let supervisor = Supervisor::new();
+        supervisor.spawn_restartable("test", std::future::pending::<()>);
+        assert!(supervisor.status()[0].running);
+        supervisor.abort_all();
+        wait_for(&supervisor, |status| !status.running).await;
+        assert_eq!(0, supervisor.status()[0].restarts);
+    }
+
+    #[test]
+    fn panic_messages() {
+        assert_eq!("static", panic_message(Box::new("static")));
+        assert_eq!("owned", panic_message(Box::new("owned".to_string())));
+        assert_eq!("unknown panic", panic_message(Box::new(42)));
+    }
+}
@@ -1,12 +1,26 @@
+//! Utilities for testing
+//!
+//! These are used by the unit tests, and are available to client libraries and other integrators
+//! when the `testing` Cargo feature is enabled. They allow valid requests to be constructed and the
+//! expected responses to be generated programmatically, rather than maintaining JSON fixtures that
+//! may drift from the schema.
+
+use crate::app::{self, AppState};
+use crate::cli::CommandLineArgs;
+use crate::error::ActiveStorageError;
 use crate::models::*;
+use crate::operation::{Operation, OperationVisitor};
+use crate::operations;
 use crate::types::{ByteOrder, Missing};
 
+use axum::body::Bytes;
+use clap::Parser;
 use url::Url;
 
 /// Create a RequestData object with only required fields set.
-pub(crate) fn get_test_request_data() -> RequestData {
+pub fn get_test_request_data() -> RequestData {
     RequestData {
-        source: Url::parse("http://example.com").unwrap(),
+        source: Source::Url(Url::parse("http://example.com").unwrap()),
         bucket: "bar".to_string(),
         object: "baz".to_string(),
         dtype: DType::Int32,
@@ -19,13 +33,26 @@ pub(crate) fn get_test_request_data() -> RequestData {
         compression: None,
         filters: None,
         missing: None,
+        shard: None,
+        cf_convention: false,
+        scale_factor: None,
+        add_offset: None,
+        group_by: None,
+        coarsen: None,
+        ensemble: None,
+        expression: None,
+        precision: None,
+        ddof: None,
+        nan_policy: None,
+        partial: false,
+        requester_pays: false,
     }
 }
 
 /// Create a RequestData object with all fields set.
-pub(crate) fn get_test_request_data_optional() -> RequestData {
+pub fn get_test_request_data_optional() -> RequestData {
     RequestData {
-        source: Url::parse("http://example.com").unwrap(),
+        source: Source::Url(Url::parse("http://example.com").unwrap()),
         bucket: "bar".to_string(),
         object: "baz".to_string(),
         dtype: DType::Int32,
@@ -34,9 +61,125 @@ pub(crate) fn get_test_request_data_optional() -> RequestData {
         size: Some(8),
         shape: Some(vec![2, 5]),
         order: Some(Order::C),
-        selection: Some(vec![Slice::new(1, 2, 3), Slice::new(4, 5, 6)]),
+        selection: Some(vec![Slice::new(1, 2, 3).into(), Slice::new(4, 5, 6).into()]),
         compression: Some(Compression::Gzip),
         filters: Some(vec![Filter::Shuffle { element_size: 4 }]),
         missing: Some(Missing::MissingValue(42.into())),
+        shard: None,
+        cf_convention: false,
+        scale_factor: None,
+        add_offset: None,
+        group_by: None,
+        coarsen: None,
+        ensemble: None,
+        expression: None,
+        precision: None,
+        ddof: None,
+        nan_policy: None,
+        partial: false,
+        requester_pays: false,
+    }
+}
+
+/// Execute an operation on object data held in memory.
+///
+/// The request is validated, and the data decoded and the operation executed as the server would
+/// after downloading the data, allowing the expected response to a request to be generated
+/// without an object store. Sharded requests and ensembles are not supported, and no resource
+/// limits are applied.
+///
+/// # Arguments
+///
+/// * `operation`: Name of the operation, as used in the API
+/// * `request_data`: RequestData object for the request
+/// * `data`: Object data for the request's byte range
+pub fn execute(
+    operation: &str,
+    request_data: &RequestData,
+    data: Vec<u8>,
+) -> Result<Response, ActiveStorageError> {
+    let state = AppState::new(&CommandLineArgs::parse_from(["reductionist"]));
+    let visitor = Execute {
+        state: &state,
+        request_data: request_data.clone(),
+        data: data.into(),
+    };
+    operations::visit(operation, visitor).unwrap_or_else(|| {
+        Err(ActiveStorageError::UnsupportedOperation {
+            operation: operation.to_string(),
+        })
+    })
+}
+
+/// Visitor executing an operation on object data held in memory
+struct Execute<'a> {
+    /// Application state with no resource limits
+    state: &'a AppState,
+    /// RequestData object for the request
+    request_data: RequestData,
+    /// Object data for the request's byte range
+    data: Bytes,
+}
+
+impl OperationVisitor for Execute<'_> {
+    type Output = Result<Response, ActiveStorageError>;
+
+    fn visit<T: Operation + 'static>(mut self) -> Self::Output {
+        app::validate_request::<T>(self.state, &self.request_data)?;
+        let data = app::decode(self.state, &mut self.request_data, self.data)?;
+        app::compute::<T>(&self.request_data, data.into(), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn execute_sum() {
+        let mut request_data = get_test_request_data();
+        request_data.dtype = DType::Uint32;
+        let data = [1u32, 2, 3, 4]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        let response = execute("sum", &request_data, data).unwrap();
+        assert_eq!(10u32.to_ne_bytes().as_slice(), response.body);
+        assert_eq!(DType::Uint32, response.dtype);
+        assert_eq!(4, response.count);
+    }
+
+    #[test]
+    fn execute_compressed_selection() {
+        let mut request_data = get_test_request_data_optional();
+        request_data.offset = None;
+        request_data.size = None;
+        request_data.missing = None;
+        request_data.filters = None;
+        let data: Vec<u8> = (0..10i32).flat_map(|value| value.to_le_bytes()).collect();
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &data).unwrap();
+        let response = execute("select", &request_data, encoder.finish().unwrap()).unwrap();
+        // Element 4 of row 1.
+        assert_eq!(9i32.to_ne_bytes().as_slice(), response.body);
+        assert_eq!(vec![1, 1], response.shape);
+    }
+
+    #[test]
+    fn execute_invalid_request() {
+        let request_data = get_test_request_data();
+        assert!(matches!(
+            execute("expr", &request_data, vec![0; 4]),
+            Err(ActiveStorageError::RequestDataValidationSingle(_))
+        ));
+    }
+
+    #[test]
+    fn execute_unknown_operation() {
+        let request_data = get_test_request_data();
+        assert!(matches!(
+            execute("foo", &request_data, vec![0; 4]),
+            Err(ActiveStorageError::UnsupportedOperation { operation }) if operation == "foo"
+        ));
     }
 }
@@ -1,11 +1,23 @@
 //! Tracing (logging)
+//!
+//! Background work, such as supervised tasks and request recording, runs outside of any request
+//! span. It is traced in root spans of its own, which are linked to the span from which the work
+//! originated, so that its latency is visible in Jaeger without extending the request trace.
 
 use crate::cli::CommandLineArgs;
+use crate::error::ActiveStorageError;
 
 use opentelemetry::runtime::Tokio;
 use opentelemetry::sdk::trace::Tracer;
-use opentelemetry::trace::TraceError;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use opentelemetry::trace::{SpanContext, TraceContextExt, TraceError};
+use std::sync::OnceLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+/// Handle for changing the log filter at runtime.
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
 
 /// Initialise and return a Jaeger tracer.
 fn init_tracer() -> Result<Tracer, TraceError> {
@@ -26,11 +38,13 @@ fn init_tracer() -> Result<Tracer, TraceError> {
 /// * `args`: Command line arguments.
 pub fn init_tracing(args: &CommandLineArgs) {
     let tracer = init_tracer().expect("Failed to initialize tracer");
+    let (filter, handle) = reload::Layer::new(
+        EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| "reductionist=debug,tower_http=debug".into()),
+    );
+    let _ = LOG_FILTER.set(handle);
     let subscriber = tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "reductionist=debug,tower_http=debug".into()),
-        )
+        .with(filter)
         .with(tracing_subscriber::fmt::layer());
     if args.enable_jaeger {
         subscriber
@@ -41,7 +55,84 @@ pub fn init_tracing(args: &CommandLineArgs) {
     }
 }
 
+/// Returns the current log filter, if tracing has been initialised.
+pub fn log_filter() -> Option<String> {
+    LOG_FILTER
+        .get()
+        .and_then(|handle| handle.with_current(|filter| filter.to_string()).ok())
+}
+
+/// Change the log filter
+///
+/// The filter uses the same syntax as the `RUST_LOG` environment variable. Has no effect if
+/// tracing has not been initialised.
+///
+/// # Arguments
+///
+/// * `filter`: New log filter
+pub fn set_log_filter(filter: &str) -> Result<(), ActiveStorageError> {
+    let filter = EnvFilter::try_new(filter)?;
+    if let Some(handle) = LOG_FILTER.get() {
+        handle.reload(filter)?;
+    }
+    Ok(())
+}
+
+/// Returns the OpenTelemetry span context of the current span.
+///
+/// The context is invalid if there is no current span or Jaeger tracing is not enabled.
+pub fn current_span_context() -> SpanContext {
+    tracing::Span::current()
+        .context()
+        .span()
+        .span_context()
+        .clone()
+}
+
+/// Returns a new root span for background work, linked to the span from which it originated.
+///
+/// # Arguments
+///
+/// * `name`: Name of the background work
+/// * `origin`: Span context from which the work originated
+pub fn background_span(name: &'static str, origin: &SpanContext) -> tracing::Span {
+    let span = tracing::info_span!(parent: None, "background", name);
+    if origin.is_valid() {
+        span.add_link(origin.clone());
+    }
+    span
+}
+
 /// Shutdown tracing (logging)
 pub fn shutdown_tracing() {
     opentelemetry::global::shutdown_tracer_provider();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use opentelemetry::sdk::trace::TracerProvider;
+    use opentelemetry::trace::TracerProvider as _;
+
+    #[test]
+    fn background_span_is_root() {
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("request");
+            let origin = request.in_scope(current_span_context);
+            assert!(origin.is_valid());
+            let span = request.in_scope(|| background_span("test", &origin));
+            let context = span.context().span().span_context().clone();
+            assert!(context.is_valid());
+            assert_ne!(origin.trace_id(), context.trace_id());
+        });
+    }
+
+    #[test]
+    fn current_span_context_no_span() {
+        assert!(!current_span_context().is_valid());
+    }
+}
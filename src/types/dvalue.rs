@@ -40,6 +40,18 @@ pub trait TryFromDValue: Sized {
 
 // Implement the TryFromDValue trait for all supported numeric data types.
 
+impl TryFromDValue for i8 {
+    fn try_from_dvalue(value: DValue) -> Result<Self, ActiveStorageError> {
+        Self::try_from(as_i64(&value)?).map_err(|_| ActiveStorageError::IncompatibleMissing(value))
+    }
+}
+
+impl TryFromDValue for i16 {
+    fn try_from_dvalue(value: DValue) -> Result<Self, ActiveStorageError> {
+        Self::try_from(as_i64(&value)?).map_err(|_| ActiveStorageError::IncompatibleMissing(value))
+    }
+}
+
 impl TryFromDValue for i32 {
     fn try_from_dvalue(value: DValue) -> Result<Self, ActiveStorageError> {
         Self::try_from(as_i64(&value)?).map_err(|_| ActiveStorageError::IncompatibleMissing(value))
@@ -52,6 +64,18 @@ impl TryFromDValue for i64 {
     }
 }
 
+impl TryFromDValue for u8 {
+    fn try_from_dvalue(value: DValue) -> Result<Self, ActiveStorageError> {
+        Self::try_from(as_u64(&value)?).map_err(|_| ActiveStorageError::IncompatibleMissing(value))
+    }
+}
+
+impl TryFromDValue for u16 {
+    fn try_from_dvalue(value: DValue) -> Result<Self, ActiveStorageError> {
+        Self::try_from(as_u64(&value)?).map_err(|_| ActiveStorageError::IncompatibleMissing(value))
+    }
+}
+
 impl TryFromDValue for u32 {
     fn try_from_dvalue(value: DValue) -> Result<Self, ActiveStorageError> {
         Self::try_from(as_u64(&value)?).map_err(|_| ActiveStorageError::IncompatibleMissing(value))
@@ -98,6 +122,30 @@ mod tests {
         assert!(DValue::from_f64(f64::nan()).is_none());
     }
 
+    #[test]
+    fn test_try_from_dvalue_i8() {
+        let result = i8::try_from_dvalue((-42).into()).unwrap();
+        assert_eq!(-42, result);
+    }
+
+    #[test]
+    #[should_panic(expected = "IncompatibleMissing(Number(128))")]
+    fn test_try_from_dvalue_i8_too_large() {
+        i8::try_from_dvalue(128.into()).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_dvalue_i16() {
+        let result = i16::try_from_dvalue((-42).into()).unwrap();
+        assert_eq!(-42, result);
+    }
+
+    #[test]
+    #[should_panic(expected = "IncompatibleMissing(Number(-32769))")]
+    fn test_try_from_dvalue_i16_too_negative() {
+        i16::try_from_dvalue((-32769).into()).unwrap();
+    }
+
     #[test]
     fn test_try_from_dvalue_i32() {
         let result = i32::try_from_dvalue(42.into()).unwrap();
@@ -134,6 +182,30 @@ mod tests {
         i64::try_from_dvalue(DValue::from_f64(1.0).unwrap()).unwrap();
     }
 
+    #[test]
+    fn test_try_from_dvalue_u8() {
+        let result = u8::try_from_dvalue(42.into()).unwrap();
+        assert_eq!(42, result);
+    }
+
+    #[test]
+    #[should_panic(expected = "IncompatibleMissing(Number(256))")]
+    fn test_try_from_dvalue_u8_too_large() {
+        u8::try_from_dvalue(256.into()).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_dvalue_u16() {
+        let result = u16::try_from_dvalue(42.into()).unwrap();
+        assert_eq!(42, result);
+    }
+
+    #[test]
+    #[should_panic(expected = "IncompatibleMissing(Number(-1))")]
+    fn test_try_from_dvalue_u16_negative() {
+        u16::try_from_dvalue((-1).into()).unwrap();
+    }
+
     #[test]
     fn test_try_from_dvalue_u32() {
         let result = u32::try_from_dvalue(42.into()).unwrap();
@@ -45,8 +45,12 @@ impl Missing<DValue> {
     /// [DType].
     pub fn validate(&self, dtype: DType) -> Result<(), ValidationError> {
         match dtype {
+            DType::Int8 => Missing::<i8>::validate_dvalue(self),
+            DType::Int16 => Missing::<i16>::validate_dvalue(self),
             DType::Int32 => Missing::<i32>::validate_dvalue(self),
             DType::Int64 => Missing::<i64>::validate_dvalue(self),
+            DType::Uint8 => Missing::<u8>::validate_dvalue(self),
+            DType::Uint16 => Missing::<u16>::validate_dvalue(self),
             DType::Uint32 => Missing::<u32>::validate_dvalue(self),
             DType::Uint64 => Missing::<u64>::validate_dvalue(self),
             DType::Float32 => Missing::<f32>::validate_dvalue(self),
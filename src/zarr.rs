@@ -0,0 +1,1084 @@
+//! Zarr-native requests
+//!
+//! Operations on a [Zarr](https://zarr.dev/) array are performed without the client describing
+//! each chunk. The array's metadata is read from the `.zarray` object of a Zarr v2 array or the
+//! `zarr.json` object of a Zarr v3 array, and the operation is performed on each chunk
+//! intersecting the selection. The results for each chunk are then merged into a single response.
+//!
+//! Only reductions that can be merged exactly are supported, and chunks must use codecs supported
+//! by Reductionist. Chunks that have not been written are filled with the array's fill value.
+
+use crate::array;
+use crate::error::ActiveStorageError;
+use crate::models::{
+    Compression, DType, Filter, Order, RequestData, Response, Slice, ZarrRequestData,
+};
+use crate::operation::Element;
+use crate::types::{ByteOrder, DValue};
+
+use axum::body::Bytes;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use zerocopy::AsBytes;
+
+/// Name of the metadata object of a Zarr v2 array
+pub const METADATA_V2: &str = ".zarray";
+
+/// Name of the metadata object of a Zarr v3 array
+pub const METADATA_V3: &str = "zarr.json";
+
+/// Names of the operations supported on Zarr arrays
+pub const OPERATIONS: &[&str] = &["count", "max", "mean", "min", "sum"];
+
+/// Returns an [ActiveStorageError::ZarrMetadataInvalid] error.
+fn invalid(reason: impl Into<String>) -> ActiveStorageError {
+    ActiveStorageError::ZarrMetadataInvalid {
+        reason: reason.into(),
+    }
+}
+
+/// A numcodecs codec in Zarr v2 metadata
+#[derive(Debug, Deserialize)]
+struct CodecV2 {
+    /// Codec identifier
+    id: String,
+    /// Codec configuration
+    #[serde(flatten)]
+    configuration: Map<String, Value>,
+}
+
+/// Zarr v2 array metadata
+#[derive(Debug, Deserialize)]
+struct MetadataV2 {
+    shape: Vec<usize>,
+    chunks: Vec<usize>,
+    dtype: String,
+    compressor: Option<CodecV2>,
+    #[serde(default)]
+    fill_value: Value,
+    order: Order,
+    filters: Option<Vec<CodecV2>>,
+    #[serde(default = "default_separator_v2")]
+    dimension_separator: String,
+}
+
+/// Returns the default separator of Zarr v2 chunk keys.
+fn default_separator_v2() -> String {
+    ".".to_string()
+}
+
+/// A named extension point in Zarr v3 metadata, such as a codec
+#[derive(Debug, Deserialize)]
+struct NamedConfiguration {
+    name: String,
+    #[serde(default)]
+    configuration: Map<String, Value>,
+}
+
+/// Zarr v3 array metadata
+#[derive(Debug, Deserialize)]
+struct MetadataV3 {
+    node_type: String,
+    shape: Vec<usize>,
+    data_type: String,
+    chunk_grid: NamedConfiguration,
+    chunk_key_encoding: NamedConfiguration,
+    fill_value: Value,
+    codecs: Vec<NamedConfiguration>,
+}
+
+/// Metadata of a Zarr array, describing how to read its chunks
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrayMetadata {
+    /// Shape of the array
+    pub shape: Vec<usize>,
+    /// Shape of each chunk
+    pub chunks: Vec<usize>,
+    /// Data type
+    pub dtype: DType,
+    /// Byte order of multi-byte data types
+    pub byte_order: Option<ByteOrder>,
+    /// Order of the elements within each chunk
+    pub order: Order,
+    /// Compression of each chunk
+    pub compression: Option<Compression>,
+    /// Filters applied to each chunk
+    pub filters: Option<Vec<Filter>>,
+    /// Fill value, as a single element in native byte order
+    pub fill_value: Vec<u8>,
+    /// Prefix of chunk keys, before the chunk coordinates
+    pub key_prefix: Option<&'static str>,
+    /// Separator of the chunk coordinates in chunk keys
+    pub separator: String,
+}
+
+/// The part of a Zarr array's selection within a single chunk
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkSelection {
+    /// Coordinates of the chunk in the chunk grid
+    pub coords: Vec<usize>,
+    /// Selection within the chunk
+    pub selection: Vec<Slice>,
+}
+
+impl ChunkSelection {
+    /// Returns the number of selected elements in the chunk.
+    pub fn num_elements(&self) -> usize {
+        self.selection
+            .iter()
+            .map(|slice| ((slice.end - slice.start - 1) / slice.stride + 1) as usize)
+            .product()
+    }
+}
+
+impl ArrayMetadata {
+    /// Parse Zarr v2 array metadata from a `.zarray` object.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: Contents of the `.zarray` object
+    pub fn from_v2(data: &[u8]) -> Result<Self, ActiveStorageError> {
+        let metadata: MetadataV2 =
+            serde_json::from_slice(data).map_err(|err| invalid(err.to_string()))?;
+        let (dtype, byte_order) = parse_dtype_v2(&metadata.dtype)?;
+        let compression = metadata
+            .compressor
+            .map(|compressor| compression(&compressor.id))
+            .transpose()?;
+        let filters = metadata
+            .filters
+            .map(|filters| {
+                filters
+                    .iter()
+                    .map(|filter| match filter.id.as_str() {
+                        "shuffle" => {
+                            let element_size = filter
+                                .configuration
+                                .get("elementsize")
+                                .and_then(Value::as_u64)
+                                .map_or(dtype.size_of(), |size| size as usize);
+                            Ok(Filter::Shuffle { element_size })
+                        }
+                        id => Err(invalid(format!("unsupported filter {}", id))),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+        let metadata = Self {
+            fill_value: fill_value(dtype, &metadata.fill_value)?,
+            shape: metadata.shape,
+            chunks: metadata.chunks,
+            dtype,
+            byte_order,
+            order: metadata.order,
+            compression,
+            filters,
+            key_prefix: None,
+            separator: metadata.dimension_separator,
+        };
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    /// Parse Zarr v3 array metadata from a `zarr.json` object.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: Contents of the `zarr.json` object
+    pub fn from_v3(data: &[u8]) -> Result<Self, ActiveStorageError> {
+        let metadata: MetadataV3 =
+            serde_json::from_slice(data).map_err(|err| invalid(err.to_string()))?;
+        if metadata.node_type != "array" {
+            return Err(invalid(format!(
+                "node type {} is not an array",
+                metadata.node_type
+            )));
+        }
+        let dtype = parse_dtype_v3(&metadata.data_type)?;
+        if metadata.chunk_grid.name != "regular" {
+            return Err(invalid(format!(
+                "unsupported chunk grid {}",
+                metadata.chunk_grid.name
+            )));
+        }
+        let chunks = metadata
+            .chunk_grid
+            .configuration
+            .get("chunk_shape")
+            .and_then(|chunks| serde_json::from_value(chunks.clone()).ok())
+            .ok_or_else(|| invalid("missing chunk_shape"))?;
+        let encoding = &metadata.chunk_key_encoding;
+        let (key_prefix, default_separator) = match encoding.name.as_str() {
+            "default" => (Some("c"), "/"),
+            "v2" => (None, "."),
+            name => return Err(invalid(format!("unsupported chunk key encoding {}", name))),
+        };
+        let separator = encoding
+            .configuration
+            .get("separator")
+            .and_then(Value::as_str)
+            .unwrap_or(default_separator)
+            .to_string();
+        let mut byte_order = None;
+        let mut compression = None;
+        let mut bytes = false;
+        for codec in &metadata.codecs {
+            match codec.name.as_str() {
+                "bytes" if !bytes => {
+                    byte_order = match codec.configuration.get("endian").and_then(Value::as_str) {
+                        Some("big") => Some(ByteOrder::Big),
+                        Some("little") => Some(ByteOrder::Little),
+                        None if dtype.size_of() == 1 => None,
+                        _ => return Err(invalid("invalid bytes codec endian")),
+                    };
+                    bytes = true;
+                }
+                // Only a single compressor following the bytes codec is supported.
+                name @ ("blosc" | "gzip" | "zstd") if bytes && compression.is_none() => {
+                    compression = Some(self::compression(name)?);
+                }
+                name => return Err(invalid(format!("unsupported codec {}", name))),
+            }
+        }
+        if !bytes {
+            return Err(invalid("missing bytes codec"));
+        }
+        let metadata = Self {
+            fill_value: fill_value(dtype, &metadata.fill_value)?,
+            shape: metadata.shape,
+            chunks,
+            dtype,
+            byte_order,
+            order: Order::C,
+            compression,
+            filters: None,
+            key_prefix,
+            separator,
+        };
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    /// Check that the shape and chunk shape are consistent.
+    fn validate(&self) -> Result<(), ActiveStorageError> {
+        if self.chunks.len() != self.shape.len() {
+            return Err(invalid("chunk shape and shape must have the same length"));
+        }
+        if self.chunks.contains(&0) {
+            return Err(invalid("chunk shape must not contain zeros"));
+        }
+        Ok(())
+    }
+
+    /// Returns the key of a chunk, relative to the array.
+    ///
+    /// # Arguments
+    ///
+    /// * `coords`: Coordinates of the chunk in the chunk grid
+    pub fn chunk_key(&self, coords: &[usize]) -> String {
+        let coords = coords.iter().map(usize::to_string);
+        match (self.key_prefix, coords.len()) {
+            (Some(prefix), _) => std::iter::once(prefix.to_string())
+                .chain(coords)
+                .collect::<Vec<_>>()
+                .join(&self.separator),
+            // Zero-dimensional arrays have a single chunk.
+            (None, 0) => "0".to_string(),
+            (None, _) => coords.collect::<Vec<_>>().join(&self.separator),
+        }
+    }
+
+    /// Returns the part of a selection within each chunk it intersects.
+    ///
+    /// Selections are only used for reductions, so the selection within each chunk is in
+    /// ascending order regardless of the direction of the selection.
+    ///
+    /// # Arguments
+    ///
+    /// * `selection`: Optional selection of the array
+    /// * `max_chunks`: Maximum number of chunks the selection may intersect
+    pub fn chunk_selections(
+        &self,
+        selection: Option<&[Slice]>,
+        max_chunks: usize,
+    ) -> Result<Vec<ChunkSelection>, ActiveStorageError> {
+        let full: Vec<_> = self
+            .shape
+            .iter()
+            .map(|&length| Slice::new(0, length as isize, 1))
+            .collect();
+        let selection = selection.unwrap_or(&full);
+        if selection.len() != self.shape.len() {
+            return Err(validator::ValidationError::new(
+                "selection must have the same length as the array shape",
+            )
+            .into());
+        }
+        // Check the number of chunks before building the selection within each of them.
+        let count = selection
+            .iter()
+            .zip(&self.shape)
+            .zip(&self.chunks)
+            .try_fold(1_usize, |count, ((slice, &length), &chunk)| {
+                count.checked_mul(dimension_chunk_count(slice, length, chunk))
+            });
+        if !count.is_some_and(|count| count <= max_chunks) {
+            return Err(validator::ValidationError::new(
+                "selection exceeds the maximum batch size in chunks",
+            )
+            .into());
+        }
+        let mut chunks = vec![ChunkSelection {
+            coords: vec![],
+            selection: vec![],
+        }];
+        for ((slice, &length), &chunk) in selection.iter().zip(&self.shape).zip(&self.chunks) {
+            let dimension = dimension_chunks(slice, length, chunk);
+            chunks = chunks
+                .into_iter()
+                .flat_map(|partial| {
+                    dimension.iter().map(move |(coord, slice)| {
+                        let mut partial = partial.clone();
+                        partial.coords.push(*coord);
+                        partial.selection.push(*slice);
+                        partial
+                    })
+                })
+                .collect();
+        }
+        Ok(chunks)
+    }
+
+    /// Returns request data for an operation on the selection within a chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * `request`: Request data for the operation on the array
+    /// * `chunk`: Selection within the chunk
+    pub fn chunk_request(
+        &self,
+        request: &ZarrRequestData,
+        chunk: &ChunkSelection,
+    ) -> Result<RequestData, ActiveStorageError> {
+        let object = format!(
+            "{}/{}",
+            request.array.trim_end_matches('/'),
+            self.chunk_key(&chunk.coords)
+        );
+        let mut builder = RequestData::builder(
+            request.source.clone(),
+            request.bucket.clone(),
+            object,
+            self.dtype,
+        )
+        .order(self.order.clone())
+        .requester_pays(request.requester_pays);
+        // Zero-dimensional arrays are treated as one-dimensional arrays with a single element.
+        if !self.shape.is_empty() {
+            builder = builder
+                .shape(self.chunks.clone())
+                .selection(chunk.selection.iter().map(|&slice| slice.into()).collect());
+        }
+        if let Some(byte_order) = self.byte_order {
+            builder = builder.byte_order(byte_order);
+        }
+        if let Some(compression) = self.compression {
+            builder = builder.compression(compression);
+        }
+        if let Some(filters) = &self.filters {
+            builder = builder.filters(filters.clone());
+        }
+        if let Some(missing) = &request.missing {
+            builder = builder.missing(missing.clone());
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Returns the size in bytes of the uncompressed data of a chunk.
+    pub fn chunk_size(&self) -> Result<usize, ActiveStorageError> {
+        self.chunks
+            .iter()
+            .try_fold(self.fill_value.len(), |size, &length| {
+                size.checked_mul(length)
+            })
+            .ok_or_else(|| invalid("chunk size out of range"))
+    }
+
+    /// Returns the data of a chunk that has not been written, containing the fill value in native
+    /// byte order.
+    ///
+    /// The request data is updated to describe the data, which has no compression or filters.
+    /// Memory for the data, of [ArrayMetadata::chunk_size] bytes, should be reserved by the caller.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_data`: Request data for the operation on the chunk
+    pub fn fill_chunk(&self, request_data: &mut RequestData) -> Result<Bytes, ActiveStorageError> {
+        let len = self.chunk_size()?;
+        request_data.byte_order = None;
+        request_data.compression = None;
+        request_data.filters = None;
+        let mut data = maligned::align_first::<u8, maligned::A8>(len);
+        for _ in 0..self.chunks.iter().product::<usize>() {
+            data.extend_from_slice(&self.fill_value);
+        }
+        Ok(data.into())
+    }
+}
+
+/// Returns the compression of a Zarr codec.
+///
+/// # Arguments
+///
+/// * `id`: Identifier of the codec
+fn compression(id: &str) -> Result<Compression, ActiveStorageError> {
+    match id {
+        "blosc" => Ok(Compression::Blosc),
+        "gzip" => Ok(Compression::Gzip),
+        "lz4" => Ok(Compression::Lz4),
+        "zlib" => Ok(Compression::Zlib),
+        "zstd" => Ok(Compression::Zstd),
+        id => Err(invalid(format!("unsupported compressor {}", id))),
+    }
+}
+
+/// Parse a NumPy type string from Zarr v2 metadata, such as `<i4`.
+///
+/// # Arguments
+///
+/// * `dtype`: NumPy type string
+fn parse_dtype_v2(dtype: &str) -> Result<(DType, Option<ByteOrder>), ActiveStorageError> {
+    let unsupported = || invalid(format!("unsupported dtype {}", dtype));
+    let (byte_order, kind) = match (dtype.get(..1), dtype.get(1..)) {
+        (Some("<"), Some(kind)) => (Some(ByteOrder::Little), kind),
+        (Some(">"), Some(kind)) => (Some(ByteOrder::Big), kind),
+        (Some("|"), Some(kind)) => (None, kind),
+        _ => return Err(unsupported()),
+    };
+    let dtype = match kind {
+        "i1" => DType::Int8,
+        "i2" => DType::Int16,
+        "i4" => DType::Int32,
+        "i8" => DType::Int64,
+        "u1" => DType::Uint8,
+        "u2" => DType::Uint16,
+        "u4" => DType::Uint32,
+        "u8" => DType::Uint64,
+        "f4" => DType::Float32,
+        "f8" => DType::Float64,
+        _ => return Err(unsupported()),
+    };
+    // The byte order of single byte types is irrelevant.
+    let byte_order = byte_order.filter(|_| dtype.size_of() > 1);
+    Ok((dtype, byte_order))
+}
+
+/// Parse a data type from Zarr v3 metadata, such as `int32`.
+///
+/// # Arguments
+///
+/// * `data_type`: Zarr v3 data type
+fn parse_dtype_v3(data_type: &str) -> Result<DType, ActiveStorageError> {
+    serde_json::from_value(Value::String(data_type.to_string()))
+        .map_err(|_| invalid(format!("unsupported data type {}", data_type)))
+}
+
+/// Returns a fill value as a single element in native byte order.
+///
+/// # Arguments
+///
+/// * `dtype`: Data type of the array
+/// * `value`: Fill value from the array metadata
+fn fill_value(dtype: DType, value: &Value) -> Result<Vec<u8>, ActiveStorageError> {
+    match dtype {
+        DType::Int8 => fill_value_t::<i8>(value),
+        DType::Int16 => fill_value_t::<i16>(value),
+        DType::Int32 => fill_value_t::<i32>(value),
+        DType::Int64 => fill_value_t::<i64>(value),
+        DType::Uint8 => fill_value_t::<u8>(value),
+        DType::Uint16 => fill_value_t::<u16>(value),
+        DType::Uint32 => fill_value_t::<u32>(value),
+        DType::Uint64 => fill_value_t::<u64>(value),
+        DType::Float32 => fill_value_t::<f32>(value),
+        DType::Float64 => fill_value_t::<f64>(value),
+    }
+}
+
+/// Returns a fill value as a single element of type `T` in native byte order.
+///
+/// # Arguments
+///
+/// * `value`: Fill value from the array metadata
+fn fill_value_t<T: Element>(value: &Value) -> Result<Vec<u8>, ActiveStorageError> {
+    let fill_value = match value {
+        // Zarr v2 arrays without a fill value have uninitialised chunks, which are read as zeros.
+        Value::Null => Some(T::zero()),
+        Value::Number(number) => T::try_from_dvalue(DValue::clone(number)).ok(),
+        Value::String(string) => match string.as_str() {
+            "NaN" => T::from_f64(f64::NAN),
+            "Infinity" => T::from_f64(f64::INFINITY),
+            "-Infinity" => T::from_f64(f64::NEG_INFINITY),
+            _ => None,
+        },
+        _ => None,
+    };
+    let fill_value = fill_value.ok_or_else(|| invalid(format!("invalid fill value {}", value)))?;
+    Ok(fill_value.as_bytes().to_vec())
+}
+
+/// Returns the first and last elements selected by a slice of a dimension in ascending order, and
+/// the stride between them, or `None` if the slice is empty.
+///
+/// # Arguments
+///
+/// * `slice`: Slice of the dimension
+/// * `length`: Length of the dimension
+fn selected_elements(slice: &Slice, length: usize) -> Option<(usize, usize, usize)> {
+    let ndarray::SliceInfoElem::Slice { start, end, step } = array::to_ndarray_slice(slice, length)
+    else {
+        unreachable!("slices should convert to ndarray slices")
+    };
+    let (start, end) = (start as usize, end.unwrap_or_default() as usize);
+    if start >= end {
+        return None;
+    }
+    let stride = step.unsigned_abs();
+    // With a negative step, ndarray selects elements counting back from the end of the range.
+    let first = if step < 0 {
+        start + (end - 1 - start) % stride
+    } else {
+        start
+    };
+    let last = first + (end - 1 - first) / stride * stride;
+    Some((first, last, stride))
+}
+
+/// Returns the number of chunks intersecting a slice of a dimension, without building the
+/// selection within each chunk.
+///
+/// # Arguments
+///
+/// * `slice`: Slice of the dimension
+/// * `length`: Length of the dimension
+/// * `chunk`: Length of each chunk in the dimension
+fn dimension_chunk_count(slice: &Slice, length: usize, chunk: usize) -> usize {
+    match selected_elements(slice, length) {
+        // Strides of at least a chunk select each element from a different chunk, while shorter
+        // strides cannot skip a chunk.
+        Some((first, last, stride)) if stride >= chunk => (last - first) / stride + 1,
+        Some((first, last, _)) => last / chunk - first / chunk + 1,
+        None => 0,
+    }
+}
+
+/// Returns the chunks intersecting a slice of a dimension, with the part of the slice within each
+/// chunk in ascending order.
+///
+/// # Arguments
+///
+/// * `slice`: Slice of the dimension
+/// * `length`: Length of the dimension
+/// * `chunk`: Length of each chunk in the dimension
+fn dimension_chunks(slice: &Slice, length: usize, chunk: usize) -> Vec<(usize, Slice)> {
+    let Some((first, last, stride)) = selected_elements(slice, length) else {
+        return vec![];
+    };
+    (first / chunk..=last / chunk)
+        .filter_map(|coord| {
+            let chunk_start = coord * chunk;
+            let chunk_first = if first >= chunk_start {
+                first
+            } else {
+                first + (chunk_start - first).div_ceil(stride) * stride
+            };
+            let chunk_end = std::cmp::min(last, chunk_start + chunk - 1);
+            // Strides longer than a chunk may skip it.
+            (chunk_first <= chunk_end).then(|| {
+                let chunk_last = chunk_first + (chunk_end - chunk_first) / stride * stride;
+                let slice = Slice::new(
+                    (chunk_first - chunk_start) as isize,
+                    (chunk_last - chunk_start + 1) as isize,
+                    stride as isize,
+                );
+                (coord, slice)
+            })
+        })
+        .collect()
+}
+
+/// Merge the responses of an operation on each chunk into a single response.
+///
+/// Chunks without a response have no non-missing elements in the selection, and so do not
+/// contribute to the result.
+///
+/// # Arguments
+///
+/// * `operation`: Name of the operation
+/// * `dtype`: Data type of the array
+/// * `missing`: Number of missing elements in chunks without a response
+/// * `responses`: Response of the operation on each chunk with non-missing elements
+pub fn merge(
+    operation: &str,
+    dtype: DType,
+    missing: i64,
+    responses: Vec<Response>,
+) -> Result<Response, ActiveStorageError> {
+    let count: i64 = responses.iter().map(|response| response.count).sum();
+    let missing = missing
+        + responses
+            .iter()
+            .map(|response| response.missing)
+            .sum::<i64>();
+    let response = match operation {
+        "count" => {
+            let body = Bytes::copy_from_slice(count.as_bytes());
+            Response::new(body, DType::Int64, vec![], count)
+        }
+        "mean" => {
+            let sum: f64 = responses
+                .iter()
+                .map(|response| read::<f64>(&response.body) * response.count as f64)
+                .sum();
+            let mean = if count > 0 {
+                sum / count as f64
+            } else {
+                f64::NAN
+            };
+            Response::new(
+                Bytes::copy_from_slice(mean.as_bytes()),
+                DType::Float64,
+                vec![],
+                count,
+            )
+        }
+        "max" | "min" | "sum" => {
+            let body = match dtype {
+                DType::Int8 => merge_t::<i8>(operation, &responses),
+                DType::Int16 => merge_t::<i16>(operation, &responses),
+                DType::Int32 => merge_t::<i32>(operation, &responses),
+                DType::Int64 => merge_t::<i64>(operation, &responses),
+                DType::Uint8 => merge_t::<u8>(operation, &responses),
+                DType::Uint16 => merge_t::<u16>(operation, &responses),
+                DType::Uint32 => merge_t::<u32>(operation, &responses),
+                DType::Uint64 => merge_t::<u64>(operation, &responses),
+                DType::Float32 => merge_t::<f32>(operation, &responses),
+                DType::Float64 => merge_t::<f64>(operation, &responses),
+            }?;
+            Response::new(body, dtype, vec![], count)
+        }
+        _ => {
+            return Err(ActiveStorageError::UnsupportedOperation {
+                operation: operation.to_string(),
+            })
+        }
+    };
+    Ok(response.with_missing(missing))
+}
+
+/// Merge the scalar responses of a max, min or sum operation on each chunk.
+///
+/// # Arguments
+///
+/// * `operation`: Name of the operation
+/// * `responses`: Response of the operation on each chunk with non-missing elements
+fn merge_t<T: Element>(
+    operation: &str,
+    responses: &[Response],
+) -> Result<Bytes, ActiveStorageError> {
+    let values = responses.iter().map(|response| read::<T>(&response.body));
+    let value = match operation {
+        "sum" => Some(values.fold(T::zero(), |a, b| a + b)),
+        // NaN is propagated, as it is by the operation on each chunk.
+        "max" => values.reduce(|a, b| {
+            if b > a || b.partial_cmp(&b).is_none() {
+                b
+            } else {
+                a
+            }
+        }),
+        _ => values.reduce(|a, b| {
+            if b < a || b.partial_cmp(&b).is_none() {
+                b
+            } else {
+                a
+            }
+        }),
+    };
+    let value = value.ok_or_else(|| ActiveStorageError::EmptyArray {
+        operation: if operation == "max" { "max" } else { "min" },
+    })?;
+    Ok(Bytes::copy_from_slice(value.as_bytes()))
+}
+
+/// Read a scalar from a response body.
+///
+/// # Arguments
+///
+/// * `body`: Response body containing a single element
+fn read<T: Element>(body: &[u8]) -> T {
+    T::read_from(body).expect("response body should contain a single element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::Source;
+
+    use url::Url;
+    use zerocopy::FromBytes;
+
+    fn zarray(dtype: &str, compressor: &str, filters: &str, fill_value: &str) -> Vec<u8> {
+        format!(
+            r#"{{"zarr_format": 2, "shape": [10, 7], "chunks": [4, 3], "dtype": "{}",
+                "compressor": {}, "fill_value": {}, "order": "C", "filters": {}}}"#,
+            dtype, compressor, fill_value, filters
+        )
+        .into_bytes()
+    }
+
+    fn zarr_json(data_type: &str, codecs: &str, key_encoding: &str) -> Vec<u8> {
+        format!(
+            r#"{{"zarr_format": 3, "node_type": "array", "shape": [10, 7],
+                "data_type": "{}",
+                "chunk_grid": {{"name": "regular", "configuration": {{"chunk_shape": [4, 3]}}}},
+                "chunk_key_encoding": {},
+                "fill_value": "NaN", "codecs": {}, "attributes": {{}}}}"#,
+            data_type, key_encoding, codecs
+        )
+        .into_bytes()
+    }
+
+    fn make_request() -> ZarrRequestData {
+        ZarrRequestData {
+            source: Source::Url(Url::parse("http://example.com").unwrap()),
+            bucket: "bar".to_string(),
+            array: "data/tas/".to_string(),
+            selection: None,
+            missing: None,
+            requester_pays: false,
+        }
+    }
+
+    #[test]
+    fn from_v2() {
+        let data = zarray(
+            "<i4",
+            r#"{"id": "zlib", "level": 1}"#,
+            r#"[{"id": "shuffle", "elementsize": 4}]"#,
+            "-1",
+        );
+        let metadata = ArrayMetadata::from_v2(&data).unwrap();
+        assert_eq!(vec![10, 7], metadata.shape);
+        assert_eq!(vec![4, 3], metadata.chunks);
+        assert_eq!(DType::Int32, metadata.dtype);
+        assert_eq!(Some(ByteOrder::Little), metadata.byte_order);
+        assert_eq!(Some(Compression::Zlib), metadata.compression);
+        assert_eq!(
+            Some(vec![Filter::Shuffle { element_size: 4 }]),
+            metadata.filters
+        );
+        assert_eq!((-1_i32).as_bytes(), metadata.fill_value);
+        assert_eq!("1.2", metadata.chunk_key(&[1, 2]));
+    }
+
+    #[test]
+    fn from_v2_unsupported() {
+        let data = zarray("<c8", "null", "null", "0");
+        let err = ArrayMetadata::from_v2(&data).unwrap_err();
+        assert_eq!(
+            "invalid Zarr array metadata: unsupported dtype <c8",
+            err.to_string()
+        );
+        let data = zarray("|u1", r#"{"id": "bz2"}"#, "null", "0");
+        let err = ArrayMetadata::from_v2(&data).unwrap_err();
+        assert_eq!(
+            "invalid Zarr array metadata: unsupported compressor bz2",
+            err.to_string()
+        );
+        let data = zarray("|u1", "null", r#"[{"id": "delta"}]"#, "0");
+        let err = ArrayMetadata::from_v2(&data).unwrap_err();
+        assert_eq!(
+            "invalid Zarr array metadata: unsupported filter delta",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn from_v2_single_byte() {
+        let data = zarray("|u1", "null", "null", "null");
+        let metadata = ArrayMetadata::from_v2(&data).unwrap();
+        assert_eq!(DType::Uint8, metadata.dtype);
+        assert_eq!(None, metadata.byte_order);
+        assert_eq!(None, metadata.compression);
+        assert_eq!(vec![0], metadata.fill_value);
+    }
+
+    #[test]
+    fn from_v3() {
+        let data = zarr_json(
+            "float32",
+            r#"[{"name": "bytes", "configuration": {"endian": "big"}},
+                {"name": "zstd", "configuration": {"level": 0, "checksum": false}}]"#,
+            r#"{"name": "default", "configuration": {"separator": "/"}}"#,
+        );
+        let metadata = ArrayMetadata::from_v3(&data).unwrap();
+        assert_eq!(vec![10, 7], metadata.shape);
+        assert_eq!(vec![4, 3], metadata.chunks);
+        assert_eq!(DType::Float32, metadata.dtype);
+        assert_eq!(Some(ByteOrder::Big), metadata.byte_order);
+        assert_eq!(Some(Compression::Zstd), metadata.compression);
+        assert!(f32::read_from(&metadata.fill_value[..]).unwrap().is_nan());
+        assert_eq!("c/1/2", metadata.chunk_key(&[1, 2]));
+    }
+
+    #[test]
+    fn from_v3_v2_key_encoding() {
+        let data = zarr_json(
+            "float64",
+            r#"[{"name": "bytes", "configuration": {"endian": "little"}}]"#,
+            r#"{"name": "v2"}"#,
+        );
+        let metadata = ArrayMetadata::from_v3(&data).unwrap();
+        assert_eq!(None, metadata.compression);
+        assert_eq!("1.2", metadata.chunk_key(&[1, 2]));
+    }
+
+    #[test]
+    fn from_v3_unsupported_codec() {
+        let data = zarr_json(
+            "int32",
+            r#"[{"name": "sharding_indexed", "configuration": {}}]"#,
+            r#"{"name": "default"}"#,
+        );
+        let err = ArrayMetadata::from_v3(&data).unwrap_err();
+        assert_eq!(
+            "invalid Zarr array metadata: unsupported codec sharding_indexed",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn from_v3_invalid_fill_value() {
+        let data = zarr_json(
+            "int32",
+            r#"[{"name": "bytes", "configuration": {"endian": "little"}}]"#,
+            r#"{"name": "default"}"#,
+        );
+        let err = ArrayMetadata::from_v3(&data).unwrap_err();
+        assert_eq!(
+            "invalid Zarr array metadata: invalid fill value \"NaN\"",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn dimension_chunks_full() {
+        let chunks = dimension_chunks(&Slice::new(0, 10, 1), 10, 4);
+        assert_eq!(
+            vec![
+                (0, Slice::new(0, 4, 1)),
+                (1, Slice::new(0, 4, 1)),
+                (2, Slice::new(0, 2, 1)),
+            ],
+            chunks
+        );
+    }
+
+    #[test]
+    fn dimension_chunks_strided() {
+        // Elements 1, 4 and 7.
+        let chunks = dimension_chunks(&Slice::new(1, 9, 3), 10, 4);
+        assert_eq!(
+            vec![(0, Slice::new(1, 2, 3)), (1, Slice::new(0, 4, 3))],
+            chunks
+        );
+        // Elements 1 and 9 skip chunk 1.
+        let chunks = dimension_chunks(&Slice::new(1, 10, 8), 10, 4);
+        assert_eq!(
+            vec![(0, Slice::new(1, 2, 8)), (2, Slice::new(1, 2, 8))],
+            chunks
+        );
+    }
+
+    #[test]
+    fn dimension_chunks_reversed() {
+        // Elements 9, 7 and 5.
+        let chunks = dimension_chunks(&Slice::new(-1, 4, -2), 10, 4);
+        assert_eq!(
+            vec![(1, Slice::new(1, 4, 2)), (2, Slice::new(1, 2, 2))],
+            chunks
+        );
+    }
+
+    #[test]
+    fn dimension_chunks_empty() {
+        assert!(dimension_chunks(&Slice::new(5, 2, 1), 10, 4).is_empty());
+    }
+
+    #[test]
+    fn dimension_chunk_counts() {
+        for (slice, length, chunk) in [
+            (Slice::new(0, 10, 1), 10, 4),
+            (Slice::new(1, 9, 3), 10, 4),
+            (Slice::new(1, 10, 8), 10, 4),
+            (Slice::new(1, 10, 4), 10, 4),
+            (Slice::new(-1, 4, -2), 10, 4),
+            (Slice::new(-1, -10, -5), 10, 2),
+            (Slice::new(5, 2, 1), 10, 4),
+        ] {
+            assert_eq!(
+                dimension_chunks(&slice, length, chunk).len(),
+                dimension_chunk_count(&slice, length, chunk),
+                "{:?}",
+                slice
+            );
+        }
+    }
+
+    #[test]
+    fn chunk_selections() {
+        let data = zarray("<i4", "null", "null", "0");
+        let metadata = ArrayMetadata::from_v2(&data).unwrap();
+        let selection = [Slice::new(2, 6, 1), Slice::new(0, 2, 1)];
+        let chunks = metadata.chunk_selections(Some(&selection), 2).unwrap();
+        assert_eq!(
+            vec![
+                ChunkSelection {
+                    coords: vec![0, 0],
+                    selection: vec![Slice::new(2, 4, 1), Slice::new(0, 2, 1)],
+                },
+                ChunkSelection {
+                    coords: vec![1, 0],
+                    selection: vec![Slice::new(0, 2, 1), Slice::new(0, 2, 1)],
+                },
+            ],
+            chunks
+        );
+        assert_eq!(4, chunks[0].num_elements());
+        // All 3 x 3 chunks.
+        assert_eq!(9, metadata.chunk_selections(None, 9).unwrap().len());
+        metadata.chunk_selections(None, 8).unwrap_err();
+        // Huge selections are rejected before the selection within each chunk is built.
+        let mut metadata = metadata;
+        metadata.shape = vec![usize::MAX / 2, usize::MAX / 2];
+        metadata.chunks = vec![1, 1];
+        metadata.chunk_selections(None, 9).unwrap_err();
+        metadata
+            .chunk_selections(Some(&[Slice::new(0, 1, 1)]), 9)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn chunk_request() {
+        let data = zarray(">u2", r#"{"id": "gzip"}"#, "null", "0");
+        let metadata = ArrayMetadata::from_v2(&data).unwrap();
+        let mut request = make_request();
+        request.missing = Some(crate::types::Missing::MissingValue(0.into()));
+        let chunk = ChunkSelection {
+            coords: vec![1, 0],
+            selection: vec![Slice::new(0, 2, 1), Slice::new(0, 3, 1)],
+        };
+        let request_data = metadata.chunk_request(&request, &chunk).unwrap();
+        assert_eq!("data/tas/1.0", request_data.object);
+        assert_eq!(DType::Uint16, request_data.dtype);
+        assert_eq!(Some(ByteOrder::Big), request_data.byte_order);
+        assert_eq!(Some(vec![4, 3]), request_data.shape);
+        assert_eq!(
+            Some(vec![Slice::new(0, 2, 1).into(), Slice::new(0, 3, 1).into()]),
+            request_data.selection
+        );
+        assert_eq!(Some(Compression::Gzip), request_data.compression);
+        assert_eq!(request.missing, request_data.missing);
+    }
+
+    #[test]
+    fn fill_chunk() {
+        let data = zarray(">i2", r#"{"id": "gzip"}"#, "null", "-5");
+        let metadata = ArrayMetadata::from_v2(&data).unwrap();
+        let chunk = ChunkSelection {
+            coords: vec![0, 0],
+            selection: vec![Slice::new(0, 4, 1), Slice::new(0, 3, 1)],
+        };
+        let mut request_data = metadata.chunk_request(&make_request(), &chunk).unwrap();
+        assert_eq!(24, metadata.chunk_size().unwrap());
+        let data = metadata.fill_chunk(&mut request_data).unwrap();
+        assert_eq!([-5_i16; 12].as_bytes(), data);
+        assert_eq!(0, data.as_ptr().align_offset(8));
+        assert_eq!(None, request_data.byte_order);
+        assert_eq!(None, request_data.compression);
+    }
+
+    #[test]
+    fn fill_chunk_too_large() {
+        let data = zarray(">i2", "null", "null", "-5");
+        let mut metadata = ArrayMetadata::from_v2(&data).unwrap();
+        metadata.chunks = vec![usize::MAX, 2];
+        let mut request_data = crate::test_utils::get_test_request_data();
+        assert_eq!(
+            "invalid Zarr array metadata: chunk size out of range",
+            metadata
+                .fill_chunk(&mut request_data)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    fn scalar<T: Element>(value: T, count: i64, missing: i64) -> Response {
+        Response::new(
+            Bytes::copy_from_slice(value.as_bytes()),
+            DType::Int32,
+            vec![],
+            count,
+        )
+        .with_missing(missing)
+    }
+
+    #[test]
+    fn merge_count_and_sum() {
+        let responses = vec![scalar(3_i32, 2, 1), scalar(4_i32, 3, 0)];
+        let response = merge("sum", DType::Int32, 2, responses).unwrap();
+        assert_eq!(7_i32.as_bytes(), response.body);
+        assert_eq!(DType::Int32, response.dtype);
+        assert_eq!(5, response.count);
+        assert_eq!(3, response.missing);
+        let responses = vec![scalar(2_i64, 2, 0), scalar(3_i64, 3, 0)];
+        let response = merge("count", DType::Int32, 0, responses).unwrap();
+        assert_eq!(5_i64.as_bytes(), response.body);
+        assert_eq!(DType::Int64, response.dtype);
+    }
+
+    #[test]
+    fn merge_min_max() {
+        let responses = vec![scalar(3_i32, 2, 0), scalar(-4_i32, 3, 0)];
+        let response = merge("min", DType::Int32, 0, responses).unwrap();
+        assert_eq!((-4_i32).as_bytes(), response.body);
+        let responses = vec![scalar(3_i32, 2, 0), scalar(-4_i32, 3, 0)];
+        let response = merge("max", DType::Int32, 0, responses).unwrap();
+        assert_eq!(3_i32.as_bytes(), response.body);
+        let err = merge("max", DType::Int32, 4, vec![]).err().unwrap();
+        assert_eq!(
+            "cannot perform max on empty array or selection",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn merge_nan() {
+        let responses = vec![scalar(f32::NAN, 2, 0), scalar(1_f32, 3, 0)];
+        let response = merge("max", DType::Float32, 0, responses).unwrap();
+        assert!(f32::read_from(&response.body[..]).unwrap().is_nan());
+    }
+
+    #[test]
+    fn merge_mean() {
+        let responses = vec![scalar(1.0_f64, 1, 0), scalar(3.0_f64, 3, 0)];
+        let response = merge("mean", DType::Int32, 0, responses).unwrap();
+        assert_eq!(2.5_f64.as_bytes(), response.body);
+        assert_eq!(DType::Float64, response.dtype);
+        assert_eq!(4, response.count);
+        let response = merge("mean", DType::Int32, 2, vec![]).unwrap();
+        assert!(f64::read_from(&response.body[..]).unwrap().is_nan());
+        assert_eq!(2, response.missing);
+    }
+}